@@ -134,4 +134,16 @@ impl Camera {
     pub fn set_projection(&mut self, projection: Projection<f32>) {
         self.frustum = Frustum::new(projection);
     }
+
+    /// Gets the projected diameter of sphere divided by the height of screen, which is
+    /// used to select the level of detail of meshes.
+    pub fn screen_size(&self, center: Vector3<f32>, radius: f32) -> f32 {
+        match self.frustum.projection() {
+            Projection::Ortho { height, .. } => 2.0 * radius / height,
+            Projection::Perspective { fovy, .. } => {
+                let distance = self.transform.position.distance(center);
+                radius / (distance * (fovy.0 * 0.5).tan())
+            }
+        }
+    }
 }
@@ -4,11 +4,16 @@ mod mesh_renderer;
 mod simple;
 
 pub mod headless;
+pub mod postprocess;
 
 pub mod prelude {
     pub use super::camera::Camera;
     pub use super::lit::{Lit, LitSource};
     pub use super::mesh_renderer::MeshRenderer;
+    pub use super::postprocess::{
+        Bloom, ColorGrading, Fxaa, PostEffect, PostEffectTargets, PostProcessRenderer,
+        PostProcessStack, TonemapOperator, Tonemapping, Vignette,
+    };
     pub use super::simple::{SimpleMaterial, SimpleRenderer};
     pub use super::{Renderable, Renderer};
 }
@@ -0,0 +1,339 @@
+//! The built-in effects.
+
+use crayon::math::prelude::{Color, Vector2};
+use crayon::video::assets::prelude::*;
+use crayon::video::prelude::{Draw, RenderGraph};
+use failure::Error;
+
+use super::{sample, shader_variants, PostEffect, PostEffectTargets};
+
+/// The curves which map HDR colors into displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemapOperator {
+    Reinhard,
+    /// The fitted ACES filmic curve.
+    Aces,
+}
+
+/// Maps the HDR colors into [0, 1] range, and applies the gamma correction.
+pub struct Tonemapping {
+    pub operator: TonemapOperator,
+    /// The multiplier of colors before tonemapping.
+    pub exposure: f32,
+    pub gamma: f32,
+    shaders: ShaderVariants,
+}
+
+impl Default for Tonemapping {
+    fn default() -> Self {
+        Tonemapping::new(TonemapOperator::Aces)
+    }
+}
+
+impl Tonemapping {
+    pub fn new(operator: TonemapOperator) -> Self {
+        let uniforms = UniformVariableLayout::build()
+            .with("u_Exposure", UniformVariableType::F32)
+            .with("u_InvGamma", UniformVariableType::F32);
+
+        Tonemapping {
+            operator: operator,
+            exposure: 1.0,
+            gamma: 2.2,
+            shaders: shader_variants(include_str!("shaders/tonemapping.fs"), uniforms),
+        }
+    }
+}
+
+impl PostEffect for Tonemapping {
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error> {
+        let key = match self.operator {
+            TonemapOperator::Reinhard => ShaderVariantKey::new(),
+            TonemapOperator::Aces => ShaderVariantKey::new().define("ACES"),
+        };
+
+        let shader = self.shaders.variant(&key)?;
+        let quad = targets.quad();
+        let input = targets.input().to_owned();
+        let exposure = self.exposure;
+        let inv_gamma = 1.0 / self.gamma;
+
+        let pass = graph.add_pass("tonemapping").read(targets.input());
+        targets.write_output(pass).execute(move |ctx, cmds| {
+            let mut dc = Draw::new(shader, quad);
+            dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+            dc.set_uniform_variable("u_Exposure", exposure);
+            dc.set_uniform_variable("u_InvGamma", inv_gamma);
+            cmds.draw(dc);
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
+
+/// Makes the bright areas glow. The colors brighter than `threshold` are blurred at half
+/// resolution, and added back to the image.
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    bright: ShaderVariants,
+    blur: ShaderVariants,
+    composite: ShaderVariants,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom::new()
+    }
+}
+
+impl Bloom {
+    pub fn new() -> Self {
+        let bright = UniformVariableLayout::build().with("u_Threshold", UniformVariableType::F32);
+        let blur =
+            UniformVariableLayout::build().with("u_Direction", UniformVariableType::Vector2f);
+        let composite = UniformVariableLayout::build()
+            .with("u_BloomTexture", UniformVariableType::RenderTexture)
+            .with("u_Intensity", UniformVariableType::F32);
+
+        Bloom {
+            threshold: 1.0,
+            intensity: 0.5,
+            bright: shader_variants(include_str!("shaders/bloom_bright.fs"), bright),
+            blur: shader_variants(include_str!("shaders/bloom_blur.fs"), blur),
+            composite: shader_variants(include_str!("shaders/bloom_composite.fs"), composite),
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error> {
+        let key = ShaderVariantKey::new();
+        let bright_shader = self.bright.variant(&key)?;
+        let blur_shader = self.blur.variant(&key)?;
+        let composite_shader = self.composite.variant(&key)?;
+        let quad = targets.quad();
+
+        let mut params = targets.params();
+        params.dimensions.x = (params.dimensions.x / 2).max(1);
+        params.dimensions.y = (params.dimensions.y / 2).max(1);
+        let texel = Vector2::new(
+            1.0 / params.dimensions.x as f32,
+            1.0 / params.dimensions.y as f32,
+        );
+
+        // The blurred image is written into another target, since a pass which reads its
+        // own target from an earlier pass makes a cycle. They are aliased anyway.
+        let bright = targets.target("bloom.bright");
+        let horizontal = targets.target("bloom.horizontal");
+        let vertical = targets.target("bloom.vertical");
+        graph.create_target(bright.as_str(), params);
+        graph.create_target(horizontal.as_str(), params);
+        graph.create_target(vertical.as_str(), params);
+
+        let input = targets.input().to_owned();
+        let threshold = self.threshold;
+        graph
+            .add_pass("bloom.bright")
+            .read(targets.input())
+            .write(bright.as_str())
+            .execute(move |ctx, cmds| {
+                let mut dc = Draw::new(bright_shader, quad);
+                dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+                dc.set_uniform_variable("u_Threshold", threshold);
+                cmds.draw(dc);
+                Ok(())
+            });
+
+        let blurs = [
+            (bright, horizontal.clone(), Vector2::new(texel.x, 0.0)),
+            (horizontal, vertical.clone(), Vector2::new(0.0, texel.y)),
+        ];
+
+        for (src, dst, direction) in blurs.iter().cloned() {
+            graph
+                .add_pass("bloom.blur")
+                .read(src.as_str())
+                .write(dst)
+                .execute(move |ctx, cmds| {
+                    let mut dc = Draw::new(blur_shader, quad);
+                    dc.set_uniform_variable("u_Texture", sample(ctx, &src)?);
+                    dc.set_uniform_variable("u_Direction", direction);
+                    cmds.draw(dc);
+                    Ok(())
+                });
+        }
+
+        let input = targets.input().to_owned();
+        let intensity = self.intensity;
+        let pass = graph
+            .add_pass("bloom.composite")
+            .read(targets.input())
+            .read(vertical.as_str());
+
+        targets.write_output(pass).execute(move |ctx, cmds| {
+            let mut dc = Draw::new(composite_shader, quad);
+            dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+            dc.set_uniform_variable("u_BloomTexture", sample(ctx, &vertical)?);
+            dc.set_uniform_variable("u_Intensity", intensity);
+            cmds.draw(dc);
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
+
+/// The fast approximate anti-aliasing. It works best on the tonemapped colors, so it
+/// should be pushed after `Tonemapping`.
+pub struct Fxaa {
+    shaders: ShaderVariants,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa::new()
+    }
+}
+
+impl Fxaa {
+    pub fn new() -> Self {
+        let uniforms =
+            UniformVariableLayout::build().with("u_InvResolution", UniformVariableType::Vector2f);
+
+        Fxaa {
+            shaders: shader_variants(include_str!("shaders/fxaa.fs"), uniforms),
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error> {
+        let shader = self.shaders.variant(&ShaderVariantKey::new())?;
+        let quad = targets.quad();
+        let input = targets.input().to_owned();
+
+        let dimensions = targets.params().dimensions;
+        let inv_resolution = Vector2::new(1.0 / dimensions.x as f32, 1.0 / dimensions.y as f32);
+
+        let pass = graph.add_pass("fxaa").read(targets.input());
+        targets.write_output(pass).execute(move |ctx, cmds| {
+            let mut dc = Draw::new(shader, quad);
+            dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+            dc.set_uniform_variable("u_InvResolution", inv_resolution);
+            cmds.draw(dc);
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
+
+/// Remaps the colors with a 3D lookup table.
+///
+/// The `lut` is a `size * size` by `size` texture, which is a horizontal strip of `size`
+/// slices. The red channel maps to the x axis of slice, the green channel maps to the y
+/// axis, and the blue channel picks the slice. It should be sampled with linear filter
+/// and clamp wrap mode.
+pub struct ColorGrading {
+    pub lut: TextureHandle,
+    pub size: u32,
+    /// The blend factor between the original and graded colors.
+    pub contribution: f32,
+    shaders: ShaderVariants,
+}
+
+impl ColorGrading {
+    pub fn new(lut: TextureHandle, size: u32) -> Self {
+        let uniforms = UniformVariableLayout::build()
+            .with("u_LutTexture", UniformVariableType::Texture)
+            .with("u_Contribution", UniformVariableType::F32);
+
+        ColorGrading {
+            lut: lut,
+            size: size,
+            contribution: 1.0,
+            shaders: shader_variants(include_str!("shaders/color_grading.fs"), uniforms),
+        }
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error> {
+        let key = ShaderVariantKey::new().define_value("LUT_SIZE", self.size);
+        let shader = self.shaders.variant(&key)?;
+        let quad = targets.quad();
+        let input = targets.input().to_owned();
+        let lut = self.lut;
+        let contribution = self.contribution;
+
+        let pass = graph.add_pass("color_grading").read(targets.input());
+        targets.write_output(pass).execute(move |ctx, cmds| {
+            let mut dc = Draw::new(shader, quad);
+            dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+            dc.set_uniform_variable("u_LutTexture", lut);
+            dc.set_uniform_variable("u_Contribution", contribution);
+            cmds.draw(dc);
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
+
+/// Darkens the edges of image towards `color`.
+pub struct Vignette {
+    pub color: Color<f32>,
+    /// How much the corners are darkened, in [0, 1].
+    pub intensity: f32,
+    /// The width of falloff from the corners, in [0, 1].
+    pub smoothness: f32,
+    shaders: ShaderVariants,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette::new()
+    }
+}
+
+impl Vignette {
+    pub fn new() -> Self {
+        let uniforms = UniformVariableLayout::build()
+            .with("u_Color", UniformVariableType::Vector3f)
+            .with("u_Intensity", UniformVariableType::F32)
+            .with("u_Smoothness", UniformVariableType::F32);
+
+        Vignette {
+            color: Color::black(),
+            intensity: 0.5,
+            smoothness: 0.5,
+            shaders: shader_variants(include_str!("shaders/vignette.fs"), uniforms),
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error> {
+        let shader = self.shaders.variant(&ShaderVariantKey::new())?;
+        let quad = targets.quad();
+        let input = targets.input().to_owned();
+        let color = self.color.rgb();
+        let intensity = self.intensity;
+        let smoothness = self.smoothness;
+
+        let pass = graph.add_pass("vignette").read(targets.input());
+        targets.write_output(pass).execute(move |ctx, cmds| {
+            let mut dc = Draw::new(shader, quad);
+            dc.set_uniform_variable("u_Texture", sample(ctx, &input)?);
+            dc.set_uniform_variable("u_Color", color);
+            dc.set_uniform_variable("u_Intensity", intensity);
+            dc.set_uniform_variable("u_Smoothness", smoothness);
+            cmds.draw(dc);
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Full-screen effects which are applied after the scene is rendered.
+//!
+//! The `PostProcessStack` renders the scene into an offscreen HDR target first, then
+//! chains the effects in order with a `RenderGraph`. The last effect writes into the
+//! surface of camera directly.
+
+mod effects;
+pub use self::effects::{Bloom, ColorGrading, Fxaa, TonemapOperator, Tonemapping, Vignette};
+
+use crayon::math::prelude::{Color, Vector2};
+use crayon::video::assets::prelude::*;
+use crayon::video::prelude::{RenderGraph, RenderGraphCache, RenderPassBuilder, RenderPassContext};
+use failure::Error;
+
+use Entity;
+
+use super::{Camera, Lit, MeshRenderer, Renderer};
+
+/// A full-screen effect of `PostProcessStack`.
+pub trait PostEffect {
+    /// Declares the passes of this effect. The passes should sample `targets.input()`, and
+    /// write the result with `PostEffectTargets::write_output`.
+    fn setup(&mut self, graph: &mut RenderGraph, targets: &PostEffectTargets) -> Result<(), Error>;
+}
+
+/// The targets and shared resources available to a `PostEffect`.
+pub struct PostEffectTargets {
+    prefix: String,
+    input: String,
+    output: Option<String>,
+    surface: Option<SurfaceHandle>,
+    params: RenderTextureParams,
+    quad: MeshHandle,
+}
+
+impl PostEffectTargets {
+    /// The name of target which holds the result of previous effect.
+    #[inline]
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// The params of input, which could be used to create intermediate targets.
+    #[inline]
+    pub fn params(&self) -> RenderTextureParams {
+        self.params
+    }
+
+    /// The quad mesh which covers the whole viewport with `postprocess.vs`.
+    #[inline]
+    pub fn quad(&self) -> MeshHandle {
+        self.quad
+    }
+
+    /// Makes an unique name for the intermediate target of this effect.
+    #[inline]
+    pub fn target(&self, name: &str) -> String {
+        format!("{}.{}", self.prefix, name)
+    }
+
+    /// Writes the output of this effect, which is either the input of next effect or the
+    /// surface of camera.
+    pub fn write_output<'g, 'a>(
+        &self,
+        pass: RenderPassBuilder<'g, 'a>,
+    ) -> RenderPassBuilder<'g, 'a> {
+        match (&self.output, self.surface) {
+            (Some(name), _) => pass.write(name.as_str()),
+            (None, Some(surface)) => pass.write_surface(surface),
+            (None, None) => pass.write_backbuffer(),
+        }
+    }
+}
+
+/// Gets the render texture of `target` in pass.
+pub fn sample(ctx: &RenderPassContext, target: &str) -> Result<RenderTextureHandle, Error> {
+    ctx.render_texture(target)
+        .ok_or_else(|| format_err!("Target {} is not allocated.", target))
+}
+
+/// Creates the shader of full-screen effect, with the shared vertex shader.
+pub(crate) fn shader_variants(fs: &str, uniforms: UniformVariableLayoutBuilder) -> ShaderVariants {
+    let mut params = ShaderParams::default();
+    params.attributes = AttributeLayout::build()
+        .with(Attribute::Position, 3)
+        .with(Attribute::Texcoord0, 2)
+        .finish();
+    params.uniforms = uniforms
+        .with("u_Texture", UniformVariableType::RenderTexture)
+        .finish();
+
+    ShaderVariants::new(params, include_str!("shaders/postprocess.vs"), fs)
+}
+
+/// A chain of `PostEffect`s.
+pub struct PostProcessStack {
+    effects: Vec<Box<PostEffect>>,
+    format: RenderTextureFormat,
+    dimensions: Option<Vector2<u32>>,
+    cache: RenderGraphCache,
+}
+
+impl Default for PostProcessStack {
+    fn default() -> Self {
+        PostProcessStack::new()
+    }
+}
+
+impl PostProcessStack {
+    /// Creates a empty stack, which renders scene into `RGBA16F` targets.
+    pub fn new() -> Self {
+        PostProcessStack {
+            effects: Vec::new(),
+            format: RenderTextureFormat::RGBA16F,
+            dimensions: None,
+            cache: RenderGraphCache::new(),
+        }
+    }
+
+    /// Appends a effect, effects are applied in the order they are added.
+    #[inline]
+    pub fn push<T: PostEffect + 'static>(&mut self, effect: T) {
+        self.effects.push(Box::new(effect));
+    }
+
+    /// Removes all the effects.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Sets the format of offscreen targets.
+    #[inline]
+    pub fn set_format(&mut self, format: RenderTextureFormat) {
+        self.format = format;
+    }
+
+    /// Sets the dimensions of offscreen targets, it follows the dimensions of window if
+    /// `None` is set. It should be set explicitly if the surface of camera has a different
+    /// size.
+    #[inline]
+    pub fn set_dimensions<T: Into<Option<Vector2<u32>>>>(&mut self, dimensions: T) {
+        self.dimensions = dimensions.into();
+    }
+
+    /// Renders the scene with `func` into an offscreen target, and presents it to
+    /// `surface` through the effects. The scene is rendered into `surface` directly if
+    /// there is no effect.
+    pub fn render<F>(&mut self, surface: Option<SurfaceHandle>, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(Option<SurfaceHandle>) -> Result<(), Error>,
+    {
+        if self.effects.is_empty() {
+            return func(surface);
+        }
+
+        let mut params = RenderTextureParams::default();
+        params.format = self.format;
+        params.dimensions = self.dimensions.unwrap_or_else(crayon::window::dimensions);
+
+        let mut depth = params;
+        depth.format = RenderTextureFormat::Depth24;
+        depth.sampler = None;
+
+        let mut graph = RenderGraph::new();
+        graph.create_target("scene", params);
+        graph.create_target("scene.depth", depth);
+        graph
+            .add_pass("scene")
+            .write("scene")
+            .write_depth_stencil("scene.depth")
+            .with_clear(Color::black(), 1.0, None)
+            .execute(|ctx, _| func(Some(ctx.surface())));
+
+        let quad = crate::default().quad;
+        self.setup_effects(&mut graph, params, surface, quad)?;
+        graph.execute(&mut self.cache)
+    }
+
+    /// Chains the effects after the `scene` pass. Every effect but the last one writes
+    /// into a target named `post{index}`, which is the input of next effect.
+    fn setup_effects(
+        &mut self,
+        graph: &mut RenderGraph,
+        params: RenderTextureParams,
+        surface: Option<SurfaceHandle>,
+        quad: MeshHandle,
+    ) -> Result<(), Error> {
+        let len = self.effects.len();
+        let mut input = "scene".to_owned();
+        for (i, effect) in self.effects.iter_mut().enumerate() {
+            let prefix = format!("post{}", i);
+            let output = if i + 1 < len {
+                graph.create_target(prefix.as_str(), params);
+                Some(prefix.clone())
+            } else {
+                None
+            };
+
+            let targets = PostEffectTargets {
+                prefix: prefix,
+                input: input,
+                output: output.clone(),
+                surface: surface,
+                params: params,
+                quad: quad,
+            };
+
+            effect.setup(graph, &targets)?;
+            input = output.unwrap_or_default();
+        }
+
+        Ok(())
+    }
+}
+
+/// A `Renderer` which applies the `PostProcessStack` to the cameras of another renderer.
+pub struct PostProcessRenderer<R: Renderer> {
+    renderer: R,
+    stack: PostProcessStack,
+}
+
+impl<R: Renderer> PostProcessRenderer<R> {
+    pub fn new(renderer: R) -> Self {
+        PostProcessRenderer {
+            renderer: renderer,
+            stack: PostProcessStack::new(),
+        }
+    }
+
+    #[inline]
+    pub fn renderer(&self) -> &R {
+        &self.renderer
+    }
+
+    #[inline]
+    pub fn renderer_mut(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    #[inline]
+    pub fn stack(&self) -> &PostProcessStack {
+        &self.stack
+    }
+
+    #[inline]
+    pub fn stack_mut(&mut self) -> &mut PostProcessStack {
+        &mut self.stack
+    }
+}
+
+impl<R: Renderer> Renderer for PostProcessRenderer<R> {
+    type Mtl = R::Mtl;
+
+    fn add_mtl(&mut self, ent: Entity, mtl: Self::Mtl) {
+        self.renderer.add_mtl(ent, mtl);
+    }
+
+    fn mtl(&self, ent: Entity) -> Option<&Self::Mtl> {
+        self.renderer.mtl(ent)
+    }
+
+    fn mtl_mut(&mut self, ent: Entity) -> Option<&mut Self::Mtl> {
+        self.renderer.mtl_mut(ent)
+    }
+
+    fn remove_mtl(&mut self, ent: Entity) {
+        self.renderer.remove_mtl(ent);
+    }
+
+    fn submit(&mut self, camera: &Camera, lits: &[Lit], meshes: &[MeshRenderer]) {
+        let renderer = &mut self.renderer;
+        let result = self.stack.render(camera.surface(), |surface| {
+            let mut camera = *camera;
+            camera.set_surface(surface);
+            renderer.submit(&camera, lits, meshes);
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            warn!("[PostProcessRenderer] {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Record = (String, Option<String>, String);
+
+    struct RecordEffect(Rc<RefCell<Vec<Record>>>);
+
+    impl PostEffect for RecordEffect {
+        fn setup(
+            &mut self,
+            graph: &mut RenderGraph,
+            targets: &PostEffectTargets,
+        ) -> Result<(), Error> {
+            let blur = targets.target("blur");
+            graph.create_target(blur.as_str(), targets.params());
+
+            let record = (targets.input().to_owned(), targets.output.clone(), blur);
+            self.0.borrow_mut().push(record);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let mut stack = PostProcessStack::new();
+        for _ in 0..3 {
+            stack.push(RecordEffect(records.clone()));
+        }
+
+        assert_eq!(stack.len(), 3);
+
+        let mut graph = RenderGraph::new();
+        let params = RenderTextureParams::default();
+        stack
+            .setup_effects(&mut graph, params, None, MeshHandle::default())
+            .unwrap();
+
+        // The last effect writes into the surface of camera.
+        let expected: Vec<Record> = vec![
+            ("scene".into(), Some("post0".into()), "post0.blur".into()),
+            ("post0".into(), Some("post1".into()), "post1.blur".into()),
+            ("post1".into(), None, "post2.blur".into()),
+        ];
+
+        assert_eq!(*records.borrow(), expected);
+
+        stack.clear();
+        assert!(stack.is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_Direction;
+
+void main() {
+    // A 9-tap gaussian kernel, which takes 5 samples with the help of linear filtering.
+    vec2 near = u_Direction * 1.3846153846;
+    vec2 far = u_Direction * 3.2307692308;
+
+    vec3 color = texture2D(u_Texture, v_Texcoord).rgb * 0.2270270270;
+    color += texture2D(u_Texture, v_Texcoord + near).rgb * 0.3162162162;
+    color += texture2D(u_Texture, v_Texcoord - near).rgb * 0.3162162162;
+    color += texture2D(u_Texture, v_Texcoord + far).rgb * 0.0702702703;
+    color += texture2D(u_Texture, v_Texcoord - far).rgb * 0.0702702703;
+    gl_FragColor = vec4(color, 1.0);
+}
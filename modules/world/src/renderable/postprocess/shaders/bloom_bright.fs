@@ -0,0 +1,11 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform float u_Threshold;
+
+void main() {
+    vec3 color = texture2D(u_Texture, v_Texcoord).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - u_Threshold, 0.0) / max(brightness, 0.0001);
+    gl_FragColor = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,11 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform sampler2D u_BloomTexture;
+uniform float u_Intensity;
+
+void main() {
+    vec4 color = texture2D(u_Texture, v_Texcoord);
+    vec3 bloom = texture2D(u_BloomTexture, v_Texcoord).rgb;
+    gl_FragColor = vec4(color.rgb + bloom * u_Intensity, color.a);
+}
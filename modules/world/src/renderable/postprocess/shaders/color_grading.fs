@@ -0,0 +1,29 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform sampler2D u_LutTexture;
+uniform float u_Contribution;
+
+// The LUT is a horizontal strip of LUT_SIZE slices. The red channel maps to the x
+// axis of slice, the green channel maps to the y axis, and the blue channel picks
+// the slice.
+vec3 grade(vec3 color) {
+    float size = float(LUT_SIZE);
+    float blue = color.b * (size - 1.0);
+    float slice = floor(blue);
+    float next = min(slice + 1.0, size - 1.0);
+
+    vec2 uv = vec2(
+        (color.r * (size - 1.0) + 0.5) / (size * size),
+        (color.g * (size - 1.0) + 0.5) / size);
+
+    vec3 a = texture2D(u_LutTexture, uv + vec2(slice / size, 0.0)).rgb;
+    vec3 b = texture2D(u_LutTexture, uv + vec2(next / size, 0.0)).rgb;
+    return mix(a, b, blue - slice);
+}
+
+void main() {
+    vec4 color = texture2D(u_Texture, v_Texcoord);
+    vec3 graded = grade(clamp(color.rgb, 0.0, 1.0));
+    gl_FragColor = vec4(mix(color.rgb, graded, u_Contribution), color.a);
+}
@@ -0,0 +1,45 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_InvResolution;
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+void main() {
+    vec3 nw = texture2D(u_Texture, v_Texcoord + vec2(-1.0, -1.0) * u_InvResolution).rgb;
+    vec3 ne = texture2D(u_Texture, v_Texcoord + vec2(1.0, -1.0) * u_InvResolution).rgb;
+    vec3 sw = texture2D(u_Texture, v_Texcoord + vec2(-1.0, 1.0) * u_InvResolution).rgb;
+    vec3 se = texture2D(u_Texture, v_Texcoord + vec2(1.0, 1.0) * u_InvResolution).rgb;
+    vec4 m = texture2D(u_Texture, v_Texcoord);
+
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+    float lumaNW = dot(nw, luma);
+    float lumaNE = dot(ne, luma);
+    float lumaSW = dot(sw, luma);
+    float lumaSE = dot(se, luma);
+    float lumaM = dot(m.rgb, luma);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Blurs along the direction of edge, which is perpendicular to the luma gradient.
+    vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * u_InvResolution;
+
+    vec3 a = 0.5 * (
+        texture2D(u_Texture, v_Texcoord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture2D(u_Texture, v_Texcoord + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (
+        texture2D(u_Texture, v_Texcoord + dir * -0.5).rgb +
+        texture2D(u_Texture, v_Texcoord + dir * 0.5).rgb);
+
+    float lumaB = dot(b, luma);
+    if (lumaB < lumaMin || lumaB > lumaMax) {
+        gl_FragColor = vec4(a, m.a);
+    } else {
+        gl_FragColor = vec4(b, m.a);
+    }
+}
@@ -0,0 +1,25 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform float u_Exposure;
+uniform float u_InvGamma;
+
+vec3 tonemap(vec3 color) {
+#ifdef ACES
+    // The fitted ACES filmic curve by Krzysztof Narkowicz.
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+#else
+    return color / (color + vec3(1.0));
+#endif
+}
+
+void main() {
+    vec4 color = texture2D(u_Texture, v_Texcoord);
+    vec3 mapped = tonemap(max(color.rgb, vec3(0.0)) * u_Exposure);
+    gl_FragColor = vec4(pow(mapped, vec3(u_InvGamma)), color.a);
+}
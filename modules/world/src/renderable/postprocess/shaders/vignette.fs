@@ -0,0 +1,15 @@
+varying vec2 v_Texcoord;
+
+uniform sampler2D u_Texture;
+uniform vec3 u_Color;
+uniform float u_Intensity;
+uniform float u_Smoothness;
+
+void main() {
+    vec4 color = texture2D(u_Texture, v_Texcoord);
+
+    // The distance is 0 at the center of screen, and 1 at the corners.
+    float dist = distance(v_Texcoord, vec2(0.5)) * 1.41421356;
+    float factor = smoothstep(0.0, max(u_Smoothness, 0.0001), 1.0 - dist);
+    gl_FragColor = vec4(mix(u_Color, color.rgb, mix(1.0, factor, u_Intensity)), color.a);
+}
@@ -17,6 +17,8 @@ pub struct SimpleRenderer {
     materials: Component<SimpleMaterial>,
 
     surface: SurfaceHandle,
+    // Owns the shader, which is deleted with the variants.
+    _shaders: ShaderVariants,
     shader: ShaderHandle,
     drawcalls: DrawCommandBuffer<DrawOrder>,
 
@@ -28,7 +30,6 @@ pub struct SimpleRenderer {
 impl Drop for SimpleRenderer {
     fn drop(&mut self) {
         video::delete_surface(self.surface);
-        video::delete_shader(self.shader);
     }
 }
 
@@ -90,35 +91,21 @@ impl SimpleRenderer {
         params.attributes = attributes;
         params.uniforms = uniforms.finish();
 
-        let vs = format!(
-            "
-            #version 100
-            precision lowp float;
-
-            #define MAX_DIR_LITS {0}
-            #define MAX_POINT_LITS {1}
-            {2}
-            ",
-            MAX_DIR_LITS,
-            MAX_POINT_LITS,
-            include_str!("shaders/simple.vs")
+        let mut shaders = ShaderVariants::new(
+            params,
+            include_str!("shaders/simple.vs"),
+            include_str!("shaders/simple.fs"),
         );
 
-        let fs = format!(
-            "
-            #version 100
-            precision lowp float;
-
-            #define MAX_DIR_LITS {0}
-            #define MAX_POINT_LITS {1}
-            {2}
-            ",
-            MAX_DIR_LITS,
-            MAX_POINT_LITS,
-            include_str!("shaders/simple.fs")
-        );
+        // Keeps the low precision of floats these shaders were written with.
+        shaders
+            .preprocessor_mut()
+            .set_precision(ShaderPrecision::Low);
 
-        let shader = video::create_shader(params, vs, fs)?;
+        let key = ShaderVariantKey::new()
+            .define_value("MAX_DIR_LITS", MAX_DIR_LITS)
+            .define_value("MAX_POINT_LITS", MAX_POINT_LITS);
+        let shader = shaders.variant(&key)?;
 
         let params = SurfaceParams::default();
         let surface = video::create_surface(params)?;
@@ -126,6 +113,7 @@ impl SimpleRenderer {
         Ok(SimpleRenderer {
             materials: Component::new(),
             surface: surface,
+            _shaders: shaders,
             shader: shader,
             drawcalls: DrawCommandBuffer::new(),
             dir_lits: dir_lits,
@@ -198,6 +186,12 @@ impl super::Renderer for SimpleRenderer {
             let vn = mv.invert().and_then(|v| Some(v.transpose())).unwrap_or(mv);
 
             let mut dc = Draw::new(self.shader, mesh.mesh);
+            dc.mesh_index = video::mesh_lod_index(mesh.mesh, |aabb| {
+                let center = model_matrix * aabb.center().to_homogeneous();
+                let radius = aabb.dim().magnitude() * 0.5 * mesh.transform.scale;
+                camera.screen_size(center.truncate(), radius)
+            });
+
             dc.set_uniform_variable("u_ModelViewMatrix", mv);
             dc.set_uniform_variable("u_MVPMatrix", mvp);
             dc.set_uniform_variable("u_ViewNormalMatrix", vn);
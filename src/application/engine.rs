@@ -55,7 +55,7 @@ impl EngineSystem {
         crate::sched::setup(0, None, None);
 
         crate::window::setup(params.window)?;
-        crate::video::setup()?;
+        crate::video::setup(params.video)?;
         crate::input::setup(params.input);
         crate::res::setup(params.res)?;
 
@@ -79,7 +79,7 @@ impl EngineSystem {
         crate::sched::setup(0, None, None);
 
         crate::window::headless();
-        crate::video::headless();
+        crate::video::headless(params.video);
         crate::input::setup(params.input);
         crate::res::setup(params.res)?;
 
@@ -50,6 +50,7 @@ use self::time::TimeSystem;
 
 use crate::input::InputParams;
 use crate::res::ResourceParams;
+use crate::video::VideoParams;
 use crate::window::WindowParams;
 
 /// A structure containing configuration data for the game engine, which are
@@ -75,6 +76,8 @@ pub struct Params {
     pub input: InputParams,
     /// The setup params for resource sub-system.
     pub res: ResourceParams,
+    /// The setup params for video sub-system.
+    pub video: VideoParams,
 }
 
 impl Default for Params {
@@ -87,6 +90,7 @@ impl Default for Params {
             window: WindowParams::default(),
             input: InputParams::default(),
             res: ResourceParams::default(),
+            video: VideoParams::default(),
         }
     }
 }
@@ -224,6 +228,19 @@ pub fn set_time_smoothing_step(step: u32) {
     time_ctx().set_time_smoothing_step(step);
 }
 
+/// Set the virtual timestep of frames. Once its set, the duration of every frame
+/// will be reported as `timestep`, regardless of the real time elapsed.
+#[inline]
+pub fn set_fixed_timestep(timestep: Option<::std::time::Duration>) {
+    time_ctx().set_fixed_timestep(timestep);
+}
+
+/// Gets the virtual timestep of frames if its set.
+#[inline]
+pub fn fixed_timestep() -> Option<::std::time::Duration> {
+    time_ctx().fixed_timestep()
+}
+
 /// Gets current fps.
 #[inline]
 pub fn fps() -> u32 {
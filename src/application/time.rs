@@ -20,6 +20,7 @@ struct TimeStateShared {
     max_fps: RwLock<u32>,
     max_inactive_fps: RwLock<u32>,
     smoothing_step: RwLock<usize>,
+    fixed_timestep: RwLock<Option<Duration>>,
     timestep: RwLock<Duration>,
 }
 
@@ -84,6 +85,11 @@ impl LifecycleListener for TimeState {
             self.timestep = elapsed;
         }
 
+        // Overrides the measured timestep with the virtual one.
+        if let Some(timestep) = *self.shared.fixed_timestep.read().unwrap() {
+            self.timestep = timestep;
+        }
+
         *self.shared.timestep.write().unwrap() = self.timestep;
         Ok(())
     }
@@ -102,6 +108,7 @@ impl TimeSystem {
             max_fps: RwLock::new(setup.max_fps),
             max_inactive_fps: RwLock::new(setup.max_inactive_fps),
             smoothing_step: RwLock::new(setup.time_smooth_step as usize),
+            fixed_timestep: RwLock::new(None),
             timestep: RwLock::new(Duration::new(0, 0)),
         });
 
@@ -150,6 +157,19 @@ impl TimeSystem {
         *self.shared.smoothing_step.write().unwrap() = step as usize;
     }
 
+    /// Set the virtual timestep of frames. Once its set, the duration of every frame
+    /// will be reported as `timestep`, regardless of the real time elapsed.
+    #[inline]
+    pub fn set_fixed_timestep(&self, timestep: Option<Duration>) {
+        *self.shared.fixed_timestep.write().unwrap() = timestep;
+    }
+
+    /// Gets the virtual timestep of frames if its set.
+    #[inline]
+    pub fn fixed_timestep(&self) -> Option<Duration> {
+        *self.shared.fixed_timestep.read().unwrap()
+    }
+
     /// Gets current fps.
     #[inline]
     pub fn fps(&self) -> u32 {
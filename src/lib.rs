@@ -49,6 +49,7 @@ extern crate cgmath;
 #[macro_use]
 extern crate serde;
 extern crate byteorder;
+extern crate image;
 extern crate serde_json;
 
 extern crate crossbeam_deque;
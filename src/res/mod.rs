@@ -41,6 +41,17 @@
 //! from general UUID or readable identifier. The `Manifest` file is generated after the build
 //! process of `crayon-cli`.
 //!
+//! ## Hot Reloading
+//!
+//! The `Dir` created with `Dir::watching` polls the modification time of loaded files. Once
+//! a file changes on disk, the `ResourcePool`s reload the resource and swap it behind the
+//! same handle, so it would be noticed by nobody. Failed reloads keep the old resource.
+//!
+//! ```rust,ignore
+//! let mut params = ResourceParams::default();
+//! params.schemas.add("file", crayon::res::vfs::dir::Dir::watching());
+//! ```
+//!
 
 pub mod manifest;
 pub mod request;
@@ -55,6 +66,7 @@ pub mod prelude {
 }
 
 mod system;
+mod watcher;
 
 use std::sync::Arc;
 
@@ -160,6 +172,31 @@ pub fn exists(uuid: Uuid) -> bool {
     ctx().exists(uuid)
 }
 
+/// Watches the modification of resource file. It takes effect only if the `VFS` of
+/// resource supports watching, e.g. `Dir::watching`.
+#[inline]
+pub fn watch(uuid: Uuid) -> Result<(), failure::Error> {
+    ctx().watch(uuid)
+}
+
+/// Stops watching the modification of resource file.
+#[inline]
+pub fn unwatch(uuid: Uuid) {
+    ctx().unwatch(uuid)
+}
+
+/// The revision increases every time a modification of watched files is detected.
+#[inline]
+pub fn revision() -> usize {
+    ctx().revision()
+}
+
+/// The revision when the file of resource is modified for the last time.
+#[inline]
+pub fn revision_of(uuid: Uuid) -> usize {
+    ctx().revision_of(uuid)
+}
+
 /// Loads file asynchronously with response callback.
 #[inline]
 pub fn load_with_callback<T>(uuid: Uuid, func: T) -> Result<(), failure::Error>
@@ -1,5 +1,5 @@
 use std::io::Read;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use uuid::Uuid;
 
@@ -10,6 +10,7 @@ use super::request::{Request, RequestQueue, Response};
 use super::shortcut::ShortcutResolver;
 use super::url::Url;
 use super::vfs::SchemaResolver;
+use super::watcher::Watcher;
 use super::ResourceParams;
 
 pub struct ResourceSystem {
@@ -17,16 +18,19 @@ pub struct ResourceSystem {
     schemas: SchemaResolver,
     manifest: RwLock<ManfiestResolver>,
     requests: Arc<RequestQueue>,
+    watcher: Arc<Mutex<Watcher>>,
     lifecycle: LifecycleListenerHandle,
 }
 
 struct Lifecycle {
     requests: Arc<RequestQueue>,
+    watcher: Arc<Mutex<Watcher>>,
 }
 
 impl LifecycleListener for Lifecycle {
     fn on_post_update(&mut self) -> Result<(), failure::Error> {
         self.requests.advance();
+        self.watcher.lock().unwrap().advance();
         Ok(())
     }
 }
@@ -42,12 +46,19 @@ impl ResourceSystem {
         debug_assert!(crate::application::valid(), "");
 
         let requests = Arc::new(RequestQueue::new());
+        let watcher = Arc::new(Mutex::new(Watcher::new()));
+        let lifecycle = Lifecycle {
+            requests: requests.clone(),
+            watcher: watcher.clone(),
+        };
+
         let sys = ResourceSystem {
             shortcut: params.shortcuts,
             schemas: params.schemas,
             manifest: RwLock::new(ManfiestResolver::new()),
-            requests: requests.clone(),
-            lifecycle: crate::application::attach(Lifecycle { requests }),
+            requests,
+            watcher,
+            lifecycle: crate::application::attach(lifecycle),
         };
 
         Ok(sys)
@@ -88,6 +99,38 @@ impl ResourceSystem {
         self.manifest.read().unwrap().contains(uuid)
     }
 
+    /// Watches the modification of resource file. It takes effect only if the `VFS` of
+    /// resource supports watching.
+    pub fn watch(&self, uuid: Uuid) -> Result<(), failure::Error> {
+        let url =
+            self.manifest.read().unwrap().resolve(uuid).ok_or_else(|| {
+                format_err!("Could not found resource {} in this registry.", uuid)
+            })?;
+
+        let url = Url::new(url)?;
+        let vfs = self.schemas.locate(url.schema())?;
+        self.watcher.lock().unwrap().watch(uuid, vfs, url);
+        Ok(())
+    }
+
+    /// Stops watching the modification of resource file.
+    #[inline]
+    pub fn unwatch(&self, uuid: Uuid) {
+        self.watcher.lock().unwrap().unwatch(uuid);
+    }
+
+    /// The revision increases every time a modification of watched files is detected.
+    #[inline]
+    pub fn revision(&self) -> usize {
+        self.watcher.lock().unwrap().revision()
+    }
+
+    /// The revision when the file of resource is modified for the last time.
+    #[inline]
+    pub fn revision_of(&self, uuid: Uuid) -> usize {
+        self.watcher.lock().unwrap().revision_of(uuid)
+    }
+
     /// Loads file asynchronously with response callback.
     #[inline]
     pub fn load_with_callback<T>(&self, uuid: Uuid, func: T) -> Result<(), failure::Error>
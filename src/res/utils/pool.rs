@@ -24,6 +24,12 @@
 //! the resource by 1. And when you are done with the resource, its the user's responsibility to
 //! drop the ownership of the resource. And when the last ownership to a given resource is dropped,
 //! the corresponding resource is also destroyed.
+//!
+//! ## Hot Reloading
+//!
+//! Resources created from files are watched through `crate::res::watch`. Once the file is
+//! modified, it will be loaded again and passed to `ResourceLoader::reload`, which swaps the
+//! resource behind the same handle. Failed reloads are logged, and the old resource is kept.
 
 use failure::Error;
 use std::sync::{Arc, Mutex};
@@ -41,6 +47,17 @@ pub trait ResourceLoader: Send + Sync {
     fn load(&self, _: Self::Handle, _: &[u8]) -> Result<Self::Intermediate, Error>;
     fn create(&self, _: Self::Handle, _: Self::Intermediate) -> Result<Self::Resource, Error>;
     fn delete(&self, _: Self::Handle, _: Self::Resource);
+
+    /// Replaces the `prev` resource behind handle with the reloaded one. The `prev` should be
+    /// kept untouched if this fails. By default, it's just created once again.
+    fn reload(
+        &self,
+        handle: Self::Handle,
+        _prev: &Self::Resource,
+        item: Self::Intermediate,
+    ) -> Result<Self::Resource, Error> {
+        self.create(handle, item)
+    }
 }
 
 // The `ResourcePool` is a standardized resources manager that defines a set of interface for creation,
@@ -51,8 +68,10 @@ where
     Loader: ResourceLoader<Handle = H> + Clone + 'static,
 {
     items: ObjectPool<H, Item<Loader::Resource>>,
-    requests: FastHashMap<H, Arc<Mutex<ResourceAsyncState<Loader::Intermediate>>>>,
+    requests: FastHashMap<H, AsyncState<Loader::Intermediate>>,
+    reloads: FastHashMap<H, AsyncState<Loader::Intermediate>>,
     registry: FastHashMap<Uuid, H>,
+    revision: usize,
     loader: Loader,
 }
 
@@ -67,11 +86,19 @@ where
             items: ObjectPool::new(),
             registry: FastHashMap::default(),
             requests: FastHashMap::default(),
+            reloads: FastHashMap::default(),
+            revision: 0,
             loader,
         }
     }
 
     pub fn advance(&mut self) -> Result<(), Error> {
+        self.advance_requests();
+        self.advance_reloads();
+        Ok(())
+    }
+
+    fn advance_requests(&mut self) {
         let items = &mut self.items;
         let loader = &self.loader;
 
@@ -107,8 +134,91 @@ where
 
             false
         });
+    }
 
-        Ok(())
+    fn advance_reloads(&mut self) {
+        self.request_reloads();
+
+        let items = &mut self.items;
+        let loader = &self.loader;
+
+        self.reloads.retain(|&handle, req| {
+            let mut req = req.lock().unwrap();
+            if let ResourceAsyncState::NotReady = *req {
+                return true;
+            }
+
+            let mut tmp = ResourceAsyncState::NotReady;
+            std::mem::swap(&mut *req, &mut tmp);
+
+            match tmp {
+                ResourceAsyncState::Err(err) => {
+                    warn!("Failed to reload {:?}. {:?}", handle, err);
+                }
+                ResourceAsyncState::Ok(intermediate) => {
+                    if let Some(item) = items.get_mut(handle) {
+                        let result = match item.resource {
+                            Some(ref prev) => loader.reload(handle, prev, intermediate),
+                            None => loader.create(handle, intermediate),
+                        };
+
+                        match result {
+                            Ok(resource) => {
+                                info!("{:?} is reloaded.", handle);
+                                item.resource = Some(resource);
+                                item.error = None;
+                            }
+                            Err(err) => warn!("Failed to reload {:?}. {:?}", handle, err),
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+
+            false
+        });
+    }
+
+    /// Requests the files that have been modified since last time.
+    fn request_reloads(&mut self) {
+        if self.registry.is_empty() {
+            return;
+        }
+
+        let revision = crate::res::revision();
+        if revision != self.revision {
+            let mut pending = false;
+            let mut modified = Vec::new();
+            for (&uuid, &handle) in &self.registry {
+                let item = self.items.get_mut(handle).unwrap();
+                let revision = crate::res::revision_of(uuid);
+
+                if item.revision != revision {
+                    // The file is being loaded, which might be read before the modification.
+                    // So it's checked again once the load is completed.
+                    if self.requests.contains_key(&handle) {
+                        pending = true;
+                        continue;
+                    }
+
+                    item.revision = revision;
+                    modified.push((handle, uuid));
+                }
+            }
+
+            if !pending {
+                self.revision = revision;
+            }
+
+            for (handle, uuid) in modified {
+                match self.request(handle, uuid) {
+                    Ok(rx) => {
+                        self.reloads.insert(handle, rx);
+                    }
+                    Err(err) => warn!("Failed to reload {}. {:?}", uuid, err),
+                }
+            }
+        }
     }
 
     /// Create a resource with provided value instance.
@@ -148,31 +258,9 @@ where
 
         let handle = self.alloc(Some(uuid));
 
-        let rx = Arc::new(Mutex::new(ResourceAsyncState::NotReady));
-        let tx = rx.clone();
-        let loader = self.loader.clone();
-
-        let result = crate::res::load_with_callback(uuid, move |rsp| match rsp {
-            Ok(bytes) => {
-                let itermediate = loader.load(handle, &bytes);
-
-                match itermediate {
-                    Ok(item) => {
-                        *tx.lock().unwrap() = ResourceAsyncState::Ok(item);
-                    }
-                    Err(err) => {
-                        *tx.lock().unwrap() = ResourceAsyncState::Err(err);
-                    }
-                }
-            }
-
-            Err(err) => {
-                *tx.lock().unwrap() = ResourceAsyncState::Err(err);
-            }
-        });
-
-        match result {
-            Ok(_) => {
+        match self.request(handle, uuid) {
+            Ok(rx) => {
+                self.items.get_mut(handle).unwrap().revision = crate::res::revision_of(uuid);
                 self.requests.insert(handle, rx);
                 Ok(handle)
             }
@@ -199,6 +287,8 @@ where
 
             if let Some(uuid) = e.uuid {
                 self.registry.remove(&uuid);
+                self.reloads.remove(&handle);
+                crate::res::unwatch(uuid);
             }
 
             if let Some(resource) = e.resource {
@@ -242,11 +332,44 @@ where
         self.items.get_mut(handle).and_then(|e| e.resource.as_mut())
     }
 
+    /// Loads the file of resource asynchronously, and watches its modification.
+    fn request(&self, handle: H, uuid: Uuid) -> Result<AsyncState<Loader::Intermediate>, Error> {
+        let rx = Arc::new(Mutex::new(ResourceAsyncState::NotReady));
+        let tx = rx.clone();
+        let loader = self.loader.clone();
+
+        crate::res::load_with_callback(uuid, move |rsp| match rsp {
+            Ok(bytes) => {
+                let itermediate = loader.load(handle, &bytes);
+
+                match itermediate {
+                    Ok(item) => {
+                        *tx.lock().unwrap() = ResourceAsyncState::Ok(item);
+                    }
+                    Err(err) => {
+                        *tx.lock().unwrap() = ResourceAsyncState::Err(err);
+                    }
+                }
+            }
+
+            Err(err) => {
+                *tx.lock().unwrap() = ResourceAsyncState::Err(err);
+            }
+        })?;
+
+        if let Err(err) = crate::res::watch(uuid) {
+            warn!("Failed to watch {}. {:?}", uuid, err);
+        }
+
+        Ok(rx)
+    }
+
     #[inline]
     fn alloc(&mut self, uuid: Option<Uuid>) -> H {
         let entry = Item {
             rc: 1,
             uuid,
+            revision: 0,
             resource: None,
             error: None,
         };
@@ -264,12 +387,62 @@ where
 struct Item<T> {
     rc: u32,
     uuid: Option<Uuid>,
+    revision: usize,
     resource: Option<T>,
     error: Option<Error>,
 }
 
+type AsyncState<T> = Arc<Mutex<ResourceAsyncState<T>>>;
+
 enum ResourceAsyncState<T> {
     Ok(T),
     Err(Error),
     NotReady,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::Handle;
+
+    #[derive(Clone)]
+    struct FallibleLoader;
+
+    impl ResourceLoader for FallibleLoader {
+        type Handle = Handle;
+        type Intermediate = Option<u32>;
+        type Resource = u32;
+
+        fn load(&self, _: Handle, _: &[u8]) -> Result<Self::Intermediate, Error> {
+            unreachable!();
+        }
+
+        fn create(&self, _: Handle, item: Self::Intermediate) -> Result<u32, Error> {
+            item.ok_or_else(|| format_err!("Failed to create."))
+        }
+
+        fn delete(&self, _: Handle, _: u32) {}
+    }
+
+    fn reload(pool: &mut ResourcePool<Handle, FallibleLoader>, handle: Handle, v: Option<u32>) {
+        let req = Arc::new(Mutex::new(ResourceAsyncState::Ok(v)));
+        pool.reloads.insert(handle, req);
+        pool.advance_reloads();
+        assert!(pool.reloads.is_empty());
+    }
+
+    #[test]
+    fn advance_reloads() {
+        let mut pool = ResourcePool::new(FallibleLoader);
+        let handle = pool.create(Some(1)).unwrap();
+
+        // The previous resource survives the failed reloading.
+        reload(&mut pool, handle, None);
+        assert_eq!(pool.state(handle), ResourceState::Ok);
+        assert_eq!(pool.resource(handle), Some(&1));
+
+        reload(&mut pool, handle, Some(2));
+        assert_eq!(pool.state(handle), ResourceState::Ok);
+        assert_eq!(pool.resource(handle), Some(&2));
+    }
+}
@@ -1,6 +1,7 @@
 use std::fs;
 use std::io::Read;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use crate::sched::prelude::LockLatch;
 
@@ -9,11 +10,19 @@ use super::super::url::Url;
 use super::VFS;
 
 #[derive(Debug, Default, Clone, Copy)]
-pub struct Dir {}
+pub struct Dir {
+    watching: bool,
+}
 
 impl Dir {
     pub fn new() -> Self {
-        Dir {}
+        Dir { watching: false }
+    }
+
+    /// Creates a `Dir` that watches the modification of loaded files, so resources could
+    /// be reloaded once their files changed on disk.
+    pub fn watching() -> Self {
+        Dir { watching: true }
     }
 
     fn load_from(self, location: &str) -> Response {
@@ -29,4 +38,12 @@ impl VFS for Dir {
         let response = self.load_from(url.path());
         state.set(response);
     }
+
+    fn modified(&self, url: &Url) -> Option<SystemTime> {
+        if !self.watching {
+            return None;
+        }
+
+        fs::metadata(url.path()).and_then(|v| v.modified()).ok()
+    }
 }
@@ -4,6 +4,7 @@ pub mod dir;
 pub mod http;
 
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use crate::sched::prelude::LockLatch;
 use crate::utils::hash::FastHashMap;
@@ -13,6 +14,12 @@ use super::url::Url;
 
 pub trait VFS: std::fmt::Debug + Send + Sync + 'static {
     fn request(&self, url: &Url, state: Arc<LockLatch<Response>>);
+
+    /// Returns the last modification time of file at `url`, which is used to detect
+    /// changes of loaded files. `None` should be returned if watching is not supported.
+    fn modified(&self, _: &Url) -> Option<SystemTime> {
+        None
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -0,0 +1,161 @@
+//! Polls the modification time of loaded files, which makes hot reloading possible.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use uuid::Uuid;
+
+use crate::utils::hash::FastHashMap;
+
+use super::url::Url;
+use super::vfs::VFS;
+
+/// The minimal interval between two polls, since querying the filesystem is not free.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+struct WatchedFile {
+    vfs: Arc<VFS>,
+    url: Url,
+    modified: SystemTime,
+    revision: usize,
+}
+
+#[derive(Default)]
+pub struct Watcher {
+    files: FastHashMap<Uuid, WatchedFile>,
+    revision: usize,
+    timestamp: Option<Instant>,
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Starts watching the file of resource, it would be ignored if the `VFS` does not
+    /// support watching.
+    pub fn watch(&mut self, uuid: Uuid, vfs: Arc<VFS>, url: Url) {
+        if self.files.contains_key(&uuid) {
+            return;
+        }
+
+        if let Some(modified) = vfs.modified(&url) {
+            let file = WatchedFile {
+                vfs,
+                url,
+                modified,
+                revision: self.revision,
+            };
+
+            self.files.insert(uuid, file);
+        }
+    }
+
+    #[inline]
+    pub fn unwatch(&mut self, uuid: Uuid) {
+        self.files.remove(&uuid);
+    }
+
+    /// The revision increases every time a modification of file is detected.
+    #[inline]
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// The revision when the file of resource is modified for the last time.
+    #[inline]
+    pub fn revision_of(&self, uuid: Uuid) -> usize {
+        self.files.get(&uuid).map(|v| v.revision).unwrap_or(0)
+    }
+
+    pub fn advance(&mut self) {
+        if self.files.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        if let Some(timestamp) = self.timestamp {
+            if now - timestamp < INTERVAL {
+                return;
+            }
+        }
+
+        self.timestamp = Some(now);
+
+        let mut dirty = false;
+        for (uuid, v) in &mut self.files {
+            // The file might be missing temporarily while it's being saved.
+            if let Some(modified) = v.vfs.modified(&v.url) {
+                if modified != v.modified {
+                    info!("[Watcher] {} ({}) is modified.", v.url.path(), uuid);
+                    v.modified = modified;
+                    v.revision = self.revision + 1;
+                    dirty = true;
+                }
+            }
+        }
+
+        if dirty {
+            self.revision += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::res::request::Response;
+    use crate::sched::prelude::LockLatch;
+
+    #[derive(Debug)]
+    struct Stub {
+        modified: Mutex<Option<SystemTime>>,
+    }
+
+    impl VFS for Stub {
+        fn request(&self, _: &Url, _: Arc<LockLatch<Response>>) {}
+
+        fn modified(&self, _: &Url) -> Option<SystemTime> {
+            *self.modified.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn watch() {
+        let stub = Arc::new(Stub {
+            modified: Mutex::new(Some(SystemTime::UNIX_EPOCH)),
+        });
+
+        let url = Url::new("file://localhost/a.png").unwrap();
+        let a = Uuid::parse_str("936DA01F9ABD4d9d80C702AF85C822A8").unwrap();
+        let b = Uuid::parse_str("936DA01F9ABD4d9d80C702AF85C822A9").unwrap();
+
+        let mut watcher = Watcher::new();
+        watcher.watch(a, stub.clone(), url.clone());
+        watcher.advance();
+        assert_eq!(watcher.revision(), 0);
+
+        *stub.modified.lock().unwrap() = Some(SystemTime::now());
+        watcher.advance();
+        assert_eq!(watcher.revision(), 0);
+
+        watcher.timestamp = None;
+        watcher.advance();
+        assert_eq!(watcher.revision(), 1);
+        assert_eq!(watcher.revision_of(a), 1);
+
+        // Missing files are skipped.
+        *stub.modified.lock().unwrap() = None;
+        watcher.timestamp = None;
+        watcher.advance();
+        assert_eq!(watcher.revision(), 1);
+
+        watcher.watch(b, stub.clone(), url);
+        assert_eq!(watcher.revision_of(b), 0);
+
+        watcher.unwatch(a);
+        assert_eq!(watcher.revision_of(a), 0);
+    }
+}
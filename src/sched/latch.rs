@@ -70,6 +70,7 @@ impl LatchProbe for SpinLatch {
 
 /// A Latch starts as false and eventually becomes true. You can block until
 /// it becomes true.
+#[derive(Debug)]
 pub struct LockLatch<T> {
     m: Mutex<Option<T>>,
     v: Condvar,
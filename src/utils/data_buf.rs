@@ -30,6 +30,7 @@ impl DataBuffer {
         let data =
             unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
 
+        self.align(mem::align_of::<T>());
         self.0.extend_from_slice(data);
 
         DataBufferPtr {
@@ -46,6 +47,7 @@ impl DataBuffer {
     {
         let len = mem::size_of::<T>().wrapping_mul(slice.len());
         let u8_slice = unsafe { slice::from_raw_parts(slice.as_ptr() as *const u8, len) };
+        self.align(mem::align_of::<T>());
         self.0.extend_from_slice(u8_slice);
         DataBufferPtr {
             position: (self.0.len() - len) as u32,
@@ -54,6 +56,29 @@ impl DataBuffer {
         }
     }
 
+    /// Appends `len` default elements to the buffer, which could be filled later with
+    /// `as_mut_slice`.
+    pub fn alloc_slice<T>(&mut self, len: usize) -> DataBufferPtr<[T]>
+    where
+        T: Copy + Default,
+    {
+        let size = mem::size_of::<T>().wrapping_mul(len);
+        self.align(mem::align_of::<T>());
+        self.0.resize(self.0.len() + size, 0);
+
+        let ptr = DataBufferPtr {
+            position: (self.0.len() - size) as u32,
+            size: size as u32,
+            _phantom: PhantomData,
+        };
+
+        for v in self.as_mut_slice(ptr) {
+            *v = T::default();
+        }
+
+        ptr
+    }
+
     /// Clones and append all bytes in a string slice to the buffer.
     pub fn extend_from_str<T>(&mut self, value: T) -> DataBufferPtr<str>
     where
@@ -67,6 +92,15 @@ impl DataBuffer {
         }
     }
 
+    /// Pads the buffer, so the next object could be read in place without unaligned access.
+    fn align(&mut self, align: usize) {
+        let rem = self.0.len() % align;
+        if rem != 0 {
+            let len = self.0.len() + align - rem;
+            self.0.resize(len, 0);
+        }
+    }
+
     /// Returns reference to object indicated by `DataBufferPtr`.
     #[inline]
     pub fn as_ref<T>(&self, ptr: DataBufferPtr<T>) -> &T
@@ -90,6 +124,18 @@ impl DataBuffer {
         unsafe { slice::from_raw_parts(slice.as_ptr() as *const T, len) }
     }
 
+    /// Returns a mutable object slice indicated by `DataBufferPtr`.
+    #[inline]
+    pub fn as_mut_slice<T>(&mut self, ptr: DataBufferPtr<[T]>) -> &mut [T]
+    where
+        T: Copy,
+    {
+        let slice = &mut self.0[ptr.position as usize..(ptr.position + ptr.size) as usize];
+        let len = slice.len() / mem::size_of::<T>();
+        assert_eq!(slice.len(), mem::size_of::<T>().wrapping_mul(len));
+        unsafe { slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut T, len) }
+    }
+
     /// Returns string slice indicated by `DataBufferPtr`.
     #[inline]
     pub fn as_str(&self, ptr: DataBufferPtr<str>) -> &str {
@@ -128,6 +174,18 @@ impl<T: ?Sized> Clone for DataBufferPtr<T> {
 
 impl<T: ?Sized> Copy for DataBufferPtr<T> {}
 
+impl<T: Copy> DataBufferPtr<[T]> {
+    /// Reinterprets the slice as bytes.
+    #[inline]
+    pub fn into_bytes(self) -> DataBufferPtr<[u8]> {
+        DataBufferPtr {
+            position: self.position,
+            size: self.size,
+            _phantom: PhantomData,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -159,6 +217,10 @@ mod test {
         let slice_arr_1_2 = buffer.extend_from_slice(&arr[0..2]);
         assert_eq!(buffer.as_slice(slice_arr_1_2), &arr[0..2]);
 
+        let slice_alloc = buffer.alloc_slice::<u32>(3);
+        buffer.as_mut_slice(slice_alloc)[1] = 7;
+        assert_eq!(buffer.as_slice(slice_alloc), &[0, 7, 0]);
+
         let text = "string serialization";
         let slice_text = buffer.extend_from_str(text);
         assert_eq!(text, buffer.as_str(slice_text));
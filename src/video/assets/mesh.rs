@@ -1,9 +1,14 @@
 //! Immutable or dynamic vertex and index data.
 
-use crate::math::prelude::Aabb3;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::math::prelude::{Aabb3, InnerSpace, Vector2, Vector3, Zero};
+use crate::utils::hash::FastHashMap;
 use crate::video::assets::shader::Attribute;
 use crate::video::errors::{Error, Result};
 use crate::video::MAX_VERTEX_ATTRIBUTES;
+use cgmath::Point3;
 use smallvec::SmallVec;
 
 impl_handle!(MeshHandle);
@@ -27,6 +32,21 @@ pub struct MeshParams {
     pub sub_mesh_offsets: SmallVec<[usize; 8]>,
     /// Trivial bounding box of vertices.
     pub aabb: Aabb3<f32>,
+    /// The levels of detail, in the order of decreasing screen sizes. It's empty if the
+    /// mesh has only one level.
+    pub lods: SmallVec<[MeshLod; 4]>,
+}
+
+/// A level of detail of mesh, whose indices are stored as sub-meshes. Each level has
+/// the same number of sub-meshes, which are the simplified versions of the sub-meshes in
+/// the first level.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MeshLod {
+    /// The first sub-mesh of this level.
+    pub sub_mesh: usize,
+    /// The minimum screen size that this level is used, which is the projected diameter
+    /// of bounding sphere divided by the height of screen.
+    pub screen_size: f32,
 }
 
 /// Continuous data of vertices and its indices.
@@ -49,6 +69,7 @@ impl Default for MeshParams {
             num_idxes: 0,
             aabb: Aabb3::zero(),
             sub_mesh_offsets: SmallVec::new(),
+            lods: SmallVec::new(),
         }
     }
 }
@@ -71,9 +92,70 @@ impl MeshParams {
             }
         }
 
+        for v in self.sub_mesh_offsets.windows(2) {
+            if v[0] > v[1] {
+                return Err(Error::MeshInvalid("Sub-meshes are not in order.".into()));
+            }
+        }
+
+        for v in self.lods.windows(2) {
+            if v[0].sub_mesh >= v[1].sub_mesh {
+                return Err(Error::MeshInvalid("LODs are not in order.".into()));
+            }
+        }
+
+        if let Some(v) = self.lods.last() {
+            if v.sub_mesh >= self.sub_mesh_offsets.len() {
+                return Err(Error::OutOfBounds);
+            }
+        }
+
         Ok(())
     }
 
+    /// Selects the level of detail by `screen_size`, which is the projected diameter of
+    /// bounding sphere divided by the height of screen. The last level is used if the
+    /// mesh is smaller than all the levels.
+    pub fn lod(&self, screen_size: f32) -> usize {
+        self.lods
+            .iter()
+            .position(|v| screen_size >= v.screen_size)
+            .unwrap_or_else(|| self.lods.len().max(1) - 1)
+    }
+
+    /// Gets the indices of all the sub-meshes at the level of detail.
+    pub fn lod_index(&self, level: usize) -> MeshIndex {
+        match self.lods.get(level) {
+            Some(lod) => {
+                let from = self.sub_mesh_offsets[lod.sub_mesh];
+                let to = self
+                    .lods
+                    .get(level + 1)
+                    .map_or(self.num_idxes, |v| self.sub_mesh_offsets[v.sub_mesh]);
+
+                MeshIndex::Ptr(from, to - from)
+            }
+            None => MeshIndex::All,
+        }
+    }
+
+    /// Computes the bounding box of the positions in `data`, or `None` if there is no
+    /// `Position` attribute.
+    pub fn compute_aabb(&self, data: &MeshData) -> Option<Aabb3<f32>> {
+        let element = self.layout.element(Attribute::Position)?;
+        let offset = self.layout.offset(Attribute::Position)? as usize;
+        let stride = self.layout.stride() as usize;
+
+        let mut aabb: Option<Aabb3<f32>> = None;
+        for i in 0..self.num_verts.min(data.vptr.len() / stride) {
+            let v = read_vertex_attribute(&data.vptr[i * stride + offset..], element);
+            let p = Point3::new(v[0], v[1], v[2]);
+            aabb = Some(aabb.map_or(Aabb3::new(p, p), |aabb| aabb.grow(p)));
+        }
+
+        aabb
+    }
+
     #[inline]
     pub fn vertex_buffer_len(&self) -> usize {
         self.num_verts * self.layout.stride() as usize
@@ -93,6 +175,39 @@ pub enum MeshIndex {
     All,
 }
 
+/// The setup of instanced draw call, which draws the same mesh `count` times in one go.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MeshInstances {
+    /// Number of instances to draw.
+    pub count: u32,
+    /// The optional mesh whose vertices are used as per-instance attributes. Attributes
+    /// that are found in its `VertexLayout` take precedence over the ones of drawing mesh.
+    pub mesh: Option<MeshHandle>,
+    /// Number of instances that share the same vertex of per-instance mesh.
+    pub divisor: u32,
+}
+
+impl MeshInstances {
+    /// Creates a instanced draw without per-instance attributes.
+    pub fn new(count: u32) -> Self {
+        MeshInstances {
+            count,
+            mesh: None,
+            divisor: 1,
+        }
+    }
+
+    /// Creates a instanced draw, which advances the per-instance `mesh` by one vertex
+    /// every instance.
+    pub fn with_mesh(count: u32, mesh: MeshHandle) -> Self {
+        MeshInstances {
+            count,
+            mesh: Some(mesh),
+            divisor: 1,
+        }
+    }
+}
+
 /// Hint abouts the intended update strategy of the data.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum MeshHint {
@@ -177,6 +292,148 @@ pub enum VertexFormat {
     Short,
     UShort,
     Float,
+    /// IEEE 754 half-precision floats, see `pack_half`.
+    HalfFloat,
+    /// Three signed 10-bit components and a 2-bit one, which are packed into 32 bits
+    /// from the lowest bits, see `pack_int_2_10_10_10_rev`. It's useful for normals and
+    /// tangents, and always has 4 components.
+    Int2_10_10_10Rev,
+    /// The unsigned version of `Int2_10_10_10Rev`, see `pack_uint_2_10_10_10_rev`.
+    UInt2_10_10_10Rev,
+    /// Integers which are passed to shaders without being converted into floats, e.g. the
+    /// indices of bones. They should be declared as `ivec` or `uvec` in shaders, and could
+    /// not be normalized.
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+}
+
+impl VertexFormat {
+    /// Gets the size in bytes of an attribute with `size` components.
+    pub fn size_of(self, size: u8) -> u8 {
+        match self {
+            VertexFormat::Byte | VertexFormat::UByte => size,
+            VertexFormat::Int8 | VertexFormat::UInt8 => size,
+            VertexFormat::Short | VertexFormat::UShort | VertexFormat::HalfFloat => size * 2,
+            VertexFormat::Int16 | VertexFormat::UInt16 => size * 2,
+            VertexFormat::Float | VertexFormat::Int32 | VertexFormat::UInt32 => size * 4,
+            VertexFormat::Int2_10_10_10Rev | VertexFormat::UInt2_10_10_10Rev => 4,
+        }
+    }
+
+    /// Checks if all the components are packed into 32 bits.
+    pub fn packed(self) -> bool {
+        match self {
+            VertexFormat::Int2_10_10_10Rev | VertexFormat::UInt2_10_10_10Rev => true,
+            _ => false,
+        }
+    }
+
+    /// Checks if the components are passed to shaders as integers.
+    pub fn integer(self) -> bool {
+        match self {
+            VertexFormat::Int8 | VertexFormat::UInt8 => true,
+            VertexFormat::Int16 | VertexFormat::UInt16 => true,
+            VertexFormat::Int32 | VertexFormat::UInt32 => true,
+            _ => false,
+        }
+    }
+
+    /// Checks if the format requires `Capabilities::extended_vertex_formats`.
+    pub fn extended(self) -> bool {
+        self == VertexFormat::HalfFloat || self.packed() || self.integer()
+    }
+}
+
+/// Converts `v` into IEEE 754 half-precision float, which is rounded to nearest even.
+pub fn pack_half(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let round = |v: u32, shift: u32| {
+        let (v, rem, halfway) = (v >> shift, v & ((1 << shift) - 1), 1 << (shift - 1));
+        if rem > halfway || (rem == halfway && v & 1 == 1) {
+            v + 1
+        } else {
+            v
+        }
+    };
+
+    let e = exponent - 127 + 15;
+    if e >= 31 {
+        sign | 0x7C00
+    } else if e > 0 {
+        // The carry of rounding overflows into exponent, which is still correct.
+        sign | round((e as u32) << 23 | mantissa, 13) as u16
+    } else if e >= -10 {
+        sign | round(mantissa | 0x80_0000, (14 - e) as u32) as u16
+    } else {
+        sign
+    }
+}
+
+/// Converts the IEEE 754 half-precision float `v` into `f32`.
+pub fn unpack_half(v: u16) -> f32 {
+    let sign = if v & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((v >> 10) & 0x1F);
+    let mantissa = f32::from(v & 0x3FF);
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Packs `v` into `VertexFormat::Int2_10_10_10Rev`. The components are clamped into
+/// `[-1, 1]` if `normalized`, otherwise they are rounded into integers.
+pub fn pack_int_2_10_10_10_rev(v: [f32; 4], normalized: bool) -> u32 {
+    let component = |v: f32, bits: u32| {
+        let max = ((1 << (bits - 1)) - 1) as f32;
+        let v = if normalized {
+            v.clamp(-1.0, 1.0) * max
+        } else {
+            v.clamp(-max - 1.0, max)
+        };
+
+        (v.round() as i32 as u32) & ((1 << bits) - 1)
+    };
+
+    component(v[0], 10)
+        | component(v[1], 10) << 10
+        | component(v[2], 10) << 20
+        | component(v[3], 2) << 30
+}
+
+/// Packs `v` into `VertexFormat::UInt2_10_10_10Rev`. The components are clamped into
+/// `[0, 1]` if `normalized`, otherwise they are rounded into integers.
+pub fn pack_uint_2_10_10_10_rev(v: [f32; 4], normalized: bool) -> u32 {
+    let component = |v: f32, bits: u32| {
+        let max = ((1 << bits) - 1) as f32;
+        let v = if normalized {
+            v.clamp(0.0, 1.0) * max
+        } else {
+            v.clamp(0.0, max)
+        };
+
+        v.round() as u32
+    };
+
+    component(v[0], 10)
+        | component(v[1], 10) << 10
+        | component(v[2], 10) << 20
+        | component(v[3], 2) << 30
 }
 
 /// The details of a vertex attribute.
@@ -241,7 +498,7 @@ impl VertexLayout {
 
     /// Relative element offset from the layout.
     pub fn offset(&self, name: Attribute) -> Option<u8> {
-        for i in 0..self.elements.len() {
+        for i in 0..self.len as usize {
             match self.elements[i].name {
                 v if v == name => return Some(self.offset[i]),
                 _ => (),
@@ -253,7 +510,7 @@ impl VertexLayout {
 
     /// Returns named `Attribute` from the layout.
     pub fn element(&self, name: Attribute) -> Option<VertexAttribute> {
-        for i in 0..self.elements.len() {
+        for i in 0..self.len as usize {
             match self.elements[i].name {
                 v if v == name => return Some(self.elements[i]),
                 _ => (),
@@ -262,6 +519,13 @@ impl VertexLayout {
 
         None
     }
+
+    /// Checks if any element requires `Capabilities::extended_vertex_formats`.
+    pub fn extended(&self) -> bool {
+        self.elements[0..self.len as usize]
+            .iter()
+            .any(|v| v.format.extended())
+    }
 }
 
 /// Helper structure to build a vertex layout.
@@ -282,6 +546,8 @@ impl VertexLayoutBuilder {
         normalized: bool,
     ) -> Self {
         assert!(size > 0 && size <= 4);
+        assert!(!format.packed() || size == 4);
+        assert!(!format.integer() || !normalized);
 
         let desc = VertexAttribute {
             name,
@@ -310,7 +576,7 @@ impl VertexLayoutBuilder {
         self.0.stride = 0;
         for i in 0..self.0.len {
             let i = i as usize;
-            let len = self.0.elements[i].size * size_of_vertex(self.0.elements[i].format);
+            let len = self.0.elements[i].format.size_of(self.0.elements[i].size);
             self.0.offset[i] = self.0.stride;
             self.0.stride += len;
         }
@@ -318,12 +584,893 @@ impl VertexLayoutBuilder {
     }
 }
 
-fn size_of_vertex(format: VertexFormat) -> u8 {
-    match format {
-        VertexFormat::Byte | VertexFormat::UByte => 1,
-        VertexFormat::Short | VertexFormat::UShort => 2,
-        VertexFormat::Float => 4,
+/// A typed view of the vertices and triangles of mesh, which makes it possible to process
+/// meshes on CPU, e.g. computing normals and tangents, welding vertices or optimizing
+/// the order of triangles.
+///
+/// The optional attributes are empty if absent, otherwise they have the same length as
+/// `positions`. Attributes besides the ones below are dropped when viewing `MeshData`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshGeometry {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// The tangents, with the handedness of bitangents in `w`.
+    pub tangents: Vec<[f32; 4]>,
+    pub colors: Vec<[f32; 4]>,
+    pub texcoords: Vec<[f32; 2]>,
+    /// The indices of triangle list.
+    pub indices: Vec<u32>,
+    /// The start indices of sub-meshes.
+    pub sub_mesh_offsets: SmallVec<[usize; 8]>,
+    /// The levels of detail, see `MeshGeometry::generate_lods`.
+    pub lods: SmallVec<[MeshLod; 4]>,
+}
+
+impl MeshGeometry {
+    /// Reads the vertices and indices of a `MeshPrimitive::Triangles` mesh.
+    pub fn from_mesh(params: &MeshParams, data: &MeshData) -> Result<Self> {
+        if params.primitive != MeshPrimitive::Triangles {
+            return Err(Error::MeshInvalid(format!(
+                "Primitive {:?} is not triangles.",
+                params.primitive
+            )));
+        }
+
+        if data.vptr.len() < params.vertex_buffer_len()
+            || data.iptr.len() < params.index_buffer_len()
+        {
+            return Err(Error::OutOfBounds);
+        }
+
+        let layout = &params.layout;
+        if layout.element(Attribute::Position).is_none() {
+            return Err(Error::AttributeUndefined("Position".into()));
+        }
+
+        let stride = layout.stride() as usize;
+        let read = |name: Attribute, i: usize| {
+            let offset = i * stride + layout.offset(name).unwrap() as usize;
+            read_vertex_attribute(&data.vptr[offset..], layout.element(name).unwrap())
+        };
+
+        let mut geometry = MeshGeometry::default();
+        for i in 0..params.num_verts {
+            let v = read(Attribute::Position, i);
+            geometry.positions.push([v[0], v[1], v[2]]);
+
+            if layout.element(Attribute::Normal).is_some() {
+                let v = read(Attribute::Normal, i);
+                geometry.normals.push([v[0], v[1], v[2]]);
+            }
+
+            if layout.element(Attribute::Tangent).is_some() {
+                geometry.tangents.push(read(Attribute::Tangent, i));
+            }
+
+            if let Some(element) = layout.element(Attribute::Color0) {
+                let mut v = read(Attribute::Color0, i);
+                if element.size < 4 {
+                    v[3] = 1.0;
+                }
+
+                geometry.colors.push(v);
+            }
+
+            if layout.element(Attribute::Texcoord0).is_some() {
+                let v = read(Attribute::Texcoord0, i);
+                geometry.texcoords.push([v[0], v[1]]);
+            }
+        }
+
+        for v in data.iptr[..params.index_buffer_len()].chunks(params.index_format.stride()) {
+            let index = match params.index_format {
+                IndexFormat::U16 => u32::from(u16::from_ne_bytes([v[0], v[1]])),
+                IndexFormat::U32 => u32::from_ne_bytes([v[0], v[1], v[2], v[3]]),
+            };
+
+            if index as usize >= params.num_verts {
+                return Err(Error::OutOfBounds);
+            }
+
+            geometry.indices.push(index);
+        }
+
+        geometry.indices.truncate(params.num_idxes / 3 * 3);
+        geometry.sub_mesh_offsets = params.sub_mesh_offsets.clone();
+        geometry.lods = params.lods.clone();
+        Ok(geometry)
+    }
+
+    /// Creates the params and data of mesh, whose attributes are all `Float`s. The
+    /// bounding box is filled, and indices are `IndexFormat::U16` if possible.
+    pub fn into_mesh(self) -> (MeshParams, MeshData) {
+        let mut layout =
+            VertexLayout::build().with(Attribute::Position, VertexFormat::Float, 3, false);
+
+        if !self.normals.is_empty() {
+            layout = layout.with(Attribute::Normal, VertexFormat::Float, 3, false);
+        }
+
+        if !self.tangents.is_empty() {
+            layout = layout.with(Attribute::Tangent, VertexFormat::Float, 4, false);
+        }
+
+        if !self.colors.is_empty() {
+            layout = layout.with(Attribute::Color0, VertexFormat::Float, 4, false);
+        }
+
+        if !self.texcoords.is_empty() {
+            layout = layout.with(Attribute::Texcoord0, VertexFormat::Float, 2, false);
+        }
+
+        let mut verts = Vec::new();
+        for i in 0..self.positions.len() {
+            extend_floats(&mut verts, &self.positions[i]);
+
+            if let Some(v) = self.normals.get(i) {
+                extend_floats(&mut verts, v);
+            }
+
+            if let Some(v) = self.tangents.get(i) {
+                extend_floats(&mut verts, v);
+            }
+
+            if let Some(v) = self.colors.get(i) {
+                extend_floats(&mut verts, v);
+            }
+
+            if let Some(v) = self.texcoords.get(i) {
+                extend_floats(&mut verts, v);
+            }
+        }
+
+        let mut params = MeshParams::default();
+        params.layout = layout.finish();
+        params.num_verts = self.positions.len();
+        params.num_idxes = self.indices.len();
+        params.aabb = self.aabb();
+
+        let iptr = if params.num_verts <= 0x10000 {
+            let indices: Vec<u16> = self.indices.iter().map(|&v| v as u16).collect();
+            IndexFormat::encode(&indices).to_vec()
+        } else {
+            params.index_format = IndexFormat::U32;
+            IndexFormat::encode(&self.indices).to_vec()
+        };
+
+        params.sub_mesh_offsets = self.sub_mesh_offsets;
+        params.lods = self.lods;
+
+        let data = MeshData {
+            vptr: verts.into_boxed_slice(),
+            iptr: iptr.into_boxed_slice(),
+        };
+
+        (params, data)
+    }
+
+    /// Gets the bounding box of positions.
+    pub fn aabb(&self) -> Aabb3<f32> {
+        let mut iter = self.positions.iter().map(|&v| Point3::from(v));
+        match iter.next() {
+            Some(first) => iter.fold(Aabb3::new(first, first), |aabb, v| aabb.grow(v)),
+            None => Aabb3::zero(),
+        }
+    }
+
+    /// Computes the normals of vertices by averaging the normals of their adjacent
+    /// triangles, weighted by area. The vertices at the same position share normals, so
+    /// the seams of texcoords are smooth.
+    pub fn compute_smooth_normals(&mut self) {
+        let mut shared = FastHashMap::default();
+        let groups: Vec<_> = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, v)| *shared.entry(float_bits(v)).or_insert(i))
+            .collect();
+
+        let mut normals = vec![Vector3::zero(); self.positions.len()];
+        for tri in self.indices.chunks(3) {
+            // The length of cross product is twice the area of triangle.
+            let normal = self.face_normal(tri);
+            for &v in tri {
+                normals[groups[v as usize]] += normal;
+            }
+        }
+
+        self.normals = groups
+            .iter()
+            .map(|&v| normalize_or_zero(normals[v]).into())
+            .collect();
+    }
+
+    /// Splits the vertices of every triangle and computes the normals of faces, which
+    /// makes the edges between triangles hard.
+    pub fn compute_flat_normals(&mut self) {
+        let mut geometry = MeshGeometry::default();
+        let mut normals = Vec::new();
+        for tri in self.indices.chunks(3) {
+            let normal = normalize_or_zero(self.face_normal(tri)).into();
+            for &v in tri {
+                geometry.push_vertex(self, v as usize);
+                normals.push(normal);
+            }
+        }
+
+        geometry.normals = normals;
+        geometry.indices = (0..self.indices.len() as u32).collect();
+        geometry.sub_mesh_offsets = self.sub_mesh_offsets.clone();
+        geometry.lods = self.lods.clone();
+        *self = geometry;
+    }
+
+    /// Computes the tangents with texcoords and normals, in the way of MikkTSpace.
+    ///
+    /// The tangent of each triangle is weighted by the angles of its corners, and then
+    /// orthogonalized to the normal of vertex. The handedness of bitangent is stored in
+    /// `w`, so bitangent could be reconstructed with `cross(normal, tangent.xyz) * w`.
+    pub fn compute_tangents(&mut self) -> Result<()> {
+        if self.normals.is_empty() {
+            return Err(Error::AttributeUndefined("Normal".into()));
+        }
+
+        if self.texcoords.is_empty() {
+            return Err(Error::AttributeUndefined("Texcoord0".into()));
+        }
+
+        let mut tangents = vec![Vector3::zero(); self.positions.len()];
+        let mut bitangents = vec![Vector3::zero(); self.positions.len()];
+
+        for tri in self.indices.chunks(3) {
+            let p: Vec<Vector3<f32>> = tri
+                .iter()
+                .map(|&v| self.positions[v as usize].into())
+                .collect();
+            let t: Vec<Vector2<f32>> = tri
+                .iter()
+                .map(|&v| self.texcoords[v as usize].into())
+                .collect();
+
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (d1, d2) = (t[1] - t[0], t[2] - t[0]);
+
+            let r = d1.x * d2.y - d2.x * d1.y;
+            if r.abs() <= f32::EPSILON {
+                continue;
+            }
+
+            let tangent = normalize_or_zero((e1 * d2.y - e2 * d1.y) / r);
+            let bitangent = normalize_or_zero((e2 * d1.x - e1 * d2.x) / r);
+
+            for i in 0..3 {
+                let a = p[(i + 1) % 3] - p[i];
+                let b = p[(i + 2) % 3] - p[i];
+                let angle = normalize_or_zero(a)
+                    .dot(normalize_or_zero(b))
+                    .clamp(-1.0, 1.0)
+                    .acos();
+
+                tangents[tri[i] as usize] += tangent * angle;
+                bitangents[tri[i] as usize] += bitangent * angle;
+            }
+        }
+
+        self.tangents = (0..self.positions.len())
+            .map(|i| {
+                let n = Vector3::from(self.normals[i]);
+                let mut t = normalize_or_zero(tangents[i] - n * n.dot(tangents[i]));
+
+                // Picks any direction perpendicular to normal for degenerated texcoords.
+                if t == Vector3::zero() {
+                    let axis = if n.x.abs() < 0.9 {
+                        Vector3::unit_x()
+                    } else {
+                        Vector3::unit_y()
+                    };
+
+                    t = normalize_or_zero(axis - n * n.dot(axis));
+                }
+
+                let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+
+                [t.x, t.y, t.z, w]
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Merges the vertices whose attributes are all the same, after being snapped to the
+    /// grid of `epsilon`. The triangles that become degenerated are removed, and so do the
+    /// empty sub-meshes. Only the first level of detail is kept, the others should be
+    /// generated again afterwards.
+    pub fn weld(&mut self, epsilon: f32) {
+        let quantize = |v: &[f32], key: &mut Vec<i64>| {
+            for &v in v {
+                if epsilon > 0.0 {
+                    key.push((v / epsilon).round() as i64);
+                } else {
+                    key.push(i64::from(v.to_bits()));
+                }
+            }
+        };
+
+        let mut welded = FastHashMap::default();
+        let mut geometry = MeshGeometry::default();
+        let mut key = Vec::new();
+
+        let remap: Vec<u32> = (0..self.positions.len())
+            .map(|i| {
+                key.clear();
+                quantize(&self.positions[i], &mut key);
+
+                if let Some(v) = self.normals.get(i) {
+                    quantize(v, &mut key);
+                }
+
+                if let Some(v) = self.tangents.get(i) {
+                    quantize(v, &mut key);
+                }
+
+                if let Some(v) = self.colors.get(i) {
+                    quantize(v, &mut key);
+                }
+
+                if let Some(v) = self.texcoords.get(i) {
+                    quantize(v, &mut key);
+                }
+
+                let len = welded.len() as u32;
+                *welded.entry(key.clone()).or_insert_with(|| {
+                    geometry.push_vertex(self, i);
+                    len
+                })
+            })
+            .collect();
+
+        // The sub-meshes of the other levels start from the second level.
+        let mut ranges = self.sub_mesh_ranges();
+        if let Some(lod) = self.lods.get(1) {
+            ranges.truncate(lod.sub_mesh);
+        }
+
+        for range in ranges {
+            let start = geometry.indices.len();
+            for tri in self.indices[range].chunks(3) {
+                let tri = [
+                    remap[tri[0] as usize],
+                    remap[tri[1] as usize],
+                    remap[tri[2] as usize],
+                ];
+
+                if tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0] {
+                    geometry.indices.extend_from_slice(&tri);
+                }
+            }
+
+            if geometry.indices.len() > start {
+                geometry.sub_mesh_offsets.push(start);
+            }
+        }
+
+        *self = geometry;
+    }
+
+    /// Reorders the triangles of every sub-mesh to improve the hit rate of post-transform
+    /// vertex cache, with the algorithm of Tom Forsyth. The vertices are reordered by
+    /// the order of first use afterwards to improve the locality of fetching, and the
+    /// unused vertices are removed.
+    pub fn optimize_vertex_cache(&mut self) {
+        for range in self.sub_mesh_ranges() {
+            optimize_triangles(&mut self.indices[range], self.positions.len());
+        }
+
+        let mut remap = vec![None; self.positions.len()];
+        let mut geometry = MeshGeometry::default();
+        let mut indices = ::std::mem::take(&mut self.indices);
+        for v in &mut indices {
+            let index = *v as usize;
+            *v = *remap[index].get_or_insert_with(|| {
+                geometry.push_vertex(self, index);
+                geometry.positions.len() as u32 - 1
+            });
+        }
+
+        geometry.indices = indices;
+        geometry.sub_mesh_offsets = self.sub_mesh_offsets.clone();
+        geometry.lods = self.lods.clone();
+        *self = geometry;
+    }
+
+    /// Simplifies the triangles in `range` of indices with quadric error metrics, until
+    /// the number of indices is not greater than `target` or no more edges could be
+    /// collapsed. Edges are collapsed into one of their vertices, so the simplified
+    /// indices share the vertices of mesh.
+    ///
+    /// The vertices on borders and seams of attributes are never moved, which avoids
+    /// cracks between sub-meshes.
+    pub fn simplify(&self, range: Range<usize>, target: usize) -> Vec<u32> {
+        let mut indices = self.indices[range].to_vec();
+        let num_verts = self.positions.len();
+
+        // Locks the vertices on seams, which share positions with others.
+        let mut shared = FastHashMap::default();
+        for v in &self.positions {
+            *shared.entry(float_bits(v)).or_insert(0) += 1;
+        }
+
+        let mut locked: Vec<_> = self
+            .positions
+            .iter()
+            .map(|v| shared[&float_bits(v)] > 1)
+            .collect();
+
+        // Locks the vertices on borders, whose edges are not shared by two triangles.
+        let mut edges = FastHashMap::default();
+        for tri in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (tri[i], tri[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        for (&(a, b), &count) in &edges {
+            if count != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+
+        let mut quadrics = vec![Quadric::default(); num_verts];
+        for tri in indices.chunks(3) {
+            let normal = self.face_normal(tri).cast::<f64>().unwrap();
+            let len = normal.magnitude();
+            if len <= 0.0 {
+                continue;
+            }
+
+            let n = normal / len;
+            let p = Vector3::from(self.positions[tri[0] as usize])
+                .cast()
+                .unwrap();
+            let quadric = Quadric::plane(n, -n.dot(p), len * 0.5);
+            for &v in tri {
+                quadrics[v as usize].add(&quadric);
+            }
+        }
+
+        let mut remap: Vec<u32> = (0..num_verts as u32).collect();
+        let mut adjacency = vec![Vec::new(); num_verts];
+
+        // Collapses the edges with least errors in passes, every vertex is touched once
+        // in each pass at most.
+        while indices.len() > target {
+            for v in &mut adjacency {
+                v.clear();
+            }
+
+            let mut candidates = Vec::new();
+            for (t, tri) in indices.chunks(3).enumerate() {
+                for i in 0..3 {
+                    let (a, b) = (tri[i] as usize, tri[(i + 1) % 3] as usize);
+                    adjacency[a].push(t);
+
+                    for &(from, to) in &[(a, b), (b, a)] {
+                        if !locked[from] {
+                            let mut quadric = quadrics[from];
+                            quadric.add(&quadrics[to]);
+                            candidates.push((quadric.error(self.positions[to]), from, to));
+                        }
+                    }
+                }
+            }
+
+            candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+            let mut touched = vec![false; num_verts];
+            let mut len = indices.len();
+            for &(_, from, to) in &candidates {
+                if len <= target {
+                    break;
+                }
+
+                if touched[from]
+                    || touched[to]
+                    || self.flipped(&indices, &adjacency[from], from, to)
+                {
+                    continue;
+                }
+
+                remap[from] = to as u32;
+                for &t in &adjacency[from] {
+                    let tri = &indices[t * 3..t * 3 + 3];
+                    if tri.contains(&(to as u32)) {
+                        len -= 3;
+                    }
+
+                    for &v in tri {
+                        touched[v as usize] = true;
+                    }
+                }
+
+                let quadric = quadrics[from];
+                quadrics[to].add(&quadric);
+            }
+
+            if len == indices.len() {
+                break;
+            }
+
+            let mut simplified = Vec::with_capacity(len);
+            for tri in indices.chunks(3) {
+                let tri = [
+                    remap[tri[0] as usize],
+                    remap[tri[1] as usize],
+                    remap[tri[2] as usize],
+                ];
+
+                if tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0] {
+                    simplified.extend_from_slice(&tri);
+                }
+            }
+
+            indices = simplified;
+        }
+
+        indices
+    }
+
+    /// Generates the levels of detail, each of which keeps `ratio` of the triangles in
+    /// the previous level with `simplify`. The indices of levels are appended as extra
+    /// sub-meshes, and `screen_sizes` are the minimum screen sizes of levels, starting
+    /// from the original one.
+    ///
+    /// The levels which are generated before are replaced.
+    pub fn generate_lods(&mut self, screen_sizes: &[f32], ratio: f32) {
+        if let Some(lod) = self.lods.get(1) {
+            let len = self.sub_mesh_offsets[lod.sub_mesh];
+            self.indices.truncate(len);
+            self.sub_mesh_offsets.truncate(lod.sub_mesh);
+        }
+
+        self.lods.clear();
+        if screen_sizes.is_empty() || self.indices.is_empty() {
+            return;
+        }
+
+        if self.sub_mesh_offsets.first() != Some(&0) {
+            self.sub_mesh_offsets.insert(0, 0);
+        }
+
+        self.lods.push(MeshLod {
+            sub_mesh: 0,
+            screen_size: screen_sizes[0],
+        });
+
+        let mut ranges = self.sub_mesh_ranges();
+        for &screen_size in &screen_sizes[1..] {
+            let sub_mesh = self.sub_mesh_offsets.len();
+            for range in &mut ranges {
+                let target = (range.len() as f32 / 3.0 * ratio) as usize * 3;
+                let indices = self.simplify(range.clone(), target.max(3));
+
+                *range = self.indices.len()..self.indices.len() + indices.len();
+                self.sub_mesh_offsets.push(self.indices.len());
+                self.indices.extend_from_slice(&indices);
+            }
+
+            self.lods.push(MeshLod {
+                sub_mesh,
+                screen_size,
+            });
+        }
+    }
+
+    /// Checks if any triangle flips or becomes degenerated after collapsing `from` into
+    /// `to`.
+    fn flipped(&self, indices: &[u32], triangles: &[usize], from: usize, to: usize) -> bool {
+        for &t in triangles {
+            let tri = &indices[t * 3..t * 3 + 3];
+            if tri.contains(&(to as u32)) {
+                continue;
+            }
+
+            let collapsed: Vec<_> = tri
+                .iter()
+                .map(|&v| if v as usize == from { to as u32 } else { v })
+                .collect();
+
+            if self.face_normal(tri).dot(self.face_normal(&collapsed)) <= 0.0 {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn sub_mesh_ranges(&self) -> Vec<Range<usize>> {
+        let mut offsets: Vec<_> = self.sub_mesh_offsets.iter().cloned().collect();
+        if offsets.first() != Some(&0) {
+            offsets.insert(0, 0);
+        }
+
+        offsets.push(self.indices.len());
+        offsets.windows(2).map(|v| v[0]..v[1]).collect()
+    }
+
+    fn face_normal(&self, tri: &[u32]) -> Vector3<f32> {
+        let p0 = Vector3::from(self.positions[tri[0] as usize]);
+        let p1 = Vector3::from(self.positions[tri[1] as usize]);
+        let p2 = Vector3::from(self.positions[tri[2] as usize]);
+        (p1 - p0).cross(p2 - p0)
+    }
+
+    fn push_vertex(&mut self, src: &MeshGeometry, i: usize) {
+        self.positions.push(src.positions[i]);
+
+        if let Some(&v) = src.normals.get(i) {
+            self.normals.push(v);
+        }
+
+        if let Some(&v) = src.tangents.get(i) {
+            self.tangents.push(v);
+        }
+
+        if let Some(&v) = src.colors.get(i) {
+            self.colors.push(v);
+        }
+
+        if let Some(&v) = src.texcoords.get(i) {
+            self.texcoords.push(v);
+        }
+    }
+}
+
+/// The quadric of planes, which measures the sum of squared distances to them.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n: Vector3<f64>, d: f64, weight: f64) -> Self {
+        Quadric([
+            n.x * n.x * weight,
+            n.x * n.y * weight,
+            n.x * n.z * weight,
+            n.x * d * weight,
+            n.y * n.y * weight,
+            n.y * n.z * weight,
+            n.y * d * weight,
+            n.z * n.z * weight,
+            n.z * d * weight,
+            d * d * weight,
+        ])
+    }
+
+    fn add(&mut self, rhs: &Quadric) {
+        for (v, rhs) in self.0.iter_mut().zip(&rhs.0) {
+            *v += rhs;
+        }
+    }
+
+    fn error(&self, p: [f32; 3]) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (f64::from(p[0]), f64::from(p[1]), f64::from(p[2]));
+
+        q[0] * x * x
+            + q[4] * y * y
+            + q[7] * z * z
+            + q[9]
+            + 2.0 * (q[1] * x * y + q[2] * x * z + q[5] * y * z)
+            + 2.0 * (q[3] * x + q[6] * y + q[8] * z)
+    }
+}
+
+fn float_bits(v: &[f32; 3]) -> [u32; 3] {
+    [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()]
+}
+
+/// Appends the bytes of `values` to the vertex data in `buf`.
+fn extend_floats(buf: &mut Vec<u8>, values: &[f32]) {
+    for v in values {
+        buf.extend_from_slice(&v.to_ne_bytes());
+    }
+}
+
+fn normalize_or_zero(v: Vector3<f32>) -> Vector3<f32> {
+    if v.magnitude2() > 0.0 {
+        v.normalize()
+    } else {
+        Vector3::zero()
+    }
+}
+
+/// Reads the components of vertex attribute at the beginning of `bytes`, the missing
+/// components are zeros.
+pub(crate) fn read_vertex_attribute(bytes: &[u8], attribute: VertexAttribute) -> [f32; 4] {
+    let mut v = [0.0; 4];
+
+    if attribute.format.packed() {
+        let packed = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        for (i, c) in v.iter_mut().enumerate() {
+            let bits: u32 = if i < 3 { 10 } else { 2 };
+            let raw = (packed >> (i * 10)) & ((1 << bits) - 1);
+
+            *c = if attribute.format == VertexFormat::Int2_10_10_10Rev {
+                // Sign-extends the component.
+                let max = ((1 << (bits - 1)) - 1) as f32;
+                let v = ((raw << (32 - bits)) as i32 >> (32 - bits)) as f32;
+                if attribute.normalized {
+                    (v / max).max(-1.0)
+                } else {
+                    v
+                }
+            } else if attribute.normalized {
+                raw as f32 / ((1 << bits) - 1) as f32
+            } else {
+                raw as f32
+            };
+        }
+
+        return v;
+    }
+
+    let size = attribute.format.size_of(1) as usize;
+    for (i, c) in v.iter_mut().take(attribute.size as usize).enumerate() {
+        let b = &bytes[i * size..];
+        *c = match (attribute.format, attribute.normalized) {
+            (VertexFormat::Byte, false) => f32::from(b[0] as i8),
+            (VertexFormat::Byte, true) => (f32::from(b[0] as i8) / 127.0).max(-1.0),
+            (VertexFormat::UByte, false) => f32::from(b[0]),
+            (VertexFormat::UByte, true) => f32::from(b[0]) / 255.0,
+            (VertexFormat::Short, normalized) => {
+                let v = f32::from(i16::from_ne_bytes([b[0], b[1]]));
+                if normalized {
+                    (v / 32767.0).max(-1.0)
+                } else {
+                    v
+                }
+            }
+            (VertexFormat::UShort, normalized) => {
+                let v = f32::from(u16::from_ne_bytes([b[0], b[1]]));
+                if normalized {
+                    v / 65535.0
+                } else {
+                    v
+                }
+            }
+            (VertexFormat::Float, _) => f32::from_ne_bytes([b[0], b[1], b[2], b[3]]),
+            (VertexFormat::HalfFloat, _) => unpack_half(u16::from_ne_bytes([b[0], b[1]])),
+            (VertexFormat::Int8, _) => f32::from(b[0] as i8),
+            (VertexFormat::UInt8, _) => f32::from(b[0]),
+            (VertexFormat::Int16, _) => f32::from(i16::from_ne_bytes([b[0], b[1]])),
+            (VertexFormat::UInt16, _) => f32::from(u16::from_ne_bytes([b[0], b[1]])),
+            (VertexFormat::Int32, _) => i32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            (VertexFormat::UInt32, _) => u32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            (VertexFormat::Int2_10_10_10Rev, _) | (VertexFormat::UInt2_10_10_10Rev, _) => {
+                unreachable!()
+            }
+        };
+    }
+
+    v
+}
+
+const VERTEX_CACHE_SIZE: usize = 32;
+
+fn vertex_score(cache_position: Option<usize>, triangles: u32) -> f32 {
+    if triangles == 0 {
+        return -1.0;
+    }
+
+    let score = match cache_position {
+        // The vertices of last triangle get a fixed score, which avoids using them
+        // in the next triangle directly.
+        Some(v) if v < 3 => 0.75,
+        Some(v) => (1.0 - (v - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+
+    // Boosts the vertices with few triangles left, so we don't leave them alone.
+    score + 2.0 / (triangles as f32).sqrt()
+}
+
+/// Reorders the triangles in `indices` with the linear-speed vertex cache optimisation
+/// of Tom Forsyth.
+fn optimize_triangles(indices: &mut [u32], num_verts: usize) {
+    let num_tris = indices.len() / 3;
+
+    // The adjacent triangles of vertices, which are compacted as triangles emitted.
+    let mut live = vec![0u32; num_verts];
+    for &v in indices.iter() {
+        live[v as usize] += 1;
+    }
+
+    let mut starts = vec![0; num_verts + 1];
+    for v in 0..num_verts {
+        starts[v + 1] = starts[v] + live[v] as usize;
     }
+
+    let mut adjacency = vec![0; num_tris * 3];
+    let mut cursor = starts.clone();
+    for (t, tri) in indices.chunks(3).enumerate() {
+        for &v in tri {
+            adjacency[cursor[v as usize]] = t;
+            cursor[v as usize] += 1;
+        }
+    }
+
+    let mut vertex_scores: Vec<_> = live.iter().map(|&v| vertex_score(None, v)).collect();
+    let mut emitted = vec![false; num_tris];
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(indices.len());
+    let mut best = None;
+    let mut next = 0;
+
+    while output.len() < num_tris * 3 {
+        // Picks the next triangle in order if none of the cached vertices has triangles
+        // left.
+        let t = match best.take() {
+            Some(t) => t,
+            None => {
+                while emitted[next] {
+                    next += 1;
+                }
+                next
+            }
+        };
+
+        emitted[t] = true;
+        let tri = [indices[t * 3], indices[t * 3 + 1], indices[t * 3 + 2]];
+        output.extend_from_slice(&tri);
+
+        for &v in &tri {
+            let v = v as usize;
+            let range = starts[v]..starts[v] + live[v] as usize;
+            let i = adjacency[range.clone()]
+                .iter()
+                .position(|&x| x == t)
+                .unwrap();
+            adjacency.swap(range.start + i, range.end - 1);
+            live[v] -= 1;
+        }
+
+        // Moves the vertices of emitted triangle to the front of cache.
+        cache.retain(|v| !tri.contains(v));
+        for &v in tri.iter().rev() {
+            cache.insert(0, v);
+        }
+
+        for v in cache.drain(VERTEX_CACHE_SIZE.min(cache.len())..) {
+            vertex_scores[v as usize] = vertex_score(None, live[v as usize]);
+        }
+
+        for (i, &v) in cache.iter().enumerate() {
+            vertex_scores[v as usize] = vertex_score(Some(i), live[v as usize]);
+        }
+
+        let mut best_score = -1.0;
+        for &v in &cache {
+            let v = v as usize;
+            for &adj in &adjacency[starts[v]..starts[v] + live[v] as usize] {
+                let score: f32 = indices[adj * 3..adj * 3 + 3]
+                    .iter()
+                    .map(|&v| vertex_scores[v as usize])
+                    .sum();
+
+                if score > best_score {
+                    best_score = score;
+                    best = Some(adj);
+                }
+            }
+        }
+    }
+
+    indices[..output.len()].copy_from_slice(&output);
 }
 
 #[cfg(test)]
@@ -368,6 +1515,313 @@ mod test {
         assert_eq!(element.normalized, true);
         assert_eq!(layout.element(Attribute::Normal), None);
     }
+
+    #[test]
+    fn formats() {
+        let layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::HalfFloat, 3, false)
+            .with(Attribute::Normal, VertexFormat::Int2_10_10_10Rev, 4, true)
+            .with(Attribute::Indices, VertexFormat::UInt8, 4, false)
+            .finish();
+
+        assert_eq!(layout.stride(), 14);
+        assert_eq!(layout.offset(Attribute::Normal), Some(6));
+        assert_eq!(layout.offset(Attribute::Indices), Some(10));
+
+        for &v in &[0.0, 1.0, -2.5, 0.333_251_95, 65504.0, 5.960_464_5e-8] {
+            assert_eq!(unpack_half(pack_half(v)), v);
+        }
+
+        assert_eq!(pack_half(1.0), 0x3C00);
+        assert_eq!(pack_half(1.0e6), 0x7C00);
+        assert_eq!(pack_half(1.0e-9), 0);
+        assert!(unpack_half(pack_half(f32::NAN)).is_nan());
+
+        let packed = pack_int_2_10_10_10_rev([1.0, -1.0, 0.5, -1.0], true);
+        assert_eq!(packed, 511 | 513 << 10 | 256 << 20 | 3 << 30);
+
+        let element = layout.element(Attribute::Normal).unwrap();
+        let v = read_vertex_attribute(&packed.to_ne_bytes(), element);
+        assert_eq!(v[0..2], [1.0, -1.0]);
+        assert_eq!(v[3], -1.0);
+
+        let packed = pack_uint_2_10_10_10_rev([1.0, 0.0, 2.0, 3.0], false);
+        assert_eq!(packed, 1 | 2 << 20 | 3 << 30);
+    }
+
+    #[test]
+    #[should_panic]
+    fn packed_size() {
+        VertexLayout::build().with(Attribute::Normal, VertexFormat::Int2_10_10_10Rev, 3, true);
+    }
+
+    fn quad() -> MeshGeometry {
+        MeshGeometry {
+            positions: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            texcoords: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            sub_mesh_offsets: [0, 3].iter().cloned().collect(),
+            ..MeshGeometry::default()
+        }
+    }
+
+    #[test]
+    fn geometry() {
+        let layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Short, 2, false)
+            .with(Attribute::Color0, VertexFormat::UByte, 4, true)
+            .finish();
+
+        let mut vptr = Vec::new();
+        for &(x, y) in &[(-1i16, 2i16), (3, -4), (5, 6)] {
+            vptr.extend_from_slice(&x.to_ne_bytes());
+            vptr.extend_from_slice(&y.to_ne_bytes());
+            vptr.extend_from_slice(&[255, 0, 51, 255]);
+        }
+
+        let mut params = MeshParams::default();
+        params.layout = layout;
+        params.num_verts = 3;
+        params.num_idxes = 3;
+
+        let data = MeshData {
+            vptr: vptr.into_boxed_slice(),
+            iptr: IndexFormat::encode(&[0u16, 1, 2]).into(),
+        };
+
+        let aabb = params.compute_aabb(&data).unwrap();
+        assert_eq!(aabb.min(), Point3::new(-1.0, -4.0, 0.0));
+        assert_eq!(aabb.max(), Point3::new(5.0, 6.0, 0.0));
+
+        let geometry = MeshGeometry::from_mesh(&params, &data).unwrap();
+        assert_eq!(geometry.positions[1], [3.0, -4.0, 0.0]);
+        assert_eq!(geometry.colors[2], [1.0, 0.0, 0.2, 1.0]);
+        assert_eq!(geometry.indices, [0, 1, 2]);
+
+        let (params, data) = geometry.clone().into_mesh();
+        assert_eq!(params.layout.stride(), 28);
+        assert_eq!(params.aabb.max(), Point3::new(5.0, 6.0, 0.0));
+        assert_eq!(MeshGeometry::from_mesh(&params, &data).unwrap(), geometry);
+
+        let mut params = params.clone();
+        params.primitive = MeshPrimitive::Lines;
+        assert!(MeshGeometry::from_mesh(&params, &data).is_err());
+    }
+
+    #[test]
+    fn normals_and_tangents() {
+        let mut geometry = quad();
+        assert!(geometry.compute_tangents().is_err());
+
+        geometry.compute_smooth_normals();
+        assert!(geometry.normals.iter().all(|&v| v == [0.0, 0.0, 1.0]));
+
+        geometry.compute_tangents().unwrap();
+        assert!(geometry.tangents.iter().all(|&v| v == [1.0, 0.0, 0.0, 1.0]));
+
+        // Mirrors the texcoords horizontally.
+        for v in &mut geometry.texcoords {
+            v[0] = 1.0 - v[0];
+        }
+
+        geometry.compute_tangents().unwrap();
+        assert!(geometry
+            .tangents
+            .iter()
+            .all(|&v| v == [-1.0, 0.0, 0.0, -1.0]));
+
+        geometry.positions[3][2] = 1.0;
+        geometry.compute_flat_normals();
+        assert_eq!(geometry.positions.len(), 6);
+        assert_eq!(geometry.indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(geometry.normals[0], [0.0, 0.0, 1.0]);
+        assert!(geometry.normals[3] != [0.0, 0.0, 1.0]);
+        assert_eq!(&geometry.sub_mesh_offsets[..], &[0, 3]);
+    }
+
+    #[test]
+    fn weld() {
+        let mut geometry = quad();
+        geometry.compute_flat_normals();
+
+        // Adds a triangle which is degenerated after welding.
+        geometry.positions.push([1.0, 1.00001, 0.0]);
+        geometry.texcoords.push([1.0, 1.0]);
+        geometry.normals.push([0.0, 0.0, 1.0]);
+        geometry.indices.extend_from_slice(&[0, 2, 6]);
+
+        geometry.weld(0.001);
+        assert_eq!(geometry.positions.len(), 4);
+        assert_eq!(geometry.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(&geometry.sub_mesh_offsets[..], &[0, 3]);
+
+        // Removes the empty sub-mesh.
+        let mut geometry = quad();
+        geometry.indices[3..6].copy_from_slice(&[2, 2, 2]);
+        geometry.weld(0.0);
+        assert_eq!(geometry.indices, [0, 1, 2]);
+        assert_eq!(&geometry.sub_mesh_offsets[..], &[0]);
+    }
+
+    #[test]
+    fn optimize_vertex_cache() {
+        // A grid of 16x16 quads, whose vertices are shuffled.
+        let mut geometry = MeshGeometry::default();
+        let shuffle = |x: u32, y: u32| (x * 7 + y * 13) % 289;
+        for i in 0..289 {
+            geometry.positions.push([i as f32, 0.0, 0.0]);
+        }
+
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = [
+                    shuffle(x, y),
+                    shuffle(x + 1, y),
+                    shuffle(x + 1, y + 1),
+                    shuffle(x, y + 1),
+                ];
+                geometry
+                    .indices
+                    .extend_from_slice(&[v[0], v[1], v[2], v[0], v[2], v[3]]);
+            }
+        }
+
+        let triangles = |geometry: &MeshGeometry| {
+            let mut tris: Vec<_> = geometry
+                .indices
+                .chunks(3)
+                .map(|v| {
+                    let p = |i: usize| geometry.positions[v[i] as usize][0] as u32;
+                    [p(0), p(1), p(2)]
+                })
+                .collect();
+            tris.sort();
+            tris
+        };
+
+        let expected = triangles(&geometry);
+        geometry.optimize_vertex_cache();
+        assert_eq!(triangles(&geometry), expected);
+
+        // The vertices are ordered by first use.
+        let mut max = 0;
+        for &v in &geometry.indices {
+            assert!(v <= max + 1);
+            max = max.max(v);
+        }
+
+        // Simulates a FIFO cache of 16 vertices.
+        let mut cache = ::std::collections::VecDeque::new();
+        let mut misses = 0;
+        for &v in &geometry.indices {
+            if !cache.contains(&v) {
+                misses += 1;
+                cache.push_back(v);
+                if cache.len() > 16 {
+                    cache.pop_front();
+                }
+            }
+        }
+
+        assert!((misses as f32) / 512.0 < 0.8);
+    }
+
+    fn grid(n: u32) -> MeshGeometry {
+        let mut geometry = MeshGeometry::default();
+        for y in 0..=n {
+            for x in 0..=n {
+                geometry.positions.push([x as f32, y as f32, 0.0]);
+            }
+        }
+
+        for y in 0..n {
+            for x in 0..n {
+                let v = y * (n + 1) + x;
+                let quad = [v, v + 1, v + n + 2, v, v + n + 2, v + n + 1];
+                geometry.indices.extend_from_slice(&quad);
+            }
+        }
+
+        geometry
+    }
+
+    #[test]
+    fn simplify() {
+        let geometry = grid(8);
+        let indices = geometry.simplify(0..geometry.indices.len(), 96);
+        assert!(indices.len() <= 96);
+
+        // The borders are kept, and triangles never flip.
+        let mut area = 0.0;
+        for tri in indices.chunks(3) {
+            let normal = geometry.face_normal(tri);
+            assert!(normal.z > 0.0);
+            area += normal.z * 0.5;
+        }
+
+        assert_eq!(area, 64.0);
+
+        // Stops if no more edges could be collapsed.
+        let indices = geometry.simplify(0..geometry.indices.len(), 0);
+        assert!(indices.len() >= 6);
+    }
+
+    #[test]
+    fn lods() {
+        let mut geometry = grid(8);
+        geometry.generate_lods(&[0.5, 0.25], 0.5);
+        geometry.generate_lods(&[0.5, 0.25, 0.1], 0.5);
+
+        assert_eq!(&geometry.sub_mesh_offsets[..2], &[0, 384]);
+        assert_eq!(geometry.sub_mesh_offsets.len(), 3);
+        assert_eq!(geometry.lods.len(), 3);
+        assert_eq!(geometry.lods[2].sub_mesh, 2);
+
+        let (params, _) = geometry.into_mesh();
+        params.validate(None).unwrap();
+        assert_eq!(params.lod(1.0), 0);
+        assert_eq!(params.lod(0.3), 1);
+        assert_eq!(params.lod(0.01), 2);
+        assert_eq!(params.lod_index(0), MeshIndex::Ptr(0, 384));
+
+        let from = params.sub_mesh_offsets[2];
+        assert_eq!(
+            params.lod_index(2),
+            MeshIndex::Ptr(from, params.num_idxes - from)
+        );
+        assert_eq!(MeshParams::default().lod(1.0), 0);
+        assert_eq!(MeshParams::default().lod_index(0), MeshIndex::All);
+
+        // Welding drops the simplified levels.
+        let mut geometry = grid(8);
+        geometry.generate_lods(&[0.5, 0.25], 0.5);
+        geometry.weld(0.0);
+        assert_eq!(&geometry.sub_mesh_offsets[..], &[0]);
+        assert_eq!(geometry.indices.len(), 384);
+        assert!(geometry.lods.is_empty());
+    }
+
+    #[test]
+    fn validate() {
+        let mut params = MeshParams::default();
+        params.num_idxes = 6;
+        params.sub_mesh_offsets.push(0);
+        params.sub_mesh_offsets.push(3);
+        params.sub_mesh_offsets.push(3);
+        params.validate(None).unwrap();
+
+        params.sub_mesh_offsets.push(6);
+        assert!(params.validate(None).is_err());
+
+        params.sub_mesh_offsets.pop();
+        params.sub_mesh_offsets.push(1);
+        assert!(params.validate(None).is_err());
+    }
 }
 
 #[macro_use]
@@ -393,6 +1847,8 @@ pub mod macros {
             offset_of_field: u8,
         ) -> &mut Self {
             assert!(size > 0 && size <= 4);
+            assert!(!format.packed() || size == 4);
+            assert!(!format.integer() || !normalized);
 
             let desc = VertexAttribute {
                 name,
@@ -441,6 +1897,15 @@ pub mod macros {
                     }
                 }
 
+                /// Creates a vertex from floats, which are packed into the formats of
+                /// fields. The integer formats are left as they are.
+                #[allow(dead_code)]
+                pub fn pack($($field: $crate::impl_vertex_input!{VertexFormat::$format, $size}, ) *) -> Self {
+                    $name {
+                        $($field: $crate::impl_vertex_pack!{VertexFormat::$format, $normalized, $field},)*
+                    }
+                }
+
                 #[allow(dead_code)]
                 pub fn layout() -> $crate::video::assets::mesh::VertexLayout {
                     let mut builder = $crate::video::assets::mesh::macros::CustomVertexLayoutBuilder::new();
@@ -535,6 +2000,144 @@ pub mod macros {
         (VertexFormat::Float,4) => {
             [f32; 4]
         };
+        (VertexFormat::HalfFloat, $size:tt) => {
+            [u16; $size]
+        };
+        (VertexFormat::Int2_10_10_10Rev,4) => {
+            u32
+        };
+        (VertexFormat::UInt2_10_10_10Rev,4) => {
+            u32
+        };
+        (VertexFormat::Int8, $size:tt) => {
+            [i8; $size]
+        };
+        (VertexFormat::UInt8, $size:tt) => {
+            [u8; $size]
+        };
+        (VertexFormat::Int16, $size:tt) => {
+            [i16; $size]
+        };
+        (VertexFormat::UInt16, $size:tt) => {
+            [u16; $size]
+        };
+        (VertexFormat::Int32, $size:tt) => {
+            [i32; $size]
+        };
+        (VertexFormat::UInt32, $size:tt) => {
+            [u32; $size]
+        };
+    }
+
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! impl_vertex_input {
+        (VertexFormat::Int8, $size:tt) => {
+            [i8; $size]
+        };
+        (VertexFormat::UInt8, $size:tt) => {
+            [u8; $size]
+        };
+        (VertexFormat::Int16, $size:tt) => {
+            [i16; $size]
+        };
+        (VertexFormat::UInt16, $size:tt) => {
+            [u16; $size]
+        };
+        (VertexFormat::Int32, $size:tt) => {
+            [i32; $size]
+        };
+        (VertexFormat::UInt32, $size:tt) => {
+            [u32; $size]
+        };
+        (VertexFormat::$format:ident, $size:tt) => {
+            [f32; $size]
+        };
+    }
+
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! impl_vertex_pack {
+        (VertexFormat::Float, $normalized:tt, $v:expr) => {
+            $v
+        };
+        (VertexFormat::HalfFloat, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::macros::pack_halfs(&$v)
+        };
+        (VertexFormat::Int2_10_10_10Rev, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::pack_int_2_10_10_10_rev($v, $normalized)
+        };
+        (VertexFormat::UInt2_10_10_10Rev, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::pack_uint_2_10_10_10_rev($v, $normalized)
+        };
+        (VertexFormat::Byte, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::macros::pack_components(&$v, $normalized)
+        };
+        (VertexFormat::UByte, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::macros::pack_components(&$v, $normalized)
+        };
+        (VertexFormat::Short, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::macros::pack_components(&$v, $normalized)
+        };
+        (VertexFormat::UShort, $normalized:tt, $v:expr) => {
+            $crate::video::assets::mesh::macros::pack_components(&$v, $normalized)
+        };
+        (VertexFormat::$format:ident, $normalized:tt, $v:expr) => {
+            $v
+        };
+    }
+
+    /// The components of vertex, which could be packed from floats.
+    #[doc(hidden)]
+    pub trait VertexComponent: Copy + Default {
+        fn pack(v: f32, normalized: bool) -> Self;
+    }
+
+    macro_rules! impl_vertex_component {
+        ($ty:ty, $min:expr) => {
+            impl VertexComponent for $ty {
+                fn pack(v: f32, normalized: bool) -> Self {
+                    let max = f32::from(<$ty>::max_value());
+                    if normalized {
+                        (v.clamp($min, 1.0) * max).round() as $ty
+                    } else {
+                        v.round() as $ty
+                    }
+                }
+            }
+        };
+    }
+
+    impl_vertex_component!(i8, -1.0);
+    impl_vertex_component!(u8, 0.0);
+    impl_vertex_component!(i16, -1.0);
+    impl_vertex_component!(u16, 0.0);
+
+    #[doc(hidden)]
+    pub fn pack_components<T, C>(v: &[f32], normalized: bool) -> T
+    where
+        T: Default + AsMut<[C]>,
+        C: VertexComponent,
+    {
+        let mut components = T::default();
+        for (dst, &src) in components.as_mut().iter_mut().zip(v) {
+            *dst = C::pack(src, normalized);
+        }
+
+        components
+    }
+
+    #[doc(hidden)]
+    pub fn pack_halfs<T>(v: &[f32]) -> T
+    where
+        T: Default + AsMut<[u16]>,
+    {
+        let mut components = T::default();
+        for (dst, &src) in components.as_mut().iter_mut().zip(v) {
+            *dst = pack_half(src);
+        }
+
+        components
     }
 
     #[cfg(test)]
@@ -556,6 +2159,15 @@ pub mod macros {
             }
         }
 
+        impl_vertex! {
+            Vertex3 {
+                position => [Position; HalfFloat; 3; false],
+                normal => [Normal; Int2_10_10_10Rev; 4; true],
+                indices => [Indices; UInt8; 4; false],
+                weight => [Weight; UShort; 2; true],
+            }
+        }
+
         fn as_bytes<T>(values: &[T]) -> &[u8]
         where
             T: Copy,
@@ -599,6 +2211,31 @@ pub mod macros {
             let _v = Vertex2::new([1.0, 1.0], [0, 0, 0, 0], [0, 0]);
             let _b = Vertex2::encode(&[]);
             assert_eq!(layout.stride() as usize, ::std::mem::size_of::<Vertex2>());
+
+            let layout = Vertex3::layout();
+            assert_eq!(layout.stride() as usize, ::std::mem::size_of::<Vertex3>());
+            assert_eq!(layout.offset(Attribute::Normal), Some(8));
+        }
+
+        #[test]
+        fn pack() {
+            let v = Vertex3::pack(
+                [1.0, 0.5, -2.0],
+                [0.0, 0.0, 1.0, 1.0],
+                [1, 2, 3, 4],
+                [1.0, 0.5],
+            );
+            assert_eq!(v.position, [0x3C00, 0x3800, 0xC000]);
+            assert_eq!(
+                v.normal,
+                pack_int_2_10_10_10_rev([0.0, 0.0, 1.0, 1.0], true)
+            );
+            assert_eq!(v.indices, [1, 2, 3, 4]);
+            assert_eq!(v.weight, [65535, 32768]);
+
+            let v = Vertex2::pack([1.0, 2.0], [1.0, 0.0, 0.5, 2.0], [-1.4, 3.6]);
+            assert_eq!(v.color, [255, 0, 128, 255]);
+            assert_eq!(v.texcoord, [-1, 4]);
         }
     }
 }
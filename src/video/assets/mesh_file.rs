@@ -0,0 +1,520 @@
+//! Parsers of the common mesh files, which could be exported by most of the modeling
+//! tools directly.
+//!
+//! - Wavefront OBJ, the groups (`g`) and objects (`o`) are mapped to sub-meshes.
+//! - PLY, in ascii or binary of both endians.
+//!
+//! Polygons are triangulated as fans. The generated layout always has `Position`, and
+//! `Normal` or `Texcoord0` if the file provides them, all of them are `Float`s. Indices
+//! are `IndexFormat::U16` if possible.
+
+use std::io::Cursor;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+
+use crate::errors::*;
+use crate::utils::hash::FastHashMap;
+
+use super::mesh::*;
+
+/// The mesh files which could be parsed at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFile {
+    Obj,
+    Ply,
+}
+
+impl MeshFile {
+    /// Recognises the file by the header of `bytes`. OBJ has no magic number, so it's
+    /// recognised by its first statement.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n") {
+            return Some(MeshFile::Ply);
+        }
+
+        let text = ::std::str::from_utf8(bytes).ok()?;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            return match line.split_whitespace().next() {
+                Some("v") | Some("vt") | Some("vn") | Some("o") | Some("g") | Some("s")
+                | Some("mtllib") | Some("usemtl") => Some(MeshFile::Obj),
+                _ => None,
+            };
+        }
+
+        None
+    }
+
+    /// Parses the params and data of mesh.
+    pub fn parse(self, bytes: &[u8]) -> Result<(MeshParams, MeshData)> {
+        let geometry = match self {
+            MeshFile::Obj => parse_obj(bytes)?,
+            MeshFile::Ply => parse_ply(bytes)?,
+        };
+
+        if geometry.indices.is_empty() {
+            bail!("Mesh has no faces.");
+        }
+
+        Ok(geometry.into_mesh())
+    }
+}
+
+fn parse_obj(bytes: &[u8]) -> Result<MeshGeometry> {
+    let text = ::std::str::from_utf8(bytes)?;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+
+    // The combinations of position, texcoord and normal indices to vertex.
+    let mut verts = FastHashMap::default();
+    let mut geometry = MeshGeometry::default();
+    let (mut has_normals, mut has_texcoords) = (false, false);
+    let mut polygon = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => positions.push(obj_floats::<[f32; 3]>(tokens, 3, n)?),
+            Some("vn") => normals.push(obj_floats::<[f32; 3]>(tokens, 3, n)?),
+            Some("vt") => texcoords.push(obj_floats::<[f32; 2]>(tokens, 1, n)?),
+            Some("g") | Some("o") => {
+                let len = geometry.indices.len();
+                if geometry
+                    .sub_mesh_offsets
+                    .last()
+                    .map_or(len > 0, |&v| v < len)
+                {
+                    geometry.sub_mesh_offsets.push(len);
+                }
+            }
+            Some("f") => {
+                polygon.clear();
+                for token in tokens {
+                    let mut refs = token.split('/');
+                    let v = match obj_index(refs.next(), positions.len(), n)? {
+                        Some(v) => v,
+                        None => bail!("[OBJ] Missing position at line {}.", n + 1),
+                    };
+                    let t = obj_index(refs.next(), texcoords.len(), n)?;
+                    let vn = obj_index(refs.next(), normals.len(), n)?;
+
+                    has_texcoords |= t.is_some();
+                    has_normals |= vn.is_some();
+
+                    let len = verts.len();
+                    let index = *verts.entry((v, t, vn)).or_insert_with(|| {
+                        geometry.positions.push(positions[v]);
+                        geometry
+                            .texcoords
+                            .push(t.map_or([0.0; 2], |t| texcoords[t]));
+                        geometry.normals.push(vn.map_or([0.0; 3], |vn| normals[vn]));
+                        len as u32
+                    });
+
+                    polygon.push(index);
+                }
+
+                if polygon.len() < 3 {
+                    bail!("[OBJ] Face at line {} has less than 3 vertices.", n + 1);
+                }
+
+                if geometry.sub_mesh_offsets.is_empty() {
+                    geometry.sub_mesh_offsets.push(geometry.indices.len());
+                }
+
+                for i in 1..polygon.len() - 1 {
+                    let tri = [polygon[0], polygon[i], polygon[i + 1]];
+                    geometry.indices.extend_from_slice(&tri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Removes the empty group at the end.
+    if geometry.sub_mesh_offsets.last() == Some(&geometry.indices.len()) {
+        geometry.sub_mesh_offsets.pop();
+    }
+
+    if !has_normals {
+        geometry.normals.clear();
+    }
+
+    if !has_texcoords {
+        geometry.texcoords.clear();
+    }
+
+    Ok(geometry)
+}
+
+fn obj_floats<'a, T>(tokens: impl Iterator<Item = &'a str>, min: usize, n: usize) -> Result<T>
+where
+    T: Default + AsMut<[f32]>,
+{
+    let mut v = T::default();
+    let mut len = 0;
+
+    for (dst, token) in v.as_mut().iter_mut().zip(tokens) {
+        *dst = token
+            .parse()
+            .map_err(|_| format_err!("[OBJ] Invalid number {} at line {}.", token, n + 1))?;
+        len += 1;
+    }
+
+    if len < min {
+        bail!("[OBJ] Missing components at line {}.", n + 1);
+    }
+
+    Ok(v)
+}
+
+/// Resolves the 1-based or negative relative index.
+fn obj_index(token: Option<&str>, len: usize, n: usize) -> Result<Option<usize>> {
+    let token = match token {
+        Some(v) if !v.is_empty() => v,
+        _ => return Ok(None),
+    };
+
+    let index: i64 = token
+        .parse()
+        .map_err(|_| format_err!("[OBJ] Invalid index {} at line {}.", token, n + 1))?;
+
+    let index = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+
+    if index < 0 || index >= len as i64 {
+        bail!("[OBJ] Index {} is out of bounds at line {}.", token, n + 1);
+    }
+
+    Ok(Some(index as usize))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "char" | "int8" => Ok(PlyScalar::I8),
+            "uchar" | "uint8" => Ok(PlyScalar::U8),
+            "short" | "int16" => Ok(PlyScalar::I16),
+            "ushort" | "uint16" => Ok(PlyScalar::U16),
+            "int" | "int32" => Ok(PlyScalar::I32),
+            "uint" | "uint32" => Ok(PlyScalar::U32),
+            "float" | "float32" => Ok(PlyScalar::F32),
+            "double" | "float64" => Ok(PlyScalar::F64),
+            _ => bail!("[PLY] Property type {} is not supported.", name),
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    scalar: PlyScalar,
+    /// The scalar of count if this property is a list.
+    list: Option<PlyScalar>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+enum PlyBody<'a> {
+    Ascii(::std::str::SplitAsciiWhitespace<'a>),
+    LittleEndian(Cursor<&'a [u8]>),
+    BigEndian(Cursor<&'a [u8]>),
+}
+
+impl<'a> PlyBody<'a> {
+    fn read(&mut self, scalar: PlyScalar) -> Result<f64> {
+        macro_rules! read {
+            ($file:expr, $order:ty) => {
+                match scalar {
+                    PlyScalar::I8 => f64::from($file.read_i8()?),
+                    PlyScalar::U8 => f64::from($file.read_u8()?),
+                    PlyScalar::I16 => f64::from($file.read_i16::<$order>()?),
+                    PlyScalar::U16 => f64::from($file.read_u16::<$order>()?),
+                    PlyScalar::I32 => f64::from($file.read_i32::<$order>()?),
+                    PlyScalar::U32 => f64::from($file.read_u32::<$order>()?),
+                    PlyScalar::F32 => f64::from($file.read_f32::<$order>()?),
+                    PlyScalar::F64 => $file.read_f64::<$order>()?,
+                }
+            };
+        }
+
+        let v = match *self {
+            PlyBody::Ascii(ref mut tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| format_err!("[PLY] Unexpected end of file."))?;
+                token
+                    .parse()
+                    .map_err(|_| format_err!("[PLY] Invalid number {}.", token))?
+            }
+            PlyBody::LittleEndian(ref mut file) => read!(file, LittleEndian),
+            PlyBody::BigEndian(ref mut file) => read!(file, BigEndian),
+        };
+
+        Ok(v)
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<MeshGeometry> {
+    let end = b"end_header";
+    let pos = bytes
+        .windows(end.len())
+        .position(|v| v == end)
+        .ok_or_else(|| format_err!("[PLY] Could not find the end of header."))?;
+
+    let body = match bytes[pos..].iter().position(|&v| v == b'\n') {
+        Some(v) => &bytes[pos + v + 1..],
+        None => &[],
+    };
+
+    let header = ::std::str::from_utf8(&bytes[..pos])?;
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    for line in header.lines().skip(1) {
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", v, _] => format = Some(*v),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, scalar, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| format_err!("[PLY] Property outside of element."))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    scalar: PlyScalar::parse(scalar)?,
+                    list: Some(PlyScalar::parse(count)?),
+                });
+            }
+            ["property", scalar, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| format_err!("[PLY] Property outside of element."))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    scalar: PlyScalar::parse(scalar)?,
+                    list: None,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let mut body = match format {
+        Some("ascii") => PlyBody::Ascii(::std::str::from_utf8(body)?.split_ascii_whitespace()),
+        Some("binary_little_endian") => PlyBody::LittleEndian(Cursor::new(body)),
+        Some("binary_big_endian") => PlyBody::BigEndian(Cursor::new(body)),
+        _ => bail!("[PLY] Format {:?} is not supported.", format),
+    };
+
+    let vertex_count = elements
+        .iter()
+        .find(|v| v.name == "vertex")
+        .map_or(0, |v| v.count);
+
+    let mut geometry = MeshGeometry::default();
+    let mut values = Vec::new();
+
+    for element in &elements {
+        // The slots of position, normal and texcoord of each property.
+        let slots: Vec<_> = element
+            .properties
+            .iter()
+            .map(|v| match v.name.as_str() {
+                "x" => Some(0),
+                "y" => Some(1),
+                "z" => Some(2),
+                "nx" => Some(3),
+                "ny" => Some(4),
+                "nz" => Some(5),
+                "u" | "s" | "texture_u" | "texture_s" => Some(6),
+                "v" | "t" | "texture_v" | "texture_t" => Some(7),
+                _ => None,
+            })
+            .collect();
+
+        let has_normals = slots.contains(&Some(3));
+        let has_texcoords = slots.contains(&Some(6));
+
+        for _ in 0..element.count {
+            let mut vertex = [0.0; 8];
+
+            for (property, slot) in element.properties.iter().zip(&slots) {
+                values.clear();
+                match property.list {
+                    Some(count) => {
+                        for _ in 0..body.read(count)? as usize {
+                            values.push(body.read(property.scalar)?);
+                        }
+                    }
+                    None => values.push(body.read(property.scalar)?),
+                }
+
+                if element.name == "vertex" {
+                    if let Some(slot) = *slot {
+                        vertex[slot] = values[0] as f32;
+                    }
+                } else if element.name == "face"
+                    && (property.name == "vertex_indices" || property.name == "vertex_index")
+                {
+                    if values.len() < 3 {
+                        bail!("[PLY] Face has less than 3 vertices.");
+                    }
+
+                    for i in 1..values.len() - 1 {
+                        let tri = [values[0], values[i], values[i + 1]];
+                        for &v in &tri {
+                            if v < 0.0 || v.fract() != 0.0 || v >= vertex_count as f64 {
+                                bail!("[PLY] Vertex index {} is invalid.", v);
+                            }
+
+                            geometry.indices.push(v as u32);
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                geometry.positions.push([vertex[0], vertex[1], vertex[2]]);
+
+                if has_normals {
+                    geometry.normals.push([vertex[3], vertex[4], vertex[5]]);
+                }
+
+                if has_texcoords {
+                    geometry.texcoords.push([vertex[6], vertex[7]]);
+                }
+            }
+        }
+    }
+
+    geometry.sub_mesh_offsets.push(0);
+    Ok(geometry)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video::assets::shader::Attribute;
+    use cgmath::Point3;
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks(4)
+            .map(|v| f32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn obj() {
+        let text = "# quad and triangle\n\
+                    v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                    vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                    g empty\n\
+                    g quad\n\
+                    f 1/1 2/2 3/3 4/4\n\
+                    o triangle\n\
+                    v 0 0 2\n\
+                    f -5/-4 -4/-3 -1/-4\n\
+                    g\n";
+
+        assert_eq!(MeshFile::detect(text.as_bytes()), Some(MeshFile::Obj));
+        let (params, data) = MeshFile::Obj.parse(text.as_bytes()).unwrap();
+
+        assert_eq!(params.num_verts, 5);
+        assert_eq!(params.num_idxes, 9);
+        assert_eq!(params.index_format, IndexFormat::U16);
+        assert_eq!(&params.sub_mesh_offsets[..], &[0, 6]);
+        assert_eq!(params.layout.stride(), 20);
+        assert!(params.layout.element(Attribute::Normal).is_none());
+        assert_eq!(params.aabb.max(), Point3::new(1.0, 1.0, 2.0));
+        params.validate(Some(&data)).unwrap();
+
+        let indices: Vec<_> = data.iptr.chunks(2).map(|v| v[0]).collect();
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3, 0, 1, 4]);
+        assert_eq!(&floats(&data.vptr)[20..25], &[0.0, 0.0, 2.0, 0.0, 0.0]);
+
+        assert!(MeshFile::Obj.parse(b"v 0 0 0\nf 1 2 3\n").is_err());
+        assert!(MeshFile::Obj.parse(b"v 0 0 0\nf 1 1\n").is_err());
+    }
+
+    #[test]
+    fn ply() {
+        let ascii = "ply\nformat ascii 1.0\ncomment quad\n\
+                     element vertex 4\n\
+                     property float x\nproperty float y\nproperty float z\n\
+                     property float nx\nproperty float ny\nproperty float nz\n\
+                     element face 1\n\
+                     property list uchar int vertex_indices\n\
+                     end_header\n\
+                     0 0 0 0 0 1\n1 0 0 0 0 1\n1 1 0 0 0 1\n0 1 0 0 0 1\n\
+                     4 0 1 2 3\n";
+
+        assert_eq!(MeshFile::detect(ascii.as_bytes()), Some(MeshFile::Ply));
+        let (params, data) = MeshFile::Ply.parse(ascii.as_bytes()).unwrap();
+        assert_eq!(params.num_verts, 4);
+        assert_eq!(params.num_idxes, 6);
+        assert_eq!(params.layout.stride(), 24);
+        assert_eq!(&params.sub_mesh_offsets[..], &[0]);
+        assert_eq!(&floats(&data.vptr)[18..24], &[0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+
+        // Negative, fractional and out of bounds indices.
+        for face in &["4 0 1 2 -1\n", "4 0 1 2 2.5\n", "4 0 1 2 4\n"] {
+            let text = ascii.replace("4 0 1 2 3\n", face);
+            assert!(MeshFile::Ply.parse(text.as_bytes()).is_err());
+        }
+
+        let mut binary = b"ply\nformat binary_big_endian 1.0\n\
+                           element vertex 3\n\
+                           property float x\nproperty float y\nproperty float z\n\
+                           element face 1\n\
+                           property list uchar ushort vertex_indices\n\
+                           end_header\n"
+            .to_vec();
+
+        for v in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, -1.0] {
+            binary.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+
+        binary.extend_from_slice(&[3, 0, 0, 0, 1, 0, 2]);
+
+        let (params, data) = MeshFile::Ply.parse(&binary).unwrap();
+        assert_eq!(params.num_idxes, 3);
+        assert_eq!(params.layout.stride(), 12);
+        assert_eq!(params.aabb.min(), Point3::new(0.0, 0.0, -1.0));
+        assert_eq!(&data.iptr[..], IndexFormat::encode(&[0u16, 1, 2]));
+
+        binary.pop();
+        assert!(MeshFile::Ply.parse(&binary).is_err());
+    }
+}
@@ -3,14 +3,48 @@ use std::io::Cursor;
 use std::sync::Arc;
 
 use crate::errors::*;
+use crate::math::prelude::Aabb3;
 use crate::res::utils::prelude::ResourceLoader;
 use crate::utils::double_buf::DoubleBuf;
+use smallvec::SmallVec;
 
 use super::super::backends::frame::{Command, Frame};
 use super::mesh::*;
+use super::mesh_file::MeshFile;
 
-pub const MAGIC: [u8; 8] = [b'V', b'M', b'S', b'H', b' ', 0, 0, 1];
+pub const MAGIC: [u8; 8] = [b'V', b'M', b'S', b'H', b' ', 0, 0, 2];
 
+/// The parameters of mesh in version 1, which has no levels of detail.
+#[derive(Deserialize)]
+struct MeshParamsV1 {
+    hint: MeshHint,
+    layout: VertexLayout,
+    index_format: IndexFormat,
+    primitive: MeshPrimitive,
+    num_verts: usize,
+    num_idxes: usize,
+    sub_mesh_offsets: SmallVec<[usize; 8]>,
+    aabb: Aabb3<f32>,
+}
+
+impl From<MeshParamsV1> for MeshParams {
+    fn from(v: MeshParamsV1) -> Self {
+        MeshParams {
+            hint: v.hint,
+            layout: v.layout,
+            index_format: v.index_format,
+            primitive: v.primitive,
+            num_verts: v.num_verts,
+            num_idxes: v.num_idxes,
+            sub_mesh_offsets: v.sub_mesh_offsets,
+            aabb: v.aabb,
+            ..Default::default()
+        }
+    }
+}
+
+/// Loads meshes from the `VMSH` files produced by the offline tools, or the common mesh
+/// files recognised by `MeshFile::detect`.
 #[derive(Clone)]
 pub struct MeshLoader {
     frames: Arc<DoubleBuf<Frame>>,
@@ -20,6 +54,28 @@ impl MeshLoader {
     pub(crate) fn new(frames: Arc<DoubleBuf<Frame>>) -> Self {
         MeshLoader { frames }
     }
+
+    fn parse(bytes: &[u8]) -> Result<(MeshParams, MeshData)> {
+        let mut file = Cursor::new(&bytes[8..]);
+        let params = match bytes[7] {
+            1 => bincode::deserialize_from::<_, MeshParamsV1>(&mut file)?.into(),
+            2 => bincode::deserialize_from(&mut file)?,
+            v => bail!("[MeshLoader] Undefined version {}.", v),
+        };
+
+        Ok((params, bincode::deserialize_from(&mut file)?))
+    }
+
+    /// Fills the bounding box with the positions of vertices if it's left empty.
+    fn fill_aabb(item: &mut (MeshParams, Option<MeshData>)) {
+        if item.0.aabb != Aabb3::zero() {
+            return;
+        }
+
+        if let Some(aabb) = item.1.as_ref().and_then(|v| item.0.compute_aabb(v)) {
+            item.0.aabb = aabb;
+        }
+    }
 }
 
 impl ResourceLoader for MeshLoader {
@@ -28,13 +84,13 @@ impl ResourceLoader for MeshLoader {
     type Resource = MeshParams;
 
     fn load(&self, handle: Self::Handle, bytes: &[u8]) -> Result<Self::Intermediate> {
-        if bytes[0..8] != MAGIC[..] {
+        let (params, data) = if bytes.len() >= 8 && bytes[0..7] == MAGIC[0..7] {
+            Self::parse(bytes)?
+        } else if let Some(file) = MeshFile::detect(bytes) {
+            file.parse(bytes)?
+        } else {
             bail!("[MeshLoader] MAGIC number not match.");
-        }
-
-        let mut file = Cursor::new(&bytes[8..]);
-        let params: MeshParams = bincode::deserialize_from(&mut file)?;
-        let data = bincode::deserialize_from(&mut file)?;
+        };
 
         info!(
             "[MeshLoader] load {:?}. (Verts: {}, Indxes: {})",
@@ -44,9 +100,10 @@ impl ResourceLoader for MeshLoader {
         Ok((params, Some(data)))
     }
 
-    fn create(&self, handle: Self::Handle, item: Self::Intermediate) -> Result<Self::Resource> {
+    fn create(&self, handle: Self::Handle, mut item: Self::Intermediate) -> Result<Self::Resource> {
         info!("[MeshLoader] create {:?}.", handle);
         item.0.validate(item.1.as_ref())?;
+        Self::fill_aabb(&mut item);
         let cmd = Command::CreateMesh(Box::new((handle, item.0.clone(), item.1)));
         self.frames.write().cmds.push(cmd);
         Ok(item.0)
@@ -57,4 +114,18 @@ impl ResourceLoader for MeshLoader {
         let cmd = Command::DeleteMesh(handle);
         self.frames.write().cmds.push(cmd);
     }
+
+    fn reload(
+        &self,
+        handle: Self::Handle,
+        _: &Self::Resource,
+        mut item: Self::Intermediate,
+    ) -> Result<Self::Resource> {
+        info!("[MeshLoader] reload {:?}.", handle);
+        item.0.validate(item.1.as_ref())?;
+        Self::fill_aabb(&mut item);
+        let cmd = Command::ReloadMesh(Box::new((handle, item.0.clone(), item.1)));
+        self.frames.write().cmds.push(cmd);
+        Ok(item.0)
+    }
 }
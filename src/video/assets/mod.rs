@@ -1,9 +1,16 @@
 pub mod shader;
+pub mod shader_loader;
+pub mod shader_preprocessor;
 pub mod surface;
 pub mod texture;
+pub mod texture_container;
+pub mod texture_decoder;
+pub mod texture_image;
 pub mod texture_loader;
+pub mod uniform_block;
 #[macro_use]
 pub mod mesh;
+pub mod mesh_file;
 pub mod mesh_loader;
 
 pub mod prelude {
@@ -11,17 +18,32 @@ pub mod prelude {
 
     pub use super::shader::{
         Attribute, AttributeLayout, AttributeLayoutBuilder, BlendFactor, BlendValue, Comparison,
-        CullFace, Equation, FrontFaceOrder, RenderState, ShaderHandle, ShaderParams,
-        UniformVariable, UniformVariableLayout, UniformVariableLayoutBuilder, UniformVariableType,
+        CullFace, Equation, FrontFaceOrder, RenderState, ShaderHandle, ShaderParams, StencilFace,
+        StencilOp, StencilState, UniformVariable, UniformVariableLayout,
+        UniformVariableLayoutBuilder, UniformVariableType,
+    };
+
+    pub use super::shader_preprocessor::{
+        ShaderPrecision, ShaderPreprocessor, ShaderProfile, ShaderStage, ShaderVariantKey,
+        ShaderVariants,
+    };
+
+    pub use super::uniform_block::{
+        UniformBlockHandle, UniformBlockLayout, UniformBlockLayoutBuilder, UniformBlockParams,
     };
 
     pub use super::texture::{
-        RenderTextureFormat, RenderTextureHandle, RenderTextureParams, TextureData, TextureFilter,
-        TextureFormat, TextureHandle, TextureHint, TextureParams, TextureWrap,
+        RenderTextureFormat, RenderTextureHandle, RenderTextureParams, SamplerParams, TextureData,
+        TextureDimension, TextureFilter, TextureFormat, TextureHandle, TextureHint, TextureParams,
+        TextureWrap,
     };
 
+    pub use super::texture_image::{ImageFormat, ImageOptions};
+
+    pub use super::mesh_file::MeshFile;
+
     pub use super::mesh::{
-        IndexFormat, MeshData, MeshHandle, MeshHint, MeshIndex, MeshParams, MeshPrimitive,
-        VertexFormat, VertexLayout,
+        IndexFormat, MeshData, MeshGeometry, MeshHandle, MeshHint, MeshIndex, MeshInstances,
+        MeshLod, MeshParams, MeshPrimitive, VertexFormat, VertexLayout,
     };
 }
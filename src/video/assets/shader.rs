@@ -6,7 +6,8 @@ use std::str::FromStr;
 use crate::math::prelude::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
 use crate::utils::prelude::{FastHashMap, HashValue};
 use crate::video::assets::mesh::VertexLayout;
-use crate::video::assets::texture::{RenderTextureHandle, TextureHandle};
+use crate::video::assets::texture::{RenderTextureHandle, TextureDimension, TextureHandle};
+use crate::video::assets::uniform_block::UniformBlockHandle;
 use crate::video::errors::{Error, Result};
 use crate::video::{MAX_UNIFORM_VARIABLES, MAX_VERTEX_ATTRIBUTES};
 
@@ -14,7 +15,7 @@ impl_handle!(ShaderHandle);
 
 /// A `ShaderParams` encapusulate all the informations we need to configurate
 /// OpenGL before real drawing, like shaders, render states, etc.
-#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ShaderParams {
     pub attributes: AttributeLayout,
     pub uniforms: UniformVariableLayout,
@@ -106,7 +107,7 @@ impl FromStr for Attribute {
 }
 
 // AttributeLayout defines an layout of attributes into program.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct AttributeLayout {
     len: u8,
     elements: [(Attribute, u8, bool); MAX_VERTEX_ATTRIBUTES],
@@ -211,7 +212,7 @@ impl AttributeLayoutBuilder {
 }
 
 /// Specify whether front- or back-facing polygons can be culled.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum CullFace {
     Nothing,
     Front,
@@ -219,14 +220,14 @@ pub enum CullFace {
 }
 
 /// Define front- and back-facing polygons.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum FrontFaceOrder {
     Clockwise,
     CounterClockwise,
 }
 
 /// A pixel-wise comparison function.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Comparison {
     Never,
     Less,
@@ -240,7 +241,7 @@ pub enum Comparison {
 
 /// Specifies how incoming RGBA values (source) and the RGBA in framebuffer (destination)
 /// are combined.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Equation {
     /// Adds source and destination. Source and destination are multiplied
     /// by blending parameters before addition.
@@ -254,7 +255,7 @@ pub enum Equation {
 }
 
 /// Blend values.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BlendValue {
     SourceColor,
     SourceAlpha,
@@ -263,7 +264,7 @@ pub enum BlendValue {
 }
 
 /// Blend factors.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BlendFactor {
     Zero,
     One,
@@ -271,8 +272,82 @@ pub enum BlendFactor {
     OneMinusValue(BlendValue),
 }
 
+/// Specifies the action to take on the stencil buffer.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StencilOp {
+    /// Keeps the current value.
+    Keep,
+    /// Sets the stencil value to 0.
+    Zero,
+    /// Sets the stencil value to the reference value.
+    Replace,
+    /// Increments the stencil value, clamps to the maximum representable value.
+    Increment,
+    /// Increments the stencil value, wraps to zero when it would overflow.
+    IncrementWrap,
+    /// Decrements the stencil value, clamps to 0.
+    Decrement,
+    /// Decrements the stencil value, wraps to the maximum representable value when it
+    /// would underflow.
+    DecrementWrap,
+    /// Bitwise inverts the stencil value.
+    Invert,
+}
+
+/// The stencil test and operations of front- or back-facing polygons.
+///
+/// The test passes if `(reference & read_mask) comparison (stencil & read_mask)` is true.
+/// And only the bits enabled in `write_mask` are written into the stencil buffer.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StencilFace {
+    pub comparison: Comparison,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    /// The action to take when the stencil test fails.
+    pub fail: StencilOp,
+    /// The action to take when the stencil test passes, but the depth test fails.
+    pub depth_fail: StencilOp,
+    /// The action to take when both the stencil and depth tests pass.
+    pub pass: StencilOp,
+}
+
+impl Default for StencilFace {
+    fn default() -> Self {
+        StencilFace {
+            comparison: Comparison::Always,
+            reference: 0,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+}
+
+/// The stencil states of front- and back-facing polygons. Notes that the surface must
+/// have a `RenderTextureFormat::Depth24Stencil8` attachment, or the default framebuffer,
+/// to make stencil test works.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct StencilState {
+    pub front: StencilFace,
+    pub back: StencilFace,
+}
+
+impl StencilState {
+    /// Creates a stencil state that shares the same test and operations on both faces.
+    #[inline]
+    pub fn new(face: StencilFace) -> Self {
+        StencilState {
+            front: face,
+            back: face,
+        }
+    }
+}
+
 /// A struct that encapsulate all the necessary render states.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub struct RenderState {
     pub cull_face: CullFace,
     pub front_face_order: FrontFaceOrder,
@@ -281,6 +356,8 @@ pub struct RenderState {
     pub depth_write_offset: Option<(f32, f32)>,
     pub color_blend: Option<(Equation, BlendFactor, BlendFactor)>,
     pub color_write: (bool, bool, bool, bool),
+    /// The stencil test is disabled if `None`.
+    pub stencil: Option<StencilState>,
 }
 
 impl Default for RenderState {
@@ -293,15 +370,24 @@ impl Default for RenderState {
             depth_write_offset: None,
             color_blend: None,
             color_write: (true, true, true, true),
+            stencil: None,
         }
     }
 }
 
 /// Uniform variable type.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+///
+/// The `Texture`, `TextureCube`, `Texture2DArray` and `Texture3D` are samplers of
+/// texture objects with the corresponding `TextureDimension`, they are all supplied
+/// with `UniformVariable::Texture`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum UniformVariableType {
     Texture,
+    TextureCube,
+    Texture2DArray,
+    Texture3D,
     RenderTexture,
+    UniformBlock,
     I32,
     F32,
     Vector2f,
@@ -314,10 +400,14 @@ pub enum UniformVariableType {
 
 /// Uniform variable for video program object. Each matrix based `UniformVariable`
 /// is assumed to be supplied in row major order with a optional transpose.
+///
+/// The `UniformBlock` variable binds all the variables of uniform block to the block
+/// with the same name in shader.
 #[derive(Debug, Copy, Clone)]
 pub enum UniformVariable {
     Texture(TextureHandle),
     RenderTexture(RenderTextureHandle),
+    UniformBlock(UniformBlockHandle),
     I32(i32),
     F32(f32),
     Vector2f([f32; 2]),
@@ -333,6 +423,7 @@ impl UniformVariable {
         match *self {
             UniformVariable::RenderTexture(_) => UniformVariableType::RenderTexture,
             UniformVariable::Texture(_) => UniformVariableType::Texture,
+            UniformVariable::UniformBlock(_) => UniformVariableType::UniformBlock,
             UniformVariable::I32(_) => UniformVariableType::I32,
             UniformVariable::F32(_) => UniformVariableType::F32,
             UniformVariable::Vector2f(_) => UniformVariableType::Vector2f,
@@ -345,6 +436,17 @@ impl UniformVariable {
     }
 }
 
+impl From<TextureDimension> for UniformVariableType {
+    fn from(dimension: TextureDimension) -> Self {
+        match dimension {
+            TextureDimension::D2 => UniformVariableType::Texture,
+            TextureDimension::Cube => UniformVariableType::TextureCube,
+            TextureDimension::D2Array => UniformVariableType::Texture2DArray,
+            TextureDimension::D3 => UniformVariableType::Texture3D,
+        }
+    }
+}
+
 impl Into<UniformVariable> for TextureHandle {
     fn into(self) -> UniformVariable {
         UniformVariable::Texture(self)
@@ -357,6 +459,12 @@ impl Into<UniformVariable> for RenderTextureHandle {
     }
 }
 
+impl Into<UniformVariable> for UniformBlockHandle {
+    fn into(self) -> UniformVariable {
+        UniformVariable::UniformBlock(self)
+    }
+}
+
 impl Into<UniformVariable> for i32 {
     fn into(self) -> UniformVariable {
         UniformVariable::I32(self)
@@ -442,7 +550,7 @@ impl Into<UniformVariable> for [f32; 4] {
 }
 
 // UniformVariableLayout defines an layout of uniforms in program.
-#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct UniformVariableLayout {
     variables: FastHashMap<HashValue<str>, (String, UniformVariableType)>,
 }
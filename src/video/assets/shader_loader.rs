@@ -0,0 +1,223 @@
+use bincode;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::errors::*;
+use crate::res::utils::prelude::ResourceLoader;
+use crate::utils::double_buf::DoubleBuf;
+
+use super::super::backends::frame::{Command, Frame};
+use super::shader::*;
+
+pub const MAGIC: [u8; 8] = [b'V', b'S', b'H', b'D', b' ', 0, 0, 2];
+
+/// The render state in version 1, which has no stencil test.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct RenderStateV1 {
+    cull_face: CullFace,
+    front_face_order: FrontFaceOrder,
+    depth_test: Comparison,
+    depth_write: bool,
+    depth_write_offset: Option<(f32, f32)>,
+    color_blend: Option<(Equation, BlendFactor, BlendFactor)>,
+    color_write: (bool, bool, bool, bool),
+}
+
+/// The parameters of shader in version 1.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct ShaderParamsV1 {
+    attributes: AttributeLayout,
+    uniforms: UniformVariableLayout,
+    state: RenderStateV1,
+}
+
+impl From<ShaderParamsV1> for ShaderParams {
+    fn from(v: ShaderParamsV1) -> Self {
+        ShaderParams {
+            attributes: v.attributes,
+            uniforms: v.uniforms,
+            state: RenderState {
+                cull_face: v.state.cull_face,
+                front_face_order: v.state.front_face_order,
+                depth_test: v.state.depth_test,
+                depth_write: v.state.depth_write,
+                depth_write_offset: v.state.depth_write_offset,
+                color_blend: v.state.color_blend,
+                color_write: v.state.color_write,
+                stencil: None,
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaderLoader {
+    frames: Arc<DoubleBuf<Frame>>,
+}
+
+impl ShaderLoader {
+    pub(crate) fn new(frames: Arc<DoubleBuf<Frame>>) -> Self {
+        ShaderLoader { frames }
+    }
+}
+
+impl ResourceLoader for ShaderLoader {
+    type Handle = ShaderHandle;
+    type Intermediate = (ShaderParams, String, String);
+    type Resource = ShaderParams;
+
+    fn load(&self, handle: Self::Handle, bytes: &[u8]) -> Result<Self::Intermediate> {
+        if bytes.len() < 8 || bytes[0..7] != MAGIC[0..7] {
+            bail!("[ShaderLoader] MAGIC number not match.");
+        }
+
+        let mut file = Cursor::new(&bytes[8..]);
+        let params: ShaderParams = match bytes[7] {
+            1 => bincode::deserialize_from::<_, ShaderParamsV1>(&mut file)?.into(),
+            2 => bincode::deserialize_from(&mut file)?,
+            v => bail!("[ShaderLoader] Undefined version {}.", v),
+        };
+
+        let vs: String = bincode::deserialize_from(&mut file)?;
+        let fs: String = bincode::deserialize_from(&mut file)?;
+
+        info!(
+            "[ShaderLoader] load {:?}. (Attributes: {}, Uniforms: {})",
+            handle,
+            params.attributes.iter().count(),
+            params.uniforms.len()
+        );
+
+        Ok((params, vs, fs))
+    }
+
+    fn create(&self, handle: Self::Handle, item: Self::Intermediate) -> Result<Self::Resource> {
+        info!("[ShaderLoader] create {:?}.", handle);
+
+        let (params, vs, fs) = item;
+        params.validate(&vs, &fs)?;
+
+        let cmd = Command::CreateShader(Box::new((handle, params.clone(), vs, fs)));
+        self.frames.write().cmds.push(cmd);
+
+        Ok(params)
+    }
+
+    fn delete(&self, handle: Self::Handle, _: Self::Resource) {
+        info!("[ShaderLoader] delete {:?}.", handle);
+
+        let cmd = Command::DeleteShader(handle);
+        self.frames.write().cmds.push(cmd);
+    }
+
+    fn reload(
+        &self,
+        handle: Self::Handle,
+        _: &Self::Resource,
+        item: Self::Intermediate,
+    ) -> Result<Self::Resource> {
+        info!("[ShaderLoader] reload {:?}.", handle);
+
+        let (params, vs, fs) = item;
+        params.validate(&vs, &fs)?;
+
+        // The backend keeps the previous program if the new sources fail to compile.
+        let cmd = Command::ReloadShader(Box::new((handle, params.clone(), vs, fs)));
+        self.frames.write().cmds.push(cmd);
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+
+    fn params() -> ShaderParams {
+        let mut params = ShaderParams::default();
+        params.attributes = AttributeLayout::build()
+            .with(Attribute::Position, 3)
+            .finish();
+        params.uniforms = UniformVariableLayout::build()
+            .with("u_MVPMatrix", UniformVariableType::Matrix4f)
+            .finish();
+        params.state.depth_test = Comparison::Less;
+        params.state.color_write = (true, true, true, false);
+        params
+    }
+
+    fn load(bytes: &[u8]) -> Result<(ShaderParams, String, String)> {
+        let frame = Frame::with_capacity(0);
+        let loader = ShaderLoader::new(Arc::new(DoubleBuf::new(frame.clone(), frame)));
+        loader.load(ShaderHandle::new(1, 1), bytes)
+    }
+
+    #[test]
+    fn load_v1() {
+        let params = params();
+        let state = RenderStateV1 {
+            cull_face: params.state.cull_face,
+            front_face_order: params.state.front_face_order,
+            depth_test: params.state.depth_test,
+            depth_write: params.state.depth_write,
+            depth_write_offset: params.state.depth_write_offset,
+            color_blend: params.state.color_blend,
+            color_write: params.state.color_write,
+        };
+
+        let v1 = ShaderParamsV1 {
+            attributes: params.attributes,
+            uniforms: params.uniforms.clone(),
+            state,
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes[7] = 1;
+        bincode::serialize_into(&mut bytes, &v1).unwrap();
+        bincode::serialize_into(&mut bytes, "vs").unwrap();
+        bincode::serialize_into(&mut bytes, "fs").unwrap();
+
+        let (v, vs, fs) = load(&bytes).unwrap();
+        assert_eq!(v.attributes, params.attributes);
+        assert_eq!(v.state, params.state);
+        assert_eq!(
+            v.uniforms.variable_type("u_MVPMatrix"),
+            Some(UniformVariableType::Matrix4f)
+        );
+        assert_eq!((vs.as_ref(), fs.as_ref()), ("vs", "fs"));
+
+        bytes[7] = 0;
+        assert!(load(&bytes).is_err());
+    }
+
+    #[test]
+    fn load_v2() {
+        let mut params = params();
+        params.state.stencil = Some(StencilState::new(StencilFace {
+            comparison: Comparison::Equal,
+            reference: 1,
+            pass: StencilOp::Replace,
+            ..StencilFace::default()
+        }));
+
+        let mut bytes = MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, &params).unwrap();
+        bincode::serialize_into(&mut bytes, "vs").unwrap();
+        bincode::serialize_into(&mut bytes, "fs").unwrap();
+
+        let (v, vs, fs) = load(&bytes).unwrap();
+        assert_eq!(v.attributes, params.attributes);
+        assert_eq!(v.state, params.state);
+        assert_eq!(
+            v.uniforms.variable_type("u_MVPMatrix"),
+            Some(UniformVariableType::Matrix4f)
+        );
+        assert_eq!((vs.as_ref(), fs.as_ref()), ("vs", "fs"));
+
+        bytes[3] = b'X';
+        assert!(load(&bytes).is_err());
+    }
+}
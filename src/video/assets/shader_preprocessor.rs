@@ -0,0 +1,483 @@
+//! A preprocessor of GLSL sources, and the permutations of shader built on top of it.
+//!
+//! Sources could be split into files with `#include "name"` directives, the `name` is
+//! resolved through `res` like any other file, or registered ahead with
+//! `ShaderPreprocessor::add_include`. Every file is included at most once.
+//!
+//! The `#version` and precision header is chosen per backend, which is GLSL 3.30 on
+//! desktop and GLSL ES 3.00 on WebGL. The sources written in GLSL ES 1.00, with
+//! `attribute`, `varying`, `texture2D` and `gl_FragColor`, are still accepted by the
+//! compatibility defines of header. A `#version` directive in the sources takes priority
+//! over the default one, and the compatibility defines are left out in that case.
+//!
+//! The `#extension` directives are hoisted right after `#version`, and the defines of the
+//! `ShaderVariantKey` are inserted after the header.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::utils::prelude::{FastHashMap, FastHashSet};
+use crate::video::assets::shader::{ShaderHandle, ShaderParams};
+use crate::video::errors::{Error, Result};
+
+/// The GLSL dialects of backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderProfile {
+    /// The desktop OpenGL backend.
+    GL,
+    /// The WebGL backend.
+    WebGL,
+}
+
+impl ShaderProfile {
+    /// Gets the profile of backend in current target.
+    pub fn current() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            ShaderProfile::WebGL
+        } else {
+            ShaderProfile::GL
+        }
+    }
+
+    /// The default `#version` directive. Both versions support integer vertex
+    /// attributes, e.g. `ivec4` and `uvec4`.
+    pub fn version(self) -> &'static str {
+        match self {
+            ShaderProfile::GL => "#version 330 core",
+            ShaderProfile::WebGL => "#version 300 es",
+        }
+    }
+
+    /// The default precision of floats. The precision qualifiers are no-op on desktop,
+    /// while `highp` is not always available in the fragment shaders of WebGL.
+    pub fn precision(self) -> ShaderPrecision {
+        match self {
+            ShaderProfile::GL => ShaderPrecision::High,
+            ShaderProfile::WebGL => ShaderPrecision::Medium,
+        }
+    }
+}
+
+/// The stages of shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    /// The defines that map the keywords and built-ins of GLSL ES 1.00 to the ones of
+    /// default version.
+    fn compatibility(self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => {
+                "#define attribute in\n\
+                 #define varying out\n\
+                 #define texture2D texture\n\
+                 #define texture2DLod textureLod\n\
+                 #define textureCube texture\n\
+                 #define textureCubeLod textureLod\n"
+            }
+            ShaderStage::Fragment => {
+                "#define varying in\n\
+                 #define texture2D texture\n\
+                 #define texture2DLod textureLod\n\
+                 #define textureCube texture\n\
+                 #define textureCubeLod textureLod\n\
+                 out vec4 o_FragColor;\n\
+                 #define gl_FragColor o_FragColor\n"
+            }
+        }
+    }
+}
+
+/// The default precision of floats in shaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderPrecision {
+    Low,
+    Medium,
+    High,
+}
+
+impl ShaderPrecision {
+    /// The precision statement of floats.
+    pub fn statement(self) -> &'static str {
+        match self {
+            ShaderPrecision::Low => "precision lowp float;",
+            ShaderPrecision::Medium => "precision mediump float;",
+            ShaderPrecision::High => "precision highp float;",
+        }
+    }
+}
+
+/// The defines that identify a variant of shader.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderVariantKey {
+    defines: BTreeMap<String, String>,
+}
+
+impl ShaderVariantKey {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Defines `name` as `1`.
+    #[inline]
+    pub fn define<T: Into<String>>(self, name: T) -> Self {
+        self.define_value(name, "1")
+    }
+
+    /// Defines `name` as `value`, the former value would be replaced.
+    pub fn define_value<T1, T2>(mut self, name: T1, value: T2) -> Self
+    where
+        T1: Into<String>,
+        T2: ToString,
+    {
+        self.defines.insert(name.into(), value.to_string());
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.defines.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.defines.is_empty()
+    }
+
+    /// Iterates the defines, which are sorted by names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.defines.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+}
+
+/// Expands `#include` directives and inserts headers into GLSL sources.
+#[derive(Debug, Clone)]
+pub struct ShaderPreprocessor {
+    profile: ShaderProfile,
+    precision: Option<ShaderPrecision>,
+    includes: FastHashMap<String, String>,
+}
+
+/// The states of expanding sources.
+#[derive(Default)]
+struct Expansion {
+    version: Option<String>,
+    extensions: Vec<String>,
+    included: FastHashSet<String>,
+    body: String,
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        ShaderPreprocessor::new(ShaderProfile::current())
+    }
+}
+
+impl ShaderPreprocessor {
+    pub fn new(profile: ShaderProfile) -> Self {
+        ShaderPreprocessor {
+            profile,
+            precision: None,
+            includes: FastHashMap::default(),
+        }
+    }
+
+    #[inline]
+    pub fn profile(&self) -> ShaderProfile {
+        self.profile
+    }
+
+    /// Overrides the default precision of floats, which is chosen by profile.
+    #[inline]
+    pub fn set_precision(&mut self, precision: ShaderPrecision) {
+        self.precision = Some(precision);
+    }
+
+    /// Registers the source of `name`, so it would not be loaded through `res`. This is
+    /// required on WebAssembly, since the files could not be loaded synchronously.
+    pub fn add_include<T1, T2>(&mut self, name: T1, source: T2)
+    where
+        T1: Into<String>,
+        T2: Into<String>,
+    {
+        self.includes.insert(name.into(), source.into());
+    }
+
+    /// Preprocesses the source of `stage` with defines of `key`.
+    pub fn process(
+        &mut self,
+        stage: ShaderStage,
+        source: &str,
+        key: &ShaderVariantKey,
+    ) -> Result<String> {
+        let mut v = Expansion::default();
+        v.body.reserve(source.len());
+        self.expand(source, &mut v)?;
+
+        let mut out = String::with_capacity(v.body.len() + 512);
+        match v.version {
+            Some(ref version) => out.push_str(version),
+            None => out.push_str(self.profile.version()),
+        }
+        out.push('\n');
+
+        // The extensions must be declared before any non-preprocessor tokens.
+        for extension in &v.extensions {
+            out.push_str(extension);
+            out.push('\n');
+        }
+
+        let precision = self.precision.unwrap_or_else(|| self.profile.precision());
+        out.push_str(precision.statement());
+        out.push('\n');
+
+        if v.version.is_none() {
+            out.push_str(stage.compatibility());
+        }
+
+        for (name, value) in key.iter() {
+            writeln!(out, "#define {} {}", name, value).unwrap();
+        }
+
+        out.push_str(&v.body);
+        Ok(out)
+    }
+
+    fn expand(&mut self, source: &str, v: &mut Expansion) -> Result<()> {
+        for line in source.lines() {
+            let directive = line.trim_start();
+
+            if directive.starts_with("#version") {
+                if v.version.is_none() {
+                    v.version = Some(directive.trim_end().to_owned());
+                }
+
+                continue;
+            }
+
+            if directive.starts_with("#extension") {
+                let extension = directive.trim_end().to_owned();
+                if !v.extensions.contains(&extension) {
+                    v.extensions.push(extension);
+                }
+
+                continue;
+            }
+
+            if directive.starts_with("#include") {
+                let name = Self::include_name(&directive["#include".len()..])
+                    .ok_or_else(|| Error::ShaderInvalid(format!("Malformed {}", directive)))?;
+
+                if v.included.insert(name.to_owned()) {
+                    let source = self.resolve(name)?;
+                    self.expand(&source, v)?;
+                }
+
+                continue;
+            }
+
+            v.body.push_str(line);
+            v.body.push('\n');
+        }
+
+        Ok(())
+    }
+
+    fn include_name(v: &str) -> Option<&str> {
+        let v = v.trim();
+        if v.len() < 2 {
+            return None;
+        }
+
+        match (v.as_bytes()[0], v.as_bytes()[v.len() - 1]) {
+            (b'"', b'"') | (b'<', b'>') => Some(&v[1..v.len() - 1]),
+            _ => None,
+        }
+    }
+
+    fn resolve(&mut self, name: &str) -> Result<String> {
+        if let Some(source) = self.includes.get(name) {
+            return Ok(source.clone());
+        }
+
+        let source = Self::load(name)
+            .map_err(|err| Error::ShaderInvalid(format!("Failed to include {}. {}", name, err)))?;
+
+        self.includes.insert(name.to_owned(), source.clone());
+        Ok(source)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load(name: &str) -> ::std::result::Result<String, failure::Error> {
+        use crate::res::request::Request;
+        use crate::sched::latch::LatchWaitProbe;
+
+        if !crate::res::valid() {
+            bail!("The resource system is not available.");
+        }
+
+        let rsp = match crate::res::load_from(name)? {
+            Request::Ok(rsp) => rsp,
+            Request::NotReady(latch) => {
+                latch.wait();
+                latch.take()
+            }
+        };
+
+        Ok(String::from_utf8(rsp?.into_vec())?)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn load(_: &str) -> ::std::result::Result<String, failure::Error> {
+        bail!("Files could not be loaded synchronously, use `add_include` instead.");
+    }
+}
+
+/// The permutations of shader, which share the same sources and `ShaderParams` but differ
+/// in defines. Variants are created on demand and cached until this is dropped.
+pub struct ShaderVariants {
+    params: ShaderParams,
+    vs: String,
+    fs: String,
+    preprocessor: ShaderPreprocessor,
+    variants: FastHashMap<ShaderVariantKey, ShaderHandle>,
+}
+
+impl Drop for ShaderVariants {
+    fn drop(&mut self) {
+        for (_, handle) in self.variants.drain() {
+            crate::video::delete_shader(handle);
+        }
+    }
+}
+
+impl ShaderVariants {
+    pub fn new<T1, T2>(params: ShaderParams, vs: T1, fs: T2) -> Self
+    where
+        T1: Into<String>,
+        T2: Into<String>,
+    {
+        ShaderVariants {
+            params,
+            vs: vs.into(),
+            fs: fs.into(),
+            preprocessor: ShaderPreprocessor::default(),
+            variants: FastHashMap::default(),
+        }
+    }
+
+    /// Gets the preprocessor, which could be used to register includes.
+    #[inline]
+    pub fn preprocessor_mut(&mut self) -> &mut ShaderPreprocessor {
+        &mut self.preprocessor
+    }
+
+    /// Preprocesses the vertex and fragment sources of variant.
+    pub fn sources(&mut self, key: &ShaderVariantKey) -> Result<(String, String)> {
+        let vs = self
+            .preprocessor
+            .process(ShaderStage::Vertex, &self.vs, key)?;
+        let fs = self
+            .preprocessor
+            .process(ShaderStage::Fragment, &self.fs, key)?;
+        Ok((vs, fs))
+    }
+
+    /// Gets the shader of variant, it will be created if not exists.
+    pub fn variant(&mut self, key: &ShaderVariantKey) -> Result<ShaderHandle> {
+        if let Some(&handle) = self.variants.get(key) {
+            return Ok(handle);
+        }
+
+        let (vs, fs) = self.sources(key)?;
+        let handle = crate::video::create_shader(self.params.clone(), vs, fs)?;
+        self.variants.insert(key.clone(), handle);
+        Ok(handle)
+    }
+
+    /// The number of created variants.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn process() {
+        let mut preprocessor = ShaderPreprocessor::new(ShaderProfile::WebGL);
+        preprocessor.add_include("common.glsl", "#include \"math.glsl\"\nvarying vec2 v_UV;");
+        preprocessor.add_include("math.glsl", "#define PI 3.14");
+
+        let source = "#include \"common.glsl\"\n#include <math.glsl>\nvoid main() {}";
+        let key = ShaderVariantKey::new()
+            .define("SHADOW")
+            .define_value("MAX_LITS", 4);
+
+        let vs = ShaderStage::Vertex;
+        let v = preprocessor.process(vs, source, &key).unwrap();
+        let header = format!(
+            "#version 300 es\nprecision mediump float;\n{}",
+            vs.compatibility()
+        );
+
+        assert_eq!(
+            v,
+            format!(
+                "{}#define MAX_LITS 4\n#define SHADOW 1\n\
+                 #define PI 3.14\nvarying vec2 v_UV;\nvoid main() {{}}\n",
+                header
+            )
+        );
+
+        // The extensions are hoisted before the precision and defines.
+        preprocessor.set_precision(ShaderPrecision::Low);
+        let source =
+            "  #version 100\nvoid main() {}\n#extension GL_OES_standard_derivatives : enable";
+        let v = preprocessor
+            .process(ShaderStage::Fragment, source, &ShaderVariantKey::new())
+            .unwrap();
+        assert_eq!(
+            v,
+            "#version 100\n#extension GL_OES_standard_derivatives : enable\n\
+             precision lowp float;\nvoid main() {}\n"
+        );
+
+        assert!(preprocessor.process(vs, "#include common", &key).is_err());
+        assert!(preprocessor.process(vs, "#include \"\"", &key).is_err());
+    }
+
+    #[test]
+    fn profile() {
+        let mut preprocessor = ShaderPreprocessor::new(ShaderProfile::GL);
+        let source = "in ivec4 Indices;\nvoid main() { gl_FragColor = vec4(1.0); }";
+        let v = preprocessor
+            .process(ShaderStage::Fragment, source, &ShaderVariantKey::new())
+            .unwrap();
+
+        assert!(v.starts_with("#version 330 core\nprecision highp float;\n"));
+        assert!(v.contains("out vec4 o_FragColor;\n#define gl_FragColor o_FragColor\n"));
+        assert!(v.ends_with("in ivec4 Indices;\nvoid main() { gl_FragColor = vec4(1.0); }\n"));
+    }
+
+    #[test]
+    fn key() {
+        let lhs = ShaderVariantKey::new().define("A").define_value("B", 2);
+        let rhs = ShaderVariantKey::new().define_value("B", 2).define("A");
+        assert_eq!(lhs, rhs);
+        assert_eq!(lhs.len(), 2);
+        assert_ne!(lhs, rhs.define_value("A", 0));
+    }
+}
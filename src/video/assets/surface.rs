@@ -11,7 +11,7 @@ use crate::video::MAX_FRAMEBUFFER_ATTACHMENTS;
 /// Likes clearing, MSAA resolves, etc.. The `RenderTarget` is the window framebuffer as
 /// default, but you can specify `RenderTarget` with `SurfaceParams::set_attachments`
 /// manually also.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct SurfaceParams {
     pub(crate) colors: [Option<RenderTextureHandle>; MAX_FRAMEBUFFER_ATTACHMENTS],
     pub(crate) depth_stencil: Option<RenderTextureHandle>,
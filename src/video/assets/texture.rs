@@ -1,6 +1,12 @@
-//! Immutable or dynamic 2D texture. A texture is a container of one or more images. It
+//! Immutable or dynamic texture. A texture is a container of one or more images. It
 //! can be the source of a texture access from a Shader.
-use crate::math::prelude::Vector2;
+//!
+//! Besides the plain 2D texture, a texture could also be a cubemap, an array of 2D
+//! images or a 3D volume, see `TextureDimension` for details.
+use std::convert::TryFrom;
+
+use crate::math::prelude::{Color, Vector2};
+use crate::video::assets::shader::Comparison;
 use crate::video::errors::{Error, Result};
 
 impl_handle!(TextureHandle);
@@ -10,76 +16,232 @@ impl_handle!(TextureHandle);
 pub struct TextureParams {
     /// Hint abouts the intended update strategy of the data.
     pub hint: TextureHint,
-    /// Sets the wrap parameter for texture.
-    pub wrap: TextureWrap,
     /// Specify how the texture is used whenever the pixel being sampled.
-    pub filter: TextureFilter,
+    pub sampler: SamplerParams,
     /// Sets the format of data.
     pub format: TextureFormat,
     /// Sets the dimensions of texture.
     pub dimensions: Vector2<u32>,
+    /// Sets the kind of texture.
+    pub dimension: TextureDimension,
+    /// Sets the number of layers of 2D array texture, or the depth of 3D texture. It's
+    /// ignored by 2D and cube textures.
+    pub layers: u32,
+    /// Generates the whole mipmap chain from the base level at creation, and after every
+    /// update. The `TextureData` should only contain the base level in this case.
+    pub generate_mipmaps: bool,
 }
 
 impl Default for TextureParams {
     fn default() -> Self {
         TextureParams {
             format: TextureFormat::RGBA8,
-            wrap: TextureWrap::Clamp,
-            filter: TextureFilter::Linear,
+            sampler: SamplerParams::default(),
             hint: TextureHint::Immutable,
             dimensions: Vector2::new(0, 0),
+            dimension: TextureDimension::D2,
+            layers: 1,
+            generate_mipmaps: false,
         }
     }
 }
 
 impl TextureParams {
     pub fn validate(&self, data: Option<&TextureData>) -> Result<()> {
+        match self.dimension {
+            TextureDimension::Cube if self.dimensions.x != self.dimensions.y => {
+                return Err(Error::TextureInvalid(
+                    "The faces of cube texture should be square.".into(),
+                ));
+            }
+            TextureDimension::D2Array | TextureDimension::D3 if self.layers == 0 => {
+                return Err(Error::TextureInvalid(
+                    "Array and 3D textures should have at least one layer.".into(),
+                ));
+            }
+            _ => {}
+        }
+
+        if self.generate_mipmaps {
+            if self.format.compressed() {
+                return Err(Error::TextureInvalid(
+                    "Mipmaps of compressed texture can not be generated.".into(),
+                ));
+            }
+
+            if data.map(|v| v.bytes.len() > 1).unwrap_or(false) {
+                return Err(Error::TextureInvalid(
+                    "Only the base level should be provided when generating mipmaps.".into(),
+                ));
+            }
+        }
+
         if let Some(buf) = data {
-            let len = self.format.size(self.dimensions);
-            if !buf.bytes.is_empty() && buf.bytes[0].len() > len as usize {
-                return Err(Error::OutOfBounds);
+            if self.dimension == TextureDimension::D2 {
+                let len = self
+                    .format
+                    .checked_size(self.dimensions)
+                    .ok_or(Error::OutOfBounds)?;
+                if !buf.bytes.is_empty() && buf.bytes[0].len() > len as usize {
+                    return Err(Error::OutOfBounds);
+                }
+            } else {
+                // The images of layered texture are located by their sizes, so every
+                // mipmap level should be filled completely.
+                for (i, v) in buf.bytes.iter().enumerate() {
+                    let level = i as u32;
+                    if level >= 32 {
+                        return Err(Error::OutOfBounds);
+                    }
+
+                    let dimensions = Vector2::new(
+                        (self.dimensions.x >> level).max(1),
+                        (self.dimensions.y >> level).max(1),
+                    );
+
+                    let len = self
+                        .format
+                        .checked_size(dimensions)
+                        .and_then(|v| v.checked_mul(self.images(level)))
+                        .ok_or(Error::OutOfBounds)?;
+                    if v.len() != len as usize {
+                        return Err(Error::OutOfBounds);
+                    }
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Returns the number of 2D images in mipmap `level`, which are the faces of cube
+    /// texture, the layers of array texture or the depth slices of 3D texture.
+    pub fn images(&self, level: u32) -> u32 {
+        match self.dimension {
+            TextureDimension::D2 => 1,
+            TextureDimension::Cube => 6,
+            TextureDimension::D2Array => self.layers.max(1),
+            TextureDimension::D3 => (self.layers >> level).max(1),
+        }
+    }
+
+    /// Returns the number of levels in the complete mipmap chain.
+    pub fn mipmap_levels(&self) -> u32 {
+        let mut size = self.dimensions.x.max(self.dimensions.y);
+        if self.dimension == TextureDimension::D3 {
+            size = size.max(self.layers);
+        }
+
+        32 - size.max(1).leading_zeros()
+    }
+}
+
+/// The sampling states of a texture.
+///
+/// Some of the states are not available everywhere. WebGL ignores the `lod_bias`. The
+/// `Border` wrap is clamped to the edge unless `Capabilities::texture_border_clamp` is
+/// supported. The software rasterizer does not support anisotropic filtering.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SamplerParams {
+    /// Sets the wrap parameter for texture.
+    pub wrap: TextureWrap,
+    /// The filter used when the texture is minified.
+    pub min_filter: TextureFilter,
+    /// The filter used when the texture is magnified.
+    pub mag_filter: TextureFilter,
+    /// The filter between mipmap levels, the base level is always sampled if it's `None`.
+    pub mip_filter: Option<TextureFilter>,
+    /// The maximum degree of anisotropy, which is clamped to the capability of device. Sets
+    /// it to 1 to disable anisotropic filtering.
+    pub anisotropy: u8,
+    /// The bias added to the level-of-detail before selecting mipmap levels.
+    pub lod_bias: f32,
+    /// The color of texels outside of texture when the wrap is `TextureWrap::Border`.
+    pub border_color: Color<f32>,
+    /// Compares the reference value with the depth in texture instead of returning it,
+    /// which is used to sample shadow maps with `sampler2DShadow`.
+    pub compare: Option<Comparison>,
+}
+
+impl Default for SamplerParams {
+    fn default() -> Self {
+        SamplerParams {
+            wrap: TextureWrap::Clamp,
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mip_filter: Some(TextureFilter::Linear),
+            anisotropy: 1,
+            lod_bias: 0.0,
+            border_color: Color::transparent(),
+            compare: None,
+        }
+    }
+}
+
+impl SamplerParams {
+    /// Creates sampler states with the same filter for minification, magnification
+    /// and mipmap levels.
+    pub fn new(wrap: TextureWrap, filter: TextureFilter) -> Self {
+        SamplerParams {
+            wrap,
+            min_filter: filter,
+            mag_filter: filter,
+            mip_filter: Some(filter),
+            ..Default::default()
+        }
+    }
 }
 
 /// Continuous texture data of different mipmap levels.
 ///
-/// Notes that mipmaps are stored in order from largest size to smallest size
+/// Notes that mipmaps are stored in order from largest size to smallest size. The
+/// images of a layered texture are stored continuously in each mipmap level, e.g. the
+/// six faces of cube texture in order of `+X`, `-X`, `+Y`, `-Y`, `+Z` and `-Z`.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TextureData {
     pub bytes: Vec<Box<[u8]>>,
 }
 
 /// A `RenderTexture` object is basicly texture object with special format. It can
-/// be used as a render target. If the `sampler` field is true, it can also be ther
+/// be used as a render target. If the `sampler` field is set, it can also be ther
 /// source of a texture access from a __shader__.
 ///
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct RenderTextureParams {
     pub format: RenderTextureFormat,
-    pub wrap: TextureWrap,
-    pub filter: TextureFilter,
     pub dimensions: Vector2<u32>,
-    pub sampler: bool,
+    pub sampler: Option<SamplerParams>,
 }
 
 impl Default for RenderTextureParams {
     fn default() -> Self {
         RenderTextureParams {
             format: RenderTextureFormat::RGB8,
-            wrap: TextureWrap::Clamp,
-            filter: TextureFilter::Linear,
             dimensions: Vector2::new(0, 0),
-            sampler: true,
+            sampler: Some(SamplerParams::default()),
         }
     }
 }
 
 impl_handle!(RenderTextureHandle);
 
+/// The kind of a texture object, which decides how the images are organized and the
+/// type of sampler in shader.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TextureDimension {
+    /// A single two-dimensional image.
+    D2,
+    /// Six square images which form the faces of a cube, it's sampled with a direction
+    /// vector.
+    Cube,
+    /// An array of two-dimensional images with the same size, it's sampled with the
+    /// layer index as the third coordinate.
+    D2Array,
+    /// A three-dimensional image, the depth slices are stored as layers.
+    D3,
+}
+
 /// Hint abouts the intended update strategy of the data.
 #[repr(u8)]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
@@ -120,6 +282,8 @@ pub enum TextureWrap {
     Clamp,
     /// Same as Mirror, but only for one repetition.
     MirrorClamp,
+    /// Samples at coord outside of [0, 1] map to the border color.
+    Border,
 }
 
 /// List of all the possible formats of renderable texture which could be use as
@@ -128,8 +292,11 @@ pub enum TextureWrap {
 /// Each element of `Depth` is a single depth value. The `Graphics` converts it to
 /// floating point, multiplies by the signed scale factor, adds the signed bias, and
 /// clamps to the range [0,1].
+///
+/// `RGBA16F` stores half-precision floating point colors which are NOT clamped, it's
+/// useful for HDR rendering.
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RenderTextureFormat {
     RGB8,
     RGBA4,
@@ -138,6 +305,7 @@ pub enum RenderTextureFormat {
     Depth24,
     Depth32,
     Depth24Stencil8,
+    RGBA16F,
 }
 
 impl RenderTextureFormat {
@@ -145,6 +313,13 @@ impl RenderTextureFormat {
         self == RenderTextureFormat::RGB8
             || self == RenderTextureFormat::RGBA4
             || self == RenderTextureFormat::RGBA8
+            || self == RenderTextureFormat::RGBA16F
+    }
+
+    /// Returns true if the format stores floating point values, which are not clamped
+    /// into `[0, 1]`.
+    pub fn is_float(self) -> bool {
+        self == RenderTextureFormat::RGBA16F
     }
 
     /// Returns the size in bytes of texture with `dimensions`.
@@ -156,6 +331,7 @@ impl RenderTextureFormat {
             RenderTextureFormat::RGBA8
             | RenderTextureFormat::Depth32
             | RenderTextureFormat::Depth24Stencil8 => 4 * square,
+            RenderTextureFormat::RGBA16F => 8 * square,
         }
     }
 }
@@ -222,30 +398,56 @@ impl TextureFormat {
         }
     }
 
-    /// Returns the size in bytes of texture with `dimensions`.
+    /// Returns the size in bytes of texture with `dimensions`. Compressed formats are
+    /// measured in whole blocks, e.g. a 2x2 DXT1 image still takes a 4x4 block, and PVRTC
+    /// images take at least 2x2 blocks.
     pub fn size(self, dimensions: Vector2<u32>) -> u32 {
-        let square = dimensions.x * dimensions.y;
-        match self {
-            TextureFormat::PvrtcRGB2BPP | TextureFormat::PvrtcRGBA2BPP => square / 4,
-            TextureFormat::PvrtcRGB4BPP | TextureFormat::PvrtcRGBA4BPP => square / 2,
-            TextureFormat::Etc2RGB4BPP | TextureFormat::S3tcDxt1RGB4BPP => square / 2,
-            TextureFormat::S3tcDxt5RGBA8BPP => square,
-            TextureFormat::Etc2RGBA8BPP => square,
+        self.checked_size(dimensions)
+            .expect("The size of texture overflows.")
+    }
+
+    /// Returns the size in bytes of texture with `dimensions`, or `None` if it overflows.
+    pub fn checked_size(self, dimensions: Vector2<u32>) -> Option<u32> {
+        let (x, y) = (u64::from(dimensions.x), u64::from(dimensions.y));
+        let square = x * y;
+        let blocks = x.div_ceil(4) * y.div_ceil(4);
+        let size = match self {
+            TextureFormat::PvrtcRGB2BPP | TextureFormat::PvrtcRGBA2BPP => x.max(16) * y.max(8) / 4,
+            TextureFormat::PvrtcRGB4BPP | TextureFormat::PvrtcRGBA4BPP => x.max(8) * y.max(8) / 2,
+            TextureFormat::Etc2RGB4BPP | TextureFormat::S3tcDxt1RGB4BPP => blocks.checked_mul(8)?,
+            TextureFormat::S3tcDxt5RGBA8BPP => blocks.checked_mul(16)?,
+            TextureFormat::Etc2RGBA8BPP => blocks.checked_mul(16)?,
             TextureFormat::R8 => square,
             TextureFormat::RG8
             | TextureFormat::RGB565
             | TextureFormat::RGBA4
             | TextureFormat::RGBA5551
-            | TextureFormat::R16F => 2 * square,
-            TextureFormat::RGB8 => 3 * square,
+            | TextureFormat::R16F => square.checked_mul(2)?,
+            TextureFormat::RGB8 => square.checked_mul(3)?,
             TextureFormat::RGBA8
             | TextureFormat::RGBA1010102
             | TextureFormat::RG16F
-            | TextureFormat::R32F => 4 * square,
-            TextureFormat::RGB16F => 6 * square,
-            TextureFormat::RGBA16F | TextureFormat::RG32F => 8 * square,
-            TextureFormat::RGB32F => 12 * square,
-            TextureFormat::RGBA32F => 16 * square,
+            | TextureFormat::R32F => square.checked_mul(4)?,
+            TextureFormat::RGB16F => square.checked_mul(6)?,
+            TextureFormat::RGBA16F | TextureFormat::RG32F => square.checked_mul(8)?,
+            TextureFormat::RGB32F => square.checked_mul(12)?,
+            TextureFormat::RGBA32F => square.checked_mul(16)?,
+        };
+
+        u32::try_from(size).ok()
+    }
+
+    pub fn is_float(self) -> bool {
+        match self {
+            TextureFormat::R16F
+            | TextureFormat::RG16F
+            | TextureFormat::RGB16F
+            | TextureFormat::RGBA16F
+            | TextureFormat::R32F
+            | TextureFormat::RG32F
+            | TextureFormat::RGB32F
+            | TextureFormat::RGBA32F => true,
+            _ => false,
         }
     }
 
@@ -0,0 +1,683 @@
+//! Parsers of the standard texture containers, which could be exported by most of the
+//! texture tools directly.
+//!
+//! The containers are recognised by their headers, see `TextureContainer::detect`:
+//!
+//! - KTX 1, in little endian.
+//! - KTX 2, without supercompression.
+//! - DDS, with or without the DX10 header.
+//!
+//! Only the formats which have an equivalent `TextureFormat` are supported. Cube map
+//! arrays are not supported either.
+
+use std::io::{Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::errors::*;
+use crate::math::prelude::Vector2;
+
+use super::texture::*;
+
+const KTX_MAGIC: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'1', b'1', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+
+const DDS_MAGIC: [u8; 4] = [b'D', b'D', b'S', b' '];
+
+/// The maximum number of mipmap levels, which is enough for any 32 bits dimensions.
+const MAX_LEVELS: u32 = 32;
+
+/// The standard texture containers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureContainer {
+    Ktx,
+    Ktx2,
+    Dds,
+}
+
+impl TextureContainer {
+    /// Recognises the container by the header of `bytes`.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&KTX_MAGIC) {
+            Some(TextureContainer::Ktx)
+        } else if bytes.starts_with(&KTX2_MAGIC) {
+            Some(TextureContainer::Ktx2)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            Some(TextureContainer::Dds)
+        } else {
+            None
+        }
+    }
+
+    /// Parses the params and all the mipmap levels of texture.
+    pub fn parse(self, bytes: &[u8]) -> Result<(TextureParams, TextureData)> {
+        match self {
+            TextureContainer::Ktx => parse_ktx(bytes),
+            TextureContainer::Ktx2 => parse_ktx2(bytes),
+            TextureContainer::Dds => parse_dds(bytes),
+        }
+    }
+}
+
+/// Sets the dimension of texture with the number of depth slices, array layers and
+/// faces. A `layers` of zero means it's not an array.
+fn set_layout(params: &mut TextureParams, depth: u32, layers: u32, faces: u32) -> Result<()> {
+    if faces == 6 {
+        if layers > 0 || depth > 1 {
+            bail!("Cube map arrays are not supported.");
+        }
+
+        params.dimension = TextureDimension::Cube;
+    } else if depth > 1 {
+        if layers > 0 {
+            bail!("Arrays of 3D texture are not supported.");
+        }
+
+        params.dimension = TextureDimension::D3;
+        params.layers = depth;
+    } else if layers > 0 {
+        params.dimension = TextureDimension::D2Array;
+        params.layers = layers;
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn level_dimensions(params: &TextureParams, level: u32) -> Vector2<u32> {
+    Vector2::new(
+        (params.dimensions.x >> level).max(1),
+        (params.dimensions.y >> level).max(1),
+    )
+}
+
+/// Gets the size in bytes of `images` images of mipmap `level`. The dimensions of headers
+/// are untrusted, so the size is checked against the rest of `file` before allocating.
+fn level_size(
+    file: &Cursor<&[u8]>,
+    params: &TextureParams,
+    level: u32,
+    images: u32,
+) -> Result<usize> {
+    let remaining = (file.get_ref().len() as u64).saturating_sub(file.position());
+    let size = params
+        .format
+        .checked_size(level_dimensions(params, level))
+        .and_then(|v| u64::from(v).checked_mul(u64::from(images)))
+        .filter(|&v| v <= remaining)
+        .ok_or_else(|| {
+            format_err!(
+                "The data of {:?} texture ({}x{}, {} images) is truncated.",
+                params.format,
+                params.dimensions.x,
+                params.dimensions.y,
+                images
+            )
+        })?;
+
+    Ok(size as usize)
+}
+
+/// Reads `len` bytes into the end of `buf`.
+fn read_into(file: &mut Cursor<&[u8]>, buf: &mut Vec<u8>, len: usize) -> Result<()> {
+    let start = buf.len();
+    buf.resize(start + len, 0);
+    file.read_exact(&mut buf[start..])?;
+    Ok(())
+}
+
+#[inline]
+fn align(file: &mut Cursor<&[u8]>, alignment: u64) {
+    let pos = file.position();
+    file.set_position(pos.div_ceil(alignment) * alignment);
+}
+
+fn ktx_format(internal_format: u32, pixel_type: u32) -> Option<TextureFormat> {
+    let format = match (internal_format, pixel_type) {
+        (0x8229, _) => TextureFormat::R8,
+        (0x822B, _) => TextureFormat::RG8,
+        (0x8051, _) | (0x1907, 0x1401) => TextureFormat::RGB8,
+        (0x8058, _) | (0x1908, 0x1401) => TextureFormat::RGBA8,
+        (0x8D62, _) => TextureFormat::RGB565,
+        (0x8056, _) => TextureFormat::RGBA4,
+        (0x8057, _) => TextureFormat::RGBA5551,
+        (0x8059, _) => TextureFormat::RGBA1010102,
+        (0x822D, _) => TextureFormat::R16F,
+        (0x822F, _) => TextureFormat::RG16F,
+        (0x881B, _) => TextureFormat::RGB16F,
+        (0x881A, _) => TextureFormat::RGBA16F,
+        (0x822E, _) => TextureFormat::R32F,
+        (0x8230, _) => TextureFormat::RG32F,
+        (0x8815, _) => TextureFormat::RGB32F,
+        (0x8814, _) => TextureFormat::RGBA32F,
+        (0x8C00, _) => TextureFormat::PvrtcRGB4BPP,
+        (0x8C01, _) => TextureFormat::PvrtcRGB2BPP,
+        (0x8C02, _) => TextureFormat::PvrtcRGBA4BPP,
+        (0x8C03, _) => TextureFormat::PvrtcRGBA2BPP,
+        // ETC2 is backward compatible with ETC1.
+        (0x8D64, _) | (0x9274, _) => TextureFormat::Etc2RGB4BPP,
+        (0x9278, _) => TextureFormat::Etc2RGBA8BPP,
+        (0x83F0, _) => TextureFormat::S3tcDxt1RGB4BPP,
+        (0x83F3, _) => TextureFormat::S3tcDxt5RGBA8BPP,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn parse_ktx(bytes: &[u8]) -> Result<(TextureParams, TextureData)> {
+    let mut file = Cursor::new(bytes);
+    file.set_position(KTX_MAGIC.len() as u64);
+
+    if file.read_u32::<LittleEndian>()? != 0x0403_0201 {
+        bail!("[KTX] Big endian files are not supported.");
+    }
+
+    let pixel_type = file.read_u32::<LittleEndian>()?;
+    let _type_size = file.read_u32::<LittleEndian>()?;
+    let _pixel_format = file.read_u32::<LittleEndian>()?;
+    let internal_format = file.read_u32::<LittleEndian>()?;
+    let _base_internal_format = file.read_u32::<LittleEndian>()?;
+    let width = file.read_u32::<LittleEndian>()?;
+    let height = file.read_u32::<LittleEndian>()?;
+    let depth = file.read_u32::<LittleEndian>()?;
+    let layers = file.read_u32::<LittleEndian>()?;
+    let faces = file.read_u32::<LittleEndian>()?;
+    let levels = file.read_u32::<LittleEndian>()?;
+    let key_values = file.read_u32::<LittleEndian>()?;
+    file.set_position(file.position() + u64::from(key_values));
+
+    if levels > MAX_LEVELS {
+        bail!("[KTX] The number of mipmap levels {} is invalid.", levels);
+    }
+
+    let mut params = TextureParams::default();
+    params.format = ktx_format(internal_format, pixel_type).ok_or_else(|| {
+        format_err!(
+            "[KTX] Internal format {:#X} is not supported.",
+            internal_format
+        )
+    })?;
+    params.dimensions = Vector2::new(width, height.max(1));
+    params.generate_mipmaps = levels == 0;
+    set_layout(&mut params, depth, layers, faces)?;
+
+    // The rows of uncompressed images are aligned to 4 bytes. The paddings of cube faces
+    // and mipmap levels are always empty since the sizes of blocks are multiples of 4.
+    let compressed = params.format.compressed();
+
+    let mut data = TextureData { bytes: Vec::new() };
+    for level in 0..levels.max(1) {
+        let _image_size = file.read_u32::<LittleEndian>()?;
+        let dimensions = level_dimensions(&params, level);
+        let images = params.images(level);
+
+        let mut buf = Vec::with_capacity(level_size(&file, &params, level, images)?);
+        for _ in 0..images {
+            if compressed {
+                read_into(&mut file, &mut buf, params.format.size(dimensions) as usize)?;
+            } else {
+                let row = params.format.size(Vector2::new(dimensions.x, 1)) as usize;
+                for _ in 0..dimensions.y {
+                    read_into(&mut file, &mut buf, row)?;
+                    align(&mut file, 4);
+                }
+            }
+        }
+
+        data.bytes.push(buf.into_boxed_slice());
+    }
+
+    Ok((params, data))
+}
+
+fn vk_format(format: u32) -> Option<TextureFormat> {
+    let format = match format {
+        9 => TextureFormat::R8,
+        16 => TextureFormat::RG8,
+        23 => TextureFormat::RGB8,
+        37 => TextureFormat::RGBA8,
+        4 => TextureFormat::RGB565,
+        2 => TextureFormat::RGBA4,
+        6 => TextureFormat::RGBA5551,
+        64 => TextureFormat::RGBA1010102,
+        76 => TextureFormat::R16F,
+        83 => TextureFormat::RG16F,
+        90 => TextureFormat::RGB16F,
+        97 => TextureFormat::RGBA16F,
+        100 => TextureFormat::R32F,
+        103 => TextureFormat::RG32F,
+        106 => TextureFormat::RGB32F,
+        109 => TextureFormat::RGBA32F,
+        131 => TextureFormat::S3tcDxt1RGB4BPP,
+        137 => TextureFormat::S3tcDxt5RGBA8BPP,
+        147 => TextureFormat::Etc2RGB4BPP,
+        151 => TextureFormat::Etc2RGBA8BPP,
+        // PVRTC is always treated as RGBA in Vulkan.
+        1_000_054_000 => TextureFormat::PvrtcRGBA2BPP,
+        1_000_054_001 => TextureFormat::PvrtcRGBA4BPP,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<(TextureParams, TextureData)> {
+    let mut file = Cursor::new(bytes);
+    file.set_position(KTX2_MAGIC.len() as u64);
+
+    let format = file.read_u32::<LittleEndian>()?;
+    let _type_size = file.read_u32::<LittleEndian>()?;
+    let width = file.read_u32::<LittleEndian>()?;
+    let height = file.read_u32::<LittleEndian>()?;
+    let depth = file.read_u32::<LittleEndian>()?;
+    let layers = file.read_u32::<LittleEndian>()?;
+    let faces = file.read_u32::<LittleEndian>()?;
+    let levels = file.read_u32::<LittleEndian>()?;
+    let supercompression = file.read_u32::<LittleEndian>()?;
+
+    if supercompression != 0 {
+        bail!(
+            "[KTX2] Supercompression scheme {} is not supported.",
+            supercompression
+        );
+    }
+
+    if levels > MAX_LEVELS {
+        bail!("[KTX2] The number of mipmap levels {} is invalid.", levels);
+    }
+
+    let mut params = TextureParams::default();
+    params.format = vk_format(format)
+        .ok_or_else(|| format_err!("[KTX2] VkFormat {} is not supported.", format))?;
+    params.dimensions = Vector2::new(width, height.max(1));
+    params.generate_mipmaps = levels == 0;
+    set_layout(&mut params, depth, layers, faces)?;
+
+    // Skips the indices of data format descriptor, key/value data and supercompression
+    // global data.
+    file.set_position(file.position() + 32);
+
+    // The levels are indexed from the base level, but stored from the smallest one.
+    let mut data = TextureData { bytes: Vec::new() };
+    for level in 0..levels.max(1) {
+        let offset = file.read_u64::<LittleEndian>()? as usize;
+        let len = file.read_u64::<LittleEndian>()? as usize;
+        let _uncompressed_len = file.read_u64::<LittleEndian>()?;
+
+        // The images of levels are tightly packed, so the size should match the one
+        // computed from the untrusted dimensions exactly.
+        let size = params
+            .format
+            .checked_size(level_dimensions(&params, level))
+            .and_then(|v| u64::from(v).checked_mul(u64::from(params.images(level))));
+
+        if size != Some(len as u64) {
+            bail!(
+                "[KTX2] The size of level {} does not match {:?} texture ({}x{}).",
+                level,
+                params.format,
+                params.dimensions.x,
+                params.dimensions.y
+            );
+        }
+
+        let level = offset
+            .checked_add(len)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| format_err!("[KTX2] Level is out of bounds."))?;
+        data.bytes.push(level.into());
+    }
+
+    Ok((params, data))
+}
+
+/// Maps the format of DDS to `TextureFormat`, and whether the red and blue channels
+/// should be swapped.
+fn dds_format(
+    flags: u32,
+    four_cc: u32,
+    bits: u32,
+    masks: [u32; 4],
+    dxgi: Option<u32>,
+) -> Option<(TextureFormat, bool)> {
+    const DDPF_FOURCC: u32 = 0x4;
+
+    let format = match dxgi {
+        Some(2) => (TextureFormat::RGBA32F, false),
+        Some(6) => (TextureFormat::RGB32F, false),
+        Some(10) => (TextureFormat::RGBA16F, false),
+        Some(16) => (TextureFormat::RG32F, false),
+        Some(24) => (TextureFormat::RGBA1010102, false),
+        Some(28) => (TextureFormat::RGBA8, false),
+        Some(34) => (TextureFormat::RG16F, false),
+        Some(41) => (TextureFormat::R32F, false),
+        Some(49) => (TextureFormat::RG8, false),
+        Some(54) => (TextureFormat::R16F, false),
+        Some(61) => (TextureFormat::R8, false),
+        Some(71) => (TextureFormat::S3tcDxt1RGB4BPP, false),
+        Some(77) => (TextureFormat::S3tcDxt5RGBA8BPP, false),
+        Some(85) => (TextureFormat::RGB565, false),
+        Some(87) => (TextureFormat::RGBA8, true),
+        Some(_) => return None,
+        None if flags & DDPF_FOURCC != 0 => match &four_cc.to_le_bytes() {
+            b"DXT1" => (TextureFormat::S3tcDxt1RGB4BPP, false),
+            b"DXT5" => (TextureFormat::S3tcDxt5RGBA8BPP, false),
+            // The floating point formats are identified by their D3DFORMAT values.
+            _ => match four_cc {
+                111 => (TextureFormat::R16F, false),
+                112 => (TextureFormat::RG16F, false),
+                113 => (TextureFormat::RGBA16F, false),
+                114 => (TextureFormat::R32F, false),
+                115 => (TextureFormat::RG32F, false),
+                116 => (TextureFormat::RGBA32F, false),
+                _ => return None,
+            },
+        },
+        None => match (bits, masks) {
+            (32, [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]) => (TextureFormat::RGBA8, false),
+            (32, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]) => (TextureFormat::RGBA8, true),
+            (24, [0xFF, 0xFF00, 0xFF_0000, 0]) => (TextureFormat::RGB8, false),
+            (24, [0xFF_0000, 0xFF00, 0xFF, 0]) => (TextureFormat::RGB8, true),
+            (16, [0xF800, 0x07E0, 0x001F, 0]) => (TextureFormat::RGB565, false),
+            (8, [0xFF, 0, 0, 0]) => (TextureFormat::R8, false),
+            _ => return None,
+        },
+    };
+
+    Some(format)
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<(TextureParams, TextureData)> {
+    const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+    const DDSCAPS2_CUBEMAP: u32 = 0x200;
+    const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+    const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+    const DDS_DIMENSION_TEXTURE3D: u32 = 4;
+
+    let mut file = Cursor::new(bytes);
+    file.set_position(DDS_MAGIC.len() as u64);
+
+    if file.read_u32::<LittleEndian>()? != 124 {
+        bail!("[DDS] The size of header is invalid.");
+    }
+
+    let flags = file.read_u32::<LittleEndian>()?;
+    let height = file.read_u32::<LittleEndian>()?;
+    let width = file.read_u32::<LittleEndian>()?;
+    let _pitch = file.read_u32::<LittleEndian>()?;
+    let depth = file.read_u32::<LittleEndian>()?;
+    let levels = file.read_u32::<LittleEndian>()?;
+
+    // The pixel format.
+    file.set_position(80);
+    let pixel_flags = file.read_u32::<LittleEndian>()?;
+    let four_cc = file.read_u32::<LittleEndian>()?;
+    let bits = file.read_u32::<LittleEndian>()?;
+    let mut masks = [0; 4];
+    for v in &mut masks {
+        *v = file.read_u32::<LittleEndian>()?;
+    }
+
+    let _caps = file.read_u32::<LittleEndian>()?;
+    let caps2 = file.read_u32::<LittleEndian>()?;
+    file.set_position(128);
+
+    let mut params = TextureParams::default();
+    params.dimensions = Vector2::new(width, height.max(1));
+
+    let dxgi = if four_cc == u32::from_le_bytes(*b"DX10") {
+        let format = file.read_u32::<LittleEndian>()?;
+        let dimension = file.read_u32::<LittleEndian>()?;
+        let misc = file.read_u32::<LittleEndian>()?;
+        let size = file.read_u32::<LittleEndian>()?;
+        let _misc2 = file.read_u32::<LittleEndian>()?;
+
+        let layers = if size > 1 { size } else { 0 };
+        if misc & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+            set_layout(&mut params, 1, layers, 6)?;
+        } else if dimension == DDS_DIMENSION_TEXTURE3D {
+            set_layout(&mut params, depth, layers, 1)?;
+        } else {
+            set_layout(&mut params, 1, layers, 1)?;
+        }
+
+        Some(format)
+    } else {
+        if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            set_layout(&mut params, 1, 0, 6)?;
+        } else if caps2 & DDSCAPS2_VOLUME != 0 {
+            set_layout(&mut params, depth, 0, 1)?;
+        }
+
+        None
+    };
+
+    let (format, swizzle) = dds_format(pixel_flags, four_cc, bits, masks, dxgi)
+        .ok_or_else(|| format_err!("[DDS] The pixel format is not supported."))?;
+    params.format = format;
+
+    let levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+        levels.max(1)
+    } else {
+        1
+    };
+
+    if levels > MAX_LEVELS {
+        bail!("[DDS] The number of mipmap levels {} is invalid.", levels);
+    }
+
+    // The slices of 3D texture are stored level by level, while the other kinds of
+    // texture store the whole mipmap chain of every image one by one.
+    let mut buf = vec![Vec::new(); levels as usize];
+    if params.dimension == TextureDimension::D3 {
+        for (level, v) in buf.iter_mut().enumerate() {
+            let level = level as u32;
+            let len = level_size(&file, &params, level, params.images(level))?;
+            read_into(&mut file, v, len)?;
+        }
+    } else {
+        for _ in 0..params.images(0) {
+            for (level, v) in buf.iter_mut().enumerate() {
+                let len = level_size(&file, &params, level as u32, 1)?;
+                read_into(&mut file, v, len)?;
+            }
+        }
+    }
+
+    if swizzle {
+        let stride = params.format.size(Vector2::new(1, 1)) as usize;
+        for v in &mut buf {
+            for texel in v.chunks_mut(stride) {
+                texel.swap(0, 2);
+            }
+        }
+    }
+
+    let data = TextureData {
+        bytes: buf.into_iter().map(|v| v.into_boxed_slice()).collect(),
+    };
+
+    Ok((params, data))
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    fn write_u32s(buf: &mut Vec<u8>, values: &[u32]) {
+        for &v in values {
+            buf.write_u32::<LittleEndian>(v).unwrap();
+        }
+    }
+
+    #[test]
+    fn ktx() {
+        let mut bytes = KTX_MAGIC.to_vec();
+        // A 3x2 RGB8 texture with 2 levels, and 4 bytes of key/value data.
+        write_u32s(
+            &mut bytes,
+            &[0x0403_0201, 0x1401, 1, 0x1907, 0x8051, 0x1907],
+        );
+        write_u32s(&mut bytes, &[3, 2, 0, 0, 1, 2, 4]);
+        bytes.extend_from_slice(&[0; 4]);
+
+        write_u32s(&mut bytes, &[24]);
+        for i in 0..2 {
+            bytes.extend_from_slice(&[i; 9]);
+            bytes.extend_from_slice(&[0xFF; 3]);
+        }
+
+        write_u32s(&mut bytes, &[4]);
+        bytes.extend_from_slice(&[2, 2, 2, 0xFF]);
+
+        assert_eq!(
+            TextureContainer::detect(&bytes),
+            Some(TextureContainer::Ktx)
+        );
+        let (params, data) = TextureContainer::Ktx.parse(&bytes).unwrap();
+        assert_eq!(params.format, TextureFormat::RGB8);
+        assert_eq!(params.dimensions, Vector2::new(3, 2));
+        assert_eq!(params.dimension, TextureDimension::D2);
+        assert_eq!(data.bytes.len(), 2);
+        assert_eq!(&data.bytes[0][..9], &[0; 9]);
+        assert_eq!(&data.bytes[0][9..], &[1; 9]);
+        assert_eq!(&data.bytes[1][..], &[2, 2, 2]);
+        params.validate(Some(&data)).unwrap();
+
+        bytes[12..16].copy_from_slice(&[4, 3, 2, 1]);
+        assert!(TextureContainer::Ktx.parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        // A KTX header which claims a huge RGBA32F texture, without any data.
+        let mut bytes = KTX_MAGIC.to_vec();
+        write_u32s(
+            &mut bytes,
+            &[0x0403_0201, 0x1406, 4, 0x1908, 0x8814, 0x1908],
+        );
+        write_u32s(&mut bytes, &[0xFFFF_FFFF, 0xFFFF_FFFF, 0, 0, 1, 1, 0]);
+        write_u32s(&mut bytes, &[16]);
+        assert!(TextureContainer::Ktx.parse(&bytes).is_err());
+
+        // Garbage number of mipmap levels.
+        bytes[56..60].copy_from_slice(&[0xFF; 4]);
+        assert!(TextureContainer::Ktx.parse(&bytes).is_err());
+
+        // A 64x64 RGBA8 DDS which has only a few bytes of data.
+        let mut bytes = DDS_MAGIC.to_vec();
+        write_u32s(&mut bytes, &[124, 0x2_1007, 64, 64, 256, 0, 0xFFFF_FFFF]);
+        write_u32s(&mut bytes, &[0; 11]);
+        write_u32s(&mut bytes, &[32, 0x41, 0, 32]);
+        write_u32s(&mut bytes, &[0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]);
+        write_u32s(&mut bytes, &[0x1000, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0; 64]);
+        assert!(TextureContainer::Dds.parse(&bytes).is_err());
+
+        bytes[28..32].copy_from_slice(&[1, 0, 0, 0]);
+        assert!(TextureContainer::Dds.parse(&bytes).is_err());
+
+        // The range of level overflows.
+        let mut bytes = KTX2_MAGIC.to_vec();
+        write_u32s(&mut bytes, &[37, 1, 4, 4, 0, 0, 1, 1, 0]);
+        write_u32s(&mut bytes, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.write_u64::<LittleEndian>(u64::max_value()).unwrap();
+        bytes.write_u64::<LittleEndian>(64).unwrap();
+        bytes.write_u64::<LittleEndian>(64).unwrap();
+        assert!(TextureContainer::Ktx2.parse(&bytes).is_err());
+
+        // A 65536x65536 DXT1 texture whose level has only a single block.
+        let mut bytes = KTX2_MAGIC.to_vec();
+        write_u32s(&mut bytes, &[131, 1, 0x1_0000, 0x1_0000, 0, 0, 1, 1, 0]);
+        write_u32s(&mut bytes, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.write_u64::<LittleEndian>(104).unwrap();
+        bytes.write_u64::<LittleEndian>(8).unwrap();
+        bytes.write_u64::<LittleEndian>(8).unwrap();
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(TextureContainer::Ktx2.parse(&bytes).is_err());
+
+        // Truncated headers.
+        for &v in &[&KTX_MAGIC[..], &KTX2_MAGIC[..], &DDS_MAGIC[..]] {
+            let container = TextureContainer::detect(v).unwrap();
+            assert!(container.parse(v).is_err());
+            assert!(container.parse(&[v, &[0; 7]].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn ktx2() {
+        let mut bytes = KTX2_MAGIC.to_vec();
+        // A 4x4 DXT1 cube map without mipmaps.
+        write_u32s(&mut bytes, &[131, 1, 4, 4, 0, 0, 6, 1, 0]);
+        write_u32s(&mut bytes, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.write_u64::<LittleEndian>(104).unwrap();
+        bytes.write_u64::<LittleEndian>(48).unwrap();
+        bytes.write_u64::<LittleEndian>(48).unwrap();
+        bytes.extend_from_slice(&[7; 48]);
+
+        assert_eq!(
+            TextureContainer::detect(&bytes),
+            Some(TextureContainer::Ktx2)
+        );
+        let (params, data) = TextureContainer::Ktx2.parse(&bytes).unwrap();
+        assert_eq!(params.format, TextureFormat::S3tcDxt1RGB4BPP);
+        assert_eq!(params.dimension, TextureDimension::Cube);
+        assert_eq!(data.bytes.len(), 1);
+        assert_eq!(data.bytes[0].len(), 48);
+        params.validate(Some(&data)).unwrap();
+
+        // Supercompression.
+        bytes[44] = 1;
+        assert!(TextureContainer::Ktx2.parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn dds() {
+        let mut bytes = DDS_MAGIC.to_vec();
+        // A 2x1 BGRA texture with 2 levels.
+        write_u32s(&mut bytes, &[124, 0x2_1007, 1, 2, 8, 0, 2]);
+        write_u32s(&mut bytes, &[0; 11]);
+        write_u32s(&mut bytes, &[32, 0x41, 0, 32]);
+        write_u32s(&mut bytes, &[0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]);
+        write_u32s(&mut bytes, &[0x40_1008, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        assert_eq!(
+            TextureContainer::detect(&bytes),
+            Some(TextureContainer::Dds)
+        );
+        let (params, data) = TextureContainer::Dds.parse(&bytes).unwrap();
+        assert_eq!(params.format, TextureFormat::RGBA8);
+        assert_eq!(params.dimensions, Vector2::new(2, 1));
+        assert_eq!(&data.bytes[0][..], &[3, 2, 1, 4, 7, 6, 5, 8]);
+        assert_eq!(&data.bytes[1][..], &[11, 10, 9, 12]);
+
+        // The whole mipmap chains of faces are stored one by one.
+        let mut bytes = DDS_MAGIC.to_vec();
+        write_u32s(&mut bytes, &[124, 0x2_1007, 4, 4, 8, 0, 2]);
+        write_u32s(&mut bytes, &[0; 11]);
+        write_u32s(&mut bytes, &[32, 0x4, u32::from_le_bytes(*b"DXT1"), 0]);
+        write_u32s(&mut bytes, &[0; 4]);
+        write_u32s(&mut bytes, &[0x40_1008, 0xFE00, 0, 0, 0]);
+        for i in 0..12 {
+            bytes.extend_from_slice(&[i; 8]);
+        }
+
+        let (params, data) = TextureContainer::Dds.parse(&bytes).unwrap();
+        assert_eq!(params.format, TextureFormat::S3tcDxt1RGB4BPP);
+        assert_eq!(params.dimension, TextureDimension::Cube);
+        assert_eq!(data.bytes[0].len(), 48);
+        assert_eq!(data.bytes[1].len(), 48);
+        assert_eq!(&data.bytes[0][8..16], &[2; 8]);
+        assert_eq!(&data.bytes[1][0..8], &[1; 8]);
+        params.validate(Some(&data)).unwrap();
+    }
+}
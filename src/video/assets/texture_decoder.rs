@@ -0,0 +1,645 @@
+//! CPU decoders of the compressed texture formats.
+//!
+//! They are the fallback for backends which could not sample a compressed format
+//! natively, so one asset bundle could be shipped everywhere. The images are decoded into
+//! `RGBA8`, which takes 2 to 8 times more memory than the compressed ones.
+//!
+//! Every format except `PvrtcRGB2BPP` and `PvrtcRGBA2BPP` could be decoded.
+
+use crate::math::prelude::Vector2;
+use crate::video::errors::{Error, Result};
+
+use super::texture::{TextureData, TextureFormat, TextureParams};
+
+/// The intensity modifiers of ETC1 and the individual and differential modes of ETC2.
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// The distances between paint colors in the T and H modes of ETC2.
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// The alpha modifiers of EAC.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// A decoded 4x4 block, the texels are stored row by row.
+type Block = [[u8; 4]; 16];
+
+/// Decodes all the images of `data` into `RGBA8`, and changes the format of `params`
+/// accordingly.
+pub fn transcode(params: &mut TextureParams, data: &mut TextureData) -> Result<()> {
+    for (i, level) in data.bytes.iter_mut().enumerate() {
+        let i = i as u32;
+        let dimensions = Vector2::new(
+            (params.dimensions.x >> i).max(1),
+            (params.dimensions.y >> i).max(1),
+        );
+
+        let images = params.images(i) as usize;
+        let len = level.len() / images;
+
+        // The dimensions might come from untrusted headers, so the size is checked
+        // instead of panicking on overflows.
+        let size = TextureFormat::RGBA8
+            .checked_size(dimensions)
+            .and_then(|v| (v as usize).checked_mul(images))
+            .ok_or_else(|| {
+                Error::TextureInvalid(format!(
+                    "The size of texture ({}x{}) overflows.",
+                    dimensions.x, dimensions.y
+                ))
+            })?;
+
+        let mut rgba = Vec::with_capacity(size);
+        for j in 0..images {
+            let image = &level[j * len..(j + 1) * len];
+            rgba.extend(decode(params.format, dimensions, image)?);
+        }
+
+        *level = rgba.into_boxed_slice();
+    }
+
+    params.format = TextureFormat::RGBA8;
+    Ok(())
+}
+
+/// Decodes a compressed 2D image into `RGBA8`.
+pub fn decode(format: TextureFormat, dimensions: Vector2<u32>, bytes: &[u8]) -> Result<Vec<u8>> {
+    match format {
+        TextureFormat::S3tcDxt1RGB4BPP => decode_blocks(dimensions, bytes, 8, |src, dst| {
+            decode_dxt_color(src, true, dst);
+        }),
+        TextureFormat::S3tcDxt5RGBA8BPP => decode_blocks(dimensions, bytes, 16, |src, dst| {
+            decode_dxt_color(&src[8..], false, dst);
+            decode_dxt_alpha(src, dst);
+        }),
+        TextureFormat::Etc2RGB4BPP => decode_blocks(dimensions, bytes, 8, |src, dst| {
+            decode_etc2_color(src, dst);
+        }),
+        TextureFormat::Etc2RGBA8BPP => decode_blocks(dimensions, bytes, 16, |src, dst| {
+            decode_etc2_color(&src[8..], dst);
+            decode_eac_alpha(src, dst);
+        }),
+        TextureFormat::PvrtcRGB4BPP => decode_pvrtc4(dimensions, bytes, true),
+        TextureFormat::PvrtcRGBA4BPP => decode_pvrtc4(dimensions, bytes, false),
+        _ => Err(Error::TextureInvalid(format!(
+            "{:?} can not be decoded on CPU.",
+            format
+        ))),
+    }
+}
+
+fn decode_blocks<F>(
+    dimensions: Vector2<u32>,
+    bytes: &[u8],
+    block_size: usize,
+    func: F,
+) -> Result<Vec<u8>>
+where
+    F: Fn(&[u8], &mut Block),
+{
+    let (w, h) = (dimensions.x as usize, dimensions.y as usize);
+    let (bw, bh) = (w.div_ceil(4), h.div_ceil(4));
+
+    if bytes.len() < bw * bh * block_size {
+        return Err(Error::OutOfBounds);
+    }
+
+    let mut rgba = vec![0; w * h * 4];
+    let mut block = [[0; 4]; 16];
+    for by in 0..bh {
+        for bx in 0..bw {
+            let offset = (by * bw + bx) * block_size;
+            func(&bytes[offset..offset + block_size], &mut block);
+
+            // The blocks on the edges might be partially covered.
+            for y in 0..4.min(h - by * 4) {
+                for x in 0..4.min(w - bx * 4) {
+                    let dst = ((by * 4 + y) * w + bx * 4 + x) * 4;
+                    rgba[dst..dst + 4].copy_from_slice(&block[y * 4 + x]);
+                }
+            }
+        }
+    }
+
+    Ok(rgba)
+}
+
+#[inline]
+fn expand(v: u32, bits: u32) -> i32 {
+    ((v << (8 - bits)) | (v >> (2 * bits - 8))) as i32
+}
+
+#[inline]
+fn expand_rgb4(c: [u32; 3]) -> [i32; 3] {
+    [expand(c[0], 4), expand(c[1], 4), expand(c[2], 4)]
+}
+
+#[inline]
+fn clamp(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+#[inline]
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &v| (acc << 8) | u64::from(v))
+}
+
+#[inline]
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, &v| (acc << 8) | u64::from(v))
+}
+
+/// Extracts the bits in `[lo, hi]` of `v`.
+#[inline]
+fn bits(v: u64, hi: u32, lo: u32) -> u32 {
+    ((v >> lo) & ((1 << (hi - lo + 1)) - 1)) as u32
+}
+
+fn decode_dxt_color(bytes: &[u8], dxt1: bool, block: &mut Block) {
+    let c0 = little_endian(&bytes[0..2]) as u32;
+    let c1 = little_endian(&bytes[2..4]) as u32;
+    let indices = little_endian(&bytes[4..8]);
+
+    let rgb = |c: u32| {
+        [
+            expand(c >> 11, 5),
+            expand((c >> 5) & 0x3F, 6),
+            expand(c & 0x1F, 5),
+        ]
+    };
+    let (a, b) = (rgb(c0), rgb(c1));
+
+    let mut palette = [[0; 4]; 4];
+    for i in 0..3 {
+        palette[0][i] = a[i] as u8;
+        palette[1][i] = b[i] as u8;
+
+        // DXT1 has a 3 colors mode with transparent black, the alpha is ignored since
+        // we only support its RGB variant.
+        if c0 > c1 || !dxt1 {
+            palette[2][i] = ((2 * a[i] + b[i]) / 3) as u8;
+            palette[3][i] = ((a[i] + 2 * b[i]) / 3) as u8;
+        } else {
+            palette[2][i] = ((a[i] + b[i]) / 2) as u8;
+            palette[3][i] = 0;
+        }
+    }
+
+    for (i, v) in block.iter_mut().enumerate() {
+        *v = palette[((indices >> (2 * i)) & 0x3) as usize];
+        v[3] = 255;
+    }
+}
+
+fn decode_dxt_alpha(bytes: &[u8], block: &mut Block) {
+    let a0 = i32::from(bytes[0]);
+    let a1 = i32::from(bytes[1]);
+    let indices = little_endian(&bytes[2..8]);
+
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for (i, v) in palette.iter_mut().enumerate().skip(2) {
+            *v = ((8 - i as i32) * a0 + (i as i32 - 1) * a1) / 7;
+        }
+    } else {
+        for (i, v) in palette.iter_mut().enumerate().take(6).skip(2) {
+            *v = ((6 - i as i32) * a0 + (i as i32 - 1) * a1) / 5;
+        }
+    }
+
+    for (i, v) in block.iter_mut().enumerate() {
+        v[3] = palette[((indices >> (3 * i)) & 0x7) as usize] as u8;
+    }
+}
+
+fn decode_eac_alpha(bytes: &[u8], block: &mut Block) {
+    let base = i32::from(bytes[0]);
+    let multiplier = i32::from(bytes[1] >> 4);
+    let modifiers = &EAC_MODIFIERS[(bytes[1] & 0xF) as usize];
+    let indices = big_endian(&bytes[2..8]);
+
+    // The pixels are indexed column by column.
+    for (i, v) in block.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let index = (indices >> (45 - 3 * (x * 4 + y))) & 0x7;
+        v[3] = clamp(base + modifiers[index as usize] * multiplier);
+    }
+}
+
+fn decode_etc2_color(bytes: &[u8], block: &mut Block) {
+    let v = big_endian(&bytes[0..8]);
+
+    if bits(v, 33, 33) == 0 {
+        // The individual mode.
+        let c0 = [bits(v, 63, 60), bits(v, 55, 52), bits(v, 47, 44)];
+        let c1 = [bits(v, 59, 56), bits(v, 51, 48), bits(v, 43, 40)];
+        return decode_etc1_subblocks(v, expand_rgb4(c0), expand_rgb4(c1), block);
+    }
+
+    // The differential mode, the overflows of the second color are used to encode the
+    // T, H and planar modes of ETC2.
+    let delta = |c: u32| ((c << 29) as i32) >> 29;
+    let r = bits(v, 63, 59) as i32 + delta(bits(v, 58, 56));
+    let g = bits(v, 55, 51) as i32 + delta(bits(v, 50, 48));
+    let b = bits(v, 47, 43) as i32 + delta(bits(v, 42, 40));
+
+    if !(0..32).contains(&r) {
+        decode_etc2_t(v, block);
+    } else if !(0..32).contains(&g) {
+        decode_etc2_h(v, block);
+    } else if !(0..32).contains(&b) {
+        decode_etc2_planar(v, block);
+    } else {
+        let c0 = [
+            expand(bits(v, 63, 59), 5),
+            expand(bits(v, 55, 51), 5),
+            expand(bits(v, 47, 43), 5),
+        ];
+        let c1 = [
+            expand(r as u32, 5),
+            expand(g as u32, 5),
+            expand(b as u32, 5),
+        ];
+        decode_etc1_subblocks(v, c0, c1, block);
+    }
+}
+
+/// Gets the 2 bits selector of pixel, which are indexed column by column.
+#[inline]
+fn etc_selector(v: u64, x: usize, y: usize) -> usize {
+    let i = (x * 4 + y) as u32;
+    ((bits(v, i + 16, i + 16) << 1) | bits(v, i, i)) as usize
+}
+
+fn decode_etc1_subblocks(v: u64, c0: [i32; 3], c1: [i32; 3], block: &mut Block) {
+    let flip = bits(v, 32, 32) == 1;
+    let tables = [
+        ETC_MODIFIERS[bits(v, 39, 37) as usize],
+        ETC_MODIFIERS[bits(v, 36, 34) as usize],
+    ];
+
+    for (i, texel) in block.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let second = if flip { y >= 2 } else { x >= 2 };
+        let (base, table) = if second {
+            (c1, tables[1])
+        } else {
+            (c0, tables[0])
+        };
+
+        let modifier = match etc_selector(v, x, y) {
+            0 => table[0],
+            1 => table[1],
+            2 => -table[0],
+            _ => -table[1],
+        };
+
+        for c in 0..3 {
+            texel[c] = clamp(base[c] + modifier);
+        }
+        texel[3] = 255;
+    }
+}
+
+fn decode_etc2_paints(v: u64, paints: [[i32; 3]; 4], block: &mut Block) {
+    for (i, texel) in block.iter_mut().enumerate() {
+        let paint = paints[etc_selector(v, i % 4, i / 4)];
+        for c in 0..3 {
+            texel[c] = clamp(paint[c]);
+        }
+        texel[3] = 255;
+    }
+}
+
+fn decode_etc2_t(v: u64, block: &mut Block) {
+    let c0 = expand_rgb4([
+        (bits(v, 60, 59) << 2) | bits(v, 57, 56),
+        bits(v, 55, 52),
+        bits(v, 51, 48),
+    ]);
+    let c1 = expand_rgb4([bits(v, 47, 44), bits(v, 43, 40), bits(v, 39, 36)]);
+    let d = ETC_DISTANCES[((bits(v, 35, 34) << 1) | bits(v, 32, 32)) as usize];
+
+    let offset = |c: [i32; 3], d: i32| [c[0] + d, c[1] + d, c[2] + d];
+    let paints = [c0, offset(c1, d), c1, offset(c1, -d)];
+    decode_etc2_paints(v, paints, block);
+}
+
+fn decode_etc2_h(v: u64, block: &mut Block) {
+    let c0 = [
+        bits(v, 62, 59),
+        (bits(v, 58, 56) << 1) | bits(v, 52, 52),
+        (bits(v, 51, 51) << 3) | bits(v, 49, 47),
+    ];
+    let c1 = [bits(v, 46, 43), bits(v, 42, 39), bits(v, 38, 35)];
+
+    // The lowest bit of distance is implied by the order of colors.
+    let order = |c: [u32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+    let lsb = if order(c0) >= order(c1) { 1 } else { 0 };
+    let d = ETC_DISTANCES[((bits(v, 34, 34) << 2) | (bits(v, 32, 32) << 1) | lsb) as usize];
+
+    let (c0, c1) = (expand_rgb4(c0), expand_rgb4(c1));
+    let offset = |c: [i32; 3], d: i32| [c[0] + d, c[1] + d, c[2] + d];
+    let paints = [offset(c0, d), offset(c0, -d), offset(c1, d), offset(c1, -d)];
+    decode_etc2_paints(v, paints, block);
+}
+
+fn decode_etc2_planar(v: u64, block: &mut Block) {
+    let o = [
+        expand(bits(v, 62, 57), 6),
+        expand((bits(v, 56, 56) << 6) | bits(v, 54, 49), 7),
+        expand(
+            (bits(v, 48, 48) << 5) | (bits(v, 44, 43) << 3) | bits(v, 41, 39),
+            6,
+        ),
+    ];
+    let h = [
+        expand((bits(v, 38, 34) << 1) | bits(v, 32, 32), 6),
+        expand(bits(v, 31, 25), 7),
+        expand(bits(v, 24, 19), 6),
+    ];
+    let vv = [
+        expand(bits(v, 18, 13), 6),
+        expand(bits(v, 12, 6), 7),
+        expand(bits(v, 5, 0), 6),
+    ];
+
+    for (i, texel) in block.iter_mut().enumerate() {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        for c in 0..3 {
+            texel[c] = clamp((x * (h[c] - o[c]) + y * (vv[c] - o[c]) + 4 * o[c] + 2) >> 2);
+        }
+        texel[3] = 255;
+    }
+}
+
+/// Gets the offset of PVRTC block, which are stored in Morton order.
+fn pvrtc_offset(x: usize, y: usize, bw: usize, bh: usize) -> usize {
+    let (mut offset, mut shift) = (0, 0);
+    while (1 << shift) < bw.min(bh) {
+        offset |= ((y >> shift) & 1) << (2 * shift);
+        offset |= ((x >> shift) & 1) << (2 * shift + 1);
+        shift += 1;
+    }
+
+    // The remaining bits of the longer side.
+    let rest = if bw > bh { x >> shift } else { y >> shift };
+    offset | (rest << (2 * shift))
+}
+
+/// Gets the color A and B of PVRTC block, with 5 bits RGB and 4 bits alpha.
+fn pvrtc_colors(color: u32) -> [[i32; 4]; 2] {
+    let a = color & 0xFFFF;
+    let b = color >> 16;
+
+    let a = if a & 0x8000 != 0 {
+        [
+            (a >> 10) & 0x1F,
+            (a >> 5) & 0x1F,
+            expand5((a >> 1) & 0xF, 4),
+            0xF,
+        ]
+    } else {
+        [
+            expand5((a >> 8) & 0xF, 4),
+            expand5((a >> 4) & 0xF, 4),
+            expand5((a >> 1) & 0x7, 3),
+            ((a >> 12) & 0x7) << 1,
+        ]
+    };
+
+    let b = if b & 0x8000 != 0 {
+        [(b >> 10) & 0x1F, (b >> 5) & 0x1F, b & 0x1F, 0xF]
+    } else {
+        [
+            expand5((b >> 8) & 0xF, 4),
+            expand5((b >> 4) & 0xF, 4),
+            expand5(b & 0xF, 4),
+            ((b >> 12) & 0x7) << 1,
+        ]
+    };
+
+    let to_i32 = |v: [u32; 4]| [v[0] as i32, v[1] as i32, v[2] as i32, v[3] as i32];
+    [to_i32(a), to_i32(b)]
+}
+
+#[inline]
+fn expand5(v: u32, bits: u32) -> u32 {
+    (v << (5 - bits)) | (v >> (2 * bits - 5))
+}
+
+fn decode_pvrtc4(dimensions: Vector2<u32>, bytes: &[u8], opaque: bool) -> Result<Vec<u8>> {
+    if !dimensions.x.is_power_of_two() || !dimensions.y.is_power_of_two() {
+        return Err(Error::TextureInvalid(
+            "The dimensions of PVRTC texture should be power of two.".into(),
+        ));
+    }
+
+    let (w, h) = (dimensions.x as usize, dimensions.y as usize);
+    let (bw, bh) = ((w / 4).max(2), (h / 4).max(2));
+    if bytes.len() < bw * bh * 8 {
+        return Err(Error::OutOfBounds);
+    }
+
+    let word = |bx: usize, by: usize| {
+        let offset = pvrtc_offset(bx, by, bw, bh) * 8;
+        (
+            little_endian(&bytes[offset..offset + 4]) as u32,
+            little_endian(&bytes[offset + 4..offset + 8]) as u32,
+        )
+    };
+
+    let mut rgba = vec![0; w * h * 4];
+    for y in 0..h {
+        for x in 0..w {
+            // The colors are bilinear interpolated between the centers of the 4
+            // nearest blocks, which wrap around the edges.
+            let (fx, fy) = (x as isize - 2, y as isize - 2);
+            let (bx, by) = (fx.div_euclid(4), fy.div_euclid(4));
+            let (wx, wy) = (fx.rem_euclid(4) as i32, fy.rem_euclid(4) as i32);
+
+            let mut colors = [[0; 4]; 2];
+            let corners = [
+                (0, 0, (4 - wx) * (4 - wy)),
+                (1, 0, wx * (4 - wy)),
+                (0, 1, (4 - wx) * wy),
+                (1, 1, wx * wy),
+            ];
+
+            for &(dx, dy, weight) in &corners {
+                let cx = (bx + dx).rem_euclid(bw as isize) as usize;
+                let cy = (by + dy).rem_euclid(bh as isize) as usize;
+                let block = pvrtc_colors(word(cx, cy).1);
+                for i in 0..2 {
+                    for c in 0..4 {
+                        colors[i][c] += block[i][c] * weight;
+                    }
+                }
+            }
+
+            let (modulation, color) = word(x / 4, y / 4);
+            let bits = (modulation >> (2 * ((y % 4) * 4 + x % 4))) & 0x3;
+            let (weight, punch_through) = if color & 1 == 0 {
+                ([0, 3, 5, 8][bits as usize], false)
+            } else {
+                ([0, 4, 4, 8][bits as usize], bits == 2)
+            };
+
+            let dst = (y * w + x) * 4;
+            for c in 0..4 {
+                // The channels are scaled by 16 x 8 after interpolation and modulation.
+                let v = colors[0][c] * (8 - weight) + colors[1][c] * weight;
+                let max = if c == 3 { 15 * 128 } else { 31 * 128 };
+                rgba[dst + c] = ((v * 255 + max / 2) / max) as u8;
+            }
+
+            if opaque {
+                rgba[dst + 3] = 255;
+            } else if punch_through {
+                rgba[dst + 3] = 0;
+            }
+        }
+    }
+
+    Ok(rgba)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn texel(rgba: &[u8], w: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * w + x) * 4;
+        [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]]
+    }
+
+    #[test]
+    fn dxt() {
+        // Red and blue with indices 0, 1, 2, 3 in every row.
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let rgba = decode(TextureFormat::S3tcDxt1RGB4BPP, Vector2::new(4, 4), &block).unwrap();
+        assert_eq!(rgba.len(), 64);
+        assert_eq!(texel(&rgba, 4, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(&rgba, 4, 1, 1), [0, 0, 255, 255]);
+        assert_eq!(texel(&rgba, 4, 2, 2), [170, 0, 85, 255]);
+        assert_eq!(texel(&rgba, 4, 3, 3), [85, 0, 170, 255]);
+
+        // Alpha 255 and 0, with index 0 and 1 alternated, then index 2.
+        let mut block = vec![0xFF, 0x00, 0x08, 0x82, 0x20, 0x08, 0x82, 0x20];
+        block.extend_from_slice(&[0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        block[7] = 0x40;
+        let rgba = decode(TextureFormat::S3tcDxt5RGBA8BPP, Vector2::new(4, 4), &block).unwrap();
+        assert_eq!(texel(&rgba, 4, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(&rgba, 4, 1, 0), [255, 0, 0, 0]);
+
+        // Partially covered blocks.
+        let rgba = decode(
+            TextureFormat::S3tcDxt1RGB4BPP,
+            Vector2::new(2, 2),
+            &block[8..],
+        );
+        assert_eq!(rgba.unwrap().len(), 16);
+        assert!(decode(
+            TextureFormat::S3tcDxt1RGB4BPP,
+            Vector2::new(8, 4),
+            &block[8..]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn etc2() {
+        // The individual mode with codeword 0, the first subblock is red with modifier
+        // +2, and the second one is green with modifier +8.
+        let block = [0xF0, 0x0F, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00];
+        let rgba = decode(TextureFormat::Etc2RGB4BPP, Vector2::new(4, 4), &block).unwrap();
+        assert_eq!(texel(&rgba, 4, 0, 0), [255, 2, 2, 255]);
+        assert_eq!(texel(&rgba, 4, 2, 0), [8, 255, 8, 255]);
+        assert_eq!(texel(&rgba, 4, 3, 3), [8, 255, 8, 255]);
+
+        // The planar mode with a constant color.
+        let block = [0x7F, 0xFF, 0xFB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let rgba = decode(TextureFormat::Etc2RGB4BPP, Vector2::new(4, 4), &block).unwrap();
+        for v in rgba.chunks(4) {
+            assert_eq!(v, &[255, 255, 255, 255]);
+        }
+
+        // EAC alpha with base 128, multiplier 1 and all selectors 4.
+        let mut block = vec![0x80, 0x10, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24];
+        block.extend_from_slice(&[0xF0, 0x0F, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00]);
+        let rgba = decode(TextureFormat::Etc2RGBA8BPP, Vector2::new(4, 4), &block).unwrap();
+        assert_eq!(texel(&rgba, 4, 0, 0), [255, 2, 2, 130]);
+    }
+
+    #[test]
+    fn pvrtc() {
+        // 2x2 opaque white blocks with full modulation.
+        let block = [0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0xFF, 0xFF, 0xFF];
+        let bytes: Vec<_> = block.iter().cycle().take(32).cloned().collect();
+        let rgba = decode(TextureFormat::PvrtcRGBA4BPP, Vector2::new(8, 8), &bytes).unwrap();
+        for v in rgba.chunks(4) {
+            assert_eq!(v, &[255, 255, 255, 255]);
+        }
+
+        assert!(decode(TextureFormat::PvrtcRGBA2BPP, Vector2::new(8, 8), &bytes).is_err());
+        assert!(decode(TextureFormat::PvrtcRGBA4BPP, Vector2::new(6, 8), &bytes).is_err());
+    }
+
+    #[test]
+    fn transcode_levels() {
+        let mut params = TextureParams::default();
+        params.format = TextureFormat::S3tcDxt1RGB4BPP;
+        params.dimensions = Vector2::new(8, 4);
+
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let mut data = TextureData {
+            bytes: vec![
+                block.repeat(2).into_boxed_slice(),
+                block.to_vec().into_boxed_slice(),
+            ],
+        };
+
+        transcode(&mut params, &mut data).unwrap();
+        assert_eq!(params.format, TextureFormat::RGBA8);
+        assert_eq!(data.bytes[0].len(), 8 * 4 * 4);
+        assert_eq!(data.bytes[1].len(), 4 * 2 * 4);
+        params.validate(Some(&data)).unwrap();
+
+        // The size of decoded texture overflows.
+        params.format = TextureFormat::S3tcDxt1RGB4BPP;
+        params.dimensions = Vector2::new(0x1_0000, 0x1_0000);
+        let mut data = TextureData {
+            bytes: vec![block.to_vec().into_boxed_slice()],
+        };
+
+        assert!(transcode(&mut params, &mut data).is_err());
+    }
+}
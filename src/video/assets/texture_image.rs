@@ -0,0 +1,146 @@
+//! Runtime decoding of the common image files, e.g. the avatars of users or the contents
+//! of mods, which could not be imported by the offline tools.
+//!
+//! PNG, JPEG, TGA and BMP files are decoded into `RGBA8` textures on CPU. The rows of
+//! images are stored from top to bottom, it's usually needed to flip them with
+//! `ImageOptions::flip_vertically` since `v = 0` samples the first row of texture.
+
+use crate::errors::*;
+use crate::math::prelude::Vector2;
+
+use super::texture::TextureData;
+
+/// The image file formats which could be decoded at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Tga,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Recognises the format by the header of `bytes`. TGA has no magic number, so it's
+    /// recognised by the footer of version 2, or the sanity of its header.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']) {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if bytes.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        } else if bytes.ends_with(b"TRUEVISION-XFILE.\0") || Self::tga_header(bytes) {
+            Some(ImageFormat::Tga)
+        } else {
+            None
+        }
+    }
+
+    fn tga_header(bytes: &[u8]) -> bool {
+        if bytes.len() < 18 {
+            return false;
+        }
+
+        // The color map type, image type and pixel depth.
+        match (bytes[1], bytes[2], bytes[16]) {
+            (1, 1, _) | (1, 9, _) => true,
+            (0, 2, 15) | (0, 2, 16) | (0, 2, 24) | (0, 2, 32) => true,
+            (0, 10, 15) | (0, 10, 16) | (0, 10, 24) | (0, 10, 32) => true,
+            (0, 3, 8) | (0, 11, 8) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The options of decoding images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// Multiplies the colors by alpha, which avoids the dark fringes around transparent
+    /// texels with filtering. It should be blended with `BlendFactor::One` as source
+    /// factor.
+    pub premultiply_alpha: bool,
+    /// Reverses the order of rows, so the bottom of image is sampled at `v = 0`.
+    pub flip_vertically: bool,
+}
+
+/// Decodes an image file into `RGBA8` texture data, and returns its dimensions.
+pub fn decode(
+    format: ImageFormat,
+    bytes: &[u8],
+    options: ImageOptions,
+) -> Result<(Vector2<u32>, TextureData)> {
+    let codec = match format {
+        ImageFormat::Png => image::ImageFormat::Png,
+        ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+        ImageFormat::Tga => image::ImageFormat::Tga,
+        ImageFormat::Bmp => image::ImageFormat::Bmp,
+    };
+
+    let mut rgba = image::load_from_memory_with_format(bytes, codec)?.to_rgba8();
+    if options.flip_vertically {
+        image::imageops::flip_vertical_in_place(&mut rgba);
+    }
+
+    let dimensions = Vector2::new(rgba.width(), rgba.height());
+    let mut bytes = rgba.into_raw();
+
+    if options.premultiply_alpha {
+        for texel in bytes.chunks_mut(4) {
+            let alpha = u32::from(texel[3]);
+            for v in &mut texel[0..3] {
+                *v = ((u32::from(*v) * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+
+    let data = TextureData {
+        bytes: vec![bytes.into_boxed_slice()],
+    };
+
+    Ok((dimensions, data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video::screenshot::encode_png;
+
+    #[test]
+    fn detect() {
+        let png = encode_png(Vector2::new(1, 1), &[0; 4]).unwrap();
+        assert_eq!(ImageFormat::detect(&png), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::detect(b"BM\0\0"), Some(ImageFormat::Bmp));
+        assert_eq!(ImageFormat::detect(b"VTEX \0\0\x03"), None);
+
+        let mut tga = [0; 18];
+        tga[2] = 2;
+        tga[16] = 32;
+        assert_eq!(ImageFormat::detect(&tga), Some(ImageFormat::Tga));
+    }
+
+    #[test]
+    fn png() {
+        // The PNG encoder of screenshots flips rows, since the readbacks start from the
+        // bottom.
+        let pixels = [
+            255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 255, 255, 255, 255,
+        ];
+        let png = encode_png(Vector2::new(2, 2), &pixels).unwrap();
+
+        let options = ImageOptions::default();
+        let (dimensions, data) = decode(ImageFormat::Png, &png, options).unwrap();
+        assert_eq!(dimensions, Vector2::new(2, 2));
+        assert_eq!(&data.bytes[0][0..8], &pixels[8..16]);
+
+        let mut options = ImageOptions::default();
+        options.flip_vertically = true;
+        let (_, data) = decode(ImageFormat::Png, &png, options).unwrap();
+        assert_eq!(&data.bytes[0][..], &pixels[..]);
+
+        options.premultiply_alpha = true;
+        let (_, data) = decode(ImageFormat::Png, &png, options).unwrap();
+        assert_eq!(&data.bytes[0][4..12], &[0, 128, 0, 128, 0, 0, 0, 0]);
+
+        assert!(decode(ImageFormat::Png, &png[0..32], options).is_err());
+    }
+}
@@ -3,22 +3,98 @@ use std::io::Cursor;
 use std::sync::Arc;
 
 use crate::errors::*;
+use crate::math::prelude::Vector2;
 use crate::res::utils::prelude::ResourceLoader;
 use crate::utils::double_buf::DoubleBuf;
 
 use super::super::backends::frame::{Command, Frame};
+use super::super::capabilities::Capabilities;
 use super::texture::*;
+use super::texture_container::TextureContainer;
+use super::texture_decoder;
+use super::texture_image::{self, ImageFormat, ImageOptions};
+
+pub const MAGIC: [u8; 8] = [b'V', b'T', b'E', b'X', b' ', 0, 0, 3];
+
+/// The parameters of texture in version 1, which could only be 2D texture.
+#[derive(Deserialize)]
+struct TextureParamsV1 {
+    hint: TextureHint,
+    wrap: TextureWrap,
+    filter: TextureFilter,
+    format: TextureFormat,
+    dimensions: Vector2<u32>,
+}
+
+impl From<TextureParamsV1> for TextureParams {
+    fn from(v: TextureParamsV1) -> Self {
+        TextureParams {
+            hint: v.hint,
+            sampler: SamplerParams::new(v.wrap, v.filter),
+            format: v.format,
+            dimensions: v.dimensions,
+            ..Default::default()
+        }
+    }
+}
+
+/// The parameters of texture in version 2, which has only one filter for sampling.
+#[derive(Deserialize)]
+struct TextureParamsV2 {
+    hint: TextureHint,
+    wrap: TextureWrap,
+    filter: TextureFilter,
+    format: TextureFormat,
+    dimensions: Vector2<u32>,
+    dimension: TextureDimension,
+    layers: u32,
+}
 
-pub const MAGIC: [u8; 8] = [b'V', b'T', b'E', b'X', b' ', 0, 0, 1];
+impl From<TextureParamsV2> for TextureParams {
+    fn from(v: TextureParamsV2) -> Self {
+        TextureParams {
+            hint: v.hint,
+            sampler: SamplerParams::new(v.wrap, v.filter),
+            format: v.format,
+            dimensions: v.dimensions,
+            dimension: v.dimension,
+            layers: v.layers,
+            ..Default::default()
+        }
+    }
+}
 
+/// Loads textures from the `VTEX` files produced by the asset tool, the standard KTX 1/2
+/// and DDS containers, or the PNG, JPEG, TGA and BMP images which are decoded into `RGBA8`.
+/// The formats are recognised by their headers.
 #[derive(Clone)]
 pub struct TextureLoader {
     frames: Arc<DoubleBuf<Frame>>,
+    capabilities: Arc<Capabilities>,
 }
 
 impl TextureLoader {
-    pub(crate) fn new(frames: Arc<DoubleBuf<Frame>>) -> Self {
-        TextureLoader { frames }
+    pub(crate) fn new(frames: Arc<DoubleBuf<Frame>>, capabilities: Capabilities) -> Self {
+        TextureLoader {
+            frames,
+            capabilities: Arc::new(capabilities),
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Result<(TextureParams, TextureData)> {
+        if bytes.len() < 8 || bytes[0..7] != MAGIC[0..7] {
+            bail!("[TextureLoader] MAGIC number not match.");
+        }
+
+        let mut file = Cursor::new(&bytes[8..]);
+        let params = match bytes[7] {
+            1 => bincode::deserialize_from::<_, TextureParamsV1>(&mut file)?.into(),
+            2 => bincode::deserialize_from::<_, TextureParamsV2>(&mut file)?.into(),
+            3 => bincode::deserialize_from(&mut file)?,
+            v => bail!("[TextureLoader] Undefined version {}.", v),
+        };
+
+        Ok((params, bincode::deserialize_from(&mut file)?))
     }
 }
 
@@ -28,17 +104,35 @@ impl ResourceLoader for TextureLoader {
     type Resource = TextureParams;
 
     fn load(&self, handle: Self::Handle, bytes: &[u8]) -> Result<Self::Intermediate> {
-        if bytes[0..8] != MAGIC[..] {
-            bail!("[TextureLoader] MAGIC number not match.");
+        let (mut params, mut data) = if let Some(container) = TextureContainer::detect(bytes) {
+            container.parse(bytes)?
+        } else if let Some(format) = ImageFormat::detect(bytes) {
+            let (dimensions, data) = texture_image::decode(format, bytes, ImageOptions::default())?;
+            let mut params = TextureParams::default();
+            params.dimensions = dimensions;
+            (params, data)
+        } else {
+            Self::parse(bytes)?
+        };
+
+        // Compressed formats are decoded on CPU if the backend could not sample them.
+        if params.format.compressed() && !self.capabilities.support_texture_format(params.format) {
+            warn!(
+                "[TextureLoader] {:?} is not supported, decodes {:?} into RGBA8 on CPU.",
+                params.format, handle
+            );
+
+            texture_decoder::transcode(&mut params, &mut data)?;
         }
 
-        let mut file = Cursor::new(&bytes[8..]);
-        let params: TextureParams = bincode::deserialize_from(&mut file)?;
-        let data = bincode::deserialize_from(&mut file)?;
-
         info!(
-            "[TextureLoader] load {:?} ({}x{} - {:?}).",
-            handle, params.dimensions.x, params.dimensions.y, params.format
+            "[TextureLoader] load {:?} ({}x{}x{} {:?} - {:?}).",
+            handle,
+            params.dimensions.x,
+            params.dimensions.y,
+            params.images(0),
+            params.dimension,
+            params.format
         );
 
         Ok((params, Some(data)))
@@ -61,4 +155,20 @@ impl ResourceLoader for TextureLoader {
         let cmd = Command::DeleteTexture(handle);
         self.frames.write().cmds.push(cmd);
     }
+
+    fn reload(
+        &self,
+        handle: Self::Handle,
+        _: &Self::Resource,
+        item: Self::Intermediate,
+    ) -> Result<Self::Resource> {
+        info!("[TextureLoader] reload {:?}.", handle);
+
+        item.0.validate(item.1.as_ref())?;
+
+        let cmd = Command::ReloadTexture(Box::new((handle, item.0, item.1)));
+        self.frames.write().cmds.push(cmd);
+
+        Ok(item.0)
+    }
 }
@@ -0,0 +1,309 @@
+//! Uniform buffer objects, which share a block of uniform variables across draw calls.
+//!
+//! The variables of a block are packed with the `std140` layout rules, so the GLSL sources
+//! should declare the block with `layout(std140)` and the same members in the same order.
+
+use crate::utils::prelude::HashValue;
+use crate::video::assets::shader::{UniformVariable, UniformVariableType};
+use crate::video::errors::{Error, Result};
+
+impl_handle!(UniformBlockHandle);
+
+/// The setup parameters of uniform block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UniformBlockParams {
+    pub layout: UniformBlockLayout,
+}
+
+impl UniformBlockParams {
+    pub fn validate(&self) -> Result<()> {
+        if self.layout.is_empty() {
+            return Err(Error::UniformBlockInvalid(
+                "Uniform block should have at least one variable.".into(),
+            ));
+        }
+
+        for (name, tp) in self.layout.iter() {
+            if UniformBlockLayout::std140(tp).is_none() {
+                return Err(Error::UniformBlockInvalid(format!(
+                    "Uniform variable {} with type {:?} can not be placed in block.",
+                    name, tp
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// UniformBlockLayout defines the members of uniform block, and their offsets in buffer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UniformBlockLayout {
+    size: usize,
+    variables: Vec<(HashValue<str>, String, UniformVariableType, usize)>,
+}
+
+impl UniformBlockLayout {
+    pub fn build() -> UniformBlockLayoutBuilder {
+        UniformBlockLayoutBuilder::new()
+    }
+
+    /// Size of the whole block in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    /// Iterates the variables in declaration order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, UniformVariableType)> {
+        self.variables.iter().map(|v| (v.1.as_ref(), v.2))
+    }
+
+    pub fn variable_type<T>(&self, field: T) -> Option<UniformVariableType>
+    where
+        T: Into<HashValue<str>>,
+    {
+        let field = field.into();
+        self.variables.iter().find(|v| v.0 == field).map(|v| v.2)
+    }
+
+    pub fn variable_name<T>(&self, field: T) -> Option<&str>
+    where
+        T: Into<HashValue<str>>,
+    {
+        let field = field.into();
+        self.variables
+            .iter()
+            .find(|v| v.0 == field)
+            .map(|v| v.1.as_ref())
+    }
+
+    /// Relative offset of the variable in bytes.
+    pub fn offset<T>(&self, field: T) -> Option<usize>
+    where
+        T: Into<HashValue<str>>,
+    {
+        let field = field.into();
+        self.variables.iter().find(|v| v.0 == field).map(|v| v.3)
+    }
+
+    /// Writes the variables into `bytes`, which should have the size of this layout.
+    pub fn encode(
+        &self,
+        bytes: &mut [u8],
+        variables: &[(HashValue<str>, UniformVariable)],
+    ) -> Result<()> {
+        assert!(bytes.len() >= self.size);
+
+        for &(field, variable) in variables {
+            let e = self
+                .variables
+                .iter()
+                .find(|v| v.0 == field)
+                .ok_or_else(|| {
+                    Error::UniformBlockInvalid(format!("Undefined uniform field {:?}.", field))
+                })?;
+
+            if e.2 != variable.variable_type() {
+                return Err(Error::UniformBlockInvalid(format!(
+                    "The uniform {} needs a {:?} instead of {:?}.",
+                    e.1,
+                    e.2,
+                    variable.variable_type()
+                )));
+            }
+
+            let mut floats = [0.0; 16];
+            let len = match variable {
+                UniformVariable::I32(v) => {
+                    bytes[e.3..e.3 + 4].copy_from_slice(&v.to_le_bytes());
+                    continue;
+                }
+                UniformVariable::F32(v) => {
+                    floats[0] = v;
+                    1
+                }
+                UniformVariable::Vector2f(v) => {
+                    floats[0..2].copy_from_slice(&v);
+                    2
+                }
+                UniformVariable::Vector3f(v) => {
+                    floats[0..3].copy_from_slice(&v);
+                    3
+                }
+                UniformVariable::Vector4f(v) => {
+                    floats[0..4].copy_from_slice(&v);
+                    4
+                }
+                // Every column of matrix is aligned as `vec4`.
+                UniformVariable::Matrix2f(v, transpose) => {
+                    for i in 0..2 {
+                        for j in 0..2 {
+                            floats[i * 4 + j] = if transpose { v[j][i] } else { v[i][j] };
+                        }
+                    }
+                    6
+                }
+                UniformVariable::Matrix3f(v, transpose) => {
+                    for i in 0..3 {
+                        for j in 0..3 {
+                            floats[i * 4 + j] = if transpose { v[j][i] } else { v[i][j] };
+                        }
+                    }
+                    11
+                }
+                UniformVariable::Matrix4f(v, transpose) => {
+                    for i in 0..4 {
+                        for j in 0..4 {
+                            floats[i * 4 + j] = if transpose { v[j][i] } else { v[i][j] };
+                        }
+                    }
+                    16
+                }
+                _ => {
+                    return Err(Error::UniformBlockInvalid(format!(
+                        "The uniform {} of {:?} can not be placed in uniform block.",
+                        e.1, e.2
+                    )));
+                }
+            };
+
+            for (i, f) in floats[0..len].iter().enumerate() {
+                let offset = e.3 + i * 4;
+                bytes[offset..offset + 4].copy_from_slice(&f.to_bits().to_le_bytes());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets the base alignment and size of variable with `std140` layout rules.
+    fn std140(tp: UniformVariableType) -> Option<(usize, usize)> {
+        match tp {
+            UniformVariableType::I32 | UniformVariableType::F32 => Some((4, 4)),
+            UniformVariableType::Vector2f => Some((8, 8)),
+            UniformVariableType::Vector3f => Some((16, 12)),
+            UniformVariableType::Vector4f => Some((16, 16)),
+            UniformVariableType::Matrix2f => Some((16, 32)),
+            UniformVariableType::Matrix3f => Some((16, 48)),
+            UniformVariableType::Matrix4f => Some((16, 64)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UniformBlockLayoutBuilder(UniformBlockLayout);
+
+impl UniformBlockLayoutBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends a variable to the block. Textures and uniform blocks can NOT be placed
+    /// in uniform block.
+    pub fn with<T>(mut self, field: T, tp: UniformVariableType) -> Self
+    where
+        T: Into<String>,
+    {
+        let field = field.into();
+        let hash = HashValue::from(&field);
+        assert!(self.0.variables.iter().all(|v| v.0 != hash));
+
+        let (align, size) = UniformBlockLayout::std140(tp).unwrap_or((4, 0));
+        let offset = (self.0.size + align - 1) / align * align;
+        self.0.size = offset + size;
+        self.0.variables.push((hash, field, tp, offset));
+        self
+    }
+
+    /// Finishes the layout, the size of block is rounded up to the alignment of `vec4`.
+    #[inline]
+    pub fn finish(mut self) -> UniformBlockLayout {
+        self.0.size = (self.0.size + 15) / 16 * 16;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+    use crate::video::assets::texture::TextureHandle;
+
+    #[test]
+    fn std140() {
+        let layout = UniformBlockLayout::build()
+            .with("a", UniformVariableType::F32)
+            .with("b", UniformVariableType::Vector3f)
+            .with("c", UniformVariableType::F32)
+            .with("d", UniformVariableType::Vector2f)
+            .with("e", UniformVariableType::Matrix3f)
+            .finish();
+
+        assert_eq!(layout.offset("a"), Some(0));
+        assert_eq!(layout.offset("b"), Some(16));
+        assert_eq!(layout.offset("c"), Some(28));
+        assert_eq!(layout.offset("d"), Some(32));
+        assert_eq!(layout.offset("e"), Some(48));
+        assert_eq!(layout.size(), 96);
+
+        let mut bytes = vec![0; layout.size()];
+        let m = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+        let vars = [
+            ("c".into(), UniformVariable::F32(1.0)),
+            ("e".into(), UniformVariable::Matrix3f(m, false)),
+        ];
+        layout.encode(&mut bytes, &vars).unwrap();
+
+        let f = |offset: usize| {
+            let mut v = [0; 4];
+            v.copy_from_slice(&bytes[offset..offset + 4]);
+            f32::from_bits(u32::from_le_bytes(v))
+        };
+
+        assert_eq!(f(28), 1.0);
+        assert_eq!(f(48), 1.0);
+        assert_eq!(f(60), 0.0);
+        assert_eq!(f(64), 4.0);
+        assert_eq!(f(88), 9.0);
+
+        let vars = [("a".into(), UniformVariable::I32(1))];
+        assert!(layout.encode(&mut bytes, &vars).is_err());
+        let vars = [("z".into(), UniformVariable::F32(1.0))];
+        assert!(layout.encode(&mut bytes, &vars).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let mut params = UniformBlockParams::default();
+        assert!(params.validate().is_err());
+
+        params.layout = UniformBlockLayout::build()
+            .with("a", UniformVariableType::Texture)
+            .finish();
+        assert!(params.validate().is_err());
+
+        // Textures are rejected when encoding as well.
+        let mut bytes = vec![0; params.layout.size()];
+        let texture = UniformVariable::Texture(TextureHandle::new(1, 1));
+        let vars = [("a".into(), texture)];
+        assert!(params.layout.encode(&mut bytes, &vars).is_err());
+
+        params.layout = UniformBlockLayout::build()
+            .with("a", UniformVariableType::Matrix4f)
+            .finish();
+        assert!(params.validate().is_ok());
+    }
+}
@@ -1,5 +1,9 @@
+use std::sync::Arc;
+
 use crate::errors::*;
 use crate::math::prelude::{Aabb2, Vector2};
+use crate::res::request::Response;
+use crate::sched::prelude::LockLatch;
 use crate::utils::prelude::{DataBuffer, DataBufferPtr, HashValue};
 
 use super::super::assets::prelude::*;
@@ -7,11 +11,19 @@ use super::Visitor;
 
 type VarsPtr = DataBufferPtr<[(HashValue<str>, UniformVariable)]>;
 type BytesPtr = DataBufferPtr<[u8]>;
+type ReadLatch = Arc<LockLatch<Response>>;
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Bind(SurfaceHandle),
-    Draw(ShaderHandle, MeshHandle, MeshIndex, VarsPtr),
+    Draw(
+        ShaderHandle,
+        Option<Box<RenderState>>,
+        MeshHandle,
+        MeshIndex,
+        Option<MeshInstances>,
+        VarsPtr,
+    ),
     UpdateScissor(SurfaceScissor),
     UpdateViewport(SurfaceViewport),
 
@@ -19,10 +31,12 @@ pub enum Command {
     DeleteSurface(SurfaceHandle),
 
     CreateShader(Box<(ShaderHandle, ShaderParams, String, String)>),
+    ReloadShader(Box<(ShaderHandle, ShaderParams, String, String)>),
     DeleteShader(ShaderHandle),
 
     CreateTexture(Box<(TextureHandle, TextureParams, Option<TextureData>)>),
-    UpdateTexture(TextureHandle, Aabb2<u32>, BytesPtr),
+    UpdateTexture(TextureHandle, u32, Aabb2<u32>, BytesPtr),
+    ReloadTexture(Box<(TextureHandle, TextureParams, Option<TextureData>)>),
     DeleteTexture(TextureHandle),
 
     CreateRenderTexture(Box<(RenderTextureHandle, RenderTextureParams)>),
@@ -31,7 +45,15 @@ pub enum Command {
     CreateMesh(Box<(MeshHandle, MeshParams, Option<MeshData>)>),
     UpdateVertexBuffer(MeshHandle, usize, BytesPtr),
     UpdateIndexBuffer(MeshHandle, usize, BytesPtr),
+    ReloadMesh(Box<(MeshHandle, MeshParams, Option<MeshData>)>),
     DeleteMesh(MeshHandle),
+
+    CreateUniformBlock(Box<(UniformBlockHandle, UniformBlockParams)>),
+    UpdateUniformBlock(UniformBlockHandle, VarsPtr),
+    DeleteUniformBlock(UniformBlockHandle),
+
+    ReadSurface(Box<(SurfaceHandle, Aabb2<u32>, ReadLatch)>),
+    ReadRenderTexture(Box<(RenderTextureHandle, Aabb2<u32>, ReadLatch)>),
 }
 
 #[derive(Debug, Clone, Default)]
@@ -74,10 +96,11 @@ impl Frame {
                         visitor.bind(surface, dimensions)?;
                     }
 
-                    Command::Draw(shader, mesh, mesh_index, ptr) => {
+                    Command::Draw(shader, state, mesh, mesh_index, instances, ptr) => {
                         let vars = self.bufs.as_slice(ptr);
                         dc += 1;
-                        tris += visitor.draw(shader, mesh, mesh_index, vars)?;
+                        let state = state.map(|v| *v);
+                        tris += visitor.draw(shader, state, mesh, mesh_index, instances, vars)?;
                     }
 
                     Command::UpdateScissor(scissor) => {
@@ -100,6 +123,13 @@ impl Frame {
                         visitor.create_shader(v.0, v.1, &v.2, &v.3)?;
                     }
 
+                    Command::ReloadShader(v) => {
+                        // The previous shader is still usable, so its not fatal.
+                        if let Err(err) = visitor.reload_shader(v.0, v.1, &v.2, &v.3) {
+                            warn!("Failed to reload {:?}. {:?}", v.0, err);
+                        }
+                    }
+
                     Command::DeleteShader(handle) => {
                         visitor.delete_shader(handle)?;
                     }
@@ -108,9 +138,16 @@ impl Frame {
                         visitor.create_texture(v.0, v.1, v.2)?;
                     }
 
-                    Command::UpdateTexture(handle, area, ptr) => {
+                    Command::UpdateTexture(handle, layer, area, ptr) => {
                         let data = self.bufs.as_slice(ptr);
-                        visitor.update_texture(handle, area, data)?;
+                        visitor.update_texture(handle, layer, area, data)?;
+                    }
+
+                    Command::ReloadTexture(v) => {
+                        // The previous texture is still usable, so its not fatal.
+                        if let Err(err) = visitor.reload_texture(v.0, v.1, v.2) {
+                            warn!("Failed to reload {:?}. {:?}", v.0, err);
+                        }
                     }
 
                     Command::DeleteTexture(handle) => {
@@ -139,9 +176,39 @@ impl Frame {
                         visitor.update_index_buffer(handle, offset, data)?;
                     }
 
+                    Command::ReloadMesh(v) => {
+                        // The previous mesh is still usable, so its not fatal.
+                        if let Err(err) = visitor.reload_mesh(v.0, v.1, v.2) {
+                            warn!("Failed to reload {:?}. {:?}", v.0, err);
+                        }
+                    }
+
                     Command::DeleteMesh(handle) => {
                         visitor.delete_mesh(handle)?;
                     }
+
+                    Command::CreateUniformBlock(v) => {
+                        visitor.create_uniform_block(v.0, v.1)?;
+                    }
+
+                    Command::UpdateUniformBlock(handle, ptr) => {
+                        let vars = self.bufs.as_slice(ptr);
+                        visitor.update_uniform_block(handle, vars)?;
+                    }
+
+                    Command::DeleteUniformBlock(handle) => {
+                        visitor.delete_uniform_block(handle)?;
+                    }
+
+                    // Failed readbacks are reported through the latch, instead of
+                    // aborting the whole frame.
+                    Command::ReadSurface(v) => {
+                        v.2.set(visitor.read_surface(v.0, dimensions, v.1));
+                    }
+
+                    Command::ReadRenderTexture(v) => {
+                        v.2.set(visitor.read_render_texture(v.0, v.1));
+                    }
                 }
             }
 
@@ -171,6 +171,12 @@ extensions! {
     "GL_ARB_ES3_compatibility" => gl_arb_es3_compatibility,
     "GL_OES_compressed_ETC2_RGB8_texture" => gl_oes_compressed_etc2_rgb8_texture,
     "GL_OES_compressed_ETC2_RGBA8_texture" => gl_oes_compressed_etc2_rgba8_texture,
+    "GL_ARB_texture_filter_anisotropic" => gl_arb_texture_filter_anisotropic,
+    "GL_EXT_texture_filter_anisotropic" => gl_ext_texture_filter_anisotropic,
+    "GL_ARB_texture_float" => gl_arb_texture_float,
+    "GL_EXT_color_buffer_float" => gl_ext_color_buffer_float,
+    "GL_EXT_texture_border_clamp" => gl_ext_texture_border_clamp,
+    "GL_OES_texture_border_clamp" => gl_oes_texture_border_clamp,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -215,6 +221,12 @@ pub struct Capabilities {
     /// Maximum width and height of `glViewport`.
     pub max_viewport_dims: (u32, u32),
 
+    /// Maximum width and height of textures.
+    pub max_texture_size: u32,
+
+    /// Maximum number of vertex attributes.
+    pub max_vertex_attribs: u32,
+
     /// Maximum number of textures that can be bound to a program.
     ///
     /// `glActiveTexture` must be between `GL_TEXTURE0` and `GL_TEXTURE0` + this value - 1.
@@ -225,6 +237,9 @@ pub struct Capabilities {
 
     /// Maximum number of color attachment bind points.
     pub max_color_attachments: u32,
+
+    /// Maximum degree of anisotropic filtering, `1.0` if not supported.
+    pub max_anisotropy: f32,
 }
 
 impl Capabilities {
@@ -253,12 +268,42 @@ impl Capabilities {
             debug,
             forward_compatible,
             max_viewport_dims: Capabilities::parse_viewport_dims(),
+            max_texture_size: Capabilities::parse_integer(gl::MAX_TEXTURE_SIZE, 64),
+            max_vertex_attribs: Capabilities::parse_integer(gl::MAX_VERTEX_ATTRIBS, 8),
             max_combined_texture_image_units: Capabilities::parse_texture_image_units(),
             max_indexed_uniform_buffer: Capabilities::parse_uniform_buffers(version, &extensions),
             max_color_attachments: Capabilities::parse_color_attachments(version, &extensions),
+            max_anisotropy: Capabilities::parse_anisotropy(version, &extensions),
         })
     }
 
+    /// Whether floating point textures could be sampled.
+    pub fn has_float_texture(&self) -> bool {
+        self.version >= Version::GL(3, 0)
+            || self.version >= Version::ES(3, 0)
+            || self.extensions.gl_arb_texture_float
+    }
+
+    /// Whether floating point textures could be rendered into.
+    pub fn has_float_render_texture(&self) -> bool {
+        self.version >= Version::GL(3, 0)
+            || self.version >= Version::ES(3, 2)
+            || self.extensions.gl_ext_color_buffer_float
+    }
+
+    /// Whether vertex attributes could be half floats, packed `2_10_10_10` or integers.
+    pub fn has_extended_vertex_formats(&self) -> bool {
+        self.version >= Version::GL(3, 3) || self.version >= Version::ES(3, 0)
+    }
+
+    /// Whether textures could be wrapped with `CLAMP_TO_BORDER` and a border color.
+    pub fn has_texture_border_clamp(&self) -> bool {
+        self.version >= Version::GL(1, 3)
+            || self.version >= Version::ES(3, 2)
+            || self.extensions.gl_ext_texture_border_clamp
+            || self.extensions.gl_oes_texture_border_clamp
+    }
+
     pub fn has_compression(&self, compression: TextureCompression) -> bool {
         match compression {
             TextureCompression::ETC2 => {
@@ -290,6 +335,13 @@ impl Capabilities {
         (val[0] as u32, val[1] as u32)
     }
 
+    #[inline]
+    unsafe fn parse_integer(id: GLenum, min: u32) -> u32 {
+        let mut val = min as GLint;
+        gl::GetIntegerv(id, &mut val);
+        (val as u32).max(min)
+    }
+
     #[inline]
     unsafe fn parse_profile(version: Version) -> Option<Profile> {
         if version >= Version::GL(3, 2) {
@@ -343,4 +395,18 @@ impl Capabilities {
             0
         }
     }
+
+    #[inline]
+    unsafe fn parse_anisotropy(version: Version, exts: &Extensions) -> f32 {
+        if version >= Version::GL(4, 6)
+            || exts.gl_arb_texture_filter_anisotropic
+            || exts.gl_ext_texture_filter_anisotropic
+        {
+            let mut val = 1.0;
+            gl::GetFloatv(super::types::MAX_TEXTURE_MAX_ANISOTROPY, &mut val);
+            val.max(1.0)
+        } else {
+            1.0
+        }
+    }
 }
@@ -4,6 +4,10 @@ use gl::types::*;
 use super::super::super::assets::prelude::*;
 use super::capabilities::{Capabilities, TextureCompression, Version};
 
+// The enums of anisotropic filtering are not exposed by the generated bindings.
+pub const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
 impl From<MeshHint> for GLenum {
     fn from(hint: MeshHint) -> Self {
         match hint {
@@ -29,6 +33,21 @@ impl From<Comparison> for GLenum {
     }
 }
 
+impl From<StencilOp> for GLenum {
+    fn from(op: StencilOp) -> Self {
+        match op {
+            StencilOp::Keep => gl::KEEP,
+            StencilOp::Zero => gl::ZERO,
+            StencilOp::Replace => gl::REPLACE,
+            StencilOp::Increment => gl::INCR,
+            StencilOp::IncrementWrap => gl::INCR_WRAP,
+            StencilOp::Decrement => gl::DECR,
+            StencilOp::DecrementWrap => gl::DECR_WRAP,
+            StencilOp::Invert => gl::INVERT,
+        }
+    }
+}
+
 impl From<Equation> for GLenum {
     fn from(eq: Equation) -> Self {
         match eq {
@@ -64,6 +83,15 @@ impl From<VertexFormat> for GLenum {
             VertexFormat::Short => gl::SHORT,
             VertexFormat::UShort => gl::UNSIGNED_SHORT,
             VertexFormat::Float => gl::FLOAT,
+            VertexFormat::HalfFloat => gl::HALF_FLOAT,
+            VertexFormat::Int2_10_10_10Rev => gl::INT_2_10_10_10_REV,
+            VertexFormat::UInt2_10_10_10Rev => gl::UNSIGNED_INT_2_10_10_10_REV,
+            VertexFormat::Int8 => gl::BYTE,
+            VertexFormat::UInt8 => gl::UNSIGNED_BYTE,
+            VertexFormat::Int16 => gl::SHORT,
+            VertexFormat::UInt16 => gl::UNSIGNED_SHORT,
+            VertexFormat::Int32 => gl::INT,
+            VertexFormat::UInt32 => gl::UNSIGNED_INT,
         }
     }
 }
@@ -189,6 +217,38 @@ impl From<TextureWrap> for GLenum {
             TextureWrap::Mirror => gl::MIRRORED_REPEAT,
             TextureWrap::Clamp => gl::CLAMP_TO_EDGE,
             TextureWrap::MirrorClamp => gl::MIRROR_CLAMP_TO_EDGE,
+            TextureWrap::Border => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+impl From<TextureFilter> for GLenum {
+    fn from(filter: TextureFilter) -> Self {
+        match filter {
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR,
+        }
+    }
+}
+
+/// Combines the minifying filter with the filter between mipmap levels.
+pub fn texture_min_filter(min: TextureFilter, mip: Option<TextureFilter>) -> GLenum {
+    match (min, mip) {
+        (_, None) => min.into(),
+        (TextureFilter::Nearest, Some(TextureFilter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+        (TextureFilter::Nearest, Some(TextureFilter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+        (TextureFilter::Linear, Some(TextureFilter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+        (TextureFilter::Linear, Some(TextureFilter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+    }
+}
+
+impl From<TextureDimension> for GLenum {
+    fn from(dimension: TextureDimension) -> Self {
+        match dimension {
+            TextureDimension::D2 => gl::TEXTURE_2D,
+            TextureDimension::Cube => gl::TEXTURE_CUBE_MAP,
+            TextureDimension::D2Array => gl::TEXTURE_2D_ARRAY,
+            TextureDimension::D3 => gl::TEXTURE_3D,
         }
     }
 }
@@ -199,6 +259,7 @@ impl From<RenderTextureFormat> for (GLenum, GLenum, GLenum) {
             RenderTextureFormat::RGB8 => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE),
             RenderTextureFormat::RGBA4 => (gl::RGBA4, gl::RGBA, gl::UNSIGNED_SHORT_4_4_4_4),
             RenderTextureFormat::RGBA8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            RenderTextureFormat::RGBA16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
             RenderTextureFormat::Depth16 => (gl::DEPTH_COMPONENT16, gl::DEPTH_COMPONENT, gl::FLOAT),
             RenderTextureFormat::Depth24 => (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::FLOAT),
             RenderTextureFormat::Depth32 => (gl::DEPTH_COMPONENT32, gl::DEPTH_COMPONENT, gl::FLOAT),
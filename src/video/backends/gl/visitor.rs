@@ -1,5 +1,6 @@
 use std::cell::RefCell;
 
+use cgmath::Point2;
 use gl;
 use gl::types::*;
 use smallvec::SmallVec;
@@ -10,6 +11,7 @@ use crate::utils::hash::{FastHashMap, FastHashSet};
 use crate::utils::hash_value::HashValue;
 
 use super::super::super::assets::prelude::*;
+use super::super::super::capabilities::Capabilities as VideoCapabilities;
 use super::super::utils::DataVec;
 use super::super::{UniformVar, Visitor};
 use super::capabilities::{Capabilities, Version};
@@ -29,6 +31,7 @@ struct GLShaderData {
     id: GLuint,
     params: ShaderParams,
     uniforms: RefCell<FastHashMap<HashValue<str>, GLint>>,
+    uniform_blocks: RefCell<FastHashMap<HashValue<str>, GLint>>,
     attributes: RefCell<FastHashMap<HashValue<str>, GLint>>,
 }
 
@@ -53,6 +56,32 @@ impl GLShaderData {
         }
     }
 
+    fn hash_uniform_block_index<T: Into<HashValue<str>>>(&self, name: T) -> Option<GLint> {
+        self.uniform_blocks.borrow().get(&name.into()).cloned()
+    }
+
+    unsafe fn uniform_block_index(&self, name: &str) -> Result<GLint> {
+        let hash = name.into();
+        let mut uniform_blocks = self.uniform_blocks.borrow_mut();
+        match uniform_blocks.get(&hash).cloned() {
+            Some(index) => Ok(index),
+            None => {
+                let c_name = ::std::ffi::CString::new(name.as_bytes()).unwrap();
+                let index = gl::GetUniformBlockIndex(self.id, c_name.as_ptr());
+                check()?;
+
+                let index = if index == gl::INVALID_INDEX {
+                    -1
+                } else {
+                    index as GLint
+                };
+
+                uniform_blocks.insert(hash, index);
+                Ok(index)
+            }
+        }
+    }
+
     unsafe fn attribute_location(&self, name: &str) -> Result<GLint> {
         let hash = name.into();
         let mut attributes = self.attributes.borrow_mut();
@@ -78,6 +107,14 @@ struct GLMeshData {
     params: MeshParams,
 }
 
+#[derive(Debug, Clone)]
+struct GLUniformBlockData {
+    handle: UniformBlockHandle,
+    id: GLuint,
+    params: UniformBlockParams,
+    bytes: Vec<u8>,
+}
+
 #[derive(Debug, Clone)]
 struct GLTextureData {
     handle: TextureHandle,
@@ -99,15 +136,19 @@ enum Sampler {
     Texture(TextureHandle),
 }
 
+/// The key of vertex array object, which is made of shader, mesh and the optional per-instance
+/// mesh with its divisor.
+type VAOKey = (ShaderHandle, MeshHandle, Option<(MeshHandle, u32)>);
+
 struct GLMutableState {
     render_state: RenderState,
     scissor: SurfaceScissor,
     view: SurfaceViewport,
     cleared_surfaces: FastHashSet<SurfaceHandle>,
-    vaos: FastHashMap<(ShaderHandle, MeshHandle), GLuint>,
+    vaos: FastHashMap<VAOKey, GLuint>,
     binded_surface: Option<SurfaceHandle>,
     binded_shader: Option<ShaderHandle>,
-    binded_vao: Option<(ShaderHandle, MeshHandle)>,
+    binded_vao: Option<VAOKey>,
     binded_texture_index: usize,
     binded_textures: SmallVec<[Option<Sampler>; 8]>,
 }
@@ -120,6 +161,7 @@ pub struct GLVisitor {
     meshes: DataVec<GLMeshData>,
     textures: DataVec<GLTextureData>,
     render_textures: DataVec<GLRenderTextureData>,
+    uniform_blocks: DataVec<GLUniformBlockData>,
 }
 
 impl GLVisitor {
@@ -152,6 +194,7 @@ impl GLVisitor {
             meshes: DataVec::new(),
             textures: DataVec::new(),
             render_textures: DataVec::new(),
+            uniform_blocks: DataVec::new(),
         };
 
         Self::reset_render_state(&mut visitor.state)?;
@@ -160,6 +203,31 @@ impl GLVisitor {
 }
 
 impl Visitor for GLVisitor {
+    fn capabilities(&self) -> VideoCapabilities {
+        let caps = &self.capabilities;
+        let float = caps.has_float_texture();
+
+        VideoCapabilities {
+            max_texture_size: caps.max_texture_size,
+            max_vertex_attributes: caps.max_vertex_attribs,
+            max_texture_units: u32::from(caps.max_combined_texture_image_units),
+            max_color_attachments: caps.max_color_attachments,
+            max_anisotropy: caps.max_anisotropy,
+            texture_formats: VideoCapabilities::texture_formats(|v| {
+                v.is_support(caps) && (float || !v.is_float())
+            }),
+            render_texture_formats: VideoCapabilities::render_texture_formats(|v| {
+                v != RenderTextureFormat::RGBA16F || caps.has_float_render_texture()
+            }),
+            instancing: gl::DrawElementsInstanced::is_loaded()
+                && gl::VertexAttribDivisor::is_loaded(),
+            uniform_blocks: caps.max_indexed_uniform_buffer > 0,
+            float_textures: float,
+            extended_vertex_formats: caps.has_extended_vertex_formats(),
+            texture_border_clamp: caps.has_texture_border_clamp(),
+        }
+    }
+
     unsafe fn advance(&mut self) -> Result<()> {
         self.state.cleared_surfaces.clear();
         self.state.binded_surface = None;
@@ -316,6 +384,7 @@ impl Visitor for GLVisitor {
             id,
             params,
             uniforms: RefCell::new(FastHashMap::default()),
+            uniform_blocks: RefCell::new(FastHashMap::default()),
             attributes: RefCell::new(FastHashMap::default()),
         };
 
@@ -328,8 +397,18 @@ impl Visitor for GLVisitor {
             }
         }
 
-        for &(ref name, _) in shader.params.uniforms.iter() {
-            let location = shader.uniform_location(name)?;
+        for &(ref name, tp) in shader.params.uniforms.iter() {
+            let location = if tp == UniformVariableType::UniformBlock {
+                if !gl::GetUniformBlockIndex::is_loaded() {
+                    gl::DeleteProgram(id);
+                    bail!("The OpenGL implementation does not supports uniform blocks.");
+                }
+
+                shader.uniform_block_index(name)?
+            } else {
+                shader.uniform_location(name)?
+            };
+
             if location == -1 {
                 gl::DeleteProgram(id);
                 bail!("Uniform({:?}) is undefined in shader sources.", name);
@@ -340,28 +419,33 @@ impl Visitor for GLVisitor {
         Ok(())
     }
 
-    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
-        let shader = self
+    unsafe fn reload_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        let prev = self
             .shaders
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        // Removes deprecated `VertexArrayObject`s.
-        self.state.vaos.retain(|&(h, _), vao| {
-            if h == shader.handle {
-                gl::DeleteVertexArrays(1, vao as *mut u32);
-                false
-            } else {
-                true
-            }
-        });
-
-        if self.state.binded_shader == Some(handle) {
-            self.state.binded_shader = None;
+        if let Err(err) = self.create_shader(handle, params, vs, fs) {
+            self.shaders.create(handle, prev);
+            return Err(err);
         }
 
-        gl::DeleteProgram(shader.id);
-        check()
+        self.free_shader(prev)
+    }
+
+    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
+        let shader = self
+            .shaders
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        self.free_shader(shader)
     }
 
     unsafe fn create_texture(
@@ -378,59 +462,49 @@ impl Visitor for GLVisitor {
             );
         }
 
+        let layered = params.dimension == TextureDimension::D2Array
+            || params.dimension == TextureDimension::D3;
+
+        if layered && !gl::TexImage3D::is_loaded() {
+            bail!("The OpenGL implementation does not supports array and 3D textures.");
+        }
+
+        if params.generate_mipmaps && !gl::GenerateMipmap::is_loaded() {
+            bail!("The OpenGL implementation does not supports mipmap generation.");
+        }
+
         let mut id = 0;
         gl::GenTextures(1, &mut id);
         assert!(id != 0);
 
-        let (internal_format, format, pixel_type) =
-            types::texture_format(params.format, &self.capabilities);
-        let compressed = params.format.compressed();
+        let target: GLenum = params.dimension.into();
+        let formats = types::texture_format(params.format, &self.capabilities);
         let mut allocated = false;
 
-        if let Some(mut data) = data {
+        if let Some(data) = data {
             let len = data.bytes.len();
             if len > 0 {
-                Self::bind_texture(&mut self.state, Some(Sampler::Texture(handle)), 0, id)?;
-                Self::bind_texture_params(params.wrap, params.filter, len as u32)?;
-
-                let mut dims = (
-                    params.dimensions.x as GLsizei,
-                    params.dimensions.y as GLsizei,
-                );
+                Self::bind_texture(
+                    &mut self.state,
+                    Some(Sampler::Texture(handle)),
+                    0,
+                    target,
+                    id,
+                )?;
+                let levels = if params.generate_mipmaps {
+                    params.mipmap_levels()
+                } else {
+                    len as u32
+                };
 
-                if compressed {
-                    for (i, v) in data.bytes.drain(..).enumerate() {
-                        gl::CompressedTexImage2D(
-                            gl::TEXTURE_2D,
-                            i as GLint,
-                            internal_format,
-                            dims.0,
-                            dims.1,
-                            0,
-                            v.len() as GLint,
-                            &v[0] as *const u8 as *const ::std::os::raw::c_void,
-                        );
+                Self::bind_texture_params(&self.capabilities, target, &params.sampler, levels)?;
 
-                        dims.0 = (dims.0 / 2).max(1);
-                        dims.1 = (dims.1 / 2).max(1);
-                    }
-                } else {
-                    for (i, v) in data.bytes.drain(..).enumerate() {
-                        gl::TexImage2D(
-                            gl::TEXTURE_2D,
-                            i as GLint,
-                            internal_format as GLint,
-                            dims.0,
-                            dims.1,
-                            0,
-                            format,
-                            pixel_type,
-                            &v[0] as *const u8 as *const ::std::os::raw::c_void,
-                        );
+                for (i, v) in data.bytes.iter().enumerate() {
+                    Self::tex_image(&params, i as u32, formats, Some(v))?;
+                }
 
-                        dims.0 = (dims.0 / 2).max(1);
-                        dims.1 = (dims.1 / 2).max(1);
-                    }
+                if params.generate_mipmaps {
+                    gl::GenerateMipmap(target);
                 }
 
                 allocated = true;
@@ -455,6 +529,7 @@ impl Visitor for GLVisitor {
     unsafe fn update_texture(
         &mut self,
         handle: TextureHandle,
+        layer: u32,
         area: Aabb2<u32>,
         data: &[u8],
     ) -> Result<()> {
@@ -474,67 +549,106 @@ impl Visitor for GLVisitor {
         if data.len() > area.volume() as usize
             || area.min.x >= texture.params.dimensions.x
             || area.min.y >= texture.params.dimensions.y
+            || layer >= texture.params.images(0)
         {
             bail!("Trying to update texture data out of bounds.");
         }
 
-        let (internal_format, format, pixel_type) =
-            types::texture_format(texture.params.format, &self.capabilities);
+        let target: GLenum = texture.params.dimension.into();
+        let formats = types::texture_format(texture.params.format, &self.capabilities);
+        let (_, format, pixel_type) = formats;
 
         Self::bind_texture(
             &mut self.state,
             Some(Sampler::Texture(handle)),
             0,
+            target,
             texture.id,
         )?;
 
         if !*texture.allocated.borrow() {
-            Self::bind_texture_params(texture.params.wrap, texture.params.filter, 1)?;
-
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                internal_format as GLint,
-                texture.params.dimensions.x as GLsizei,
-                texture.params.dimensions.y as GLsizei,
-                0,
-                format,
-                pixel_type,
-                ::std::ptr::null(),
-            );
+            let levels = if texture.params.generate_mipmaps {
+                texture.params.mipmap_levels()
+            } else {
+                1
+            };
 
+            let sampler = &texture.params.sampler;
+            Self::bind_texture_params(&self.capabilities, target, sampler, levels)?;
+            Self::tex_image(&texture.params, 0, formats, None)?;
             *texture.allocated.borrow_mut() = true;
         }
 
-        gl::TexSubImage2D(
-            gl::TEXTURE_2D,
-            0,
-            area.min.x as i32,
-            area.min.y as i32,
-            area.dim().x as i32,
-            area.dim().y as i32,
-            format,
-            pixel_type,
-            &data[0] as *const u8 as *const ::std::os::raw::c_void,
-        );
+        match texture.params.dimension {
+            TextureDimension::D2 | TextureDimension::Cube => {
+                let target = if texture.params.dimension == TextureDimension::Cube {
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer
+                } else {
+                    gl::TEXTURE_2D
+                };
+
+                gl::TexSubImage2D(
+                    target,
+                    0,
+                    area.min.x as i32,
+                    area.min.y as i32,
+                    area.dim().x as i32,
+                    area.dim().y as i32,
+                    format,
+                    pixel_type,
+                    &data[0] as *const u8 as *const ::std::os::raw::c_void,
+                );
+            }
+            TextureDimension::D2Array | TextureDimension::D3 => {
+                gl::TexSubImage3D(
+                    target,
+                    0,
+                    area.min.x as i32,
+                    area.min.y as i32,
+                    layer as i32,
+                    area.dim().x as i32,
+                    area.dim().y as i32,
+                    1,
+                    format,
+                    pixel_type,
+                    &data[0] as *const u8 as *const ::std::os::raw::c_void,
+                );
+            }
+        }
+
+        if texture.params.generate_mipmaps {
+            gl::GenerateMipmap(target);
+        }
 
         check()
     }
 
-    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
-        let texture = self
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        let prev = self
             .textures
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        for v in self.state.binded_textures.iter_mut() {
-            if *v == Some(Sampler::Texture(handle)) {
-                *v = None;
-            }
+        if let Err(err) = self.create_texture(handle, params, data) {
+            self.textures.create(handle, prev);
+            return Err(err);
         }
 
-        gl::DeleteTextures(1, &texture.id);
-        check()
+        self.free_texture(prev)
+    }
+
+    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
+        let texture = self
+            .textures
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        self.free_texture(texture)
     }
 
     unsafe fn create_render_texture(
@@ -542,13 +656,25 @@ impl Visitor for GLVisitor {
         handle: RenderTextureHandle,
         params: RenderTextureParams,
     ) -> Result<()> {
-        let id = if params.sampler {
+        if params.format == RenderTextureFormat::RGBA16F
+            && !self.capabilities.has_float_render_texture()
+        {
+            bail!("Floating point render texture is not supported.");
+        }
+
+        let id = if let Some(sampler) = params.sampler {
             let mut id = 0;
             gl::GenTextures(1, &mut id);
             assert!(id != 0);
 
-            Self::bind_texture(&mut self.state, Some(Sampler::RenderTexture(handle)), 0, id)?;
-            Self::bind_texture_params(params.wrap, params.filter, 1)?;
+            Self::bind_texture(
+                &mut self.state,
+                Some(Sampler::RenderTexture(handle)),
+                0,
+                gl::TEXTURE_2D,
+                id,
+            )?;
+            Self::bind_texture_params(&self.capabilities, gl::TEXTURE_2D, &sampler, 1)?;
 
             let (internal_format, format, pixel_type) = params.format.into();
             gl::TexImage2D(
@@ -594,7 +720,7 @@ impl Visitor for GLVisitor {
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        if rt.params.sampler {
+        if rt.params.sampler.is_some() {
             for v in self.state.binded_textures.iter_mut() {
                 if *v == Some(Sampler::RenderTexture(handle)) {
                     *v = None;
@@ -615,6 +741,10 @@ impl Visitor for GLVisitor {
         params: MeshParams,
         data: Option<MeshData>,
     ) -> Result<()> {
+        if params.layout.extended() && !self.capabilities.has_extended_vertex_formats() {
+            bail!("Half float, packed and integer vertex formats are not supported.");
+        }
+
         let vbo = self.create_buffer(
             gl::ARRAY_BUFFER,
             params.hint,
@@ -688,24 +818,78 @@ impl Visitor for GLVisitor {
         Ok(())
     }
 
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        let prev = self
+            .meshes
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if let Err(err) = self.create_mesh(handle, params, data) {
+            self.meshes.create(handle, prev);
+            return Err(err);
+        }
+
+        self.free_mesh(prev)
+    }
+
     unsafe fn delete_mesh(&mut self, handle: MeshHandle) -> Result<()> {
         let mesh = self
             .meshes
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        // Removes deprecated `VertexArrayObject`s.
-        self.state.vaos.retain(|&(_, h), vao| {
-            if h == mesh.handle {
-                gl::DeleteVertexArrays(1, vao as *mut u32);
-                false
-            } else {
-                true
-            }
-        });
+        self.free_mesh(mesh)
+    }
 
-        gl::DeleteBuffers(1, &mesh.vbo);
-        gl::DeleteBuffers(1, &mesh.ibo);
+    unsafe fn create_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        params: UniformBlockParams,
+    ) -> Result<()> {
+        if !gl::BindBufferBase::is_loaded() {
+            bail!("The OpenGL implementation does not supports uniform blocks.");
+        }
+
+        let bytes = vec![0; params.layout.size()];
+        let id = self.create_buffer(gl::UNIFORM_BUFFER, MeshHint::Dynamic, bytes.len(), None)?;
+
+        let block = GLUniformBlockData {
+            handle,
+            id,
+            params,
+            bytes,
+        };
+
+        self.uniform_blocks.create(handle, block);
+        check()
+    }
+
+    unsafe fn update_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> Result<()> {
+        let block = self
+            .uniform_blocks
+            .get_mut(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        block.params.layout.encode(&mut block.bytes, variables)?;
+        Self::update_buffer(gl::UNIFORM_BUFFER, block.id, 0, &block.bytes)
+    }
+
+    unsafe fn delete_uniform_block(&mut self, handle: UniformBlockHandle) -> Result<()> {
+        let block = self
+            .uniform_blocks
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        gl::DeleteBuffers(1, &block.id);
         check()
     }
 
@@ -736,10 +920,14 @@ impl Visitor for GLVisitor {
         if !self.state.cleared_surfaces.contains(&handle) {
             // Sets depth write enable to make sure that we can clear depth buffer properly.
             if surface.params.clear_depth.is_some() {
-                self.state.binded_shader = None;
                 Self::set_depth_test(&mut self.state, true, Comparison::Always)?;
             }
 
+            // Resets the stencil write mask to make sure that we can clear stencil buffer.
+            if surface.params.clear_stencil.is_some() {
+                Self::set_stencil(&mut self.state, None)?;
+            }
+
             // Clears frame buffer.
             Self::clear(
                 surface.params.clear_color,
@@ -765,8 +953,10 @@ impl Visitor for GLVisitor {
     unsafe fn draw(
         &mut self,
         shader: ShaderHandle,
+        state: Option<RenderState>,
         mesh: MeshHandle,
         mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
         uniforms: &[UniformVar],
     ) -> Result<u32> {
         // Bind program and associated uniforms and textures.
@@ -776,20 +966,47 @@ impl Visitor for GLVisitor {
             .ok_or_else(|| format_err!("{:?} is invalid.", shader))?;
 
         Self::bind_shader(&mut self.state, &shader)?;
+        Self::set_render_state(&mut self.state, state.unwrap_or(shader.params.state))?;
 
-        let mut index = 0usize;
+        let (mut index, mut binding) = (0usize, 0);
         for &(field, variable) in uniforms {
             if let Some(tp) = shader.params.uniforms.variable_type(field) {
-                if tp != variable.variable_type() {
+                // The sampler type of texture depends on its dimension, and textures those
+                // are not ready yet are treated as compatible.
+                let vtp = match variable {
+                    UniformVariable::Texture(handle)
+                        if tp != UniformVariableType::RenderTexture =>
+                    {
+                        self.textures
+                            .get(handle)
+                            .map(|v| v.params.dimension.into())
+                            .unwrap_or(tp)
+                    }
+                    _ => variable.variable_type(),
+                };
+
+                if tp != vtp {
                     let name = shader.params.uniforms.variable_name(field).unwrap();
                     bail!(
                         "The uniform {} needs a {:?} instead of {:?}.",
                         name,
                         tp,
-                        variable.variable_type(),
+                        vtp,
                     );
                 }
 
+                if let UniformVariable::UniformBlock(handle) = variable {
+                    let location = shader.hash_uniform_block_index(field).unwrap();
+                    let id = self.uniform_blocks.get(handle).map(|v| v.id).unwrap_or(0);
+
+                    gl::UniformBlockBinding(shader.id, location as GLuint, binding);
+                    gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, id);
+                    check()?;
+
+                    binding += 1;
+                    continue;
+                }
+
                 let location = shader.hash_uniform_location(field).unwrap();
                 match variable {
                     UniformVariable::Texture(handle) => {
@@ -801,10 +1018,11 @@ impl Visitor for GLVisitor {
                                 &mut self.state,
                                 Some(Sampler::Texture(handle)),
                                 index,
+                                texture.params.dimension.into(),
                                 texture.id,
                             )?;
                         } else {
-                            Self::bind_texture(&mut self.state, None, index, 0)?;
+                            Self::bind_texture(&mut self.state, None, index, gl::TEXTURE_2D, 0)?;
                         }
 
                         index += 1;
@@ -814,7 +1032,7 @@ impl Visitor for GLVisitor {
                         Self::bind_uniform_variable(location, &v)?;
 
                         if let Some(texture) = self.render_textures.get(handle) {
-                            if !texture.params.sampler {
+                            if texture.params.sampler.is_none() {
                                 bail!("The render buffer does not have a sampler.");
                             }
 
@@ -822,10 +1040,11 @@ impl Visitor for GLVisitor {
                                 &mut self.state,
                                 Some(Sampler::RenderTexture(handle)),
                                 index,
+                                gl::TEXTURE_2D,
                                 texture.id,
                             )?;
                         } else {
-                            Self::bind_texture(&mut self.state, None, index, 0)?;
+                            Self::bind_texture(&mut self.state, None, index, gl::TEXTURE_2D, 0)?;
                         }
 
                         index += 1;
@@ -839,9 +1058,30 @@ impl Visitor for GLVisitor {
             }
         }
 
+        if instances.is_some()
+            && (!gl::DrawElementsInstanced::is_loaded() || !gl::VertexAttribDivisor::is_loaded())
+        {
+            bail!("The OpenGL implementation does not supports instanced drawing.");
+        }
+
+        let instance_mesh = match instances {
+            Some(MeshInstances {
+                mesh: Some(handle),
+                divisor,
+                ..
+            }) => {
+                let mesh = self
+                    .meshes
+                    .get(handle)
+                    .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+                Some((mesh, divisor.max(1)))
+            }
+            _ => None,
+        };
+
         if let Some(mesh) = self.meshes.get(mesh) {
             // Bind vertex buffer and vertex array object.
-            Self::bind_mesh(&mut self.state, &shader, &mesh)?;
+            Self::bind_mesh(&mut self.state, &shader, &mesh, instance_mesh)?;
 
             let (from, len) = match mesh_index {
                 MeshIndex::Ptr(from, len) => {
@@ -870,20 +1110,107 @@ impl Visitor for GLVisitor {
                 MeshIndex::All => (0, mesh.params.num_idxes),
             };
 
-            gl::DrawElements(
-                mesh.params.primitive.into(),
-                len as i32,
-                mesh.params.index_format.into(),
-                from as *const u32 as *const ::std::os::raw::c_void,
-            );
+            if let Some(instances) = instances {
+                gl::DrawElementsInstanced(
+                    mesh.params.primitive.into(),
+                    len as i32,
+                    mesh.params.index_format.into(),
+                    from as *const u32 as *const ::std::os::raw::c_void,
+                    instances.count as GLsizei,
+                );
 
-            check()?;
-            Ok(mesh.params.primitive.assemble(len as u32))
+                check()?;
+                Ok(mesh.params.primitive.assemble(len as u32) * instances.count)
+            } else {
+                gl::DrawElements(
+                    mesh.params.primitive.into(),
+                    len as i32,
+                    mesh.params.index_format.into(),
+                    from as *const u32 as *const ::std::os::raw::c_void,
+                );
+
+                check()?;
+                Ok(mesh.params.primitive.assemble(len as u32))
+            }
         } else {
             Ok(0)
         }
     }
 
+    unsafe fn read_surface(
+        &mut self,
+        handle: SurfaceHandle,
+        dimensions: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let surface = self
+            .surfaces
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if surface.id.is_some() && surface.params.colors[0].is_none() {
+            bail!("Trying to read pixels from surface without color attachment.");
+        }
+
+        let dimensions = surface.dimensions.unwrap_or(dimensions);
+        if area.max.x > dimensions.x || area.max.y > dimensions.y {
+            bail!("Trying to read pixels out of bounds.");
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, surface.id.unwrap_or(0));
+        let result = Self::read_pixels(area);
+        self.restore_framebuffer();
+        result
+    }
+
+    unsafe fn read_framebuffer(
+        &mut self,
+        dimensions: Vector2<u32>,
+    ) -> Result<(Vector2<u32>, Box<[u8]>)> {
+        let area = Aabb2::new(
+            Point2::new(0, 0),
+            Point2::new(dimensions.x, dimensions.y),
+        );
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        let result = Self::read_pixels(area);
+        self.restore_framebuffer();
+        Ok((dimensions, result?))
+    }
+
+    unsafe fn read_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let rt = *self
+            .render_textures
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if !rt.params.format.is_color() {
+            bail!("Trying to read pixels from depth render texture.");
+        }
+
+        if area.max.x > rt.params.dimensions.x || area.max.y > rt.params.dimensions.y {
+            bail!("Trying to read pixels out of bounds.");
+        }
+
+        // Attaches the render texture to a temporary framebuffer.
+        let mut id = 0;
+        gl::GenFramebuffers(1, &mut id);
+        assert!(id != 0);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+        let result = self
+            .update_framebuffer_render_texture(rt.id, rt.params, 0)
+            .and_then(|_| Self::read_pixels(area));
+
+        gl::DeleteFramebuffers(1, &id);
+        self.restore_framebuffer();
+        result
+    }
+
     unsafe fn flush(&mut self) -> Result<()> {
         if self.state.cleared_surfaces.is_empty() {
             Self::clear(Color::black(), None, None)?;
@@ -903,22 +1230,27 @@ impl GLVisitor {
         gl::UseProgram(shader.id);
         check()?;
 
-        let rs = shader.params.state;
+        state.binded_shader = Some(shader.handle);
+        Ok(())
+    }
+
+    /// Applies the render state of draw call, only the changed parts are submitted since
+    /// all the setters are backed by the state cache.
+    unsafe fn set_render_state(state: &mut GLMutableState, rs: RenderState) -> Result<()> {
         Self::set_cull_face(state, rs.cull_face)?;
         Self::set_front_face_order(state, rs.front_face_order)?;
         Self::set_depth_test(state, rs.depth_write, rs.depth_test)?;
         Self::set_depth_write_offset(state, rs.depth_write_offset)?;
         Self::set_color_blend(state, rs.color_blend)?;
         Self::set_color_write(state, rs.color_write)?;
-
-        state.binded_shader = Some(shader.handle);
-        Ok(())
+        Self::set_stencil(state, rs.stencil)
     }
 
     unsafe fn bind_uniform_variable(location: GLint, variable: &UniformVariable) -> Result<()> {
         match *variable {
             UniformVariable::Texture(_) => unreachable!(),
             UniformVariable::RenderTexture(_) => unreachable!(),
+            UniformVariable::UniformBlock(_) => unreachable!(),
             UniformVariable::I32(v) => gl::Uniform1i(location, v),
             UniformVariable::F32(v) => gl::Uniform1f(location, v),
             UniformVariable::Vector2f(v) => gl::Uniform2f(location, v[0], v[1]),
@@ -945,6 +1277,7 @@ impl GLVisitor {
         state: &mut GLMutableState,
         sampler: Option<Sampler>,
         index: usize,
+        target: GLenum,
         id: GLuint,
     ) -> Result<()> {
         if state.binded_texture_index != index {
@@ -958,7 +1291,7 @@ impl GLVisitor {
 
         if state.binded_textures[index] != sampler {
             state.binded_textures[index] = sampler;
-            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::BindTexture(target, id);
         }
 
         check()
@@ -968,10 +1301,16 @@ impl GLVisitor {
         state: &mut GLMutableState,
         shader: &GLShaderData,
         mesh: &GLMeshData,
+        instance_mesh: Option<(&GLMeshData, u32)>,
     ) -> Result<()> {
         assert!(state.binded_shader == Some(shader.handle));
 
-        let k = (shader.handle, mesh.handle);
+        let k = (
+            shader.handle,
+            mesh.handle,
+            instance_mesh.map(|(v, divisor)| (v.handle, divisor)),
+        );
+
         if state.binded_vao != Some(k) {
             if let Some(vao) = state.vaos.get(&k).cloned() {
                 gl::BindVertexArray(vao);
@@ -980,10 +1319,19 @@ impl GLVisitor {
                 let mut vao = 0;
                 gl::GenVertexArrays(1, &mut vao);
                 gl::BindVertexArray(vao);
-                gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
 
                 for (name, size, required) in shader.params.attributes.iter() {
-                    if let Some(element) = mesh.params.layout.element(name) {
+                    // Per-instance attributes take precedence over per-vertex ones.
+                    let stream = match instance_mesh {
+                        Some((v, divisor)) if v.params.layout.element(name).is_some() => {
+                            Some((v, divisor))
+                        }
+                        _ if mesh.params.layout.element(name).is_some() => Some((mesh, 0)),
+                        _ => None,
+                    };
+
+                    if let Some((mesh, divisor)) = stream {
+                        let element = mesh.params.layout.element(name).unwrap();
                         if element.size < size {
                             bail!(
                                 "Vertex buffer has incompatible attribute `{:?}` [{:?} - {:?}].",
@@ -997,15 +1345,32 @@ impl GLVisitor {
                         let stride = mesh.params.layout.stride();
 
                         let location = shader.attribute_location(name.into())?;
+                        gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
                         gl::EnableVertexAttribArray(location as GLuint);
-                        gl::VertexAttribPointer(
-                            location as GLuint,
-                            GLsizei::from(element.size),
-                            element.format.into(),
-                            element.normalized as u8,
-                            GLsizei::from(stride),
-                            offset as *const u8 as *const ::std::os::raw::c_void,
-                        );
+
+                        // Integer attributes are passed to shaders without conversion.
+                        if element.format.integer() {
+                            gl::VertexAttribIPointer(
+                                location as GLuint,
+                                GLsizei::from(element.size),
+                                element.format.into(),
+                                GLsizei::from(stride),
+                                offset as *const u8 as *const ::std::os::raw::c_void,
+                            );
+                        } else {
+                            gl::VertexAttribPointer(
+                                location as GLuint,
+                                GLsizei::from(element.size),
+                                element.format.into(),
+                                element.normalized as u8,
+                                GLsizei::from(stride),
+                                offset as *const u8 as *const ::std::os::raw::c_void,
+                            );
+                        }
+
+                        if divisor > 0 {
+                            gl::VertexAttribDivisor(location as GLuint, divisor);
+                        }
                     } else if required {
                         bail!(
                             "Can't find attribute {:?} description in vertex buffer.",
@@ -1048,6 +1413,10 @@ impl GLVisitor {
         gl::ColorMask(1, 1, 1, 1);
         state.render_state.color_write = (true, true, true, true);
 
+        gl::Disable(gl::STENCIL_TEST);
+        gl::StencilMask(0xFF);
+        state.render_state.stencil = None;
+
         gl::Disable(gl::SCISSOR_TEST);
         state.scissor = SurfaceScissor::Disable;
 
@@ -1205,6 +1574,36 @@ impl GLVisitor {
         Ok(())
     }
 
+    /// Enable or disable stencil test, and specify the test and operations of front- and
+    /// back-facing polygons.
+    unsafe fn set_stencil(state: &mut GLMutableState, stencil: Option<StencilState>) -> Result<()> {
+        let rs = &mut state.render_state;
+
+        if rs.stencil != stencil {
+            if let Some(v) = stencil {
+                if rs.stencil == None {
+                    gl::Enable(gl::STENCIL_TEST);
+                }
+
+                for &(face, s) in &[(gl::FRONT, v.front), (gl::BACK, v.back)] {
+                    let (reference, mask) = (GLint::from(s.reference), GLuint::from(s.read_mask));
+                    gl::StencilFuncSeparate(face, s.comparison.into(), reference, mask);
+                    gl::StencilOpSeparate(face, s.fail.into(), s.depth_fail.into(), s.pass.into());
+                    gl::StencilMaskSeparate(face, GLuint::from(s.write_mask));
+                }
+            } else {
+                gl::Disable(gl::STENCIL_TEST);
+                // The write mask also affects `glClear`.
+                gl::StencilMask(0xFF);
+            }
+
+            rs.stencil = stencil;
+            check()?;
+        }
+
+        Ok(())
+    }
+
     /// Set the scissor box relative to the top-lef corner of th window, in pixels.
     unsafe fn set_scissor(state: &mut GLMutableState, scissor: SurfaceScissor) -> Result<()> {
         match scissor {
@@ -1279,6 +1678,37 @@ impl GLVisitor {
 }
 
 impl GLVisitor {
+    unsafe fn read_pixels(area: Aabb2<u32>) -> Result<Box<[u8]>> {
+        let dimensions = area.dim();
+        let mut bytes = vec![0u8; area.volume() as usize * 4];
+
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            area.min.x as GLint,
+            area.min.y as GLint,
+            dimensions.x as GLsizei,
+            dimensions.y as GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            bytes.as_mut_ptr() as *mut ::std::os::raw::c_void,
+        );
+
+        check()?;
+        Ok(bytes.into_boxed_slice())
+    }
+
+    /// Rebinds the framebuffer of binded surface, after reading from another one.
+    unsafe fn restore_framebuffer(&self) {
+        let id = self
+            .state
+            .binded_surface
+            .and_then(|v| self.surfaces.get(v))
+            .and_then(|v| v.id)
+            .unwrap_or(0);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+    }
+
     unsafe fn update_framebuffer_render_texture(
         &self,
         id: GLuint,
@@ -1286,10 +1716,13 @@ impl GLVisitor {
         index: usize,
     ) -> Result<()> {
         match params.format {
-            RenderTextureFormat::RGB8 | RenderTextureFormat::RGBA4 | RenderTextureFormat::RGBA8 => {
+            RenderTextureFormat::RGB8
+            | RenderTextureFormat::RGBA4
+            | RenderTextureFormat::RGBA8
+            | RenderTextureFormat::RGBA16F => {
                 let location = gl::COLOR_ATTACHMENT0 + index as u32;
 
-                if params.sampler {
+                if params.sampler.is_some() {
                     gl::FramebufferTexture2D(gl::FRAMEBUFFER, location, gl::TEXTURE_2D, id, 0);
                 } else {
                     gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, location, gl::RENDERBUFFER, id);
@@ -1298,7 +1731,7 @@ impl GLVisitor {
             RenderTextureFormat::Depth16
             | RenderTextureFormat::Depth24
             | RenderTextureFormat::Depth32 => {
-                if params.sampler {
+                if params.sampler.is_some() {
                     gl::FramebufferTexture2D(
                         gl::FRAMEBUFFER,
                         gl::DEPTH_ATTACHMENT,
@@ -1316,7 +1749,7 @@ impl GLVisitor {
                 }
             }
             RenderTextureFormat::Depth24Stencil8 => {
-                if params.sampler {
+                if params.sampler.is_some() {
                     gl::FramebufferTexture2D(
                         gl::FRAMEBUFFER,
                         gl::DEPTH_STENCIL_ATTACHMENT,
@@ -1338,6 +1771,52 @@ impl GLVisitor {
         check()
     }
 
+    unsafe fn free_shader(&mut self, shader: GLShaderData) -> Result<()> {
+        // Removes deprecated `VertexArrayObject`s.
+        self.state.vaos.retain(|&(h, _, _), vao| {
+            if h == shader.handle {
+                gl::DeleteVertexArrays(1, vao as *mut u32);
+                false
+            } else {
+                true
+            }
+        });
+
+        if self.state.binded_shader == Some(shader.handle) {
+            self.state.binded_shader = None;
+        }
+
+        gl::DeleteProgram(shader.id);
+        check()
+    }
+
+    unsafe fn free_texture(&mut self, texture: GLTextureData) -> Result<()> {
+        for v in self.state.binded_textures.iter_mut() {
+            if *v == Some(Sampler::Texture(texture.handle)) {
+                *v = None;
+            }
+        }
+
+        gl::DeleteTextures(1, &texture.id);
+        check()
+    }
+
+    unsafe fn free_mesh(&mut self, mesh: GLMeshData) -> Result<()> {
+        // Removes deprecated `VertexArrayObject`s.
+        self.state.vaos.retain(|&(_, h, instance), vao| {
+            if h == mesh.handle || instance.map(|v| v.0) == Some(mesh.handle) {
+                gl::DeleteVertexArrays(1, vao as *mut u32);
+                false
+            } else {
+                true
+            }
+        });
+
+        gl::DeleteBuffers(1, &mesh.vbo);
+        gl::DeleteBuffers(1, &mesh.ibo);
+        check()
+    }
+
     unsafe fn compile(shader: GLenum, src: &str) -> Result<GLuint> {
         let shader = gl::CreateShader(shader);
         // Attempt to compile the shader
@@ -1436,44 +1915,158 @@ impl GLVisitor {
     }
 
     unsafe fn bind_texture_params(
-        wrap: TextureWrap,
-        filter: TextureFilter,
+        caps: &Capabilities,
+        target: GLenum,
+        sampler: &SamplerParams,
         levels: u32,
     ) -> Result<()> {
-        let wrap: GLenum = wrap.into();
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as GLint);
-
-        match filter {
-            TextureFilter::Nearest => {
-                let min_filter = if levels > 1 {
-                    gl::NEAREST_MIPMAP_NEAREST
-                } else {
-                    gl::NEAREST
-                };
+        // The `Border` wrap falls back to the edge if it's not supported.
+        let wrap = if sampler.wrap == TextureWrap::Border && !caps.has_texture_border_clamp() {
+            TextureWrap::Clamp
+        } else {
+            sampler.wrap
+        };
 
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
-            }
-            TextureFilter::Linear => {
-                let min_filter = if levels > 1 {
-                    gl::LINEAR_MIPMAP_LINEAR
-                } else {
-                    gl::LINEAR
-                };
+        let wrap_enum: GLenum = wrap.into();
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_S, wrap_enum as GLint);
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_T, wrap_enum as GLint);
 
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
-            }
+        if target == gl::TEXTURE_CUBE_MAP || target == gl::TEXTURE_3D {
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, wrap_enum as GLint);
         }
 
+        let mip_filter = if levels > 1 { sampler.mip_filter } else { None };
+        let min_filter = types::texture_min_filter(sampler.min_filter, mip_filter);
+        let mag_filter: GLenum = sampler.mag_filter.into();
+        gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+        gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, mag_filter as GLint);
+
         if levels > 1 {
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, (levels - 1) as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_BASE_LEVEL, 0);
+            gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, (levels - 1) as GLint);
+        }
+
+        // The LOD bias of texture object is only available on desktop OpenGL.
+        if sampler.lod_bias != 0.0 && caps.version >= Version::GL(1, 4) {
+            gl::TexParameterf(target, gl::TEXTURE_LOD_BIAS, sampler.lod_bias);
+        }
+
+        if wrap == TextureWrap::Border {
+            let color = sampler.border_color.rgba();
+            gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, color.as_ptr());
+        }
+
+        if let Some(cmp) = sampler.compare {
+            let func: GLenum = cmp.into();
+            let mode = gl::COMPARE_REF_TO_TEXTURE;
+            gl::TexParameteri(target, gl::TEXTURE_COMPARE_MODE, mode as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_COMPARE_FUNC, func as GLint);
+        }
+
+        if sampler.anisotropy > 1 && caps.max_anisotropy > 1.0 {
+            let anisotropy = f32::from(sampler.anisotropy).min(caps.max_anisotropy);
+            gl::TexParameterf(target, types::TEXTURE_MAX_ANISOTROPY, anisotropy);
         }
 
         Ok(())
     }
+
+    /// Specifies all the images of mipmap `level`, or allocates them if `data` is none.
+    /// The images of cube, array and 3D textures are stored continuously in `data`.
+    unsafe fn tex_image(
+        params: &TextureParams,
+        level: u32,
+        formats: (GLenum, GLenum, GLenum),
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        let (internal_format, format, pixel_type) = formats;
+        let dims = Vector2::new(
+            (params.dimensions.x >> level).max(1),
+            (params.dimensions.y >> level).max(1),
+        );
+
+        let images = params.images(level);
+        let compressed = params.format.compressed();
+
+        let ptr = |offset: usize| match data {
+            Some(v) => v[offset..].as_ptr() as *const ::std::os::raw::c_void,
+            None => ::std::ptr::null(),
+        };
+
+        match params.dimension {
+            TextureDimension::D2 | TextureDimension::Cube => {
+                let size = match (params.dimension, data) {
+                    (TextureDimension::D2, Some(v)) => v.len(),
+                    _ => params.format.size(dims) as usize,
+                };
+
+                for i in 0..images {
+                    let target = if params.dimension == TextureDimension::Cube {
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + i
+                    } else {
+                        gl::TEXTURE_2D
+                    };
+
+                    if compressed {
+                        gl::CompressedTexImage2D(
+                            target,
+                            level as GLint,
+                            internal_format,
+                            dims.x as GLsizei,
+                            dims.y as GLsizei,
+                            0,
+                            size as GLint,
+                            ptr(size * i as usize),
+                        );
+                    } else {
+                        gl::TexImage2D(
+                            target,
+                            level as GLint,
+                            internal_format as GLint,
+                            dims.x as GLsizei,
+                            dims.y as GLsizei,
+                            0,
+                            format,
+                            pixel_type,
+                            ptr(size * i as usize),
+                        );
+                    }
+                }
+            }
+            TextureDimension::D2Array | TextureDimension::D3 => {
+                let target: GLenum = params.dimension.into();
+                if compressed {
+                    let size = params.format.size(dims) * images;
+                    gl::CompressedTexImage3D(
+                        target,
+                        level as GLint,
+                        internal_format,
+                        dims.x as GLsizei,
+                        dims.y as GLsizei,
+                        images as GLsizei,
+                        0,
+                        size as GLint,
+                        ptr(0),
+                    );
+                } else {
+                    gl::TexImage3D(
+                        target,
+                        level as GLint,
+                        internal_format as GLint,
+                        dims.x as GLsizei,
+                        dims.y as GLsizei,
+                        images as GLsizei,
+                        0,
+                        format,
+                        pixel_type,
+                        ptr(0),
+                    );
+                }
+            }
+        }
+
+        check()
+    }
 }
 
 unsafe fn check_capabilities(caps: &Capabilities) -> Result<()> {
@@ -1,18 +1,40 @@
 use super::super::assets::prelude::*;
+use super::super::capabilities::Capabilities;
 use super::{UniformVar, Visitor};
 
 use crate::errors::*;
 use crate::math::prelude::{Aabb2, Vector2};
 
-pub struct HeadlessVisitor {}
+/// Discards every command. Resources which exceed the `Capabilities` are rejected like
+/// a real device would do.
+pub struct HeadlessVisitor {
+    capabilities: Capabilities,
+}
 
 impl HeadlessVisitor {
-    pub fn new() -> Self {
-        HeadlessVisitor {}
+    pub fn new(capabilities: Capabilities) -> Self {
+        HeadlessVisitor { capabilities }
+    }
+
+    fn check_dimensions(&self, dimensions: Vector2<u32>) -> Result<()> {
+        let max = self.capabilities.max_texture_size;
+        if dimensions.x > max || dimensions.y > max {
+            bail!(
+                "Dimensions {:?} exceeds the max texture size {}.",
+                dimensions,
+                max
+            );
+        }
+
+        Ok(())
     }
 }
 
 impl Visitor for HeadlessVisitor {
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
     unsafe fn create_surface(&mut self, _: SurfaceHandle, _: SurfaceParams) -> Result<()> {
         Ok(())
     }
@@ -22,6 +44,25 @@ impl Visitor for HeadlessVisitor {
     }
 
     unsafe fn create_shader(
+        &mut self,
+        _: ShaderHandle,
+        params: ShaderParams,
+        _: &str,
+        _: &str,
+    ) -> Result<()> {
+        let len = params.attributes.iter().count() as u32;
+        if len > self.capabilities.max_vertex_attributes {
+            bail!(
+                "Shader uses {} attributes, while at most {} are supported.",
+                len,
+                self.capabilities.max_vertex_attributes
+            );
+        }
+
+        Ok(())
+    }
+
+    unsafe fn reload_shader(
         &mut self,
         _: ShaderHandle,
         _: ShaderParams,
@@ -38,16 +79,35 @@ impl Visitor for HeadlessVisitor {
     unsafe fn create_texture(
         &mut self,
         _: TextureHandle,
-        _: TextureParams,
+        params: TextureParams,
         _: Option<TextureData>,
     ) -> Result<()> {
-        Ok(())
+        if !self.capabilities.support_texture_format(params.format) {
+            bail!("The texture format {:?} is not supported.", params.format);
+        }
+
+        self.check_dimensions(params.dimensions)
     }
 
-    unsafe fn update_texture(&mut self, _: TextureHandle, _: Aabb2<u32>, _: &[u8]) -> Result<()> {
+    unsafe fn update_texture(
+        &mut self,
+        _: TextureHandle,
+        _: u32,
+        _: Aabb2<u32>,
+        _: &[u8],
+    ) -> Result<()> {
         Ok(())
     }
 
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        self.create_texture(handle, params, data)
+    }
+
     unsafe fn delete_texture(&mut self, _: TextureHandle) -> Result<()> {
         Ok(())
     }
@@ -55,9 +115,19 @@ impl Visitor for HeadlessVisitor {
     unsafe fn create_render_texture(
         &mut self,
         _: RenderTextureHandle,
-        _: RenderTextureParams,
+        params: RenderTextureParams,
     ) -> Result<()> {
-        Ok(())
+        if !self
+            .capabilities
+            .support_render_texture_format(params.format)
+        {
+            bail!(
+                "The render texture format {:?} is not supported.",
+                params.format
+            );
+        }
+
+        self.check_dimensions(params.dimensions)
     }
 
     unsafe fn delete_render_texture(&mut self, _: RenderTextureHandle) -> Result<()> {
@@ -67,9 +137,13 @@ impl Visitor for HeadlessVisitor {
     unsafe fn create_mesh(
         &mut self,
         _: MeshHandle,
-        _: MeshParams,
+        params: MeshParams,
         _: Option<MeshData>,
     ) -> Result<()> {
+        if params.layout.extended() && !self.capabilities.extended_vertex_formats {
+            bail!("Half float, packed and integer vertex formats are not supported.");
+        }
+
         Ok(())
     }
 
@@ -81,10 +155,43 @@ impl Visitor for HeadlessVisitor {
         Ok(())
     }
 
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        self.create_mesh(handle, params, data)
+    }
+
     unsafe fn delete_mesh(&mut self, _: MeshHandle) -> Result<()> {
         Ok(())
     }
 
+    unsafe fn create_uniform_block(
+        &mut self,
+        _: UniformBlockHandle,
+        _: UniformBlockParams,
+    ) -> Result<()> {
+        if !self.capabilities.uniform_blocks {
+            bail!("Uniform blocks are not supported.");
+        }
+
+        Ok(())
+    }
+
+    unsafe fn update_uniform_block(
+        &mut self,
+        _: UniformBlockHandle,
+        _: &[UniformVar],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    unsafe fn delete_uniform_block(&mut self, _: UniformBlockHandle) -> Result<()> {
+        Ok(())
+    }
+
     unsafe fn bind(&mut self, _: SurfaceHandle, _: Vector2<u32>) -> Result<()> {
         Ok(())
     }
@@ -92,10 +199,16 @@ impl Visitor for HeadlessVisitor {
     unsafe fn draw(
         &mut self,
         _: ShaderHandle,
+        _: Option<RenderState>,
         _: MeshHandle,
         _: MeshIndex,
+        instances: Option<MeshInstances>,
         _: &[UniformVar],
     ) -> Result<u32> {
+        if instances.is_some() && !self.capabilities.instancing {
+            bail!("Instancing is not supported.");
+        }
+
         Ok(0)
     }
 
@@ -107,6 +220,31 @@ impl Visitor for HeadlessVisitor {
         Ok(())
     }
 
+    unsafe fn read_surface(
+        &mut self,
+        _: SurfaceHandle,
+        _: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        Ok(vec![0; area.volume() as usize * 4].into_boxed_slice())
+    }
+
+    unsafe fn read_framebuffer(
+        &mut self,
+        dimensions: Vector2<u32>,
+    ) -> Result<(Vector2<u32>, Box<[u8]>)> {
+        let len = (dimensions.x * dimensions.y) as usize * 4;
+        Ok((dimensions, vec![0; len].into_boxed_slice()))
+    }
+
+    unsafe fn read_render_texture(
+        &mut self,
+        _: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        Ok(vec![0; area.volume() as usize * 4].into_boxed_slice())
+    }
+
     unsafe fn flush(&mut self) -> Result<()> {
         Ok(())
     }
@@ -115,3 +253,55 @@ impl Visitor for HeadlessVisitor {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+
+    #[test]
+    fn capabilities() {
+        let mut visitor = HeadlessVisitor::new(Capabilities::minimal());
+        assert_eq!(visitor.capabilities(), Capabilities::minimal());
+
+        unsafe {
+            let mut params = TextureParams::default();
+            params.format = TextureFormat::RGBA8;
+            params.dimensions = (256, 256).into();
+            let handle = TextureHandle::new(1, 1);
+            assert!(visitor.create_texture(handle, params, None).is_ok());
+
+            params.dimensions = (4096, 4096).into();
+            assert!(visitor.create_texture(handle, params, None).is_err());
+
+            params.format = TextureFormat::Etc2RGB4BPP;
+            params.dimensions = (256, 256).into();
+            assert!(visitor.create_texture(handle, params, None).is_err());
+
+            let mut params = RenderTextureParams::default();
+            params.format = RenderTextureFormat::RGBA16F;
+            let handle = RenderTextureHandle::new(1, 1);
+            assert!(visitor.create_render_texture(handle, params).is_err());
+
+            let handle = UniformBlockHandle::new(1, 1);
+            let params = UniformBlockParams::default();
+            assert!(visitor.create_uniform_block(handle, params).is_err());
+
+            let mut params = MeshParams::default();
+            params.layout = VertexLayout::build()
+                .with(Attribute::Position, VertexFormat::HalfFloat, 4, false)
+                .finish();
+            let handle = MeshHandle::new(1, 1);
+            assert!(visitor.create_mesh(handle, params.clone(), None).is_err());
+            assert!(visitor.reload_mesh(handle, params, None).is_err());
+        }
+
+        let mut visitor = HeadlessVisitor::new(Capabilities::default());
+        unsafe {
+            let mut params = TextureParams::default();
+            params.format = TextureFormat::Etc2RGB4BPP;
+            let handle = TextureHandle::new(1, 1);
+            assert!(visitor.create_texture(handle, params, None).is_ok());
+        }
+    }
+}
@@ -3,9 +3,14 @@
 
 pub mod frame;
 pub mod headless;
+pub mod software;
+pub mod trace;
+pub mod validation;
 mod utils;
 
 use super::assets::prelude::*;
+use super::capabilities::Capabilities;
+use super::VideoParams;
 
 use crate::errors::*;
 use crate::math::prelude::{Aabb2, Vector2};
@@ -14,6 +19,9 @@ use crate::utils::hash_value::HashValue;
 pub type UniformVar = (HashValue<str>, UniformVariable);
 
 pub trait Visitor {
+    /// Gets the features and limits of this backend.
+    fn capabilities(&self) -> Capabilities;
+
     unsafe fn create_surface(&mut self, handle: SurfaceHandle, params: SurfaceParams)
         -> Result<()>;
 
@@ -27,6 +35,16 @@ pub trait Visitor {
         fs: &str,
     ) -> Result<()>;
 
+    /// Replaces the shader behind `handle` with new sources. The previous one should be
+    /// kept if the new sources could not be compiled or linked.
+    unsafe fn reload_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()>;
+
     unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()>;
 
     unsafe fn create_texture(
@@ -36,13 +54,26 @@ pub trait Visitor {
         bytes: Option<TextureData>,
     ) -> Result<()>;
 
+    /// Updates a subregion of texture. The `layer` is the index of face of cube texture,
+    /// layer of array texture or depth slice of 3D texture, which should be zero for 2D
+    /// texture.
     unsafe fn update_texture(
         &mut self,
         handle: TextureHandle,
+        layer: u32,
         area: Aabb2<u32>,
         bytes: &[u8],
     ) -> Result<()>;
 
+    /// Replaces the texture behind `handle` with new one. The previous one should be kept
+    /// if the new one could not be created.
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        bytes: Option<TextureData>,
+    ) -> Result<()>;
+
     unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()>;
 
     unsafe fn create_render_texture(
@@ -74,15 +105,45 @@ pub trait Visitor {
         bytes: &[u8],
     ) -> Result<()>;
 
+    /// Replaces the mesh behind `handle` with new one. The previous one should be kept if
+    /// the new one could not be created.
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        ps: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()>;
+
     unsafe fn delete_mesh(&mut self, handle: MeshHandle) -> Result<()>;
 
+    unsafe fn create_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        params: UniformBlockParams,
+    ) -> Result<()>;
+
+    /// Updates the variables of uniform block, the others keep unchanged.
+    unsafe fn update_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> Result<()>;
+
+    unsafe fn delete_uniform_block(&mut self, handle: UniformBlockHandle) -> Result<()>;
+
     unsafe fn bind(&mut self, surface: SurfaceHandle, dimensions: Vector2<u32>) -> Result<()>;
 
+    /// Draws the mesh, or `instances.count` instances of it. Returns the number of
+    /// primitives that assembled.
+    ///
+    /// The render state of shader is replaced by `state` in this draw call if specified.
     unsafe fn draw(
         &mut self,
         shader: ShaderHandle,
+        state: Option<RenderState>,
         mesh: MeshHandle,
         mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
         vars: &[UniformVar],
     ) -> Result<u32>;
 
@@ -90,6 +151,32 @@ pub trait Visitor {
 
     unsafe fn update_surface_viewport(&mut self, vp: SurfaceViewport) -> Result<()>;
 
+    /// Reads a block of pixels from the first color attachment of surface, or the default
+    /// framebuffer if the surface has no attachment. The bytes are tightly packed RGBA8,
+    /// and rows are ordered from bottom to top.
+    unsafe fn read_surface(
+        &mut self,
+        surface: SurfaceHandle,
+        dimensions: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>>;
+
+    /// Reads the whole default framebuffer, whose dimensions are `dimensions` unless the
+    /// backend owns it. Returns the real dimensions and tightly packed RGBA8 bytes, rows are
+    /// ordered from bottom to top.
+    unsafe fn read_framebuffer(
+        &mut self,
+        dimensions: Vector2<u32>,
+    ) -> Result<(Vector2<u32>, Box<[u8]>)>;
+
+    /// Reads a block of pixels from the color render texture. The bytes are tightly packed
+    /// RGBA8, and rows are ordered from bottom to top.
+    unsafe fn read_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>>;
+
     /// Blocks until all execution is complete. Such effects include all changes to render state, all
     /// changes to connection state, and all changes to the frame buffer contents.
     unsafe fn flush(&mut self) -> Result<()>;
@@ -102,20 +189,34 @@ pub trait Visitor {
 pub mod gl;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn new() -> Result<Box<Visitor>> {
+pub fn new(params: VideoParams) -> Result<Box<Visitor>> {
     let visitor = unsafe { self::gl::visitor::GLVisitor::new()? };
-    Ok(Box::new(visitor))
+    Ok(wrap(visitor, &params))
 }
 
 #[cfg(target_arch = "wasm32")]
 pub mod webgl;
 
 #[cfg(target_arch = "wasm32")]
-pub fn new() -> Result<Box<Visitor>> {
+pub fn new(params: VideoParams) -> Result<Box<Visitor>> {
     let visitor = unsafe { webgl::visitor::WebGLVisitor::new()? };
-    Ok(Box::new(visitor))
+    Ok(wrap(visitor, &params))
+}
+
+pub fn new_headless(params: VideoParams) -> Box<Visitor> {
+    match params.software {
+        Some(ref v) => wrap(self::software::SoftwareVisitor::new(v.clone()), &params),
+        None => {
+            let capabilities = params.capabilities.clone().unwrap_or_default();
+            wrap(self::headless::HeadlessVisitor::new(capabilities), &params)
+        }
+    }
 }
 
-pub fn new_headless() -> Box<Visitor> {
-    Box::new(self::headless::HeadlessVisitor::new())
+fn wrap<T: Visitor + 'static>(visitor: T, params: &VideoParams) -> Box<Visitor> {
+    if params.validation {
+        Box::new(self::validation::ValidationVisitor::new(visitor))
+    } else {
+        Box::new(visitor)
+    }
 }
@@ -0,0 +1,1924 @@
+//! A software rasterizer that implements `Visitor` on top of CPU-side color and depth
+//! buffers. It makes draw calls observable without any GPU, so the output of a frame
+//! could be checked pixel by pixel in headless mode.
+//!
+//! GLSL sources could not be executed here, so every shader is backed by a
+//! `SoftwareProgram` that registered in `SoftwareParams::programs` with the same
+//! sources. Shaders without registered program fall back to `FixedFunctionProgram`.
+//!
+//! Only triangle primitives are rasterized, points and lines are counted but never
+//! make into the framebuffer. There is no stencil buffer either, `RenderState::stencil`
+//! is ignored.
+
+mod program;
+mod raster;
+
+pub use self::program::{
+    FixedFunctionProgram, SoftwareContext, SoftwareProgram, SoftwarePrograms, SoftwareVertex,
+    Varyings, MAX_VARYINGS,
+};
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use cgmath::Point2;
+
+use crate::errors::*;
+use crate::math::prelude::{Aabb2, Color, Vector2, Vector4};
+use crate::utils::prelude::FastHashSet;
+
+use super::super::assets::mesh::read_vertex_attribute;
+use super::super::assets::prelude::*;
+use super::super::capabilities::Capabilities;
+use super::utils::DataVec;
+use super::{UniformVar, Visitor};
+
+use self::raster::{
+    ClipVertex, Image, Pipeline, RenderTextureImage, Sampler, Target, TextureImage,
+};
+
+/// The setup parameters of software rasterizer.
+#[derive(Debug, Clone)]
+pub struct SoftwareParams {
+    /// The dimensions of the default framebuffer in pixels.
+    pub dimensions: Vector2<u32>,
+    /// The `SoftwareProgram`s that stand in for the shaders.
+    pub programs: SoftwarePrograms,
+}
+
+impl Default for SoftwareParams {
+    fn default() -> Self {
+        SoftwareParams {
+            dimensions: Vector2::new(640, 320),
+            programs: SoftwarePrograms::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SoftwareSurfaceData {
+    params: SurfaceParams,
+    dimensions: Option<Vector2<u32>>,
+}
+
+#[derive(Clone)]
+struct SoftwareShaderData {
+    params: ShaderParams,
+    program: Arc<dyn SoftwareProgram>,
+}
+
+#[derive(Debug, Clone)]
+struct SoftwareMeshData {
+    params: MeshParams,
+    vertices: Vec<u8>,
+    indices: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct SoftwareUniformBlockData {
+    params: UniformBlockParams,
+    variables: Vec<UniformVar>,
+}
+
+/// The borrowed resources of a draw call, which are shared by all its instances.
+struct SoftwareDrawCall<'a> {
+    state: RenderState,
+    shader: &'a SoftwareShaderData,
+    mesh: &'a SoftwareMeshData,
+    uniforms: &'a [UniformVar],
+}
+
+pub struct SoftwareVisitor {
+    programs: SoftwarePrograms,
+    color: Image,
+    depth: Image,
+    surfaces: DataVec<SoftwareSurfaceData>,
+    shaders: DataVec<SoftwareShaderData>,
+    meshes: DataVec<SoftwareMeshData>,
+    textures: DataVec<TextureImage>,
+    render_textures: DataVec<RenderTextureImage>,
+    uniform_blocks: DataVec<SoftwareUniformBlockData>,
+    cleared_surfaces: FastHashSet<SurfaceHandle>,
+    binded_surface: Option<SurfaceHandle>,
+    viewport: SurfaceViewport,
+    scissor: SurfaceScissor,
+}
+
+impl SoftwareVisitor {
+    pub fn new(params: SoftwareParams) -> Self {
+        SoftwareVisitor {
+            programs: params.programs,
+            color: Image::new(params.dimensions, Vector4::new(0.0, 0.0, 0.0, 1.0)),
+            depth: Image::new(params.dimensions, Vector4::new(1.0, 1.0, 1.0, 1.0)),
+            surfaces: DataVec::new(),
+            shaders: DataVec::new(),
+            meshes: DataVec::new(),
+            textures: DataVec::new(),
+            render_textures: DataVec::new(),
+            uniform_blocks: DataVec::new(),
+            cleared_surfaces: FastHashSet::default(),
+            binded_surface: None,
+            viewport: SurfaceViewport {
+                position: Vector2::new(0, 0),
+                size: params.dimensions,
+            },
+            scissor: SurfaceScissor::Disable,
+        }
+    }
+
+    /// Gets the dimensions of default framebuffer.
+    #[inline]
+    pub fn dimensions(&self) -> Vector2<u32> {
+        self.color.dimensions
+    }
+
+    /// Reads the color of pixel at `(x, y)` in the default framebuffer, the origin is
+    /// the lower left corner.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Color<f32>> {
+        if x >= self.color.dimensions.x || y >= self.color.dimensions.y {
+            return None;
+        }
+
+        let v = self.color.texels[self.color.index(x, y)];
+        Some(Color::new(v.x, v.y, v.z, v.w))
+    }
+
+    /// Reads the color of texel at `(x, y)` in the render texture.
+    pub fn render_texture_pixel(
+        &self,
+        handle: RenderTextureHandle,
+        x: u32,
+        y: u32,
+    ) -> Option<Color<f32>> {
+        let rt = self.render_textures.get(handle)?;
+        if x >= rt.image.dimensions.x || y >= rt.image.dimensions.y {
+            return None;
+        }
+
+        let v = rt.image.texels[rt.image.index(x, y)];
+        Some(Color::new(v.x, v.y, v.z, v.w))
+    }
+
+    /// Reads the default framebuffer as tightly packed RGBA8 bytes, rows are ordered
+    /// from bottom to top.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let dimensions = Point2::new(self.color.dimensions.x, self.color.dimensions.y);
+        let area = Aabb2::new(Point2::new(0, 0), dimensions);
+        self.color.read(area).into_vec()
+    }
+
+    fn read_image(image: &Image, area: Aabb2<u32>) -> Result<Box<[u8]>> {
+        if area.max.x > image.dimensions.x || area.max.y > image.dimensions.y {
+            bail!("Trying to read pixels out of bounds.");
+        }
+
+        Ok(image.read(area))
+    }
+
+    fn clear(&mut self, handle: SurfaceHandle) -> Result<()> {
+        let surface = self
+            .surfaces
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?
+            .clone();
+
+        let color = surface
+            .params
+            .clear_color
+            .map(|v| Vector4::new(v.r, v.g, v.b, v.a));
+        let depth = surface.params.clear_depth.map(|v| {
+            let v = v.max(0.0).min(1.0);
+            Vector4::new(v, v, v, 1.0)
+        });
+
+        if surface.dimensions.is_none() {
+            if let Some(v) = color {
+                self.color
+                    .fill(raster::quantize(RenderTextureFormat::RGBA8, v));
+            }
+
+            if let Some(v) = depth {
+                self.depth.fill(v);
+            }
+
+            return Ok(());
+        }
+
+        for attachment in surface.params.colors.iter() {
+            if let (Some(handle), Some(v)) = (*attachment, color) {
+                if let Some(rt) = self.render_textures.get_mut(handle) {
+                    rt.image.fill(raster::quantize(rt.params.format, v));
+                }
+            }
+        }
+
+        if let (Some(handle), Some(v)) = (surface.params.depth_stencil, depth) {
+            if let Some(rt) = self.render_textures.get_mut(handle) {
+                rt.image.fill(v);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_texture_mut(&mut self, handle: RenderTextureHandle) -> Option<&mut Image> {
+        self.render_textures.get_mut(handle).map(|v| &mut v.image)
+    }
+
+    /// Takes the color and depth buffers of binded surface out.
+    fn take_target(&mut self) -> Target {
+        let surface = self
+            .binded_surface
+            .and_then(|v| self.surfaces.get(v))
+            .map(|v| v.params);
+
+        match surface {
+            Some(params) if params.colors[0].is_some() || params.depth_stencil.is_some() => {
+                let mut target = Target::default();
+
+                if let Some(handle) = params.colors[0] {
+                    let format = self.render_textures.get(handle).map(|v| v.params.format);
+                    if let (Some(format), Some(image)) = (format, self.render_texture_mut(handle)) {
+                        target.color = Some((format, ::std::mem::replace(image, Image::default())));
+                    }
+                }
+
+                if let Some(handle) = params.depth_stencil {
+                    if let Some(image) = self.render_texture_mut(handle) {
+                        target.depth = Some(::std::mem::replace(image, Image::default()));
+                    }
+                }
+
+                target
+            }
+            _ => Target {
+                color: Some((
+                    RenderTextureFormat::RGBA8,
+                    ::std::mem::replace(&mut self.color, Image::default()),
+                )),
+                depth: Some(::std::mem::replace(&mut self.depth, Image::default())),
+            },
+        }
+    }
+
+    /// Puts the color and depth buffers back into binded surface.
+    fn restore_target(&mut self, target: Target) {
+        let surface = self
+            .binded_surface
+            .and_then(|v| self.surfaces.get(v))
+            .map(|v| v.params);
+
+        match surface {
+            Some(params) if params.colors[0].is_some() || params.depth_stencil.is_some() => {
+                if let (Some(handle), Some((_, image))) = (params.colors[0], target.color) {
+                    if let Some(v) = self.render_texture_mut(handle) {
+                        *v = image;
+                    }
+                }
+
+                if let (Some(handle), Some(image)) = (params.depth_stencil, target.depth) {
+                    if let Some(v) = self.render_texture_mut(handle) {
+                        *v = image;
+                    }
+                }
+            }
+            _ => {
+                if let Some((_, image)) = target.color {
+                    self.color = image;
+                }
+
+                if let Some(image) = target.depth {
+                    self.depth = image;
+                }
+            }
+        }
+    }
+
+    fn rasterize(
+        &self,
+        target: &mut Target,
+        call: &SoftwareDrawCall,
+        instance: Option<&SoftwareVertex>,
+        indices: Range<usize>,
+    ) -> Result<()> {
+        let (shader, mesh, uniforms) = (call.shader, call.mesh, call.uniforms);
+        let sampler = Sampler {
+            textures: &self.textures,
+            render_textures: &self.render_textures,
+        };
+
+        let pipeline = Pipeline {
+            state: call.state,
+            viewport: self.viewport,
+            scissor: self.scissor,
+            program: shader.program.as_ref(),
+            uniforms,
+            sampler: &sampler,
+        };
+
+        let ctx = SoftwareContext {
+            uniforms,
+            sampler: &sampler,
+        };
+
+        let (from, len) = (indices.start, indices.len());
+        let mut cache: Vec<Option<ClipVertex>> = vec![None; mesh.params.num_verts];
+        let mut fetch = |i: usize| -> Result<ClipVertex> {
+            let index = Self::index(mesh, from + i);
+            if let Some(v) = cache.get(index).and_then(|v| *v) {
+                return Ok(v);
+            }
+
+            if index >= mesh.params.num_verts {
+                bail!("Vertex index {} is out of bounds.", index);
+            }
+
+            let mut vertex = Self::vertex(shader, mesh, index);
+
+            // Per-instance attributes take precedence over per-vertex ones.
+            if let Some(instance) = instance {
+                for (v, &attribute) in vertex.attributes.iter_mut().zip(&instance.attributes) {
+                    if attribute.is_some() {
+                        *v = attribute;
+                    }
+                }
+            }
+
+            let mut varyings = [0.0; MAX_VARYINGS];
+            let position = shader.program.vertex(&ctx, &vertex, &mut varyings);
+            let v = ClipVertex { position, varyings };
+            cache[index] = Some(v);
+            Ok(v)
+        };
+
+        match mesh.params.primitive {
+            MeshPrimitive::Triangles => {
+                for i in 0..len / 3 {
+                    let a = fetch(i * 3)?;
+                    let b = fetch(i * 3 + 1)?;
+                    let c = fetch(i * 3 + 2)?;
+                    pipeline.triangle(target, [&a, &b, &c])?;
+                }
+            }
+            MeshPrimitive::TriangleStrip => {
+                for i in 0..len.saturating_sub(2) {
+                    let a = fetch(i)?;
+                    let b = fetch(i + 1)?;
+                    let c = fetch(i + 2)?;
+
+                    // Keeps the winding order of odd triangles in strip.
+                    if i % 2 == 0 {
+                        pipeline.triangle(target, [&a, &b, &c])?;
+                    } else {
+                        pipeline.triangle(target, [&b, &a, &c])?;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn index(mesh: &SoftwareMeshData, i: usize) -> usize {
+        let stride = mesh.params.index_format.stride();
+        let v = &mesh.indices[i * stride..(i + 1) * stride];
+        match mesh.params.index_format {
+            IndexFormat::U16 => (u16::from(v[0]) | (u16::from(v[1]) << 8)) as usize,
+            IndexFormat::U32 => {
+                (u32::from(v[0])
+                    | (u32::from(v[1]) << 8)
+                    | (u32::from(v[2]) << 16)
+                    | (u32::from(v[3]) << 24)) as usize
+            }
+        }
+    }
+
+    fn vertex(
+        shader: &SoftwareShaderData,
+        mesh: &SoftwareMeshData,
+        index: usize,
+    ) -> SoftwareVertex {
+        let layout = &mesh.params.layout;
+        let base = index * layout.stride() as usize;
+
+        let mut vertex = SoftwareVertex::default();
+        for (name, _, _) in shader.params.attributes.iter() {
+            if let Some(element) = layout.element(name) {
+                let offset = base + layout.offset(name).unwrap() as usize;
+                let bytes = &mesh.vertices[offset..];
+
+                let mut v = read_vertex_attribute(bytes, element);
+                if element.size < 4 {
+                    v[3] = 1.0;
+                }
+
+                vertex.attributes[name as usize] = Some(Vector4::new(v[0], v[1], v[2], v[3]));
+            }
+        }
+
+        vertex
+    }
+}
+
+impl Visitor for SoftwareVisitor {
+    fn capabilities(&self) -> Capabilities {
+        // Surfaces only have one color buffer, and compressed textures are not decoded.
+        Capabilities {
+            max_color_attachments: 1,
+            max_anisotropy: 1.0,
+            texture_formats: Capabilities::texture_formats(|v| !v.compressed()),
+            ..Capabilities::default()
+        }
+    }
+
+    unsafe fn advance(&mut self) -> Result<()> {
+        self.cleared_surfaces.clear();
+        self.binded_surface = None;
+        Ok(())
+    }
+
+    unsafe fn create_surface(
+        &mut self,
+        handle: SurfaceHandle,
+        params: SurfaceParams,
+    ) -> Result<()> {
+        let mut dimensions = None;
+
+        for attachment in params.colors.iter() {
+            if let Some(v) = *attachment {
+                let rt = self
+                    .render_textures
+                    .get(v)
+                    .ok_or_else(|| format_err!("RenderTexture handle {:?} is invalid.", v))?;
+
+                if !rt.params.format.is_color() {
+                    bail!(
+                        "Incompitable(mismatch format) attachments of SurfaceObject {:?}",
+                        handle
+                    );
+                }
+
+                if dimensions.is_some() && dimensions != Some(rt.params.dimensions) {
+                    bail!(
+                        "Incompitable(mismatch dimensons) attachments of SurfaceObject {:?}",
+                        handle
+                    );
+                }
+
+                dimensions = Some(rt.params.dimensions);
+            }
+        }
+
+        if let Some(v) = params.depth_stencil {
+            let rt = self
+                .render_textures
+                .get(v)
+                .ok_or_else(|| format_err!("RenderTexture handle {:?} is invalid.", v))?;
+
+            if rt.params.format.is_color() {
+                bail!(
+                    "Incompitable(mismatch format) attachments of SurfaceObject {:?}",
+                    handle
+                );
+            }
+
+            if dimensions.is_some() && dimensions != Some(rt.params.dimensions) {
+                bail!(
+                    "Incompitable(mismatch dimensions) attachments of SurfaceObject {:?}",
+                    handle
+                );
+            }
+
+            dimensions = Some(rt.params.dimensions);
+        }
+
+        self.surfaces
+            .create(handle, SoftwareSurfaceData { params, dimensions });
+        Ok(())
+    }
+
+    unsafe fn delete_surface(&mut self, handle: SurfaceHandle) -> Result<()> {
+        self.surfaces
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if self.binded_surface == Some(handle) {
+            self.binded_surface = None;
+        }
+
+        Ok(())
+    }
+
+    unsafe fn create_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        let program = self
+            .programs
+            .find(vs, fs)
+            .unwrap_or_else(|| Arc::new(FixedFunctionProgram));
+
+        self.shaders
+            .create(handle, SoftwareShaderData { params, program });
+        Ok(())
+    }
+
+    unsafe fn reload_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        if self.shaders.get(handle).is_none() {
+            bail!("{:?} is invalid.", handle);
+        }
+
+        self.create_shader(handle, params, vs, fs)
+    }
+
+    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
+        self.shaders
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        Ok(())
+    }
+
+    unsafe fn create_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        if params.format.compressed() {
+            bail!(
+                "The software rasterizer does not support the texture format {:?}.",
+                params.format
+            );
+        }
+
+        let image = Image::new(params.dimensions, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        let mut levels = vec![vec![image; params.images(0) as usize]];
+
+        if let Some(data) = data {
+            // Only the base level of 3D texture is sampled.
+            let len = if params.dimension == TextureDimension::D3 {
+                1
+            } else {
+                data.bytes.len()
+            };
+
+            for (i, bytes) in data.bytes.iter().take(len).enumerate() {
+                let dimensions = Vector2::new(
+                    (params.dimensions.x >> i).max(1),
+                    (params.dimensions.y >> i).max(1),
+                );
+
+                if i > 0 {
+                    let image = Image::new(dimensions, Vector4::new(0.0, 0.0, 0.0, 1.0));
+                    levels.push(vec![image; params.images(i as u32) as usize]);
+                }
+
+                let size = params.format.size(dimensions) as usize;
+                for (image, v) in levels[i].iter_mut().zip(bytes.chunks(size.max(1))) {
+                    raster::decode(params.format, v, &mut image.texels)?;
+                }
+            }
+        }
+
+        let mut texture = TextureImage { params, levels };
+        texture.generate_mipmaps();
+
+        self.textures.create(handle, texture);
+        Ok(())
+    }
+
+    unsafe fn update_texture(
+        &mut self,
+        handle: TextureHandle,
+        layer: u32,
+        area: Aabb2<u32>,
+        data: &[u8],
+    ) -> Result<()> {
+        let params = self
+            .textures
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?
+            .params;
+
+        if params.hint == TextureHint::Immutable {
+            bail!("Trying to update immutable texture.");
+        }
+
+        if params.format.compressed() {
+            bail!("Trying to update compressed texture.");
+        }
+
+        if data.len() > params.format.size(area.dim()) as usize
+            || area.min.x >= params.dimensions.x
+            || area.min.y >= params.dimensions.y
+            || layer >= params.images(0)
+        {
+            bail!("Trying to update texture data out of bounds.");
+        }
+
+        let dim = area.dim();
+        let mut texels = vec![Vector4::new(0.0, 0.0, 0.0, 1.0); (dim.x * dim.y) as usize];
+        raster::decode(params.format, data, &mut texels)?;
+
+        let texture = self.textures.get_mut(handle).unwrap();
+        let image = &mut texture.levels[0][layer as usize];
+
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                let (tx, ty) = (area.min.x + x, area.min.y + y);
+                if tx < params.dimensions.x && ty < params.dimensions.y {
+                    let index = image.index(tx, ty);
+                    image.texels[index] = texels[(y * dim.x + x) as usize];
+                }
+            }
+        }
+
+        texture.generate_mipmaps();
+        Ok(())
+    }
+
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        if self.textures.get(handle).is_none() {
+            bail!("{:?} is invalid.", handle);
+        }
+
+        self.create_texture(handle, params, data)
+    }
+
+    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
+        self.textures
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        Ok(())
+    }
+
+    unsafe fn create_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        params: RenderTextureParams,
+    ) -> Result<()> {
+        let value = if params.format.is_color() {
+            Vector4::new(0.0, 0.0, 0.0, 1.0)
+        } else {
+            Vector4::new(1.0, 1.0, 1.0, 1.0)
+        };
+
+        let image = Image::new(params.dimensions, value);
+        self.render_textures
+            .create(handle, RenderTextureImage { params, image });
+        Ok(())
+    }
+
+    unsafe fn delete_render_texture(&mut self, handle: RenderTextureHandle) -> Result<()> {
+        self.render_textures
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        Ok(())
+    }
+
+    unsafe fn create_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        params.validate(data.as_ref())?;
+
+        let mut vertices = vec![0; params.vertex_buffer_len()];
+        let mut indices = vec![0; params.index_buffer_len()];
+
+        if let Some(data) = data {
+            vertices[..data.vptr.len()].copy_from_slice(&data.vptr);
+            indices[..data.iptr.len()].copy_from_slice(&data.iptr);
+        }
+
+        self.meshes.create(
+            handle,
+            SoftwareMeshData {
+                params,
+                vertices,
+                indices,
+            },
+        );
+
+        Ok(())
+    }
+
+    unsafe fn update_vertex_buffer(
+        &mut self,
+        handle: MeshHandle,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let mesh = self
+            .meshes
+            .get_mut(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        if mesh.params.hint == MeshHint::Immutable {
+            bail!("Trying to update immutable buffer");
+        }
+
+        if offset + data.len() > mesh.vertices.len() {
+            bail!("Trying to update buffer out of bounds.");
+        }
+
+        mesh.vertices[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    unsafe fn update_index_buffer(
+        &mut self,
+        handle: MeshHandle,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let mesh = self
+            .meshes
+            .get_mut(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        if mesh.params.hint == MeshHint::Immutable {
+            bail!("Trying to update immutable buffer");
+        }
+
+        if offset + data.len() > mesh.indices.len() {
+            bail!("Trying to update buffer out of bounds.");
+        }
+
+        mesh.indices[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        if self.meshes.get(handle).is_none() {
+            bail!("{:?} is invalid.", handle);
+        }
+
+        self.create_mesh(handle, params, data)
+    }
+
+    unsafe fn delete_mesh(&mut self, handle: MeshHandle) -> Result<()> {
+        self.meshes
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        Ok(())
+    }
+
+    unsafe fn create_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        params: UniformBlockParams,
+    ) -> Result<()> {
+        let block = SoftwareUniformBlockData {
+            params,
+            variables: Vec::new(),
+        };
+
+        self.uniform_blocks.create(handle, block);
+        Ok(())
+    }
+
+    unsafe fn update_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> Result<()> {
+        let block = self
+            .uniform_blocks
+            .get_mut(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        for &(field, variable) in variables {
+            match block.params.layout.variable_type(field) {
+                Some(tp) if tp == variable.variable_type() => {}
+                Some(tp) => {
+                    let name = block.params.layout.variable_name(field).unwrap();
+                    bail!(
+                        "The uniform {} needs a {:?} instead of {:?}.",
+                        name,
+                        tp,
+                        variable.variable_type(),
+                    );
+                }
+                None => bail!("Undefined uniform field {:?}.", field),
+            }
+
+            match block.variables.iter().position(|v| v.0 == field) {
+                Some(index) => block.variables[index] = (field, variable),
+                None => block.variables.push((field, variable)),
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn delete_uniform_block(&mut self, handle: UniformBlockHandle) -> Result<()> {
+        self.uniform_blocks
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+        Ok(())
+    }
+
+    unsafe fn bind(&mut self, handle: SurfaceHandle, _: Vector2<u32>) -> Result<()> {
+        if self.binded_surface == Some(handle) {
+            return Ok(());
+        }
+
+        let surface = self
+            .surfaces
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        // The dimensions of window are ignored, since the default framebuffer is owned
+        // by the rasterizer itself.
+        let dimensions = surface.dimensions.unwrap_or(self.color.dimensions);
+
+        // Reset the viewport and scissor box.
+        self.viewport = SurfaceViewport {
+            position: Vector2::new(0, 0),
+            size: dimensions,
+        };
+        self.scissor = SurfaceScissor::Disable;
+
+        if !self.cleared_surfaces.contains(&handle) {
+            self.clear(handle)?;
+            self.cleared_surfaces.insert(handle);
+        }
+
+        self.binded_surface = Some(handle);
+        Ok(())
+    }
+
+    unsafe fn update_surface_scissor(&mut self, scissor: SurfaceScissor) -> Result<()> {
+        self.scissor = scissor;
+        Ok(())
+    }
+
+    unsafe fn update_surface_viewport(&mut self, vp: SurfaceViewport) -> Result<()> {
+        self.viewport = vp;
+        Ok(())
+    }
+
+    unsafe fn draw(
+        &mut self,
+        shader_handle: ShaderHandle,
+        state: Option<RenderState>,
+        mesh_handle: MeshHandle,
+        mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
+        uniforms: &[UniformVar],
+    ) -> Result<u32> {
+        let shader = self
+            .shaders
+            .get(shader_handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", shader_handle))?;
+
+        for &(field, variable) in uniforms {
+            if let Some(tp) = shader.params.uniforms.variable_type(field) {
+                // The sampler type of texture depends on its dimension, and textures those
+                // are not ready yet are treated as compatible.
+                let vtp = match variable {
+                    UniformVariable::Texture(handle)
+                        if tp != UniformVariableType::RenderTexture =>
+                    {
+                        self.textures
+                            .get(handle)
+                            .map(|v| v.params.dimension.into())
+                            .unwrap_or(tp)
+                    }
+                    _ => variable.variable_type(),
+                };
+
+                if tp != vtp {
+                    let name = shader.params.uniforms.variable_name(field).unwrap();
+                    bail!(
+                        "The uniform {} needs a {:?} instead of {:?}.",
+                        name,
+                        tp,
+                        vtp,
+                    );
+                }
+
+                if let UniformVariable::RenderTexture(handle) = variable {
+                    if let Some(texture) = self.render_textures.get(handle) {
+                        if texture.params.sampler.is_none() {
+                            bail!("The render buffer does not have a sampler.");
+                        }
+                    }
+                }
+            } else {
+                bail!("Undefined uniform field {:?}.", field);
+            }
+        }
+
+        // Flattens the variables of uniform blocks, the ones of draw call take precedence.
+        let mut vars = uniforms.to_vec();
+        for &(_, variable) in uniforms {
+            if let UniformVariable::UniformBlock(handle) = variable {
+                if let Some(block) = self.uniform_blocks.get(handle) {
+                    vars.extend_from_slice(&block.variables);
+                }
+            }
+        }
+
+        let mesh = self
+            .meshes
+            .get(mesh_handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", mesh_handle))?;
+
+        let instance_mesh = match instances.and_then(|v| v.mesh) {
+            Some(handle) => Some(
+                self.meshes
+                    .get(handle)
+                    .ok_or_else(|| format_err!("{:?} is invalid.", handle))?,
+            ),
+            None => None,
+        };
+
+        for (name, size, required) in shader.params.attributes.iter() {
+            let element = instance_mesh
+                .and_then(|v| v.params.layout.element(name))
+                .or_else(|| mesh.params.layout.element(name));
+
+            if let Some(element) = element {
+                if element.size < size {
+                    bail!(
+                        "Vertex buffer has incompatible attribute `{:?}` [{:?} - {:?}].",
+                        name,
+                        element.size,
+                        size
+                    );
+                }
+            } else if required {
+                bail!(
+                    "Can't find attribute {:?} description in vertex buffer.",
+                    name
+                );
+            }
+        }
+
+        let (from, len) = match mesh_index {
+            MeshIndex::Ptr(from, len) => {
+                if (from + len) > mesh.params.num_idxes {
+                    bail!("MeshIndex is out of bounds");
+                }
+
+                (from, len)
+            }
+            MeshIndex::SubMesh(index) => {
+                let num = mesh.params.sub_mesh_offsets.len();
+                let from = *mesh
+                    .params
+                    .sub_mesh_offsets
+                    .get(index)
+                    .ok_or_else(|| format_err!("MeshIndex is out of bounds"))?;
+
+                let to = if index == (num - 1) {
+                    mesh.params.num_idxes
+                } else {
+                    mesh.params.sub_mesh_offsets[index + 1]
+                };
+
+                if to > mesh.params.num_idxes {
+                    bail!("MeshIndex is out of bounds");
+                }
+
+                let len = to
+                    .checked_sub(from)
+                    .ok_or_else(|| format_err!("MeshIndex is out of bounds"))?;
+
+                (from, len)
+            }
+            MeshIndex::All => (0, mesh.params.num_idxes),
+        };
+
+        let (count, divisor) = match instances {
+            Some(v) => (v.count, v.divisor.max(1)),
+            None => (1, 1),
+        };
+
+        let state = state.unwrap_or(shader.params.state);
+
+        let mut target = self.take_target();
+
+        // The handles have been checked above, and taking the target leaves them untouched.
+        let call = SoftwareDrawCall {
+            state,
+            shader: self.shaders.get(shader_handle).unwrap(),
+            mesh: self.meshes.get(mesh_handle).unwrap(),
+            uniforms: &vars,
+        };
+
+        let instance_mesh = instances
+            .and_then(|v| v.mesh)
+            .and_then(|v| self.meshes.get(v));
+
+        let result = (0..count).try_for_each(|i| {
+            let instance = match instance_mesh {
+                Some(v) => {
+                    let index = (i / divisor) as usize;
+                    if index >= v.params.num_verts {
+                        bail!("Instance vertex {} is out of bounds.", index);
+                    }
+
+                    Some(Self::vertex(call.shader, v, index))
+                }
+                None => None,
+            };
+
+            let indices = from..from + len;
+            self.rasterize(&mut target, &call, instance.as_ref(), indices)
+        });
+
+        let assembled = call.mesh.params.primitive.assemble(len as u32) * count;
+        self.restore_target(target);
+
+        result?;
+        Ok(assembled)
+    }
+
+    unsafe fn read_surface(
+        &mut self,
+        handle: SurfaceHandle,
+        _: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let surface = self
+            .surfaces
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if surface.dimensions.is_none() {
+            return Self::read_image(&self.color, area);
+        }
+
+        match surface.params.colors[0] {
+            Some(v) => self.read_render_texture(v, area),
+            None => bail!("Trying to read pixels from surface without color attachment."),
+        }
+    }
+
+    unsafe fn read_framebuffer(&mut self, _: Vector2<u32>) -> Result<(Vector2<u32>, Box<[u8]>)> {
+        Ok((self.color.dimensions, self.read_pixels().into_boxed_slice()))
+    }
+
+    unsafe fn read_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let rt = self
+            .render_textures
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if !rt.params.format.is_color() {
+            bail!("Trying to read pixels from depth render texture.");
+        }
+
+        Self::read_image(&rt.image, area)
+    }
+
+    unsafe fn flush(&mut self) -> Result<()> {
+        if self.cleared_surfaces.is_empty() {
+            self.color.fill(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn visitor(programs: SoftwarePrograms) -> SoftwareVisitor {
+        let params = SoftwareParams {
+            dimensions: Vector2::new(4, 4),
+            programs,
+        };
+
+        SoftwareVisitor::new(params)
+    }
+
+    fn shader(state: RenderState) -> ShaderParams {
+        let mut params = ShaderParams::default();
+        params.attributes = AttributeLayout::build()
+            .with(Attribute::Position, 2)
+            .with_optional(Attribute::Color0, 4)
+            .finish();
+        params.state = state;
+        params
+    }
+
+    fn quad(x0: f32, y0: f32, x1: f32, y1: f32, color: [u8; 4]) -> (MeshParams, MeshData) {
+        let mut vptr = Vec::new();
+        for &(x, y) in &[(x0, y0), (x1, y0), (x1, y1), (x0, y1)] {
+            vptr.extend_from_slice(&x.to_bits().to_le_bytes());
+            vptr.extend_from_slice(&y.to_bits().to_le_bytes());
+            vptr.extend_from_slice(&color);
+        }
+
+        let idxes: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+        let mut params = MeshParams::default();
+        params.layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Float, 2, false)
+            .with(Attribute::Color0, VertexFormat::UByte, 4, true)
+            .finish();
+        params.num_verts = 4;
+        params.num_idxes = 6;
+
+        let data = MeshData {
+            vptr: vptr.into_boxed_slice(),
+            iptr: IndexFormat::encode(&idxes).to_vec().into_boxed_slice(),
+        };
+
+        (params, data)
+    }
+
+    #[test]
+    fn fill() {
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+
+        unsafe {
+            let mut params = SurfaceParams::default();
+            params.set_clear(Color::white(), 1.0, None);
+            visitor.create_surface(surface, params).unwrap();
+
+            let params = shader(RenderState::default());
+            visitor
+                .create_shader(shader_handle, params, "", "")
+                .unwrap();
+
+            // Covers the left half of framebuffer.
+            let (params, data) = quad(-1.0, -1.0, 0.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            let n = visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+            assert_eq!(n, 2);
+        }
+
+        for y in 0..4 {
+            assert_eq!(visitor.pixel(0, y), Some(Color::red()));
+            assert_eq!(visitor.pixel(1, y), Some(Color::red()));
+            assert_eq!(visitor.pixel(2, y), Some(Color::white()));
+            assert_eq!(visitor.pixel(3, y), Some(Color::white()));
+        }
+    }
+
+    #[test]
+    fn cull_and_depth() {
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let surface = SurfaceHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+        let culled = ShaderHandle::new(1, 1);
+        let depth = ShaderHandle::new(2, 1);
+
+        unsafe {
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+
+            let mut state = RenderState::default();
+            state.cull_face = CullFace::Front;
+            visitor
+                .create_shader(culled, shader(state), "", "")
+                .unwrap();
+
+            let mut state = RenderState::default();
+            state.depth_test = Comparison::Greater;
+            visitor.create_shader(depth, shader(state), "", "").unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            visitor
+                .draw(culled, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+            visitor
+                .draw(depth, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+        }
+
+        assert_eq!(visitor.pixel(1, 1), Some(Color::black()));
+    }
+
+    #[test]
+    fn override_state() {
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let surface = SurfaceHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+        let culled = ShaderHandle::new(1, 1);
+
+        unsafe {
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+
+            let mut state = RenderState::default();
+            state.cull_face = CullFace::Front;
+            visitor
+                .create_shader(culled, shader(state), "", "")
+                .unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            // The culling of shader is disabled in this draw call only.
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            visitor
+                .draw(culled, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+            assert_eq!(visitor.pixel(1, 1), Some(Color::black()));
+
+            let state = Some(RenderState::default());
+            visitor
+                .draw(culled, state, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+            assert_eq!(visitor.pixel(1, 1), Some(Color::red()));
+        }
+    }
+
+    #[test]
+    fn program() {
+        let mut programs = SoftwarePrograms::new();
+        programs.add_fn(
+            "vs",
+            "fs",
+            |_, v, _| v.get(Attribute::Position).unwrap(),
+            |ctx, _| ctx.vector4("u_Color"),
+        );
+
+        let mut visitor = visitor(programs);
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+
+        unsafe {
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+
+            let mut params = shader(RenderState::default());
+            params.uniforms = UniformVariableLayout::build()
+                .with("u_Color", UniformVariableType::Vector4f)
+                .finish();
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            let color = UniformVariable::Vector4f([0.0, 0.0, 1.0, 1.0]);
+            let vars = [("u_Color".into(), color)];
+            visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                .unwrap();
+
+            let vars = [("u_Undefined".into(), color)];
+            assert!(visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                .is_err());
+        }
+
+        assert_eq!(visitor.pixel(3, 3), Some(Color::blue()));
+    }
+
+    #[test]
+    fn missing_mesh() {
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+
+        unsafe {
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+
+            let params = shader(RenderState::default());
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            assert!(visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &[])
+                .is_err());
+
+            // The offsets of sub-meshes are not in order.
+            let (mut params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            params.sub_mesh_offsets.push(3);
+            params.sub_mesh_offsets.push(0);
+            assert!(visitor.create_mesh(mesh, params, Some(data)).is_err());
+
+            // The data and sub-meshes must fit into the buffers described by params.
+            let (mut params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            params.num_verts = 3;
+            assert!(visitor.create_mesh(mesh, params, Some(data)).is_err());
+
+            let (mut params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            params.sub_mesh_offsets.push(6);
+            assert!(visitor.create_mesh(mesh, params, Some(data)).is_err());
+        }
+    }
+
+    /// Runs the passes of `examples/render_texture.rs` with the same shader sources.
+    #[test]
+    fn render_texture() {
+        let p1 = (
+            include_str!("../../../../examples/shaders/render_target_p1.vs"),
+            include_str!("../../../../examples/shaders/render_target_p1.fs"),
+        );
+
+        let p2 = (
+            include_str!("../../../../examples/shaders/render_target_p2.vs"),
+            include_str!("../../../../examples/shaders/render_target_p2.fs"),
+        );
+
+        let mut programs = SoftwarePrograms::new();
+        programs.add_fn(
+            p1.0,
+            p1.1,
+            |_, v, out| {
+                let position = v.get(Attribute::Position).unwrap();
+                out[0..2].copy_from_slice(&[position.x, position.y]);
+                Vector4::new(position.x, position.y, 0.0, 1.0)
+            },
+            |_, v| Some(Vector4::new(v[0], v[1], 0.0, 1.0)),
+        );
+
+        programs.add_fn(
+            p2.0,
+            p2.1,
+            |_, v, out| {
+                let position = v.get(Attribute::Position).unwrap();
+                out[0..2].copy_from_slice(&[(position.x + 1.0) / 2.0, (position.y + 1.0) / 2.0]);
+                Vector4::new(position.x, position.y, 0.0, 1.0)
+            },
+            |ctx, v| {
+                let time = match ctx.uniform("time") {
+                    Some(UniformVariable::F32(v)) => v,
+                    _ => 0.0,
+                };
+
+                let offset = Vector2::new(
+                    0.025 * (time + 1024.0 * v[0]).sin(),
+                    0.025 * (time + 768.0 * v[1]).cos(),
+                );
+
+                let uv = Vector2::new(v[0], v[1]) + offset;
+                let color = ctx.sample("renderedTexture", uv);
+                Some(Vector4::new(color.x, color.y, color.z, 1.0))
+            },
+        );
+
+        let params = SoftwareParams {
+            dimensions: Vector2::new(8, 8),
+            programs,
+        };
+
+        let mut visitor = SoftwareVisitor::new(params);
+        let texture = RenderTextureHandle::new(1, 1);
+        let (surface, post_surface) = (SurfaceHandle::new(1, 1), SurfaceHandle::new(2, 1));
+        let (shader, post_shader) = (ShaderHandle::new(1, 1), ShaderHandle::new(2, 1));
+        let (mesh, post_mesh) = (MeshHandle::new(1, 1), MeshHandle::new(2, 1));
+
+        let layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Float, 2, false)
+            .finish();
+
+        let attributes = AttributeLayout::build()
+            .with(Attribute::Position, 2)
+            .finish();
+
+        let mesh_data = |verts: &[f32], idxes: &[u16]| {
+            let mut params = MeshParams::default();
+            params.layout = layout;
+            params.num_verts = verts.len() / 2;
+            params.num_idxes = idxes.len();
+
+            let data = MeshData {
+                vptr: verts
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes().to_vec())
+                    .collect(),
+                iptr: IndexFormat::encode(idxes).to_vec().into_boxed_slice(),
+            };
+
+            (params, data)
+        };
+
+        unsafe {
+            let mut params = RenderTextureParams::default();
+            params.format = RenderTextureFormat::RGBA8;
+            params.dimensions = Vector2::new(8, 8);
+            visitor.create_render_texture(texture, params).unwrap();
+
+            let mut params = SurfaceParams::default();
+            params.set_attachments(&[texture], None).unwrap();
+            params.set_clear(Color::gray(), None, None);
+            visitor.create_surface(surface, params).unwrap();
+
+            let mut params = ShaderParams::default();
+            params.attributes = attributes;
+            visitor.create_shader(shader, params, p1.0, p1.1).unwrap();
+
+            let verts = [0.0, 0.5, 0.5, -0.5, -0.5, -0.5];
+            let (params, data) = mesh_data(&verts, &[0, 1, 2]);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor
+                .create_surface(post_surface, SurfaceParams::default())
+                .unwrap();
+
+            let mut params = ShaderParams::default();
+            params.attributes = attributes;
+            params.uniforms = UniformVariableLayout::build()
+                .with("renderedTexture", UniformVariableType::RenderTexture)
+                .with("time", UniformVariableType::F32)
+                .finish();
+            visitor
+                .create_shader(post_shader, params, p2.0, p2.1)
+                .unwrap();
+
+            let verts = [-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0];
+            let (params, data) = mesh_data(&verts, &[0, 1, 2, 0, 2, 3]);
+            visitor.create_mesh(post_mesh, params, Some(data)).unwrap();
+
+            visitor.advance().unwrap();
+            visitor.bind(surface, Vector2::new(8, 8)).unwrap();
+            visitor
+                .draw(shader, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+
+            visitor.bind(post_surface, Vector2::new(8, 8)).unwrap();
+            let vars = [
+                (
+                    "renderedTexture".into(),
+                    UniformVariable::RenderTexture(texture),
+                ),
+                ("time".into(), UniformVariable::F32(0.0)),
+            ];
+            visitor
+                .draw(post_shader, None, post_mesh, MeshIndex::All, None, &vars)
+                .unwrap();
+            visitor.flush().unwrap();
+        }
+
+        // The triangle is colored by the positions of its fragments, and the rest of
+        // render texture is cleared with gray.
+        let v = visitor.render_texture_pixel(texture, 5, 2).unwrap();
+        assert!((v.r - 0.375).abs() < 0.01 && v.g == 0.0 && v.b == 0.0);
+        let v = visitor.render_texture_pixel(texture, 0, 7).unwrap();
+        assert!((v.r - 0.5).abs() < 0.01 && v.r == v.g && v.g == v.b);
+
+        // The post effect distorts the render texture slightly with linear filtering.
+        let v = visitor.pixel(5, 2).unwrap();
+        assert!(v.r > 0.3 && v.g < 0.1 && v.b < 0.1);
+        let v = visitor.pixel(0, 7).unwrap();
+        assert!((v.r - 0.5).abs() < 0.01 && v.r == v.g && v.g == v.b);
+    }
+
+    #[test]
+    fn hdr() {
+        let mut programs = SoftwarePrograms::new();
+        programs.add_fn(
+            "vs",
+            "fs",
+            |_, v, _| v.get(Attribute::Position).unwrap(),
+            |ctx, _| ctx.vector4("u_Color"),
+        );
+
+        programs.add_fn(
+            "post.vs",
+            "post.fs",
+            |_, v, _| v.get(Attribute::Position).unwrap(),
+            |ctx, _| {
+                let v = ctx.sample("u_Scene", Vector2::new(0.5, 0.5));
+                Some(Vector4::new(v.x / 8.0, v.y / 8.0, v.z / 8.0, 1.0))
+            },
+        );
+
+        let mut visitor = visitor(programs);
+        let (hdr, ldr) = (RenderTextureHandle::new(1, 1), RenderTextureHandle::new(2, 1));
+        let (scene, scene_ldr, post) = (
+            SurfaceHandle::new(1, 1),
+            SurfaceHandle::new(2, 1),
+            SurfaceHandle::new(3, 1),
+        );
+        let (shader_handle, post_shader) = (ShaderHandle::new(1, 1), ShaderHandle::new(2, 1));
+        let mesh = MeshHandle::new(1, 1);
+
+        unsafe {
+            let mut params = RenderTextureParams::default();
+            params.format = RenderTextureFormat::RGBA16F;
+            params.dimensions = Vector2::new(4, 4);
+            visitor.create_render_texture(hdr, params).unwrap();
+            params.format = RenderTextureFormat::RGBA8;
+            visitor.create_render_texture(ldr, params).unwrap();
+
+            let mut params = SurfaceParams::default();
+            params.set_attachments(&[hdr], None).unwrap();
+            params.set_clear(Color::black(), None, None);
+            visitor.create_surface(scene, params).unwrap();
+            params.set_attachments(&[ldr], None).unwrap();
+            visitor.create_surface(scene_ldr, params).unwrap();
+            visitor
+                .create_surface(post, SurfaceParams::default())
+                .unwrap();
+
+            // Accumulates the lights additively.
+            let mut state = RenderState::default();
+            state.color_blend = Some((Equation::Add, BlendFactor::One, BlendFactor::One));
+            let mut params = shader(state);
+            params.uniforms = UniformVariableLayout::build()
+                .with("u_Color", UniformVariableType::Vector4f)
+                .finish();
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+
+            let mut params = shader(RenderState::default());
+            params.uniforms = UniformVariableLayout::build()
+                .with("u_Scene", UniformVariableType::RenderTexture)
+                .finish();
+            visitor
+                .create_shader(post_shader, params, "post.vs", "post.fs")
+                .unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            let vars = [(
+                "u_Color".into(),
+                UniformVariable::Vector4f([2.0, 0.5, 0.0, 1.0]),
+            )];
+
+            for &surface in &[scene, scene_ldr] {
+                visitor.bind(surface, Vector2::new(4, 4)).unwrap();
+                for _ in 0..2 {
+                    visitor
+                        .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                        .unwrap();
+                }
+            }
+
+            visitor.bind(post, Vector2::new(4, 4)).unwrap();
+            let vars = [("u_Scene".into(), UniformVariable::RenderTexture(hdr))];
+            visitor
+                .draw(post_shader, None, mesh, MeshIndex::All, None, &vars)
+                .unwrap();
+        }
+
+        // The float render texture keeps the values above 1.0, while RGBA8 saturates.
+        assert_eq!(
+            visitor.render_texture_pixel(hdr, 1, 1),
+            Some(Color::new(4.0, 1.0, 0.0, 3.0))
+        );
+        assert_eq!(
+            visitor.render_texture_pixel(ldr, 1, 1),
+            Some(Color::new(1.0, 1.0, 0.0, 1.0))
+        );
+
+        let v = visitor.pixel(1, 1).unwrap();
+        assert!((v.r - 0.5).abs() < 0.01 && (v.g - 0.125).abs() < 0.01 && v.b == 0.0);
+    }
+
+    #[test]
+    fn instancing() {
+        let mut programs = SoftwarePrograms::new();
+        programs.add_fn(
+            "vs",
+            "fs",
+            |_, v, _| {
+                let offset = v.get(Attribute::Texcoord0).unwrap();
+                v.get(Attribute::Position).unwrap() + Vector4::new(offset.x, offset.y, 0.0, 0.0)
+            },
+            |_, _| Some(Vector4::new(1.0, 0.0, 0.0, 1.0)),
+        );
+
+        let mut visitor = visitor(programs);
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+        let offsets = MeshHandle::new(2, 1);
+
+        unsafe {
+            let mut params = SurfaceParams::default();
+            params.set_clear(Color::white(), None, None);
+            visitor.create_surface(surface, params).unwrap();
+
+            let mut params = shader(RenderState::default());
+            params.attributes = AttributeLayout::build()
+                .with(Attribute::Position, 2)
+                .with(Attribute::Texcoord0, 2)
+                .finish();
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+
+            // Covers the first column of framebuffer.
+            let (params, data) = quad(-1.0, -1.0, -0.5, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            // Moves the second instance to the third column.
+            let mut vptr = Vec::new();
+            for &v in &[0.0f32, 0.0, 1.0, 0.0] {
+                vptr.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+
+            let mut params = MeshParams::default();
+            params.layout = VertexLayout::build()
+                .with(Attribute::Texcoord0, VertexFormat::Float, 2, false)
+                .finish();
+            params.num_verts = 2;
+            params.num_idxes = 0;
+
+            let data = MeshData {
+                vptr: vptr.into_boxed_slice(),
+                iptr: Vec::new().into_boxed_slice(),
+            };
+
+            visitor.create_mesh(offsets, params, Some(data)).unwrap();
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+
+            let instances = MeshInstances::with_mesh(2, offsets);
+            let n = visitor
+                .draw(
+                    shader_handle,
+                    None,
+                    mesh,
+                    MeshIndex::All,
+                    Some(instances),
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(n, 4);
+
+            // Requires more instances than the per-instance stream has.
+            let instances = MeshInstances::with_mesh(3, offsets);
+            assert!(visitor
+                .draw(
+                    shader_handle,
+                    None,
+                    mesh,
+                    MeshIndex::All,
+                    Some(instances),
+                    &[]
+                )
+                .is_err());
+
+            // Texcoord0 is missing without the per-instance stream.
+            let instances = MeshInstances::new(2);
+            assert!(visitor
+                .draw(
+                    shader_handle,
+                    None,
+                    mesh,
+                    MeshIndex::All,
+                    Some(instances),
+                    &[]
+                )
+                .is_err());
+        }
+
+        for y in 0..4 {
+            assert_eq!(visitor.pixel(0, y), Some(Color::red()));
+            assert_eq!(visitor.pixel(1, y), Some(Color::white()));
+            assert_eq!(visitor.pixel(2, y), Some(Color::red()));
+            assert_eq!(visitor.pixel(3, y), Some(Color::white()));
+        }
+    }
+
+    #[test]
+    fn cube() {
+        use crate::math::prelude::Vector3;
+
+        let mut programs = SoftwarePrograms::new();
+        programs.add_fn(
+            "vs",
+            "fs",
+            |_, v, _| v.get(Attribute::Position).unwrap(),
+            |ctx, _| Some(ctx.sample3("u_Cube", Vector3::new(0.0, -1.0, 0.0))),
+        );
+
+        let mut visitor = visitor(programs);
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+        let cube = TextureHandle::new(1, 1);
+        let texture = TextureHandle::new(2, 1);
+
+        let mut params = TextureParams::default();
+        params.hint = TextureHint::Dynamic;
+        params.dimension = TextureDimension::Cube;
+        params.dimensions = Vector2::new(1, 1);
+
+        // Faces are ordered as +X, -X, +Y, -Y, +Z and -Z.
+        let mut bytes = vec![255; 4 * 6];
+        bytes[12..16].copy_from_slice(&[0, 255, 0, 255]);
+        let data = TextureData {
+            bytes: vec![bytes.into_boxed_slice()],
+        };
+
+        let short = TextureData {
+            bytes: vec![vec![255; 4].into_boxed_slice()],
+        };
+
+        assert!(params.validate(Some(&data)).is_ok());
+        assert!(params.validate(Some(&short)).is_err());
+
+        unsafe {
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+
+            visitor.create_texture(cube, params, Some(data)).unwrap();
+            visitor
+                .create_texture(texture, TextureParams::default(), None)
+                .unwrap();
+
+            let mut params = shader(RenderState::default());
+            params.uniforms = UniformVariableLayout::build()
+                .with("u_Cube", UniformVariableType::TextureCube)
+                .finish();
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 1.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            let vars = [("u_Cube".into(), UniformVariable::Texture(cube))];
+            visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                .unwrap();
+            assert_eq!(visitor.pixel(0, 0), Some(Color::green()));
+
+            let area = Aabb2::new(Point2::new(0, 0), Point2::new(1, 1));
+            visitor
+                .update_texture(cube, 3, area, &[0, 0, 255, 255])
+                .unwrap();
+            assert!(visitor
+                .update_texture(cube, 6, area, &[0, 0, 255, 255])
+                .is_err());
+
+            visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                .unwrap();
+            assert_eq!(visitor.pixel(0, 0), Some(Color::blue()));
+
+            let vars = [("u_Cube".into(), UniformVariable::Texture(texture))];
+            assert!(visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &vars)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn sampler() {
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let handle = TextureHandle::new(1, 1);
+
+        let mut params = TextureParams::default();
+        params.format = TextureFormat::R8;
+        params.dimensions = Vector2::new(2, 2);
+        params.generate_mipmaps = true;
+        params.sampler = SamplerParams::new(TextureWrap::Border, TextureFilter::Nearest);
+        params.sampler.mip_filter = Some(TextureFilter::Nearest);
+        params.sampler.border_color = Color::white();
+
+        let data = TextureData {
+            bytes: vec![vec![0, 255, 255, 0].into_boxed_slice()],
+        };
+
+        unsafe {
+            visitor.create_texture(handle, params, Some(data)).unwrap();
+        }
+
+        let texture = visitor.textures.get(handle).unwrap();
+        assert_eq!(texture.levels.len(), 2);
+        assert_eq!(texture.sample_lod(Vector2::new(0.25, 0.25), 0.0).x, 0.0);
+        assert_eq!(texture.sample_lod(Vector2::new(0.25, 0.25), 1.0).x, 0.5);
+        assert_eq!(texture.sample(Vector2::new(-0.5, 0.25)).w, 1.0);
+        assert_eq!(texture.sample(Vector2::new(-0.5, 0.25)).x, 1.0);
+
+        params.generate_mipmaps = false;
+        params.sampler.compare = Some(Comparison::Less);
+        let data = TextureData {
+            bytes: vec![vec![0, 255, 255, 0].into_boxed_slice()],
+        };
+
+        let handle = TextureHandle::new(2, 1);
+        unsafe {
+            visitor.create_texture(handle, params, Some(data)).unwrap();
+        }
+
+        let texture = visitor.textures.get(handle).unwrap();
+        assert_eq!(texture.sample_compare(Vector2::new(0.25, 0.25), 0.5).x, 0.0);
+        assert_eq!(texture.sample_compare(Vector2::new(0.75, 0.25), 0.5).x, 1.0);
+    }
+
+    #[test]
+    fn readback() {
+        use crate::res::request::Request;
+
+        use super::super::frame::{Command, Frame};
+
+        let mut visitor = visitor(SoftwarePrograms::new());
+        let rt = RenderTextureHandle::new(1, 1);
+        let depth = RenderTextureHandle::new(2, 1);
+        let surface = SurfaceHandle::new(1, 1);
+        let shader_handle = ShaderHandle::new(1, 1);
+        let mesh = MeshHandle::new(1, 1);
+        let area = Aabb2::new(Point2::new(1, 0), Point2::new(3, 1));
+
+        unsafe {
+            let mut params = RenderTextureParams::default();
+            params.dimensions = Vector2::new(4, 4);
+            visitor.create_render_texture(rt, params).unwrap();
+
+            params.format = RenderTextureFormat::Depth16;
+            visitor.create_render_texture(depth, params).unwrap();
+
+            let mut params = SurfaceParams::default();
+            params.set_attachments(&[rt], None).unwrap();
+            params.set_clear(Color::white(), None, None);
+            visitor.create_surface(surface, params).unwrap();
+
+            let params = shader(RenderState::default());
+            visitor
+                .create_shader(shader_handle, params, "", "")
+                .unwrap();
+
+            let (params, data) = quad(-1.0, -1.0, 0.0, 1.0, RED);
+            visitor.create_mesh(mesh, params, Some(data)).unwrap();
+
+            visitor.bind(surface, Vector2::new(0, 0)).unwrap();
+            visitor
+                .draw(shader_handle, None, mesh, MeshIndex::All, None, &[])
+                .unwrap();
+
+            let bytes = visitor.read_render_texture(rt, area).unwrap();
+            assert_eq!(&bytes[..], &[255, 0, 0, 255, 255, 255, 255, 255]);
+
+            let bytes = visitor
+                .read_surface(surface, Vector2::new(0, 0), area)
+                .unwrap();
+            assert_eq!(&bytes[..], &[255, 0, 0, 255, 255, 255, 255, 255]);
+
+            let area = Aabb2::new(Point2::new(3, 3), Point2::new(5, 4));
+            assert!(visitor.read_render_texture(rt, area).is_err());
+            assert!(visitor.read_render_texture(depth, area).is_err());
+        }
+
+        // Requests are resolved when the frame is dispatched.
+        let latch = Request::latch();
+        let mut frame = Frame::default();
+        let cmd = Command::ReadRenderTexture(Box::new((rt, area, latch.clone())));
+        frame.cmds.push(cmd);
+
+        let mut request = Request::new(latch);
+        assert!(!request.poll());
+
+        frame.dispatch(&mut visitor, Vector2::new(4, 4)).unwrap();
+        assert!(request.poll());
+
+        let bytes = request.response().unwrap().as_ref().unwrap();
+        assert_eq!(&bytes[..], &[255, 0, 0, 255, 255, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Rust stand-ins for the shader programs, which are executed by the software
+//! rasterizer instead of the GLSL sources.
+
+use std::fmt;
+use std::sync::Arc;
+
+use cgmath::ElementWise;
+
+use crate::math::prelude::{Matrix, Matrix4, Vector2, Vector3, Vector4};
+use crate::utils::prelude::{FastHashMap, HashValue};
+use crate::video::MAX_VERTEX_ATTRIBUTES;
+
+use super::super::super::assets::prelude::*;
+use super::super::UniformVar;
+use super::raster::Sampler;
+
+/// Maximum number of floats interpolated from vertex stage to fragment stage.
+pub const MAX_VARYINGS: usize = 16;
+
+/// Interpolated outputs of the vertex stage.
+pub type Varyings = [f32; MAX_VARYINGS];
+
+/// The decoded attributes of a single vertex.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SoftwareVertex {
+    pub(crate) attributes: [Option<Vector4<f32>>; MAX_VERTEX_ATTRIBUTES],
+}
+
+impl SoftwareVertex {
+    /// Gets the attribute if its available in the vertex layout. Missing components
+    /// are filled with `(0, 0, 0, 1)` just like OpenGL does.
+    #[inline]
+    pub fn get(&self, attribute: Attribute) -> Option<Vector4<f32>> {
+        self.attributes[attribute as usize]
+    }
+}
+
+/// Read-only access to the uniform variables and samplers of current draw call.
+pub struct SoftwareContext<'a> {
+    pub(crate) uniforms: &'a [UniformVar],
+    pub(crate) sampler: &'a Sampler<'a>,
+}
+
+impl<'a> SoftwareContext<'a> {
+    /// Gets the uniform variable bound to the draw call.
+    pub fn uniform<T>(&self, field: T) -> Option<UniformVariable>
+    where
+        T: Into<HashValue<str>>,
+    {
+        let field = field.into();
+        self.uniforms.iter().find(|v| v.0 == field).map(|v| v.1)
+    }
+
+    /// Gets the `Vector4f` uniform variable.
+    pub fn vector4<T>(&self, field: T) -> Option<Vector4<f32>>
+    where
+        T: Into<HashValue<str>>,
+    {
+        match self.uniform(field) {
+            Some(UniformVariable::Vector4f(v)) => Some(v.into()),
+            _ => None,
+        }
+    }
+
+    /// Gets the `Matrix4f` uniform variable, with the optional transpose applied.
+    pub fn matrix4<T>(&self, field: T) -> Option<Matrix4<f32>>
+    where
+        T: Into<HashValue<str>>,
+    {
+        match self.uniform(field) {
+            Some(UniformVariable::Matrix4f(v, transpose)) => {
+                let m = Matrix4::from(v);
+                Some(if transpose { m.transpose() } else { m })
+            }
+            _ => None,
+        }
+    }
+
+    /// Samples the texture bound to `field` at `uv`. Returns opaque black if there is
+    /// no valid texture bound.
+    pub fn sample<T>(&self, field: T, uv: Vector2<f32>) -> Vector4<f32>
+    where
+        T: Into<HashValue<str>>,
+    {
+        self.uniform(field)
+            .and_then(|v| self.sampler.sample(v, uv))
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    /// Samples the texture bound to `field` at `uv` with explicit level-of-detail, since
+    /// the software rasterizer has no derivatives to select the mipmap levels.
+    pub fn sample_lod<T>(&self, field: T, uv: Vector2<f32>, lod: f32) -> Vector4<f32>
+    where
+        T: Into<HashValue<str>>,
+    {
+        self.uniform(field)
+            .and_then(|v| self.sampler.sample_lod(v, uv, lod))
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    /// Samples the depth texture bound to `field` at `uv`, and compares the depth with
+    /// `reference` by the compare function of its sampler. It's the counterpart of
+    /// sampling `sampler2DShadow` in GLSL.
+    pub fn sample_compare<T>(&self, field: T, uv: Vector2<f32>, reference: f32) -> Vector4<f32>
+    where
+        T: Into<HashValue<str>>,
+    {
+        self.uniform(field)
+            .and_then(|v| self.sampler.sample_compare(v, uv, reference))
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    /// Samples the cube, array or 3D texture bound to `field` at `coord`, which is a
+    /// direction vector, `(u, v, layer)` or `(u, v, w)` respectively. Returns opaque black
+    /// if there is no valid texture bound.
+    pub fn sample3<T>(&self, field: T, coord: Vector3<f32>) -> Vector4<f32>
+    where
+        T: Into<HashValue<str>>,
+    {
+        self.uniform(field)
+            .and_then(|v| self.sampler.sample3(v, coord))
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    /// Samples the first texture bound to this draw call.
+    pub fn sample_first(&self, uv: Vector2<f32>) -> Option<Vector4<f32>> {
+        self.uniforms
+            .iter()
+            .find(|v| match v.1 {
+                UniformVariable::Texture(_) | UniformVariable::RenderTexture(_) => true,
+                _ => false,
+            })
+            .map(|v| {
+                self.sampler
+                    .sample(v.1, uv)
+                    .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0))
+            })
+    }
+}
+
+/// A program that could be executed by the software rasterizer.
+pub trait SoftwareProgram: Send + Sync {
+    /// Transforms the vertex into clip space, and writes varyings that should be
+    /// interpolated across the primitive.
+    fn vertex(&self, ctx: &SoftwareContext, v: &SoftwareVertex, out: &mut Varyings)
+        -> Vector4<f32>;
+
+    /// Computes the color of fragment. Returns `None` to discard it.
+    fn fragment(&self, ctx: &SoftwareContext, v: &Varyings) -> Option<Vector4<f32>>;
+}
+
+struct FnProgram<V, F> {
+    vs: V,
+    fs: F,
+}
+
+impl<V, F> SoftwareProgram for FnProgram<V, F>
+where
+    V: Fn(&SoftwareContext, &SoftwareVertex, &mut Varyings) -> Vector4<f32> + Send + Sync,
+    F: Fn(&SoftwareContext, &Varyings) -> Option<Vector4<f32>> + Send + Sync,
+{
+    fn vertex(
+        &self,
+        ctx: &SoftwareContext,
+        v: &SoftwareVertex,
+        out: &mut Varyings,
+    ) -> Vector4<f32> {
+        (self.vs)(ctx, v, out)
+    }
+
+    fn fragment(&self, ctx: &SoftwareContext, v: &Varyings) -> Option<Vector4<f32>> {
+        (self.fs)(ctx, v)
+    }
+}
+
+/// The built-in shading model, which is used for shaders that have no registered
+/// `SoftwareProgram`.
+///
+/// The `Position` attribute is transformed by `u_MVPMatrix` if its bound, and the
+/// fragment color is the product of `Color0` attribute, `u_Color` uniform and the
+/// first texture bound to the draw call sampled at `Texcoord0`.
+pub struct FixedFunctionProgram;
+
+impl SoftwareProgram for FixedFunctionProgram {
+    fn vertex(
+        &self,
+        ctx: &SoftwareContext,
+        v: &SoftwareVertex,
+        out: &mut Varyings,
+    ) -> Vector4<f32> {
+        let color = v
+            .get(Attribute::Color0)
+            .unwrap_or_else(|| Vector4::new(1.0, 1.0, 1.0, 1.0));
+        let texcoord = v
+            .get(Attribute::Texcoord0)
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0));
+
+        out[0..4].copy_from_slice(&[color.x, color.y, color.z, color.w]);
+        out[4..6].copy_from_slice(&[texcoord.x, texcoord.y]);
+
+        let position = v
+            .get(Attribute::Position)
+            .unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 1.0));
+
+        match ctx.matrix4("u_MVPMatrix") {
+            Some(mvp) => mvp * position,
+            None => position,
+        }
+    }
+
+    fn fragment(&self, ctx: &SoftwareContext, v: &Varyings) -> Option<Vector4<f32>> {
+        let mut color = Vector4::new(v[0], v[1], v[2], v[3]);
+
+        if let Some(tint) = ctx.vector4("u_Color") {
+            color = color.mul_element_wise(tint);
+        }
+
+        if let Some(texel) = ctx.sample_first(Vector2::new(v[4], v[5])) {
+            color = color.mul_element_wise(texel);
+        }
+
+        Some(color)
+    }
+}
+
+/// Registry of `SoftwareProgram`s, which are matched against the GLSL sources of
+/// shaders when they are created.
+#[derive(Clone, Default)]
+pub struct SoftwarePrograms {
+    programs: FastHashMap<HashValue<str>, Arc<dyn SoftwareProgram>>,
+}
+
+impl fmt::Debug for SoftwarePrograms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SoftwarePrograms ({} programs)", self.programs.len())
+    }
+}
+
+impl SoftwarePrograms {
+    /// Creates a new and empty `SoftwarePrograms`.
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers the `SoftwareProgram` that stands in for the shader created with
+    /// vertex shader `vs` and fragment shader `fs`.
+    pub fn add<T>(&mut self, vs: &str, fs: &str, program: T)
+    where
+        T: SoftwareProgram + 'static,
+    {
+        self.programs.insert(Self::key(vs, fs), Arc::new(program));
+    }
+
+    /// Registers a pair of closures as the stand-in of the shader created with vertex
+    /// shader `vs` and fragment shader `fs`.
+    pub fn add_fn<V, F>(&mut self, vs: &str, fs: &str, vertex: V, fragment: F)
+    where
+        V: Fn(&SoftwareContext, &SoftwareVertex, &mut Varyings) -> Vector4<f32>
+            + Send
+            + Sync
+            + 'static,
+        F: Fn(&SoftwareContext, &Varyings) -> Option<Vector4<f32>> + Send + Sync + 'static,
+    {
+        let program = FnProgram {
+            vs: vertex,
+            fs: fragment,
+        };
+
+        self.add(vs, fs, program);
+    }
+
+    /// Finds the program registered for the shader sources.
+    pub(crate) fn find(&self, vs: &str, fs: &str) -> Option<Arc<dyn SoftwareProgram>> {
+        self.programs.get(&Self::key(vs, fs)).cloned()
+    }
+
+    fn key(vs: &str, fs: &str) -> HashValue<str> {
+        HashValue::from(format!("{}\0{}", vs, fs))
+    }
+}
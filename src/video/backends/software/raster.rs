@@ -0,0 +1,808 @@
+//! CPU-side images and the triangle rasterization into them.
+
+use cgmath::ElementWise;
+
+use crate::errors::*;
+use crate::math::prelude::{Aabb2, Vector2, Vector3, Vector4};
+
+use super::super::super::assets::mesh::unpack_half;
+use super::super::super::assets::prelude::*;
+use super::super::utils::DataVec;
+use super::super::UniformVar;
+use super::program::{SoftwareContext, SoftwareProgram, Varyings, MAX_VARYINGS};
+
+/// A two-dimensional image with floating point texels. The first row is the
+/// bottom of image, which is consistent with OpenGL.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub dimensions: Vector2<u32>,
+    pub texels: Vec<Vector4<f32>>,
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Image {
+            dimensions: Vector2::new(0, 0),
+            texels: Vec::new(),
+        }
+    }
+}
+
+impl Image {
+    pub fn new(dimensions: Vector2<u32>, value: Vector4<f32>) -> Self {
+        let len = (dimensions.x * dimensions.y) as usize;
+        Image {
+            dimensions,
+            texels: vec![value; len],
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.texels.is_empty()
+    }
+
+    #[inline]
+    pub fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.dimensions.x + x) as usize
+    }
+
+    pub fn fill(&mut self, value: Vector4<f32>) {
+        for v in &mut self.texels {
+            *v = value;
+        }
+    }
+
+    /// Reads the texels inside `area` as tightly packed RGBA8 bytes. The `area` should
+    /// be checked against the dimensions before.
+    pub fn read(&self, area: Aabb2<u32>) -> Box<[u8]> {
+        let mut bytes = Vec::with_capacity(area.volume() as usize * 4);
+        for y in area.min.y..area.max.y {
+            for x in area.min.x..area.max.x {
+                let v = self.texels[self.index(x, y)];
+                for &c in &[v.x, v.y, v.z, v.w] {
+                    bytes.push((c.max(0.0).min(1.0) * 255.0).round() as u8);
+                }
+            }
+        }
+
+        bytes.into_boxed_slice()
+    }
+
+    /// Samples the image at normalized coordinates `uv`. If `reference` is available and
+    /// the sampler has a compare function, the texels are replaced with the results of
+    /// comparison before filtering.
+    pub fn sample(
+        &self,
+        sampler: &SamplerParams,
+        filter: TextureFilter,
+        uv: Vector2<f32>,
+        reference: Option<f32>,
+    ) -> Vector4<f32> {
+        if self.is_empty() {
+            return Vector4::new(0.0, 0.0, 0.0, 1.0);
+        }
+
+        let border = sampler.border_color;
+        let (w, h) = (i64::from(self.dimensions.x), i64::from(self.dimensions.y));
+        let texel = |x: i64, y: i64| {
+            let v = match (
+                wrap_coord(sampler.wrap, x, w),
+                wrap_coord(sampler.wrap, y, h),
+            ) {
+                (Some(x), Some(y)) => self.texels[y * w as usize + x],
+                _ => Vector4::new(border.r, border.g, border.b, border.a),
+            };
+
+            match (sampler.compare, reference) {
+                (Some(cmp), Some(reference)) => {
+                    let v = if compare(cmp, reference, v.x) {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    Vector4::new(v, v, v, 1.0)
+                }
+                _ => v,
+            }
+        };
+
+        match filter {
+            TextureFilter::Nearest => {
+                let x = (uv.x * w as f32).floor() as i64;
+                let y = (uv.y * h as f32).floor() as i64;
+                texel(x, y)
+            }
+            TextureFilter::Linear => {
+                let fx = uv.x * w as f32 - 0.5;
+                let fy = uv.y * h as f32 - 0.5;
+                let (x0, y0) = (fx.floor(), fy.floor());
+                let (tx, ty) = (fx - x0, fy - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let bottom = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+                let top = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+                bottom * (1.0 - ty) + top * ty
+            }
+        }
+    }
+
+    /// Generates the next mipmap level with a 2x2 box filter.
+    pub fn downsample(&self) -> Image {
+        let dimensions = Vector2::new(
+            (self.dimensions.x / 2).max(1),
+            (self.dimensions.y / 2).max(1),
+        );
+
+        let mut image = Image::new(dimensions, Vector4::new(0.0, 0.0, 0.0, 0.0));
+        if self.is_empty() {
+            return image;
+        }
+
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let x0 = (x * 2).min(self.dimensions.x - 1);
+                let y0 = (y * 2).min(self.dimensions.y - 1);
+                let x1 = (x * 2 + 1).min(self.dimensions.x - 1);
+                let y1 = (y * 2 + 1).min(self.dimensions.y - 1);
+
+                let sum = self.texels[self.index(x0, y0)]
+                    + self.texels[self.index(x1, y0)]
+                    + self.texels[self.index(x0, y1)]
+                    + self.texels[self.index(x1, y1)];
+
+                let index = image.index(x, y);
+                image.texels[index] = sum * 0.25;
+            }
+        }
+
+        image
+    }
+}
+
+/// Wraps the texel coordinate into `[0, n)`. Returns `None` if it should be the border.
+fn wrap_coord(wrap: TextureWrap, v: i64, n: i64) -> Option<usize> {
+    let v = match wrap {
+        TextureWrap::Repeat => v.rem_euclid(n),
+        TextureWrap::Clamp => v.max(0).min(n - 1),
+        TextureWrap::Mirror => {
+            let m = v.rem_euclid(2 * n);
+            if m < n {
+                m
+            } else {
+                2 * n - 1 - m
+            }
+        }
+        TextureWrap::MirrorClamp => {
+            let m = if v < 0 { -1 - v } else { v };
+            m.min(n - 1)
+        }
+        TextureWrap::Border if v < 0 || v >= n => return None,
+        TextureWrap::Border => v,
+    };
+
+    Some(v as usize)
+}
+
+/// The images of texture. Each mipmap level contains the faces of cube texture, the
+/// layers of array texture or the depth slices of 3D texture.
+///
+/// Only the base level of 3D texture is sampled.
+#[derive(Debug, Clone)]
+pub struct TextureImage {
+    pub params: TextureParams,
+    pub levels: Vec<Vec<Image>>,
+}
+
+impl TextureImage {
+    /// Regenerates the mipmap levels from the base level, if `generate_mipmaps` is set.
+    pub fn generate_mipmaps(&mut self) {
+        if !self.params.generate_mipmaps || self.params.dimension == TextureDimension::D3 {
+            return;
+        }
+
+        self.levels.truncate(1);
+        for _ in 1..self.params.mipmap_levels() {
+            let next = self
+                .levels
+                .last()
+                .unwrap()
+                .iter()
+                .map(|v| v.downsample())
+                .collect();
+            self.levels.push(next);
+        }
+    }
+
+    /// Samples the 2D texture, or the first image of the others at `uv`.
+    #[inline]
+    pub fn sample(&self, uv: Vector2<f32>) -> Vector4<f32> {
+        self.sample_image(0, uv, 0.0, None)
+    }
+
+    /// Samples the 2D texture at `uv` with explicit level-of-detail.
+    #[inline]
+    pub fn sample_lod(&self, uv: Vector2<f32>, lod: f32) -> Vector4<f32> {
+        self.sample_image(0, uv, lod, None)
+    }
+
+    /// Samples the 2D texture at `uv`, and compares the texels with `reference` by the
+    /// compare function of sampler.
+    #[inline]
+    pub fn sample_compare(&self, uv: Vector2<f32>, reference: f32) -> Vector4<f32> {
+        self.sample_image(0, uv, 0.0, Some(reference))
+    }
+
+    /// Samples the texture at `coord`, which is a direction vector for cube texture,
+    /// `(u, v, layer)` for array texture and `(u, v, w)` for 3D texture.
+    pub fn sample3(&self, coord: Vector3<f32>) -> Vector4<f32> {
+        let sampler = &self.params.sampler;
+        match self.params.dimension {
+            TextureDimension::D2 => self.sample(Vector2::new(coord.x, coord.y)),
+            TextureDimension::Cube => {
+                let (face, uv) = cube_face(coord);
+                self.sample_image(face, uv, 0.0, None)
+            }
+            TextureDimension::D2Array => {
+                let layer = coord.z.round().max(0.0) as usize;
+                let layer = layer.min(self.levels[0].len() - 1);
+                self.sample_image(layer, Vector2::new(coord.x, coord.y), 0.0, None)
+            }
+            TextureDimension::D3 => {
+                let uv = Vector2::new(coord.x, coord.y);
+                let filter = sampler.mag_filter;
+                let images = &self.levels[0];
+                let n = images.len() as i64;
+                let slice = |z: i64| match wrap_coord(sampler.wrap, z, n) {
+                    Some(z) => images[z].sample(sampler, filter, uv, None),
+                    None => {
+                        let v = sampler.border_color;
+                        Vector4::new(v.r, v.g, v.b, v.a)
+                    }
+                };
+
+                match filter {
+                    TextureFilter::Nearest => slice((coord.z * n as f32).floor() as i64),
+                    TextureFilter::Linear => {
+                        let fz = coord.z * n as f32 - 0.5;
+                        let z0 = fz.floor();
+                        let tz = fz - z0;
+                        slice(z0 as i64) * (1.0 - tz) + slice(z0 as i64 + 1) * tz
+                    }
+                }
+            }
+        }
+    }
+
+    /// Samples the `index`th image with level-of-detail `lod`. The software rasterizer
+    /// has no derivatives of coordinates, so the level-of-detail is explicit.
+    fn sample_image(
+        &self,
+        index: usize,
+        uv: Vector2<f32>,
+        lod: f32,
+        reference: Option<f32>,
+    ) -> Vector4<f32> {
+        // The faces of cube are always clamped to edge.
+        let mut sampler = self.params.sampler;
+        if self.params.dimension == TextureDimension::Cube {
+            sampler.wrap = TextureWrap::Clamp;
+        }
+
+        let lod = lod + sampler.lod_bias;
+        let filter = if lod > 0.0 {
+            sampler.min_filter
+        } else {
+            sampler.mag_filter
+        };
+
+        let level = |i: usize| self.levels[i][index].sample(&sampler, filter, uv, reference);
+        let max = (self.levels.len() - 1) as f32;
+        let lod = lod.max(0.0).min(max);
+
+        match sampler.mip_filter {
+            None => level(0),
+            Some(TextureFilter::Nearest) => level(lod.round() as usize),
+            Some(TextureFilter::Linear) => {
+                let l0 = lod.floor();
+                let t = lod - l0;
+                if t > 0.0 {
+                    level(l0 as usize) * (1.0 - t) + level(l0 as usize + 1) * t
+                } else {
+                    level(l0 as usize)
+                }
+            }
+        }
+    }
+}
+
+/// Selects the face of cube with the major axis of direction, and projects the
+/// direction onto it with the same rules as OpenGL.
+fn cube_face(v: Vector3<f32>) -> (usize, Vector2<f32>) {
+    let (ax, ay, az) = (v.x.abs(), v.y.abs(), v.z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if v.x >= 0.0 {
+            (0, -v.z, -v.y, ax)
+        } else {
+            (1, v.z, -v.y, ax)
+        }
+    } else if ay >= az {
+        if v.y >= 0.0 {
+            (2, v.x, v.z, ay)
+        } else {
+            (3, v.x, -v.z, ay)
+        }
+    } else if v.z >= 0.0 {
+        (4, v.x, -v.y, az)
+    } else {
+        (5, -v.x, -v.y, az)
+    };
+
+    if ma <= 0.0 {
+        return (0, Vector2::new(0.5, 0.5));
+    }
+
+    let uv = Vector2::new((sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5);
+    (face, uv)
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderTextureImage {
+    pub params: RenderTextureParams,
+    pub image: Image,
+}
+
+impl RenderTextureImage {
+    /// Samples the render texture, returns `None` if it does not have a sampler.
+    pub fn sample(&self, uv: Vector2<f32>, reference: Option<f32>) -> Option<Vector4<f32>> {
+        let sampler = self.params.sampler.as_ref()?;
+        let filter = sampler.mag_filter;
+        Some(self.image.sample(sampler, filter, uv, reference))
+    }
+}
+
+/// Resolves sampler uniforms into texels.
+pub struct Sampler<'a> {
+    pub textures: &'a DataVec<TextureImage>,
+    pub render_textures: &'a DataVec<RenderTextureImage>,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn sample(&self, variable: UniformVariable, uv: Vector2<f32>) -> Option<Vector4<f32>> {
+        match variable {
+            UniformVariable::Texture(handle) => self.textures.get(handle).map(|v| v.sample(uv)),
+            UniformVariable::RenderTexture(handle) => self
+                .render_textures
+                .get(handle)
+                .and_then(|v| v.sample(uv, None)),
+            _ => None,
+        }
+    }
+
+    /// Samples the 2D texture or render texture at `uv` with explicit level-of-detail.
+    pub fn sample_lod(
+        &self,
+        variable: UniformVariable,
+        uv: Vector2<f32>,
+        lod: f32,
+    ) -> Option<Vector4<f32>> {
+        match variable {
+            UniformVariable::Texture(handle) => {
+                self.textures.get(handle).map(|v| v.sample_lod(uv, lod))
+            }
+            _ => self.sample(variable, uv),
+        }
+    }
+
+    /// Samples the depth texture at `uv`, and compares the texels with `reference`.
+    pub fn sample_compare(
+        &self,
+        variable: UniformVariable,
+        uv: Vector2<f32>,
+        reference: f32,
+    ) -> Option<Vector4<f32>> {
+        match variable {
+            UniformVariable::Texture(handle) => self
+                .textures
+                .get(handle)
+                .map(|v| v.sample_compare(uv, reference)),
+            UniformVariable::RenderTexture(handle) => self
+                .render_textures
+                .get(handle)
+                .and_then(|v| v.sample(uv, Some(reference))),
+            _ => None,
+        }
+    }
+
+    /// Samples the cube, array or 3D texture at `coord`, see `TextureImage::sample3`.
+    pub fn sample3(&self, variable: UniformVariable, coord: Vector3<f32>) -> Option<Vector4<f32>> {
+        match variable {
+            UniformVariable::Texture(handle) => self.textures.get(handle).map(|v| v.sample3(coord)),
+            UniformVariable::RenderTexture(_) => {
+                self.sample(variable, Vector2::new(coord.x, coord.y))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The color and depth buffers that draw calls are writing into.
+#[derive(Debug, Default)]
+pub struct Target {
+    pub color: Option<(RenderTextureFormat, Image)>,
+    pub depth: Option<Image>,
+}
+
+/// A vertex that has been processed by the vertex stage.
+#[derive(Debug, Copy, Clone)]
+pub struct ClipVertex {
+    pub position: Vector4<f32>,
+    pub varyings: Varyings,
+}
+
+/// The fixed-function states of a draw call.
+pub struct Pipeline<'a> {
+    pub state: RenderState,
+    pub viewport: SurfaceViewport,
+    pub scissor: SurfaceScissor,
+    pub program: &'a dyn SoftwareProgram,
+    pub uniforms: &'a [UniformVar],
+    pub sampler: &'a Sampler<'a>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Rasterizes a triangle into target. Triangles that have vertices behind the
+    /// eye (w <= 0) are rejected instead of clipped.
+    pub fn triangle(&self, target: &mut Target, v: [&ClipVertex; 3]) -> Result<()> {
+        if v.iter().any(|p| p.position.w <= ::std::f32::EPSILON) {
+            return Ok(());
+        }
+
+        let vp = self.viewport;
+        let window = |p: &ClipVertex| {
+            let inv = 1.0 / p.position.w;
+            Vector3::new(
+                vp.position.x as f32 + (p.position.x * inv + 1.0) * 0.5 * vp.size.x as f32,
+                vp.position.y as f32 + (p.position.y * inv + 1.0) * 0.5 * vp.size.y as f32,
+                (p.position.z * inv + 1.0) * 0.5,
+            )
+        };
+
+        let mut w = [window(v[0]), window(v[1]), window(v[2])];
+        let mut v = v;
+        let area = edge(w[0], w[1], w[2]);
+        if area == 0.0 {
+            return Ok(());
+        }
+
+        let ccw = area > 0.0;
+        let front = ccw == (self.state.front_face_order == FrontFaceOrder::CounterClockwise);
+        match self.state.cull_face {
+            CullFace::Front if front => return Ok(()),
+            CullFace::Back if !front => return Ok(()),
+            _ => {}
+        }
+
+        if !ccw {
+            w.swap(1, 2);
+            v.swap(1, 2);
+        }
+
+        let area = area.abs();
+        let offset = self.depth_offset(&w, area);
+
+        let (x0, y0, x1, y1) = match self.bounds(target) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let min_x = (w.iter().fold(::std::f32::MAX, |a, p| a.min(p.x)).floor() as i64).max(x0);
+        let min_y = (w.iter().fold(::std::f32::MAX, |a, p| a.min(p.y)).floor() as i64).max(y0);
+        let max_x = (w.iter().fold(::std::f32::MIN, |a, p| a.max(p.x)).ceil() as i64).min(x1);
+        let max_y = (w.iter().fold(::std::f32::MIN, |a, p| a.max(p.y)).ceil() as i64).min(y1);
+
+        let inv_w = [
+            1.0 / v[0].position.w,
+            1.0 / v[1].position.w,
+            1.0 / v[2].position.w,
+        ];
+
+        let ctx = SoftwareContext {
+            uniforms: self.uniforms,
+            sampler: self.sampler,
+        };
+
+        let mut varyings = [0.0; MAX_VARYINGS];
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = Vector3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                let e0 = edge(w[1], w[2], p);
+                let e1 = edge(w[2], w[0], p);
+                let e2 = edge(w[0], w[1], p);
+
+                if !covered(e0, w[1], w[2]) || !covered(e1, w[2], w[0]) || !covered(e2, w[0], w[1])
+                {
+                    continue;
+                }
+
+                let l = [e0 / area, e1 / area, e2 / area];
+                let z = l[0] * w[0].z + l[1] * w[1].z + l[2] * w[2].z + offset;
+                if z < 0.0 || z > 1.0 {
+                    continue;
+                }
+
+                let iw = l[0] * inv_w[0] + l[1] * inv_w[1] + l[2] * inv_w[2];
+                for (i, value) in varyings.iter_mut().enumerate() {
+                    *value = (l[0] * v[0].varyings[i] * inv_w[0]
+                        + l[1] * v[1].varyings[i] * inv_w[1]
+                        + l[2] * v[2].varyings[i] * inv_w[2])
+                        / iw;
+                }
+
+                let color = match self.program.fragment(&ctx, &varyings) {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                self.write(target, x as u32, y as u32, z, color);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the clip rectangle [x0, x1) x [y0, y1) of viewport, scissor box and target.
+    fn bounds(&self, target: &Target) -> Option<(i64, i64, i64, i64)> {
+        let dimensions = match (&target.color, &target.depth) {
+            (Some((_, image)), _) => image.dimensions,
+            (None, Some(image)) => image.dimensions,
+            (None, None) => return None,
+        };
+
+        let vp = self.viewport;
+        let mut x0 = i64::from(vp.position.x).max(0);
+        let mut y0 = i64::from(vp.position.y).max(0);
+        let mut x1 = (i64::from(vp.position.x) + i64::from(vp.size.x)).min(i64::from(dimensions.x));
+        let mut y1 = (i64::from(vp.position.y) + i64::from(vp.size.y)).min(i64::from(dimensions.y));
+
+        if let SurfaceScissor::Enable { position, size } = self.scissor {
+            x0 = x0.max(i64::from(position.x));
+            y0 = y0.max(i64::from(position.y));
+            x1 = x1.min(i64::from(position.x) + i64::from(size.x));
+            y1 = y1.min(i64::from(position.y) + i64::from(size.y));
+        }
+
+        if x0 >= x1 || y0 >= y1 {
+            None
+        } else {
+            Some((x0, y0, x1, y1))
+        }
+    }
+
+    fn depth_offset(&self, w: &[Vector3<f32>; 3], area: f32) -> f32 {
+        match self.state.depth_write_offset {
+            Some((factor, units)) => {
+                let (a, b, c) = (w[0], w[1], w[2]);
+                let dzdx = ((b.z - a.z) * (c.y - a.y) - (c.z - a.z) * (b.y - a.y)) / area;
+                let dzdy = ((c.z - a.z) * (b.x - a.x) - (b.z - a.z) * (c.x - a.x)) / area;
+                factor * dzdx.abs().max(dzdy.abs()) + units / (1 << 24) as f32
+            }
+            None => 0.0,
+        }
+    }
+
+    fn write(&self, target: &mut Target, x: u32, y: u32, z: f32, color: Vector4<f32>) {
+        let rs = &self.state;
+
+        if let Some(ref mut depth) = target.depth {
+            let index = depth.index(x, y);
+            let enable = rs.depth_test != Comparison::Always || rs.depth_write;
+            if enable && !compare(rs.depth_test, z, depth.texels[index].x) {
+                return;
+            }
+
+            if rs.depth_write {
+                depth.texels[index] = Vector4::new(z, z, z, 1.0);
+            }
+        }
+
+        if let Some((format, ref mut image)) = target.color {
+            let index = image.index(x, y);
+            let dst = image.texels[index];
+
+            // The float formats keep the values out of `[0, 1]`, e.g. the HDR colors.
+            let saturate = |v| if format.is_float() { v } else { clamp(v) };
+            let src = saturate(color);
+
+            let mut v = match rs.color_blend {
+                Some((equation, sf, df)) => saturate(blend(equation, sf, df, src, dst)),
+                None => src,
+            };
+
+            let mask = rs.color_write;
+            if !mask.0 {
+                v.x = dst.x;
+            }
+            if !mask.1 {
+                v.y = dst.y;
+            }
+            if !mask.2 {
+                v.z = dst.z;
+            }
+            if !mask.3 {
+                v.w = dst.w;
+            }
+
+            image.texels[index] = quantize(format, v);
+        }
+    }
+}
+
+#[inline]
+fn edge(a: Vector3<f32>, b: Vector3<f32>, p: Vector3<f32>) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Checks if the point is covered by the edge `a -> b` of a counter-clockwise triangle,
+/// the samples exactly on the edges are shared with top-left fill rule.
+#[inline]
+fn covered(e: f32, a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    if e > 0.0 {
+        true
+    } else if e < 0.0 {
+        false
+    } else {
+        (a.y == b.y && b.x < a.x) || b.y < a.y
+    }
+}
+
+pub fn compare(cmp: Comparison, lhs: f32, rhs: f32) -> bool {
+    match cmp {
+        Comparison::Never => false,
+        Comparison::Less => lhs < rhs,
+        Comparison::LessOrEqual => lhs <= rhs,
+        Comparison::Greater => lhs > rhs,
+        Comparison::GreaterOrEqual => lhs >= rhs,
+        Comparison::Equal => lhs == rhs,
+        Comparison::NotEqual => lhs != rhs,
+        Comparison::Always => true,
+    }
+}
+
+fn clamp(v: Vector4<f32>) -> Vector4<f32> {
+    Vector4::new(
+        v.x.max(0.0).min(1.0),
+        v.y.max(0.0).min(1.0),
+        v.z.max(0.0).min(1.0),
+        v.w.max(0.0).min(1.0),
+    )
+}
+
+fn blend(
+    equation: Equation,
+    sf: BlendFactor,
+    df: BlendFactor,
+    src: Vector4<f32>,
+    dst: Vector4<f32>,
+) -> Vector4<f32> {
+    let s = src.mul_element_wise(factor(sf, src, dst));
+    let d = dst.mul_element_wise(factor(df, src, dst));
+
+    match equation {
+        Equation::Add => s + d,
+        Equation::Subtract => s - d,
+        Equation::ReverseSubtract => d - s,
+    }
+}
+
+fn factor(f: BlendFactor, src: Vector4<f32>, dst: Vector4<f32>) -> Vector4<f32> {
+    let value = |v: BlendValue| match v {
+        BlendValue::SourceColor => src,
+        BlendValue::SourceAlpha => Vector4::new(src.w, src.w, src.w, src.w),
+        BlendValue::DestinationColor => dst,
+        BlendValue::DestinationAlpha => Vector4::new(dst.w, dst.w, dst.w, dst.w),
+    };
+
+    match f {
+        BlendFactor::Zero => Vector4::new(0.0, 0.0, 0.0, 0.0),
+        BlendFactor::One => Vector4::new(1.0, 1.0, 1.0, 1.0),
+        BlendFactor::Value(v) => value(v),
+        BlendFactor::OneMinusValue(v) => Vector4::new(1.0, 1.0, 1.0, 1.0) - value(v),
+    }
+}
+
+/// Rounds the color to the precision of render texture format.
+pub fn quantize(format: RenderTextureFormat, v: Vector4<f32>) -> Vector4<f32> {
+    let q = |c: f32, max: f32| (c * max).round() / max;
+    match format {
+        RenderTextureFormat::RGB8 => Vector4::new(q(v.x, 255.0), q(v.y, 255.0), q(v.z, 255.0), 1.0),
+        RenderTextureFormat::RGBA4 => {
+            Vector4::new(q(v.x, 15.0), q(v.y, 15.0), q(v.z, 15.0), q(v.w, 15.0))
+        }
+        RenderTextureFormat::RGBA16F => v,
+        _ => Vector4::new(q(v.x, 255.0), q(v.y, 255.0), q(v.z, 255.0), q(v.w, 255.0)),
+    }
+}
+
+/// Decodes the uncompressed texture data into floating point texels.
+pub fn decode(format: TextureFormat, bytes: &[u8], texels: &mut [Vector4<f32>]) -> Result<()> {
+    if format.compressed() {
+        bail!(
+            "The software rasterizer does not support the texture format {:?}.",
+            format
+        );
+    }
+
+    let stride = format.size(Vector2::new(1, 1)) as usize;
+    for (texel, v) in texels.iter_mut().zip(bytes.chunks(stride)) {
+        if v.len() < stride {
+            break;
+        }
+
+        let u8n = |i: usize| f32::from(v[i]) / 255.0;
+        let u16v = |i: usize| u16::from(v[i]) | (u16::from(v[i + 1]) << 8);
+        let f16v = |i: usize| unpack_half(u16v(i));
+        let f32v = |i: usize| {
+            f32::from_bits(
+                u32::from(v[i])
+                    | (u32::from(v[i + 1]) << 8)
+                    | (u32::from(v[i + 2]) << 16)
+                    | (u32::from(v[i + 3]) << 24),
+            )
+        };
+
+        *texel = match format {
+            TextureFormat::R8 => Vector4::new(u8n(0), 0.0, 0.0, 1.0),
+            TextureFormat::RG8 => Vector4::new(u8n(0), u8n(1), 0.0, 1.0),
+            TextureFormat::RGB8 => Vector4::new(u8n(0), u8n(1), u8n(2), 1.0),
+            TextureFormat::RGBA8 => Vector4::new(u8n(0), u8n(1), u8n(2), u8n(3)),
+            TextureFormat::RGB565 => {
+                let p = u16v(0);
+                Vector4::new(
+                    f32::from(p >> 11) / 31.0,
+                    f32::from((p >> 5) & 0x3F) / 63.0,
+                    f32::from(p & 0x1F) / 31.0,
+                    1.0,
+                )
+            }
+            TextureFormat::RGBA4 => {
+                let p = u16v(0);
+                Vector4::new(
+                    f32::from(p >> 12) / 15.0,
+                    f32::from((p >> 8) & 0xF) / 15.0,
+                    f32::from((p >> 4) & 0xF) / 15.0,
+                    f32::from(p & 0xF) / 15.0,
+                )
+            }
+            TextureFormat::RGBA5551 => {
+                let p = u16v(0);
+                Vector4::new(
+                    f32::from(p >> 11) / 31.0,
+                    f32::from((p >> 6) & 0x1F) / 31.0,
+                    f32::from((p >> 1) & 0x1F) / 31.0,
+                    f32::from(p & 0x1),
+                )
+            }
+            TextureFormat::RGBA1010102 => {
+                let p = f32v(0).to_bits();
+                Vector4::new(
+                    (p & 0x3FF) as f32 / 1023.0,
+                    ((p >> 10) & 0x3FF) as f32 / 1023.0,
+                    ((p >> 20) & 0x3FF) as f32 / 1023.0,
+                    (p >> 30) as f32 / 3.0,
+                )
+            }
+            TextureFormat::R16F => Vector4::new(f16v(0), 0.0, 0.0, 1.0),
+            TextureFormat::RG16F => Vector4::new(f16v(0), f16v(2), 0.0, 1.0),
+            TextureFormat::RGB16F => Vector4::new(f16v(0), f16v(2), f16v(4), 1.0),
+            TextureFormat::RGBA16F => Vector4::new(f16v(0), f16v(2), f16v(4), f16v(6)),
+            TextureFormat::R32F => Vector4::new(f32v(0), 0.0, 0.0, 1.0),
+            TextureFormat::RG32F => Vector4::new(f32v(0), f32v(4), 0.0, 1.0),
+            TextureFormat::RGB32F => Vector4::new(f32v(0), f32v(4), f32v(8), 1.0),
+            TextureFormat::RGBA32F => Vector4::new(f32v(0), f32v(4), f32v(8), f32v(12)),
+            _ => unreachable!(),
+        };
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1380 @@
+//! Capturing and replaying of frames.
+//!
+//! A trace file starts with `MAGIC`, which also carries the version of format, and
+//! is followed by frames in dispatching order. Each frame records the dimensions of
+//! window and the complete list of commands, including the payloads of resource
+//! creations and updates. So it could be feeded back into any `Visitor` later.
+//!
+//! Pixel readbacks are recorded as well, but their results are discarded when
+//! replaying.
+//!
+//! Notes that resources created before capturing are not recorded, you should start
+//! capturing before creating any resource to get a self-contained trace.
+
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::errors::*;
+use crate::math::prelude::{Color, Vector2};
+use crate::res::request::Request;
+use crate::utils::handle::{Handle, HandleLike};
+use crate::utils::prelude::{DataBuffer, HashValue};
+
+use super::super::assets::prelude::*;
+use super::frame::{Command, Frame};
+use super::{UniformVar, Visitor};
+
+/// The magic number and version of trace file.
+pub const MAGIC: [u8; 8] = [b'V', b'T', b'R', b'C', b' ', 0, 0, 11];
+
+/// Serializes dispatched frames into a trace file.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    frames: usize,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Creates a new `TraceWriter`, the header of trace will be written immediately.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&MAGIC)?;
+        Ok(TraceWriter { writer, frames: 0 })
+    }
+
+    /// Returns the number of frames that have been written.
+    #[inline]
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Flushes and unwraps the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    pub(crate) fn write(&mut self, frame: &Frame, dimensions: Vector2<u32>) -> Result<()> {
+        let w = &mut self.writer;
+        w.write_u32::<LittleEndian>(dimensions.x)?;
+        w.write_u32::<LittleEndian>(dimensions.y)?;
+        w.write_u32::<LittleEndian>(frame.cmds.len() as u32)?;
+
+        for cmd in &frame.cmds {
+            encode_command(w, cmd, &frame.bufs)?;
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Reads frames from a trace file, and replays them into `Visitor`.
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Creates a new `TraceReader`, returns error if the header does not match.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+
+        if magic[0..5] != MAGIC[0..5] {
+            bail!("[TraceReader] MAGIC number not match.");
+        }
+
+        if magic[5..8] != MAGIC[5..8] {
+            bail!(
+                "[TraceReader] Version {:?} of trace is not supported (expects {:?}).",
+                &magic[5..8],
+                &MAGIC[5..8]
+            );
+        }
+
+        Ok(TraceReader { reader })
+    }
+
+    /// Replays the next frame into `visitor`. Returns the number of draw calls and
+    /// triangles, or `None` if there are no more frames.
+    pub fn replay_frame(&mut self, visitor: &mut dyn Visitor) -> Result<Option<(u32, u32)>> {
+        match self.read()? {
+            Some((mut frame, dimensions)) => Ok(Some(frame.dispatch(visitor, dimensions)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replays all the remaining frames into `visitor`. Returns the number of frames.
+    pub fn replay(&mut self, visitor: &mut dyn Visitor) -> Result<usize> {
+        let mut frames = 0;
+        while self.replay_frame(visitor)?.is_some() {
+            frames += 1;
+        }
+
+        Ok(frames)
+    }
+
+    pub(crate) fn read(&mut self) -> Result<Option<(Frame, Vector2<u32>)>> {
+        let r = &mut self.reader;
+
+        // Reaching the end of trace file at the start of frame is expected.
+        let x = match r.read_u32::<LittleEndian>() {
+            Ok(v) => v,
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let y = r.read_u32::<LittleEndian>()?;
+        let len = r.read_u32::<LittleEndian>()?;
+
+        let mut frame = Frame::default();
+        for _ in 0..len {
+            let cmd = decode_command(r, &mut frame.bufs)?;
+            frame.cmds.push(cmd);
+        }
+
+        Ok(Some((frame, Vector2::new(x, y))))
+    }
+}
+
+fn encode_command<W: Write>(w: &mut W, cmd: &Command, bufs: &DataBuffer) -> Result<()> {
+    match *cmd {
+        Command::Bind(surface) => {
+            w.write_u8(0)?;
+            encode_handle(w, *surface)?;
+        }
+        Command::Draw(shader, ref state, mesh, mesh_index, instances, ptr) => {
+            w.write_u8(1)?;
+            encode_handle(w, *shader)?;
+
+            match *state {
+                Some(ref v) => {
+                    w.write_u8(1)?;
+                    encode_render_state(w, v)?;
+                }
+                None => w.write_u8(0)?,
+            }
+
+            encode_handle(w, *mesh)?;
+            encode_mesh_index(w, mesh_index)?;
+            encode_mesh_instances(w, instances)?;
+            encode_uniform_vars(w, bufs.as_slice(ptr))?;
+        }
+        Command::UpdateScissor(scissor) => {
+            w.write_u8(2)?;
+            encode_scissor(w, scissor)?;
+        }
+        Command::UpdateViewport(vp) => {
+            w.write_u8(3)?;
+            encode_viewport(w, vp)?;
+        }
+        Command::CreateSurface(ref v) => {
+            w.write_u8(4)?;
+            encode_handle(w, *v.0)?;
+            encode_surface_params(w, &v.1)?;
+        }
+        Command::DeleteSurface(handle) => {
+            w.write_u8(5)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::CreateShader(ref v) => {
+            w.write_u8(6)?;
+            encode_handle(w, *v.0)?;
+            encode_shader_params(w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+            bincode::serialize_into(&mut *w, &v.3)?;
+        }
+        Command::DeleteShader(handle) => {
+            w.write_u8(7)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::CreateTexture(ref v) => {
+            w.write_u8(8)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+        }
+        Command::UpdateTexture(handle, layer, area, ptr) => {
+            w.write_u8(9)?;
+            encode_handle(w, *handle)?;
+            w.write_u32::<LittleEndian>(layer)?;
+            bincode::serialize_into(&mut *w, &area)?;
+            encode_bytes(w, bufs.as_slice(ptr))?;
+        }
+        Command::DeleteTexture(handle) => {
+            w.write_u8(10)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::CreateRenderTexture(ref v) => {
+            w.write_u8(11)?;
+            encode_handle(w, *v.0)?;
+            encode_render_texture_params(w, &v.1)?;
+        }
+        Command::DeleteRenderTexture(handle) => {
+            w.write_u8(12)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::CreateMesh(ref v) => {
+            w.write_u8(13)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+        }
+        Command::UpdateVertexBuffer(handle, offset, ptr) => {
+            w.write_u8(14)?;
+            encode_handle(w, *handle)?;
+            w.write_u64::<LittleEndian>(offset as u64)?;
+            encode_bytes(w, bufs.as_slice(ptr))?;
+        }
+        Command::UpdateIndexBuffer(handle, offset, ptr) => {
+            w.write_u8(15)?;
+            encode_handle(w, *handle)?;
+            w.write_u64::<LittleEndian>(offset as u64)?;
+            encode_bytes(w, bufs.as_slice(ptr))?;
+        }
+        Command::DeleteMesh(handle) => {
+            w.write_u8(16)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::ReadSurface(ref v) => {
+            w.write_u8(17)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+        }
+        Command::ReadRenderTexture(ref v) => {
+            w.write_u8(18)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+        }
+        Command::CreateUniformBlock(ref v) => {
+            w.write_u8(19)?;
+            encode_handle(w, *v.0)?;
+            encode_uniform_block_params(w, &v.1)?;
+        }
+        Command::UpdateUniformBlock(handle, ptr) => {
+            w.write_u8(20)?;
+            encode_handle(w, *handle)?;
+            encode_uniform_vars(w, bufs.as_slice(ptr))?;
+        }
+        Command::DeleteUniformBlock(handle) => {
+            w.write_u8(21)?;
+            encode_handle(w, *handle)?;
+        }
+        Command::ReloadShader(ref v) => {
+            w.write_u8(22)?;
+            encode_handle(w, *v.0)?;
+            encode_shader_params(w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+            bincode::serialize_into(&mut *w, &v.3)?;
+        }
+        Command::ReloadTexture(ref v) => {
+            w.write_u8(23)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+        }
+        Command::ReloadMesh(ref v) => {
+            w.write_u8(24)?;
+            encode_handle(w, *v.0)?;
+            bincode::serialize_into(&mut *w, &v.1)?;
+            bincode::serialize_into(&mut *w, &v.2)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_command<R: Read>(r: &mut R, bufs: &mut DataBuffer) -> Result<Command> {
+    let cmd = match r.read_u8()? {
+        0 => Command::Bind(decode_handle(r)?),
+        1 => {
+            let shader = decode_handle(r)?;
+            let state = match r.read_u8()? {
+                0 => None,
+                _ => Some(Box::new(decode_render_state(r)?)),
+            };
+
+            let mesh = decode_handle(r)?;
+            let mesh_index = decode_mesh_index(r)?;
+            let instances = decode_mesh_instances(r)?;
+            let ptr = bufs.extend_from_slice(&decode_uniform_vars(r)?);
+            Command::Draw(shader, state, mesh, mesh_index, instances, ptr)
+        }
+        2 => Command::UpdateScissor(decode_scissor(r)?),
+        3 => Command::UpdateViewport(decode_viewport(r)?),
+        4 => {
+            let handle = decode_handle(r)?;
+            let params = decode_surface_params(r)?;
+            Command::CreateSurface(Box::new((handle, params)))
+        }
+        5 => Command::DeleteSurface(decode_handle(r)?),
+        6 => {
+            let handle = decode_handle(r)?;
+            let params = decode_shader_params(r)?;
+            let vs: String = bincode::deserialize_from(&mut *r)?;
+            let fs: String = bincode::deserialize_from(&mut *r)?;
+            Command::CreateShader(Box::new((handle, params, vs, fs)))
+        }
+        7 => Command::DeleteShader(decode_handle(r)?),
+        8 => {
+            let handle = decode_handle(r)?;
+            let params: TextureParams = bincode::deserialize_from(&mut *r)?;
+            let data: Option<TextureData> = bincode::deserialize_from(&mut *r)?;
+            Command::CreateTexture(Box::new((handle, params, data)))
+        }
+        9 => {
+            let handle = decode_handle(r)?;
+            let layer = r.read_u32::<LittleEndian>()?;
+            let area = bincode::deserialize_from(&mut *r)?;
+            let bytes = decode_bytes(r)?;
+            Command::UpdateTexture(handle, layer, area, bufs.extend_from_slice(&bytes))
+        }
+        10 => Command::DeleteTexture(decode_handle(r)?),
+        11 => {
+            let handle = decode_handle(r)?;
+            let params = decode_render_texture_params(r)?;
+            Command::CreateRenderTexture(Box::new((handle, params)))
+        }
+        12 => Command::DeleteRenderTexture(decode_handle(r)?),
+        13 => {
+            let handle = decode_handle(r)?;
+            let params: MeshParams = bincode::deserialize_from(&mut *r)?;
+            let data: Option<MeshData> = bincode::deserialize_from(&mut *r)?;
+            Command::CreateMesh(Box::new((handle, params, data)))
+        }
+        14 => {
+            let handle = decode_handle(r)?;
+            let offset = r.read_u64::<LittleEndian>()? as usize;
+            let bytes = decode_bytes(r)?;
+            Command::UpdateVertexBuffer(handle, offset, bufs.extend_from_slice(&bytes))
+        }
+        15 => {
+            let handle = decode_handle(r)?;
+            let offset = r.read_u64::<LittleEndian>()? as usize;
+            let bytes = decode_bytes(r)?;
+            Command::UpdateIndexBuffer(handle, offset, bufs.extend_from_slice(&bytes))
+        }
+        16 => Command::DeleteMesh(decode_handle(r)?),
+        17 => {
+            let handle = decode_handle(r)?;
+            let area = bincode::deserialize_from(&mut *r)?;
+            Command::ReadSurface(Box::new((handle, area, Request::latch())))
+        }
+        18 => {
+            let handle = decode_handle(r)?;
+            let area = bincode::deserialize_from(&mut *r)?;
+            Command::ReadRenderTexture(Box::new((handle, area, Request::latch())))
+        }
+        19 => {
+            let handle = decode_handle(r)?;
+            let params = decode_uniform_block_params(r)?;
+            Command::CreateUniformBlock(Box::new((handle, params)))
+        }
+        20 => {
+            let handle = decode_handle(r)?;
+            let ptr = bufs.extend_from_slice(&decode_uniform_vars(r)?);
+            Command::UpdateUniformBlock(handle, ptr)
+        }
+        21 => Command::DeleteUniformBlock(decode_handle(r)?),
+        22 => {
+            let handle = decode_handle(r)?;
+            let params = decode_shader_params(r)?;
+            let vs: String = bincode::deserialize_from(&mut *r)?;
+            let fs: String = bincode::deserialize_from(&mut *r)?;
+            Command::ReloadShader(Box::new((handle, params, vs, fs)))
+        }
+        23 => {
+            let handle = decode_handle(r)?;
+            let params: TextureParams = bincode::deserialize_from(&mut *r)?;
+            let data: Option<TextureData> = bincode::deserialize_from(&mut *r)?;
+            Command::ReloadTexture(Box::new((handle, params, data)))
+        }
+        24 => {
+            let handle = decode_handle(r)?;
+            let params: MeshParams = bincode::deserialize_from(&mut *r)?;
+            let data: Option<MeshData> = bincode::deserialize_from(&mut *r)?;
+            Command::ReloadMesh(Box::new((handle, params, data)))
+        }
+        v => bail!("[TraceReader] Undefined command {}.", v),
+    };
+
+    Ok(cmd)
+}
+
+fn encode_handle<W: Write>(w: &mut W, handle: Handle) -> Result<()> {
+    w.write_u32::<LittleEndian>(handle.index())?;
+    w.write_u32::<LittleEndian>(handle.version())?;
+    Ok(())
+}
+
+fn decode_handle<R: Read, H: HandleLike>(r: &mut R) -> Result<H> {
+    let index = r.read_u32::<LittleEndian>()?;
+    let version = r.read_u32::<LittleEndian>()?;
+    Ok(H::new(index, version))
+}
+
+fn encode_option_handle<W: Write>(w: &mut W, handle: Option<Handle>) -> Result<()> {
+    match handle {
+        Some(v) => {
+            w.write_u8(1)?;
+            encode_handle(w, v)
+        }
+        None => {
+            w.write_u8(0)?;
+            Ok(())
+        }
+    }
+}
+
+fn decode_option_handle<R: Read, H: HandleLike>(r: &mut R) -> Result<Option<H>> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        _ => Ok(Some(decode_handle(r)?)),
+    }
+}
+
+fn encode_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> Result<()> {
+    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+fn decode_bytes<R: Read>(r: &mut R) -> Result<Vec<u8>> {
+    let len = r.read_u32::<LittleEndian>()?;
+    let mut bytes = vec![0; len as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn encode_floats<W: Write>(w: &mut W, v: &[f32]) -> Result<()> {
+    for &f in v {
+        w.write_f32::<LittleEndian>(f)?;
+    }
+
+    Ok(())
+}
+
+fn decode_floats<R: Read>(r: &mut R, v: &mut [f32]) -> Result<()> {
+    for f in v {
+        *f = r.read_f32::<LittleEndian>()?;
+    }
+
+    Ok(())
+}
+
+fn encode_mesh_index<W: Write>(w: &mut W, index: MeshIndex) -> Result<()> {
+    match index {
+        MeshIndex::SubMesh(v) => {
+            w.write_u8(0)?;
+            w.write_u64::<LittleEndian>(v as u64)?;
+        }
+        MeshIndex::Ptr(from, len) => {
+            w.write_u8(1)?;
+            w.write_u64::<LittleEndian>(from as u64)?;
+            w.write_u64::<LittleEndian>(len as u64)?;
+        }
+        MeshIndex::All => {
+            w.write_u8(2)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_mesh_index<R: Read>(r: &mut R) -> Result<MeshIndex> {
+    let index = match r.read_u8()? {
+        0 => MeshIndex::SubMesh(r.read_u64::<LittleEndian>()? as usize),
+        1 => {
+            let from = r.read_u64::<LittleEndian>()? as usize;
+            let len = r.read_u64::<LittleEndian>()? as usize;
+            MeshIndex::Ptr(from, len)
+        }
+        2 => MeshIndex::All,
+        v => bail!("[TraceReader] Undefined mesh index {}.", v),
+    };
+
+    Ok(index)
+}
+
+fn encode_mesh_instances<W: Write>(w: &mut W, instances: Option<MeshInstances>) -> Result<()> {
+    match instances {
+        Some(v) => {
+            w.write_u8(1)?;
+            w.write_u32::<LittleEndian>(v.count)?;
+            encode_option_handle(w, v.mesh.map(|v| *v))?;
+            w.write_u32::<LittleEndian>(v.divisor)?;
+        }
+        None => {
+            w.write_u8(0)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_mesh_instances<R: Read>(r: &mut R) -> Result<Option<MeshInstances>> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        1 => {
+            let count = r.read_u32::<LittleEndian>()?;
+            let mesh = decode_option_handle(r)?;
+            let divisor = r.read_u32::<LittleEndian>()?;
+            Ok(Some(MeshInstances {
+                count,
+                mesh,
+                divisor,
+            }))
+        }
+        v => bail!("[TraceReader] Undefined mesh instances {}.", v),
+    }
+}
+
+fn encode_uniform_vars<W: Write>(w: &mut W, vars: &[UniformVar]) -> Result<()> {
+    w.write_u32::<LittleEndian>(vars.len() as u32)?;
+    for &(field, variable) in vars {
+        bincode::serialize_into(&mut *w, &field)?;
+        encode_uniform_variable(w, variable)?;
+    }
+
+    Ok(())
+}
+
+fn decode_uniform_vars<R: Read>(r: &mut R) -> Result<Vec<UniformVar>> {
+    let len = r.read_u32::<LittleEndian>()?;
+    let mut vars = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let field: HashValue<str> = bincode::deserialize_from(&mut *r)?;
+        vars.push((field, decode_uniform_variable(r)?));
+    }
+
+    Ok(vars)
+}
+
+fn encode_uniform_variable<W: Write>(w: &mut W, variable: UniformVariable) -> Result<()> {
+    match variable {
+        UniformVariable::Texture(handle) => {
+            w.write_u8(0)?;
+            encode_handle(w, *handle)?;
+        }
+        UniformVariable::RenderTexture(handle) => {
+            w.write_u8(1)?;
+            encode_handle(w, *handle)?;
+        }
+        UniformVariable::I32(v) => {
+            w.write_u8(2)?;
+            w.write_i32::<LittleEndian>(v)?;
+        }
+        UniformVariable::F32(v) => {
+            w.write_u8(3)?;
+            w.write_f32::<LittleEndian>(v)?;
+        }
+        UniformVariable::Vector2f(v) => {
+            w.write_u8(4)?;
+            encode_floats(w, &v)?;
+        }
+        UniformVariable::Vector3f(v) => {
+            w.write_u8(5)?;
+            encode_floats(w, &v)?;
+        }
+        UniformVariable::Vector4f(v) => {
+            w.write_u8(6)?;
+            encode_floats(w, &v)?;
+        }
+        UniformVariable::Matrix2f(v, transpose) => {
+            w.write_u8(7)?;
+            for column in &v {
+                encode_floats(w, column)?;
+            }
+            w.write_u8(transpose as u8)?;
+        }
+        UniformVariable::Matrix3f(v, transpose) => {
+            w.write_u8(8)?;
+            for column in &v {
+                encode_floats(w, column)?;
+            }
+            w.write_u8(transpose as u8)?;
+        }
+        UniformVariable::Matrix4f(v, transpose) => {
+            w.write_u8(9)?;
+            for column in &v {
+                encode_floats(w, column)?;
+            }
+            w.write_u8(transpose as u8)?;
+        }
+        UniformVariable::UniformBlock(handle) => {
+            w.write_u8(10)?;
+            encode_handle(w, *handle)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_uniform_variable<R: Read>(r: &mut R) -> Result<UniformVariable> {
+    let variable = match r.read_u8()? {
+        0 => UniformVariable::Texture(decode_handle(r)?),
+        1 => UniformVariable::RenderTexture(decode_handle(r)?),
+        2 => UniformVariable::I32(r.read_i32::<LittleEndian>()?),
+        3 => UniformVariable::F32(r.read_f32::<LittleEndian>()?),
+        4 => {
+            let mut v = [0.0; 2];
+            decode_floats(r, &mut v)?;
+            UniformVariable::Vector2f(v)
+        }
+        5 => {
+            let mut v = [0.0; 3];
+            decode_floats(r, &mut v)?;
+            UniformVariable::Vector3f(v)
+        }
+        6 => {
+            let mut v = [0.0; 4];
+            decode_floats(r, &mut v)?;
+            UniformVariable::Vector4f(v)
+        }
+        7 => {
+            let mut v = [[0.0; 2]; 2];
+            for column in &mut v {
+                decode_floats(r, column)?;
+            }
+            UniformVariable::Matrix2f(v, r.read_u8()? != 0)
+        }
+        8 => {
+            let mut v = [[0.0; 3]; 3];
+            for column in &mut v {
+                decode_floats(r, column)?;
+            }
+            UniformVariable::Matrix3f(v, r.read_u8()? != 0)
+        }
+        9 => {
+            let mut v = [[0.0; 4]; 4];
+            for column in &mut v {
+                decode_floats(r, column)?;
+            }
+            UniformVariable::Matrix4f(v, r.read_u8()? != 0)
+        }
+        10 => UniformVariable::UniformBlock(decode_handle(r)?),
+        v => bail!("[TraceReader] Undefined uniform variable {}.", v),
+    };
+
+    Ok(variable)
+}
+
+fn encode_scissor<W: Write>(w: &mut W, scissor: SurfaceScissor) -> Result<()> {
+    match scissor {
+        SurfaceScissor::Enable { position, size } => {
+            w.write_u8(1)?;
+            encode_viewport(w, SurfaceViewport { position, size })
+        }
+        SurfaceScissor::Disable => {
+            w.write_u8(0)?;
+            Ok(())
+        }
+    }
+}
+
+fn decode_scissor<R: Read>(r: &mut R) -> Result<SurfaceScissor> {
+    match r.read_u8()? {
+        0 => Ok(SurfaceScissor::Disable),
+        _ => {
+            let vp = decode_viewport(r)?;
+            Ok(SurfaceScissor::Enable {
+                position: vp.position,
+                size: vp.size,
+            })
+        }
+    }
+}
+
+fn encode_viewport<W: Write>(w: &mut W, vp: SurfaceViewport) -> Result<()> {
+    w.write_i32::<LittleEndian>(vp.position.x)?;
+    w.write_i32::<LittleEndian>(vp.position.y)?;
+    w.write_u32::<LittleEndian>(vp.size.x)?;
+    w.write_u32::<LittleEndian>(vp.size.y)?;
+    Ok(())
+}
+
+fn decode_viewport<R: Read>(r: &mut R) -> Result<SurfaceViewport> {
+    let x = r.read_i32::<LittleEndian>()?;
+    let y = r.read_i32::<LittleEndian>()?;
+    let w = r.read_u32::<LittleEndian>()?;
+    let h = r.read_u32::<LittleEndian>()?;
+
+    Ok(SurfaceViewport {
+        position: Vector2::new(x, y),
+        size: Vector2::new(w, h),
+    })
+}
+
+fn encode_surface_params<W: Write>(w: &mut W, params: &SurfaceParams) -> Result<()> {
+    for v in &params.colors {
+        encode_option_handle(w, v.map(|v| *v))?;
+    }
+
+    encode_option_handle(w, params.depth_stencil.map(|v| *v))?;
+
+    match params.clear_color {
+        Some(v) => {
+            w.write_u8(1)?;
+            encode_floats(w, &[v.r, v.g, v.b, v.a])?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    match params.clear_depth {
+        Some(v) => {
+            w.write_u8(1)?;
+            w.write_f32::<LittleEndian>(v)?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    match params.clear_stencil {
+        Some(v) => {
+            w.write_u8(1)?;
+            w.write_i32::<LittleEndian>(v)?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    Ok(())
+}
+
+fn decode_surface_params<R: Read>(r: &mut R) -> Result<SurfaceParams> {
+    let mut params = SurfaceParams::default();
+
+    for v in &mut params.colors {
+        *v = decode_option_handle(r)?;
+    }
+
+    params.depth_stencil = decode_option_handle(r)?;
+
+    params.clear_color = match r.read_u8()? {
+        0 => None,
+        _ => {
+            let mut v = [0.0; 4];
+            decode_floats(r, &mut v)?;
+            Some(Color::new(v[0], v[1], v[2], v[3]))
+        }
+    };
+
+    params.clear_depth = match r.read_u8()? {
+        0 => None,
+        _ => Some(r.read_f32::<LittleEndian>()?),
+    };
+
+    params.clear_stencil = match r.read_u8()? {
+        0 => None,
+        _ => Some(r.read_i32::<LittleEndian>()?),
+    };
+
+    Ok(params)
+}
+
+fn encode_shader_params<W: Write>(w: &mut W, params: &ShaderParams) -> Result<()> {
+    let attributes: Vec<_> = params.attributes.iter().collect();
+    w.write_u8(attributes.len() as u8)?;
+    for (name, size, required) in attributes {
+        bincode::serialize_into(&mut *w, &name)?;
+        w.write_u8(size)?;
+        w.write_u8(required as u8)?;
+    }
+
+    w.write_u8(params.uniforms.len() as u8)?;
+    for &(ref name, tp) in params.uniforms.iter() {
+        bincode::serialize_into(&mut *w, name)?;
+        w.write_u8(encode_uniform_variable_type(tp))?;
+    }
+
+    encode_render_state(w, &params.state)
+}
+
+fn decode_shader_params<R: Read>(r: &mut R) -> Result<ShaderParams> {
+    let mut params = ShaderParams::default();
+
+    let mut attributes = AttributeLayout::build();
+    for _ in 0..r.read_u8()? {
+        let name: Attribute = bincode::deserialize_from(&mut *r)?;
+        let size = r.read_u8()?;
+        attributes = if r.read_u8()? != 0 {
+            attributes.with(name, size)
+        } else {
+            attributes.with_optional(name, size)
+        };
+    }
+    params.attributes = attributes.finish();
+
+    let mut uniforms = UniformVariableLayout::build();
+    for _ in 0..r.read_u8()? {
+        let name: String = bincode::deserialize_from(&mut *r)?;
+        let tp = decode_uniform_variable_type(r.read_u8()?)?;
+        uniforms = uniforms.with(name, tp);
+    }
+    params.uniforms = uniforms.finish();
+
+    params.state = decode_render_state(r)?;
+    Ok(params)
+}
+
+fn encode_uniform_block_params<W: Write>(w: &mut W, params: &UniformBlockParams) -> Result<()> {
+    w.write_u32::<LittleEndian>(params.layout.len() as u32)?;
+    for (name, tp) in params.layout.iter() {
+        bincode::serialize_into(&mut *w, name)?;
+        w.write_u8(encode_uniform_variable_type(tp))?;
+    }
+
+    Ok(())
+}
+
+fn decode_uniform_block_params<R: Read>(r: &mut R) -> Result<UniformBlockParams> {
+    let mut layout = UniformBlockLayout::build();
+    for _ in 0..r.read_u32::<LittleEndian>()? {
+        let name: String = bincode::deserialize_from(&mut *r)?;
+        let tp = decode_uniform_variable_type(r.read_u8()?)?;
+        layout = layout.with(name, tp);
+    }
+
+    Ok(UniformBlockParams {
+        layout: layout.finish(),
+    })
+}
+
+fn encode_uniform_variable_type(tp: UniformVariableType) -> u8 {
+    match tp {
+        UniformVariableType::Texture => 0,
+        UniformVariableType::RenderTexture => 1,
+        UniformVariableType::I32 => 2,
+        UniformVariableType::F32 => 3,
+        UniformVariableType::Vector2f => 4,
+        UniformVariableType::Vector3f => 5,
+        UniformVariableType::Vector4f => 6,
+        UniformVariableType::Matrix2f => 7,
+        UniformVariableType::Matrix3f => 8,
+        UniformVariableType::Matrix4f => 9,
+        UniformVariableType::UniformBlock => 10,
+        UniformVariableType::TextureCube => 11,
+        UniformVariableType::Texture2DArray => 12,
+        UniformVariableType::Texture3D => 13,
+    }
+}
+
+fn decode_uniform_variable_type(v: u8) -> Result<UniformVariableType> {
+    let tp = match v {
+        0 => UniformVariableType::Texture,
+        1 => UniformVariableType::RenderTexture,
+        2 => UniformVariableType::I32,
+        3 => UniformVariableType::F32,
+        4 => UniformVariableType::Vector2f,
+        5 => UniformVariableType::Vector3f,
+        6 => UniformVariableType::Vector4f,
+        7 => UniformVariableType::Matrix2f,
+        8 => UniformVariableType::Matrix3f,
+        9 => UniformVariableType::Matrix4f,
+        10 => UniformVariableType::UniformBlock,
+        11 => UniformVariableType::TextureCube,
+        12 => UniformVariableType::Texture2DArray,
+        13 => UniformVariableType::Texture3D,
+        v => bail!("[TraceReader] Undefined uniform variable type {}.", v),
+    };
+
+    Ok(tp)
+}
+
+fn encode_render_state<W: Write>(w: &mut W, state: &RenderState) -> Result<()> {
+    w.write_u8(match state.cull_face {
+        CullFace::Nothing => 0,
+        CullFace::Front => 1,
+        CullFace::Back => 2,
+    })?;
+
+    w.write_u8(match state.front_face_order {
+        FrontFaceOrder::Clockwise => 0,
+        FrontFaceOrder::CounterClockwise => 1,
+    })?;
+
+    w.write_u8(encode_comparison(state.depth_test))?;
+    w.write_u8(state.depth_write as u8)?;
+
+    match state.depth_write_offset {
+        Some((factor, units)) => {
+            w.write_u8(1)?;
+            encode_floats(w, &[factor, units])?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    match state.color_blend {
+        Some((equation, src, dst)) => {
+            w.write_u8(1)?;
+            w.write_u8(match equation {
+                Equation::Add => 0,
+                Equation::Subtract => 1,
+                Equation::ReverseSubtract => 2,
+            })?;
+            w.write_u8(encode_blend_factor(src))?;
+            w.write_u8(encode_blend_factor(dst))?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    let mask = state.color_write;
+    w.write_u8(mask.0 as u8 | (mask.1 as u8) << 1 | (mask.2 as u8) << 2 | (mask.3 as u8) << 3)?;
+
+    match state.stencil {
+        Some(v) => {
+            w.write_u8(1)?;
+            encode_stencil_face(w, &v.front)?;
+            encode_stencil_face(w, &v.back)?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    Ok(())
+}
+
+fn decode_render_state<R: Read>(r: &mut R) -> Result<RenderState> {
+    let mut state = RenderState::default();
+
+    state.cull_face = match r.read_u8()? {
+        0 => CullFace::Nothing,
+        1 => CullFace::Front,
+        2 => CullFace::Back,
+        v => bail!("[TraceReader] Undefined cull face {}.", v),
+    };
+
+    state.front_face_order = match r.read_u8()? {
+        0 => FrontFaceOrder::Clockwise,
+        _ => FrontFaceOrder::CounterClockwise,
+    };
+
+    state.depth_test = decode_comparison(r.read_u8()?)?;
+    state.depth_write = r.read_u8()? != 0;
+
+    state.depth_write_offset = match r.read_u8()? {
+        0 => None,
+        _ => {
+            let mut v = [0.0; 2];
+            decode_floats(r, &mut v)?;
+            Some((v[0], v[1]))
+        }
+    };
+
+    state.color_blend = match r.read_u8()? {
+        0 => None,
+        _ => {
+            let equation = match r.read_u8()? {
+                0 => Equation::Add,
+                1 => Equation::Subtract,
+                2 => Equation::ReverseSubtract,
+                v => bail!("[TraceReader] Undefined blend equation {}.", v),
+            };
+
+            let src = decode_blend_factor(r.read_u8()?)?;
+            let dst = decode_blend_factor(r.read_u8()?)?;
+            Some((equation, src, dst))
+        }
+    };
+
+    let mask = r.read_u8()?;
+    state.color_write = (mask & 1 != 0, mask & 2 != 0, mask & 4 != 0, mask & 8 != 0);
+
+    state.stencil = match r.read_u8()? {
+        0 => None,
+        _ => {
+            let front = decode_stencil_face(r)?;
+            let back = decode_stencil_face(r)?;
+            Some(StencilState { front, back })
+        }
+    };
+
+    Ok(state)
+}
+
+fn encode_stencil_face<W: Write>(w: &mut W, face: &StencilFace) -> Result<()> {
+    w.write_u8(encode_comparison(face.comparison))?;
+    w.write_u8(face.reference)?;
+    w.write_u8(face.read_mask)?;
+    w.write_u8(face.write_mask)?;
+    w.write_u8(encode_stencil_op(face.fail))?;
+    w.write_u8(encode_stencil_op(face.depth_fail))?;
+    w.write_u8(encode_stencil_op(face.pass))?;
+    Ok(())
+}
+
+fn decode_stencil_face<R: Read>(r: &mut R) -> Result<StencilFace> {
+    Ok(StencilFace {
+        comparison: decode_comparison(r.read_u8()?)?,
+        reference: r.read_u8()?,
+        read_mask: r.read_u8()?,
+        write_mask: r.read_u8()?,
+        fail: decode_stencil_op(r.read_u8()?)?,
+        depth_fail: decode_stencil_op(r.read_u8()?)?,
+        pass: decode_stencil_op(r.read_u8()?)?,
+    })
+}
+
+fn encode_stencil_op(op: StencilOp) -> u8 {
+    match op {
+        StencilOp::Keep => 0,
+        StencilOp::Zero => 1,
+        StencilOp::Replace => 2,
+        StencilOp::Increment => 3,
+        StencilOp::IncrementWrap => 4,
+        StencilOp::Decrement => 5,
+        StencilOp::DecrementWrap => 6,
+        StencilOp::Invert => 7,
+    }
+}
+
+fn decode_stencil_op(v: u8) -> Result<StencilOp> {
+    let op = match v {
+        0 => StencilOp::Keep,
+        1 => StencilOp::Zero,
+        2 => StencilOp::Replace,
+        3 => StencilOp::Increment,
+        4 => StencilOp::IncrementWrap,
+        5 => StencilOp::Decrement,
+        6 => StencilOp::DecrementWrap,
+        7 => StencilOp::Invert,
+        v => bail!("[TraceReader] Undefined stencil operation {}.", v),
+    };
+
+    Ok(op)
+}
+
+fn encode_comparison(cmp: Comparison) -> u8 {
+    match cmp {
+        Comparison::Never => 0,
+        Comparison::Less => 1,
+        Comparison::LessOrEqual => 2,
+        Comparison::Greater => 3,
+        Comparison::GreaterOrEqual => 4,
+        Comparison::Equal => 5,
+        Comparison::NotEqual => 6,
+        Comparison::Always => 7,
+    }
+}
+
+fn decode_comparison(v: u8) -> Result<Comparison> {
+    let cmp = match v {
+        0 => Comparison::Never,
+        1 => Comparison::Less,
+        2 => Comparison::LessOrEqual,
+        3 => Comparison::Greater,
+        4 => Comparison::GreaterOrEqual,
+        5 => Comparison::Equal,
+        6 => Comparison::NotEqual,
+        7 => Comparison::Always,
+        v => bail!("[TraceReader] Undefined comparison {}.", v),
+    };
+
+    Ok(cmp)
+}
+
+fn encode_blend_factor(factor: BlendFactor) -> u8 {
+    let value = |v: BlendValue| match v {
+        BlendValue::SourceColor => 0,
+        BlendValue::SourceAlpha => 1,
+        BlendValue::DestinationColor => 2,
+        BlendValue::DestinationAlpha => 3,
+    };
+
+    match factor {
+        BlendFactor::Zero => 0,
+        BlendFactor::One => 1,
+        BlendFactor::Value(v) => 0x10 | value(v),
+        BlendFactor::OneMinusValue(v) => 0x20 | value(v),
+    }
+}
+
+fn decode_blend_factor(v: u8) -> Result<BlendFactor> {
+    let value = match v & 0xF {
+        0 => BlendValue::SourceColor,
+        1 => BlendValue::SourceAlpha,
+        2 => BlendValue::DestinationColor,
+        _ => BlendValue::DestinationAlpha,
+    };
+
+    let factor = match v {
+        0 => BlendFactor::Zero,
+        1 => BlendFactor::One,
+        _ if v >> 4 == 1 => BlendFactor::Value(value),
+        _ if v >> 4 == 2 => BlendFactor::OneMinusValue(value),
+        _ => bail!("[TraceReader] Undefined blend factor {}.", v),
+    };
+
+    Ok(factor)
+}
+
+fn encode_render_texture_params<W: Write>(w: &mut W, params: &RenderTextureParams) -> Result<()> {
+    w.write_u8(params.format as u8)?;
+    w.write_u32::<LittleEndian>(params.dimensions.x)?;
+    w.write_u32::<LittleEndian>(params.dimensions.y)?;
+    bincode::serialize_into(&mut *w, &params.sampler)?;
+    Ok(())
+}
+
+fn decode_render_texture_params<R: Read>(r: &mut R) -> Result<RenderTextureParams> {
+    let format = match r.read_u8()? {
+        0 => RenderTextureFormat::RGB8,
+        1 => RenderTextureFormat::RGBA4,
+        2 => RenderTextureFormat::RGBA8,
+        3 => RenderTextureFormat::Depth16,
+        4 => RenderTextureFormat::Depth24,
+        5 => RenderTextureFormat::Depth32,
+        6 => RenderTextureFormat::Depth24Stencil8,
+        7 => RenderTextureFormat::RGBA16F,
+        v => bail!("[TraceReader] Undefined render texture format {}.", v),
+    };
+
+    let x = r.read_u32::<LittleEndian>()?;
+    let y = r.read_u32::<LittleEndian>()?;
+    let sampler = bincode::deserialize_from(&mut *r)?;
+
+    Ok(RenderTextureParams {
+        format,
+        dimensions: Vector2::new(x, y),
+        sampler,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::software::{SoftwareParams, SoftwareVisitor};
+    use super::*;
+    use crate::math::prelude::Aabb2;
+    use cgmath::Point2;
+
+    fn frame(block: bool) -> Frame {
+        let mut frame = Frame::default();
+
+        let surface = SurfaceHandle::new(1, 1);
+        let mut params = SurfaceParams::default();
+        params.set_clear(Color::red(), 1.0, None);
+        frame
+            .cmds
+            .push(Command::CreateSurface(Box::new((surface, params))));
+
+        let shader = ShaderHandle::new(1, 1);
+        let mut params = ShaderParams::default();
+        params.attributes = AttributeLayout::build()
+            .with(Attribute::Position, 2)
+            .finish();
+        params.uniforms = if block {
+            UniformVariableLayout::build()
+                .with("Material", UniformVariableType::UniformBlock)
+                .finish()
+        } else {
+            UniformVariableLayout::build()
+                .with("u_Color", UniformVariableType::Vector4f)
+                .finish()
+        };
+        params.state.color_blend = Some((
+            Equation::Add,
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        ));
+        params.state.stencil = Some(StencilState {
+            front: StencilFace {
+                comparison: Comparison::Equal,
+                reference: 1,
+                pass: StencilOp::IncrementWrap,
+                ..StencilFace::default()
+            },
+            back: StencilFace::default(),
+        });
+        let cmd =
+            Command::CreateShader(Box::new((shader, params.clone(), "vs".into(), "fs".into())));
+        frame.cmds.push(cmd);
+
+        // The draw call overrides the state of shader with the same blending, but without
+        // stencil test. The shader itself is reloaded with the stencil test.
+        let mut state = params.state;
+        state.stencil = None;
+
+        let cmd = Command::ReloadShader(Box::new((shader, params, "vs".into(), "fs".into())));
+        frame.cmds.push(cmd);
+
+        let texture = TextureHandle::new(1, 1);
+        let mut params = TextureParams::default();
+        params.hint = TextureHint::Dynamic;
+        params.dimensions = Vector2::new(2, 2);
+        frame
+            .cmds
+            .push(Command::CreateTexture(Box::new((texture, params, None))));
+        frame
+            .cmds
+            .push(Command::ReloadTexture(Box::new((texture, params, None))));
+        let area = Aabb2::new(Point2::new(0, 0), Point2::new(2, 2));
+        let ptr = frame.bufs.extend_from_slice(&[255u8; 16]);
+        frame.cmds.push(Command::UpdateTexture(texture, 0, area, ptr));
+
+        let mesh = MeshHandle::new(1, 1);
+        let mut params = MeshParams::default();
+        params.layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Float, 2, false)
+            .finish();
+        params.num_verts = 3;
+        params.num_idxes = 3;
+
+        let mut vptr = Vec::new();
+        for v in &[-1.0f32, -1.0, 3.0, -1.0, -1.0, 3.0] {
+            vptr.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+
+        let data = MeshData {
+            vptr: vptr.into_boxed_slice(),
+            iptr: IndexFormat::encode(&[0u16, 1, 2])
+                .to_vec()
+                .into_boxed_slice(),
+        };
+        frame
+            .cmds
+            .push(Command::CreateMesh(Box::new((mesh, params.clone(), None))));
+        frame
+            .cmds
+            .push(Command::ReloadMesh(Box::new((mesh, params, Some(data)))));
+
+        let vars = [(
+            "u_Color".into(),
+            UniformVariable::Vector4f([0.0, 0.0, 1.0, 0.5]),
+        )];
+
+        let ptr = if block {
+            let block = UniformBlockHandle::new(1, 1);
+            let params = UniformBlockParams {
+                layout: UniformBlockLayout::build()
+                    .with("u_Color", UniformVariableType::Vector4f)
+                    .finish(),
+            };
+            frame
+                .cmds
+                .push(Command::CreateUniformBlock(Box::new((block, params))));
+            let ptr = frame.bufs.extend_from_slice(&vars);
+            frame.cmds.push(Command::UpdateUniformBlock(block, ptr));
+
+            let vars = [("Material".into(), UniformVariable::UniformBlock(block))];
+            frame.bufs.extend_from_slice(&vars)
+        } else {
+            frame.bufs.extend_from_slice(&vars)
+        };
+
+        frame.cmds.push(Command::Bind(surface));
+        let state = Some(Box::new(state));
+        let cmd = Command::Draw(shader, state, mesh, MeshIndex::All, None, ptr);
+        frame.cmds.push(cmd);
+        frame
+    }
+
+    fn replay_and_check(block: bool) -> Vec<u8> {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.write(&frame(block), Vector2::new(4, 4)).unwrap();
+        writer.write(&Frame::default(), Vector2::new(4, 4)).unwrap();
+        assert_eq!(writer.frames(), 2);
+
+        let bytes = writer.into_inner().unwrap();
+
+        let params = SoftwareParams {
+            dimensions: Vector2::new(4, 4),
+            ..Default::default()
+        };
+
+        let mut visitor = SoftwareVisitor::new(params);
+        let mut reader = TraceReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.replay_frame(&mut visitor).unwrap(), Some((1, 1)));
+
+        let mut expected = SoftwareVisitor::new(SoftwareParams {
+            dimensions: Vector2::new(4, 4),
+            ..Default::default()
+        });
+
+        frame(block)
+            .dispatch(&mut expected, Vector2::new(4, 4))
+            .unwrap();
+
+        let pixels = visitor.read_pixels();
+        assert_eq!(pixels, expected.read_pixels());
+        assert_eq!(reader.replay(&mut visitor).unwrap(), 1);
+        assert_eq!(reader.replay_frame(&mut visitor).unwrap(), None);
+        pixels
+    }
+
+    #[test]
+    fn replay() {
+        // The blue color is blended over the red clear color.
+        assert_eq!(replay_and_check(false)[0..3], [128, 0, 128]);
+    }
+
+    #[test]
+    fn replay_uniform_block() {
+        assert_eq!(replay_and_check(true)[0..3], [128, 0, 128]);
+    }
+
+    #[test]
+    fn override_state() {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.write(&frame(false), Vector2::new(4, 4)).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = TraceReader::new(&bytes[..]).unwrap();
+        let (frame, _) = reader.read().unwrap().unwrap();
+
+        let mut checked = 0;
+        for cmd in &frame.cmds {
+            match *cmd {
+                Command::ReloadShader(ref v) => {
+                    assert!(v.1.state.stencil.is_some());
+                    checked += 1;
+                }
+                Command::Draw(_, ref state, _, _, _, _) => {
+                    let state = state.as_ref().unwrap();
+                    assert!(state.stencil.is_none());
+                    assert!(state.color_blend.is_some());
+                    checked += 1;
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(checked, 2);
+    }
+
+    #[test]
+    fn render_state() {
+        let mut state = RenderState::default();
+        state.stencil = Some(StencilState {
+            front: StencilFace {
+                comparison: Comparison::NotEqual,
+                reference: 3,
+                read_mask: 0x0F,
+                write_mask: 0xF0,
+                fail: StencilOp::Zero,
+                depth_fail: StencilOp::DecrementWrap,
+                pass: StencilOp::Replace,
+            },
+            back: StencilFace {
+                comparison: Comparison::Greater,
+                reference: 0xFF,
+                pass: StencilOp::Invert,
+                ..StencilFace::default()
+            },
+        });
+
+        for v in &[RenderState::default(), state] {
+            let mut bytes = Vec::new();
+            encode_render_state(&mut bytes, v).unwrap();
+            assert_eq!(decode_render_state(&mut &bytes[..]).unwrap(), *v);
+        }
+    }
+
+    #[test]
+    fn magic() {
+        let mut bytes = TraceWriter::new(Vec::new()).unwrap().into_inner().unwrap();
+        bytes[7] = 1;
+        assert!(TraceReader::new(&bytes[..]).is_err());
+
+        // The traces before readback, uniform block, reload commands and mesh LODs are
+        // rejected by version.
+        bytes[7] = 10;
+        let err = TraceReader::new(&bytes[..]).err().unwrap();
+        assert!(err.to_string().contains("Version"));
+    }
+}
@@ -0,0 +1,964 @@
+//! A debug layer that validates commands before they are passed to another `Visitor`.
+//!
+//! The `ValidationVisitor` tracks the parameters of every resource object that is alive
+//! in the wrapped backend, so it could tell misuses apart from the resources which are
+//! still being loaded. Such like draws with incompatible mesh and shader, uniforms that are
+//! not declared or have wrong types, updates out of bounds and handles used after deletion.
+//!
+//! It's enabled with `VideoParams::validation`.
+
+use std::fmt::Debug;
+
+use crate::errors::*;
+use crate::math::prelude::{Aabb2, Vector2};
+use crate::utils::handle::{Handle, HandleIndex};
+use crate::utils::prelude::FastHashMap;
+
+use super::super::assets::prelude::*;
+use super::super::capabilities::Capabilities;
+use super::{UniformVar, Visitor};
+
+/// Validates commands, and forwards them to the wrapped `Visitor`.
+pub struct ValidationVisitor<V: Visitor> {
+    visitor: V,
+    binded_surface: Option<SurfaceHandle>,
+    surfaces: Registry<SurfaceParams>,
+    shaders: Registry<ShaderParams>,
+    textures: Registry<TextureParams>,
+    render_textures: Registry<RenderTextureParams>,
+    meshes: Registry<MeshParams>,
+    uniform_blocks: Registry<UniformBlockParams>,
+}
+
+enum Liveness<'a, T> {
+    Alive(&'a T),
+    /// The handle has not been created in backend yet, the resource might be loading.
+    Pending,
+    Deleted,
+}
+
+/// The parameters of alive resources, and the latest deleted versions of handles.
+struct Registry<T> {
+    items: FastHashMap<Handle, T>,
+    deleted: FastHashMap<HandleIndex, HandleIndex>,
+}
+
+impl<T> Registry<T> {
+    fn new() -> Self {
+        Registry {
+            items: FastHashMap::default(),
+            deleted: FastHashMap::default(),
+        }
+    }
+
+    #[inline]
+    fn get<H: Into<Handle>>(&self, handle: H) -> Option<&T> {
+        self.items.get(&handle.into())
+    }
+
+    fn liveness<H: Into<Handle>>(&self, handle: H) -> Liveness<T> {
+        let handle = handle.into();
+        if let Some(v) = self.items.get(&handle) {
+            return Liveness::Alive(v);
+        }
+
+        match self.deleted.get(&handle.index()) {
+            Some(&version) if version >= handle.version() => Liveness::Deleted,
+            _ => Liveness::Pending,
+        }
+    }
+
+    fn alive<H: Into<Handle> + Debug + Copy>(&self, handle: H) -> Result<&T> {
+        match self.liveness(handle) {
+            Liveness::Alive(v) => Ok(v),
+            Liveness::Pending => bail!("{:?} is used before it has been created.", handle),
+            Liveness::Deleted => bail!("{:?} is used after it has been deleted.", handle),
+        }
+    }
+
+    fn vacant<H: Into<Handle> + Debug + Copy>(&self, handle: H) -> Result<()> {
+        if self.get(handle).is_some() {
+            bail!("{:?} has been created already.", handle);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn create<H: Into<Handle>>(&mut self, handle: H, value: T) {
+        self.items.insert(handle.into(), value);
+    }
+
+    fn free<H: Into<Handle>>(&mut self, handle: H) {
+        let handle = handle.into();
+        self.items.remove(&handle);
+        self.deleted.insert(handle.index(), handle.version());
+    }
+}
+
+impl<V: Visitor> ValidationVisitor<V> {
+    pub fn new(visitor: V) -> Self {
+        ValidationVisitor {
+            visitor,
+            binded_surface: None,
+            surfaces: Registry::new(),
+            shaders: Registry::new(),
+            textures: Registry::new(),
+            render_textures: Registry::new(),
+            meshes: Registry::new(),
+            uniform_blocks: Registry::new(),
+        }
+    }
+
+    /// Unwraps the underlying `Visitor`.
+    #[inline]
+    pub fn into_inner(self) -> V {
+        self.visitor
+    }
+
+    fn validate_surface(&self, params: &SurfaceParams) -> Result<()> {
+        let mut dimensions = None;
+
+        for (i, v) in params.colors.iter().enumerate() {
+            if let Some(handle) = *v {
+                let rt = self.render_textures.alive(handle)?;
+                if !rt.format.is_color() {
+                    bail!(
+                        "The color attachment {} ({:?}) has a non-color format {:?}.",
+                        i,
+                        handle,
+                        rt.format
+                    );
+                }
+
+                if let Some(v) = dimensions.filter(|&v| v != rt.dimensions) {
+                    bail!(
+                        "The color attachment {} ({:?}) is {:?}, which mismatches {:?} of others.",
+                        i,
+                        handle,
+                        rt.dimensions,
+                        v
+                    );
+                }
+
+                dimensions = Some(rt.dimensions);
+            } else if params.colors[i..].iter().any(|v| v.is_some()) {
+                bail!(
+                    "The color attachments should be contiguous, but {} is empty.",
+                    i
+                );
+            }
+        }
+
+        if let Some(handle) = params.depth_stencil {
+            let rt = self.render_textures.alive(handle)?;
+            if rt.format.is_color() {
+                bail!(
+                    "The depth stencil attachment ({:?}) has a color format {:?}.",
+                    handle,
+                    rt.format
+                );
+            }
+
+            if let Some(v) = dimensions.filter(|&v| v != rt.dimensions) {
+                bail!(
+                    "The depth stencil attachment ({:?}) is {:?}, which mismatches {:?} of colors.",
+                    handle,
+                    rt.dimensions,
+                    v
+                );
+            }
+
+            if params.clear_stencil.is_some() && rt.format != RenderTextureFormat::Depth24Stencil8 {
+                bail!(
+                    "The stencil is cleared, but the attachment ({:?}) has no stencil in {:?}.",
+                    handle,
+                    rt.format
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_stencil(
+        &self,
+        surface: SurfaceHandle,
+        shader: ShaderHandle,
+        state: Option<RenderState>,
+    ) -> Result<()> {
+        match state.or_else(|| self.shaders.get(shader).map(|v| v.state)) {
+            Some(rs) if rs.stencil.is_some() => {}
+            _ => return Ok(()),
+        }
+
+        // The surface without any attachment renders into the default framebuffer.
+        let surface = self.surfaces.alive(surface)?;
+        if surface.colors.iter().all(|v| v.is_none()) && surface.depth_stencil.is_none() {
+            return Ok(());
+        }
+
+        let format = surface
+            .depth_stencil
+            .and_then(|v| self.render_textures.get(v))
+            .map(|v| v.format);
+
+        if format != Some(RenderTextureFormat::Depth24Stencil8) {
+            bail!(
+                "{:?} enables stencil test, but the binded surface has no stencil attachment.",
+                shader
+            );
+        }
+
+        Ok(())
+    }
+
+    fn validate_draw(
+        &self,
+        shader: ShaderHandle,
+        mesh: MeshHandle,
+        mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
+        vars: &[UniformVar],
+    ) -> Result<()> {
+        let params = self.shaders.alive(shader)?;
+
+        let instance_mesh = match instances {
+            Some(v) => {
+                if v.count == 0 {
+                    bail!("The instanced draw of {:?} has zero instance.", mesh);
+                }
+
+                match v.mesh {
+                    Some(handle) => Some(self.meshes.alive(handle)?),
+                    None => None,
+                }
+            }
+            None => None,
+        };
+
+        for &(field, variable) in vars {
+            let tp = match params.uniforms.variable_type(field) {
+                Some(tp) => tp,
+                None => bail!(
+                    "The uniform {:?} is not declared in the UniformVariableLayout of {:?}.",
+                    field,
+                    shader
+                ),
+            };
+
+            let name = params.uniforms.variable_name(field).unwrap();
+            let vtp = match variable {
+                UniformVariable::Texture(handle) => match self.textures.liveness(handle) {
+                    Liveness::Alive(texture) => texture.dimension.into(),
+                    // Textures those are still loading are treated as compatible.
+                    Liveness::Pending => match tp {
+                        UniformVariableType::Texture
+                        | UniformVariableType::TextureCube
+                        | UniformVariableType::Texture2DArray
+                        | UniformVariableType::Texture3D => tp,
+                        _ => UniformVariableType::Texture,
+                    },
+                    Liveness::Deleted => bail!(
+                        "The uniform {} is bound to {:?}, which has been deleted.",
+                        name,
+                        handle
+                    ),
+                },
+                UniformVariable::RenderTexture(handle) => {
+                    let rt = self.render_textures.alive(handle)?;
+                    if rt.sampler.is_none() {
+                        bail!(
+                            "The uniform {} is bound to {:?}, which does not have a sampler.",
+                            name,
+                            handle
+                        );
+                    }
+
+                    let feedback = self
+                        .binded_surface
+                        .and_then(|v| self.surfaces.get(v))
+                        .map(|v| {
+                            v.colors.contains(&Some(handle)) || v.depth_stencil == Some(handle)
+                        })
+                        .unwrap_or(false);
+
+                    if feedback {
+                        bail!(
+                            "The uniform {} samples {:?}, which is attached to the binded surface.",
+                            name,
+                            handle
+                        );
+                    }
+
+                    UniformVariableType::RenderTexture
+                }
+                UniformVariable::UniformBlock(handle) => {
+                    self.uniform_blocks.alive(handle)?;
+                    UniformVariableType::UniformBlock
+                }
+                _ => variable.variable_type(),
+            };
+
+            if tp != vtp {
+                bail!(
+                    "The uniform {} of {:?} needs a {:?} instead of {:?}.",
+                    name,
+                    shader,
+                    tp,
+                    vtp
+                );
+            }
+        }
+
+        let mesh_params = match self.meshes.liveness(mesh) {
+            Liveness::Alive(v) => v,
+            // Meshes those are still loading are skipped by backends silently.
+            Liveness::Pending => return Ok(()),
+            Liveness::Deleted => bail!("{:?} is drawn after it has been deleted.", mesh),
+        };
+
+        for (attribute, size, required) in params.attributes.iter() {
+            let element = instance_mesh
+                .and_then(|v| v.layout.element(attribute))
+                .or_else(|| mesh_params.layout.element(attribute));
+
+            match element {
+                Some(v) if v.size < size => bail!(
+                    "The attribute {:?} of {:?} has {} components, but {:?} provides only {}.",
+                    attribute,
+                    shader,
+                    size,
+                    mesh,
+                    v.size
+                ),
+                None if required => bail!(
+                    "The attribute {:?} required by {:?} is missing in the VertexLayout of {:?}.",
+                    attribute,
+                    shader,
+                    mesh
+                ),
+                _ => {}
+            }
+        }
+
+        match mesh_index {
+            MeshIndex::Ptr(from, len) if from + len > mesh_params.num_idxes => bail!(
+                "The indices [{}, {}) are out of bounds of {:?}, which has {} indices.",
+                from,
+                from + len,
+                mesh,
+                mesh_params.num_idxes
+            ),
+            MeshIndex::SubMesh(index) if index >= mesh_params.sub_mesh_offsets.len() => bail!(
+                "The sub-mesh {} is out of bounds of {:?}, which has {} sub-meshes.",
+                index,
+                mesh,
+                mesh_params.sub_mesh_offsets.len()
+            ),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn validate_mesh_update(
+        &self,
+        handle: MeshHandle,
+        len: usize,
+        o: usize,
+        n: usize,
+    ) -> Result<()> {
+        let params = self.meshes.alive(handle)?;
+        if params.hint == MeshHint::Immutable {
+            bail!("{:?} is immutable, it can not be updated.", handle);
+        }
+
+        if o + n > len {
+            bail!(
+                "The update [{}, {}) is out of bounds of {:?}, whose buffer has {} bytes.",
+                o,
+                o + n,
+                handle,
+                len
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl<V: Visitor> Visitor for ValidationVisitor<V> {
+    fn capabilities(&self) -> Capabilities {
+        self.visitor.capabilities()
+    }
+
+    unsafe fn create_surface(
+        &mut self,
+        handle: SurfaceHandle,
+        params: SurfaceParams,
+    ) -> Result<()> {
+        self.surfaces.vacant(handle)?;
+        self.validate_surface(&params)?;
+        self.visitor.create_surface(handle, params)?;
+        self.surfaces.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn delete_surface(&mut self, handle: SurfaceHandle) -> Result<()> {
+        self.surfaces.alive(handle)?;
+        self.visitor.delete_surface(handle)?;
+        self.surfaces.free(handle);
+
+        if self.binded_surface == Some(handle) {
+            self.binded_surface = None;
+        }
+
+        Ok(())
+    }
+
+    unsafe fn create_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        self.shaders.vacant(handle)?;
+        params.validate(vs, fs)?;
+        self.visitor.create_shader(handle, params.clone(), vs, fs)?;
+        self.shaders.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn reload_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        self.shaders.alive(handle)?;
+        params.validate(vs, fs)?;
+        self.visitor.reload_shader(handle, params.clone(), vs, fs)?;
+        self.shaders.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
+        self.shaders.alive(handle)?;
+        self.visitor.delete_shader(handle)?;
+        self.shaders.free(handle);
+        Ok(())
+    }
+
+    unsafe fn create_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        self.textures.vacant(handle)?;
+        params.validate(data.as_ref())?;
+        self.visitor.create_texture(handle, params, data)?;
+        self.textures.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn update_texture(
+        &mut self,
+        handle: TextureHandle,
+        layer: u32,
+        area: Aabb2<u32>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let params = *self.textures.alive(handle)?;
+        if params.hint == TextureHint::Immutable {
+            bail!("{:?} is immutable, it can not be updated.", handle);
+        }
+
+        if params.format.compressed() {
+            bail!(
+                "{:?} has compressed format {:?}, it can not be updated.",
+                handle,
+                params.format
+            );
+        }
+
+        if area.max.x > params.dimensions.x || area.max.y > params.dimensions.y {
+            bail!(
+                "The update area {:?} is out of bounds of {:?}, which is {:?}.",
+                area,
+                handle,
+                params.dimensions
+            );
+        }
+
+        if layer >= params.images(0) {
+            bail!(
+                "The layer {} is out of bounds of {:?}, which has {} images.",
+                layer,
+                handle,
+                params.images(0)
+            );
+        }
+
+        let len = params.format.size(area.dim()) as usize;
+        if bytes.len() > len {
+            bail!(
+                "The update of {:?} has {} bytes, while the area {:?} only holds {}.",
+                handle,
+                bytes.len(),
+                area,
+                len
+            );
+        }
+
+        self.visitor.update_texture(handle, layer, area, bytes)
+    }
+
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        self.textures.alive(handle)?;
+        params.validate(data.as_ref())?;
+        self.visitor.reload_texture(handle, params, data)?;
+        self.textures.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
+        self.textures.alive(handle)?;
+        self.visitor.delete_texture(handle)?;
+        self.textures.free(handle);
+        Ok(())
+    }
+
+    unsafe fn create_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        params: RenderTextureParams,
+    ) -> Result<()> {
+        self.render_textures.vacant(handle)?;
+        if params.dimensions.x == 0 || params.dimensions.y == 0 {
+            bail!("{:?} has zero dimensions {:?}.", handle, params.dimensions);
+        }
+
+        self.visitor.create_render_texture(handle, params)?;
+        self.render_textures.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn delete_render_texture(&mut self, handle: RenderTextureHandle) -> Result<()> {
+        self.render_textures.alive(handle)?;
+        self.visitor.delete_render_texture(handle)?;
+        self.render_textures.free(handle);
+        Ok(())
+    }
+
+    unsafe fn create_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        self.meshes.vacant(handle)?;
+        params.validate(data.as_ref())?;
+        self.visitor.create_mesh(handle, params.clone(), data)?;
+        self.meshes.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn update_vertex_buffer(
+        &mut self,
+        handle: MeshHandle,
+        o: usize,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let len = self.meshes.alive(handle)?.vertex_buffer_len();
+        self.validate_mesh_update(handle, len, o, bytes.len())?;
+        self.visitor.update_vertex_buffer(handle, o, bytes)
+    }
+
+    unsafe fn update_index_buffer(
+        &mut self,
+        handle: MeshHandle,
+        o: usize,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let len = self.meshes.alive(handle)?.index_buffer_len();
+        self.validate_mesh_update(handle, len, o, bytes.len())?;
+        self.visitor.update_index_buffer(handle, o, bytes)
+    }
+
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        self.meshes.alive(handle)?;
+        params.validate(data.as_ref())?;
+        self.visitor.reload_mesh(handle, params.clone(), data)?;
+        self.meshes.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn delete_mesh(&mut self, handle: MeshHandle) -> Result<()> {
+        self.meshes.alive(handle)?;
+        self.visitor.delete_mesh(handle)?;
+        self.meshes.free(handle);
+        Ok(())
+    }
+
+    unsafe fn create_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        params: UniformBlockParams,
+    ) -> Result<()> {
+        self.uniform_blocks.vacant(handle)?;
+        params.validate()?;
+        self.visitor.create_uniform_block(handle, params.clone())?;
+        self.uniform_blocks.create(handle, params);
+        Ok(())
+    }
+
+    unsafe fn update_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> Result<()> {
+        let params = self.uniform_blocks.alive(handle)?;
+        for &(field, variable) in variables {
+            match params.layout.variable_type(field) {
+                Some(tp) if tp != variable.variable_type() => bail!(
+                    "The variable {} of {:?} needs a {:?} instead of {:?}.",
+                    params.layout.variable_name(field).unwrap(),
+                    handle,
+                    tp,
+                    variable.variable_type()
+                ),
+                None => bail!(
+                    "The variable {:?} is not declared in the layout of {:?}.",
+                    field,
+                    handle
+                ),
+                _ => {}
+            }
+        }
+
+        self.visitor.update_uniform_block(handle, variables)
+    }
+
+    unsafe fn delete_uniform_block(&mut self, handle: UniformBlockHandle) -> Result<()> {
+        self.uniform_blocks.alive(handle)?;
+        self.visitor.delete_uniform_block(handle)?;
+        self.uniform_blocks.free(handle);
+        Ok(())
+    }
+
+    unsafe fn bind(&mut self, surface: SurfaceHandle, dimensions: Vector2<u32>) -> Result<()> {
+        let params = *self.surfaces.alive(surface)?;
+        for handle in params.colors.iter().chain(Some(&params.depth_stencil)) {
+            if let Some(handle) = *handle {
+                if let Liveness::Deleted = self.render_textures.liveness(handle) {
+                    bail!(
+                        "{:?} is binded, but its attachment {:?} has been deleted.",
+                        surface,
+                        handle
+                    );
+                }
+            }
+        }
+
+        self.visitor.bind(surface, dimensions)?;
+        self.binded_surface = Some(surface);
+        Ok(())
+    }
+
+    unsafe fn draw(
+        &mut self,
+        shader: ShaderHandle,
+        state: Option<RenderState>,
+        mesh: MeshHandle,
+        mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
+        vars: &[UniformVar],
+    ) -> Result<u32> {
+        let surface = match self.binded_surface {
+            Some(surface) => surface,
+            None => bail!("Draws {:?} without any binded surface.", mesh),
+        };
+
+        self.validate_stencil(surface, shader, state)?;
+        self.validate_draw(shader, mesh, mesh_index, instances, vars)?;
+        self.visitor
+            .draw(shader, state, mesh, mesh_index, instances, vars)
+    }
+
+    unsafe fn update_surface_scissor(&mut self, scissor: SurfaceScissor) -> Result<()> {
+        self.visitor.update_surface_scissor(scissor)
+    }
+
+    unsafe fn update_surface_viewport(&mut self, vp: SurfaceViewport) -> Result<()> {
+        self.visitor.update_surface_viewport(vp)
+    }
+
+    unsafe fn read_surface(
+        &mut self,
+        surface: SurfaceHandle,
+        dimensions: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        self.surfaces.alive(surface)?;
+        self.visitor.read_surface(surface, dimensions, area)
+    }
+
+    unsafe fn read_framebuffer(
+        &mut self,
+        dimensions: Vector2<u32>,
+    ) -> Result<(Vector2<u32>, Box<[u8]>)> {
+        self.visitor.read_framebuffer(dimensions)
+    }
+
+    unsafe fn read_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let params = self.render_textures.alive(handle)?;
+        if !params.format.is_color() {
+            bail!(
+                "{:?} has a non-color format {:?}, it can not be read.",
+                handle,
+                params.format
+            );
+        }
+
+        if area.max.x > params.dimensions.x || area.max.y > params.dimensions.y {
+            bail!(
+                "The read area {:?} is out of bounds of {:?}, which is {:?}.",
+                area,
+                handle,
+                params.dimensions
+            );
+        }
+
+        self.visitor.read_render_texture(handle, area)
+    }
+
+    unsafe fn flush(&mut self) -> Result<()> {
+        self.visitor.flush()
+    }
+
+    unsafe fn advance(&mut self) -> Result<()> {
+        self.binded_surface = None;
+        self.visitor.advance()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::headless::HeadlessVisitor;
+    use super::*;
+    use crate::utils::handle::HandleLike;
+    use cgmath::Point2;
+
+    fn shader() -> ShaderParams {
+        let mut params = ShaderParams::default();
+        params.attributes = AttributeLayout::build()
+            .with(Attribute::Position, 3)
+            .finish();
+        params.uniforms = UniformVariableLayout::build()
+            .with("u_Color", UniformVariableType::Vector4f)
+            .with("u_Texture", UniformVariableType::Texture)
+            .finish();
+        params
+    }
+
+    fn mesh(size: u8) -> MeshParams {
+        let mut params = MeshParams::default();
+        params.hint = MeshHint::Dynamic;
+        params.layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Float, size, false)
+            .finish();
+        params.num_verts = 3;
+        params.num_idxes = 3;
+        params
+    }
+
+    #[test]
+    fn draw() {
+        unsafe {
+            let mut visitor = ValidationVisitor::new(HeadlessVisitor::new(Capabilities::default()));
+            let (surface, shader_handle) = (SurfaceHandle::new(1, 1), ShaderHandle::new(1, 1));
+            let (m1, m2) = (MeshHandle::new(1, 1), MeshHandle::new(2, 1));
+
+            visitor
+                .create_surface(surface, SurfaceParams::default())
+                .unwrap();
+            visitor
+                .create_shader(shader_handle, shader(), "vs", "fs")
+                .unwrap();
+            visitor.create_mesh(m1, mesh(3), None).unwrap();
+            visitor.create_mesh(m2, mesh(2), None).unwrap();
+            assert!(visitor.create_mesh(m2, mesh(2), None).is_err());
+
+            let all = MeshIndex::All;
+            assert!(visitor
+                .draw(shader_handle, None, m1, all, None, &[])
+                .is_err());
+            visitor.bind(surface, Vector2::new(1, 1)).unwrap();
+            visitor
+                .draw(shader_handle, None, m1, all, None, &[])
+                .unwrap();
+            assert!(visitor
+                .draw(shader_handle, None, m2, all, None, &[])
+                .is_err());
+
+            // The extra components of attributes are ignored.
+            let m4 = MeshHandle::new(4, 1);
+            visitor.create_mesh(m4, mesh(4), None).unwrap();
+            visitor
+                .draw(shader_handle, None, m4, all, None, &[])
+                .unwrap();
+
+            // Meshes and textures those are still loading are skipped.
+            let pending = MeshHandle::new(3, 1);
+            visitor
+                .draw(shader_handle, None, pending, all, None, &[])
+                .unwrap();
+
+            let vars = [("u_Color".into(), UniformVariable::Vector4f([1.0; 4]))];
+            visitor
+                .draw(shader_handle, None, m1, all, None, &vars)
+                .unwrap();
+            let vars = [("u_Color".into(), UniformVariable::F32(1.0))];
+            assert!(visitor
+                .draw(shader_handle, None, m1, all, None, &vars)
+                .is_err());
+            let vars = [("u_Undefined".into(), UniformVariable::F32(1.0))];
+            assert!(visitor
+                .draw(shader_handle, None, m1, all, None, &vars)
+                .is_err());
+
+            let texture = TextureHandle::new(1, 1);
+            let vars = [("u_Texture".into(), UniformVariable::Texture(texture))];
+            visitor
+                .draw(shader_handle, None, m1, all, None, &vars)
+                .unwrap();
+
+            let ptr = MeshIndex::Ptr(1, 3);
+            assert!(visitor
+                .draw(shader_handle, None, m1, ptr, None, &[])
+                .is_err());
+
+            visitor.delete_mesh(m1).unwrap();
+            assert!(visitor
+                .draw(shader_handle, None, m1, all, None, &[])
+                .is_err());
+            assert!(visitor.delete_mesh(m1).is_err());
+            assert!(visitor.update_vertex_buffer(m1, 0, &[0; 4]).is_err());
+        }
+    }
+
+    #[test]
+    fn update() {
+        unsafe {
+            let mut visitor = ValidationVisitor::new(HeadlessVisitor::new(Capabilities::default()));
+
+            let mesh_handle = MeshHandle::new(1, 1);
+            visitor.create_mesh(mesh_handle, mesh(3), None).unwrap();
+            visitor
+                .update_vertex_buffer(mesh_handle, 0, &[0; 36])
+                .unwrap();
+            assert!(visitor
+                .update_vertex_buffer(mesh_handle, 4, &[0; 36])
+                .is_err());
+
+            let texture = TextureHandle::new(1, 1);
+            let mut params = TextureParams::default();
+            params.hint = TextureHint::Dynamic;
+            params.dimensions = Vector2::new(2, 2);
+            visitor.create_texture(texture, params, None).unwrap();
+
+            let area = Aabb2::new(Point2::new(0, 0), Point2::new(2, 2));
+            visitor.update_texture(texture, 0, area, &[0; 16]).unwrap();
+            assert!(visitor.update_texture(texture, 1, area, &[0; 16]).is_err());
+            assert!(visitor.update_texture(texture, 0, area, &[0; 20]).is_err());
+
+            let area = Aabb2::new(Point2::new(1, 1), Point2::new(3, 3));
+            assert!(visitor.update_texture(texture, 0, area, &[0; 16]).is_err());
+
+            let block = UniformBlockHandle::new(1, 1);
+            let params = UniformBlockParams {
+                layout: UniformBlockLayout::build()
+                    .with("u_Color", UniformVariableType::Vector4f)
+                    .finish(),
+            };
+            visitor.create_uniform_block(block, params).unwrap();
+
+            let vars = [("u_Color".into(), UniformVariable::Vector4f([1.0; 4]))];
+            visitor.update_uniform_block(block, &vars).unwrap();
+            let vars = [("u_Color".into(), UniformVariable::F32(1.0))];
+            assert!(visitor.update_uniform_block(block, &vars).is_err());
+        }
+    }
+
+    #[test]
+    fn surface() {
+        unsafe {
+            let mut visitor = ValidationVisitor::new(HeadlessVisitor::new(Capabilities::default()));
+
+            let (color, depth) = (
+                RenderTextureHandle::new(1, 1),
+                RenderTextureHandle::new(2, 1),
+            );
+            let mut params = RenderTextureParams::default();
+            params.dimensions = Vector2::new(4, 4);
+            visitor.create_render_texture(color, params).unwrap();
+            params.format = RenderTextureFormat::Depth24;
+            params.dimensions = Vector2::new(8, 8);
+            visitor.create_render_texture(depth, params).unwrap();
+
+            let mut params = SurfaceParams::default();
+            params.set_attachments(&[depth], None).unwrap();
+            assert!(visitor
+                .create_surface(SurfaceHandle::new(1, 1), params)
+                .is_err());
+
+            params.set_attachments(&[color], depth).unwrap();
+            assert!(visitor
+                .create_surface(SurfaceHandle::new(1, 1), params)
+                .is_err());
+
+            params.set_attachments(&[color], None).unwrap();
+            visitor
+                .create_surface(SurfaceHandle::new(1, 1), params)
+                .unwrap();
+
+            // Stencil test requires a stencil attachment.
+            let (shader_handle, mesh_handle) = (ShaderHandle::new(1, 1), MeshHandle::new(1, 1));
+            let mut params = shader();
+            params.state.stencil = Some(StencilState::default());
+            visitor
+                .create_shader(shader_handle, params, "vs", "fs")
+                .unwrap();
+            visitor.create_mesh(mesh_handle, mesh(3), None).unwrap();
+            visitor
+                .bind(SurfaceHandle::new(1, 1), Vector2::new(4, 4))
+                .unwrap();
+            assert!(visitor
+                .draw(shader_handle, None, mesh_handle, MeshIndex::All, None, &[])
+                .is_err());
+
+            visitor.delete_render_texture(color).unwrap();
+            assert!(visitor
+                .bind(SurfaceHandle::new(1, 1), Vector2::new(4, 4))
+                .is_err());
+        }
+    }
+}
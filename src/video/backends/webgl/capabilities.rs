@@ -2,6 +2,10 @@ use web_sys::WebGl2RenderingContext as WebGL;
 
 use crate::video::assets::texture::TextureFormat;
 
+// The enums of `EXT_texture_filter_anisotropic`.
+pub const TEXTURE_MAX_ANISOTROPY: u32 = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: u32 = 0x84FF;
+
 /// Represents the capabilities of the context.
 ///
 /// Contrary to the state, these values never change.
@@ -9,15 +13,59 @@ use crate::video::assets::texture::TextureFormat;
 pub struct Capabilities {
     /// The list of OpenGL extensions support by this implementation.
     pub extensions: Extensions,
+
+    /// Maximum degree of anisotropic filtering, `1.0` if not supported.
+    pub max_anisotropy: f32,
+
+    /// Maximum width and height of textures.
+    pub max_texture_size: u32,
+
+    /// Maximum number of vertex attributes.
+    pub max_vertex_attribs: u32,
+
+    /// Maximum number of textures that can be bound to a program.
+    pub max_combined_texture_image_units: u32,
+
+    /// Maximum number of color attachment bind points.
+    pub max_color_attachments: u32,
 }
 
 impl Capabilities {
     pub unsafe fn new(ctx: &WebGL) -> Result<Capabilities, failure::Error> {
+        let extensions = Extensions::parse(ctx)?;
+
+        let max_anisotropy = if extensions.ext_texture_filter_anisotropic {
+            ctx.get_parameter(MAX_TEXTURE_MAX_ANISOTROPY)
+                .ok()
+                .and_then(|v| v.as_f64())
+                .map(|v| (v as f32).max(1.0))
+                .unwrap_or(1.0)
+        } else {
+            1.0
+        };
+
         Ok(Capabilities {
-            extensions: Extensions::parse(ctx)?,
+            extensions,
+            max_anisotropy,
+            max_texture_size: Self::parse_integer(ctx, WebGL::MAX_TEXTURE_SIZE, 2048),
+            max_vertex_attribs: Self::parse_integer(ctx, WebGL::MAX_VERTEX_ATTRIBS, 16),
+            max_combined_texture_image_units: Self::parse_integer(
+                ctx,
+                WebGL::MAX_COMBINED_TEXTURE_IMAGE_UNITS,
+                32,
+            ),
+            max_color_attachments: Self::parse_integer(ctx, WebGL::MAX_COLOR_ATTACHMENTS, 4),
         })
     }
 
+    fn parse_integer(ctx: &WebGL, id: u32, min: u32) -> u32 {
+        ctx.get_parameter(id)
+            .ok()
+            .and_then(|v| v.as_f64())
+            .map(|v| (v as u32).max(min))
+            .unwrap_or(min)
+    }
+
     pub fn support_texture_format(&self, format: TextureFormat) -> bool {
         match format {
             TextureFormat::Etc2RGB4BPP | TextureFormat::Etc2RGBA8BPP => {
@@ -72,4 +120,6 @@ extensions! {
     "WEBGL_compressed_texture_s3tc" => webgl_compressed_texture_s3tc,
     "WEBGL_compressed_texture_pvrtc" => webgl_compressed_texture_pvrtc,
     "WEBGL_compressed_texture_etc" => webgl_compressed_texture_etc,
+    "EXT_texture_filter_anisotropic" => ext_texture_filter_anisotropic,
+    "EXT_color_buffer_float" => ext_color_buffer_float,
 }
@@ -20,6 +20,15 @@ impl From<VertexFormat> for u32 {
             VertexFormat::Short => WebGL::SHORT,
             VertexFormat::UShort => WebGL::UNSIGNED_SHORT,
             VertexFormat::Float => WebGL::FLOAT,
+            VertexFormat::HalfFloat => WebGL::HALF_FLOAT,
+            VertexFormat::Int2_10_10_10Rev => WebGL::INT_2_10_10_10_REV,
+            VertexFormat::UInt2_10_10_10Rev => WebGL::UNSIGNED_INT_2_10_10_10_REV,
+            VertexFormat::Int8 => WebGL::BYTE,
+            VertexFormat::UInt8 => WebGL::UNSIGNED_BYTE,
+            VertexFormat::Int16 => WebGL::SHORT,
+            VertexFormat::UInt16 => WebGL::UNSIGNED_SHORT,
+            VertexFormat::Int32 => WebGL::INT,
+            VertexFormat::UInt32 => WebGL::UNSIGNED_INT,
         }
     }
 }
@@ -60,6 +69,21 @@ impl From<Comparison> for u32 {
     }
 }
 
+impl From<StencilOp> for u32 {
+    fn from(op: StencilOp) -> Self {
+        match op {
+            StencilOp::Keep => WebGL::KEEP,
+            StencilOp::Zero => WebGL::ZERO,
+            StencilOp::Replace => WebGL::REPLACE,
+            StencilOp::Increment => WebGL::INCR,
+            StencilOp::IncrementWrap => WebGL::INCR_WRAP,
+            StencilOp::Decrement => WebGL::DECR,
+            StencilOp::DecrementWrap => WebGL::DECR_WRAP,
+            StencilOp::Invert => WebGL::INVERT,
+        }
+    }
+}
+
 impl From<Equation> for u32 {
     fn from(eq: Equation) -> Self {
         match eq {
@@ -93,12 +117,33 @@ impl From<TextureWrap> for u32 {
             TextureWrap::Repeat => WebGL::REPEAT,
             TextureWrap::Mirror => WebGL::MIRRORED_REPEAT,
             TextureWrap::Clamp => WebGL::CLAMP_TO_EDGE,
-            // WebGL does NOT support MIRROR_CLAMP_TO_EDGE
+            // WebGL does NOT support MIRROR_CLAMP_TO_EDGE and CLAMP_TO_BORDER
             TextureWrap::MirrorClamp => WebGL::CLAMP_TO_EDGE,
+            TextureWrap::Border => WebGL::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+impl From<TextureFilter> for u32 {
+    fn from(filter: TextureFilter) -> Self {
+        match filter {
+            TextureFilter::Nearest => WebGL::NEAREST,
+            TextureFilter::Linear => WebGL::LINEAR,
         }
     }
 }
 
+/// Combines the minifying filter with the filter between mipmap levels.
+pub fn texture_min_filter(min: TextureFilter, mip: Option<TextureFilter>) -> u32 {
+    match (min, mip) {
+        (_, None) => min.into(),
+        (TextureFilter::Nearest, Some(TextureFilter::Nearest)) => WebGL::NEAREST_MIPMAP_NEAREST,
+        (TextureFilter::Nearest, Some(TextureFilter::Linear)) => WebGL::NEAREST_MIPMAP_LINEAR,
+        (TextureFilter::Linear, Some(TextureFilter::Nearest)) => WebGL::LINEAR_MIPMAP_NEAREST,
+        (TextureFilter::Linear, Some(TextureFilter::Linear)) => WebGL::LINEAR_MIPMAP_LINEAR,
+    }
+}
+
 impl From<TextureFormat> for (u32, u32, u32) {
     fn from(format: TextureFormat) -> Self {
         // FIXME
@@ -141,6 +186,17 @@ impl From<TextureFormat> for (u32, u32, u32) {
     }
 }
 
+impl From<TextureDimension> for u32 {
+    fn from(dimension: TextureDimension) -> Self {
+        match dimension {
+            TextureDimension::D2 => WebGL::TEXTURE_2D,
+            TextureDimension::Cube => WebGL::TEXTURE_CUBE_MAP,
+            TextureDimension::D2Array => WebGL::TEXTURE_2D_ARRAY,
+            TextureDimension::D3 => WebGL::TEXTURE_3D,
+        }
+    }
+}
+
 impl From<RenderTextureFormat> for (u32, u32, u32) {
     fn from(format: RenderTextureFormat) -> Self {
         // Notes that WebGL does NOT support sized texture format.
@@ -148,6 +204,7 @@ impl From<RenderTextureFormat> for (u32, u32, u32) {
             RenderTextureFormat::RGB8 => (WebGL::RGB, WebGL::RGB, WebGL::UNSIGNED_BYTE),
             RenderTextureFormat::RGBA4 => (WebGL::RGBA, WebGL::RGBA, WebGL::UNSIGNED_SHORT_4_4_4_4),
             RenderTextureFormat::RGBA8 => (WebGL::RGBA, WebGL::RGBA, WebGL::UNSIGNED_BYTE),
+            RenderTextureFormat::RGBA16F => (WebGL::RGBA16F, WebGL::RGBA, WebGL::HALF_FLOAT),
             RenderTextureFormat::Depth16 => {
                 (WebGL::DEPTH_COMPONENT, WebGL::DEPTH_COMPONENT, WebGL::FLOAT)
             }
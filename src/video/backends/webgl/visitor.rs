@@ -1,9 +1,10 @@
 use std::cell::RefCell;
 
+use cgmath::Point2;
 use smallvec::SmallVec;
 use web_sys::{
-    self, HtmlCanvasElement, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlRenderbuffer,
-    WebGlShader, WebGlTexture, WebGlUniformLocation, WebGlVertexArrayObject,
+    self, HtmlCanvasElement, WebGlBuffer, WebGlContextAttributes, WebGlFramebuffer, WebGlProgram,
+    WebGlRenderbuffer, WebGlShader, WebGlTexture, WebGlUniformLocation, WebGlVertexArrayObject,
 };
 
 use wasm_bindgen::JsCast;
@@ -14,10 +15,12 @@ use crate::math::prelude::*;
 use crate::utils::hash::{FastHashMap, FastHashSet};
 use crate::utils::hash_value::HashValue;
 use crate::video::assets::prelude::*;
+use crate::video::capabilities::Capabilities as VideoCapabilities;
 
 use super::super::utils::DataVec;
 use super::super::{UniformVar, Visitor};
-use super::capabilities::Capabilities;
+use super::capabilities::{Capabilities, TEXTURE_MAX_ANISOTROPY};
+use super::types;
 
 #[derive(Debug, Clone)]
 struct GLSurfaceData {
@@ -33,6 +36,7 @@ pub struct GLShaderData {
     id: WebGlProgram,
     params: ShaderParams,
     uniforms: RefCell<FastHashMap<HashValue<str>, WebGlUniformLocation>>,
+    uniform_blocks: RefCell<FastHashMap<HashValue<str>, u32>>,
     attributes: RefCell<FastHashMap<HashValue<str>, i32>>,
 }
 
@@ -61,6 +65,28 @@ impl GLShaderData {
         }
     }
 
+    fn hash_uniform_block_index<T: Into<HashValue<str>>>(&self, name: T) -> Option<u32> {
+        self.uniform_blocks.borrow().get(&name.into()).cloned()
+    }
+
+    unsafe fn uniform_block_index(&self, ctx: &WebGL, name: &str) -> Result<u32> {
+        let hash = name.into();
+        let mut uniform_blocks = self.uniform_blocks.borrow_mut();
+        match uniform_blocks.get(&hash).cloned() {
+            Some(index) => Ok(index),
+            None => {
+                let index = ctx.get_uniform_block_index(&self.id, name);
+                if index != WebGL::INVALID_INDEX {
+                    check(ctx)?;
+                    uniform_blocks.insert(hash, index);
+                    Ok(index)
+                } else {
+                    bail!("Uniform({:?}) is undefined in shader sources.", name);
+                }
+            }
+        }
+    }
+
     unsafe fn attribute_location(&self, ctx: &WebGL, name: &str) -> Result<i32> {
         let hash = name.into();
         let mut attributes = self.attributes.borrow_mut();
@@ -80,6 +106,14 @@ impl GLShaderData {
     }
 }
 
+#[derive(Debug, Clone)]
+struct GLUniformBlockData {
+    handle: UniformBlockHandle,
+    id: WebGlBuffer,
+    params: UniformBlockParams,
+    bytes: Vec<u8>,
+}
+
 #[derive(Debug, Clone)]
 struct GLTextureData {
     handle: TextureHandle,
@@ -115,17 +149,21 @@ struct GLMeshData {
     params: MeshParams,
 }
 
+/// The key of vertex array object, which is made of shader, mesh and the optional per-instance
+/// mesh with its divisor.
+type VAOKey = (ShaderHandle, MeshHandle, Option<(MeshHandle, u32)>);
+
 struct WebGLState {
     render_state: RenderState,
     scissor: SurfaceScissor,
     view: SurfaceViewport,
     cleared_surfaces: FastHashSet<SurfaceHandle>,
-    vaos: FastHashMap<(ShaderHandle, MeshHandle), WebGlVertexArrayObject>,
+    vaos: FastHashMap<VAOKey, WebGlVertexArrayObject>,
     binded_surface: Option<SurfaceHandle>,
     binded_shader: Option<ShaderHandle>,
     binded_texture_index: usize,
     binded_textures: SmallVec<[Option<Sampler>; 8]>,
-    binded_vao: Option<(ShaderHandle, MeshHandle)>,
+    binded_vao: Option<VAOKey>,
 }
 
 pub struct WebGLVisitor {
@@ -138,6 +176,7 @@ pub struct WebGLVisitor {
     meshes: DataVec<GLMeshData>,
     textures: DataVec<GLTextureData>,
     render_textures: DataVec<GLRenderTextureData>,
+    uniform_blocks: DataVec<GLUniformBlockData>,
 }
 
 impl WebGLVisitor {
@@ -145,13 +184,17 @@ impl WebGLVisitor {
         let window = web_sys::window().expect("no global `window` exists");
         let document = window.document().expect("should have a document on window");
 
+        // The default framebuffer of WebGL has no stencil buffer unless it's requested.
+        let mut attributes = WebGlContextAttributes::new();
+        attributes.stencil(true);
+
         let ctx = document
             .get_element_by_id("canvas")
             .unwrap()
             .dyn_into::<HtmlCanvasElement>()
             .map_err(|_| ())
             .unwrap()
-            .get_context("webgl2")
+            .get_context_with_context_options("webgl2", &attributes)
             .unwrap()
             .unwrap()
             .dyn_into::<WebGL>()
@@ -184,11 +227,35 @@ impl WebGLVisitor {
             textures: DataVec::new(),
             render_textures: DataVec::new(),
             meshes: DataVec::new(),
+            uniform_blocks: DataVec::new(),
         })
     }
 }
 
 impl Visitor for WebGLVisitor {
+    fn capabilities(&self) -> VideoCapabilities {
+        let caps = &self.capabilities;
+
+        // WebGL2 supports instancing, uniform blocks, floating point textures and the
+        // extended vertex formats natively.
+        VideoCapabilities {
+            max_texture_size: caps.max_texture_size,
+            max_vertex_attributes: caps.max_vertex_attribs,
+            max_texture_units: caps.max_combined_texture_image_units,
+            max_color_attachments: caps.max_color_attachments,
+            max_anisotropy: caps.max_anisotropy,
+            texture_formats: VideoCapabilities::texture_formats(|v| caps.support_texture_format(v)),
+            render_texture_formats: VideoCapabilities::render_texture_formats(|v| {
+                v != RenderTextureFormat::RGBA16F || caps.extensions.ext_color_buffer_float
+            }),
+            instancing: true,
+            uniform_blocks: true,
+            float_textures: true,
+            extended_vertex_formats: true,
+            texture_border_clamp: false,
+        }
+    }
+
     unsafe fn advance(&mut self) -> Result<()> {
         self.state.cleared_surfaces.clear();
         self.state.binded_surface = None;
@@ -323,6 +390,7 @@ impl Visitor for WebGLVisitor {
             id: id,
             params: params,
             uniforms: RefCell::new(FastHashMap::default()),
+            uniform_blocks: RefCell::new(FastHashMap::default()),
             attributes: RefCell::new(FastHashMap::default()),
         };
 
@@ -334,8 +402,14 @@ impl Visitor for WebGLVisitor {
             }
         }
 
-        for &(ref name, _) in shader.params.uniforms.iter() {
-            if let Err(err) = shader.uniform_location(&self.ctx, name) {
+        for &(ref name, tp) in shader.params.uniforms.iter() {
+            let result = if tp == UniformVariableType::UniformBlock {
+                shader.uniform_block_index(&self.ctx, name).map(|_| ())
+            } else {
+                shader.uniform_location(&self.ctx, name).map(|_| ())
+            };
+
+            if let Err(err) = result {
                 self.ctx.delete_program(Some(&shader.id));
                 bail!(err);
             }
@@ -345,31 +419,33 @@ impl Visitor for WebGLVisitor {
         Ok(())
     }
 
-    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
-        let shader = self
+    unsafe fn reload_shader(
+        &mut self,
+        handle: ShaderHandle,
+        params: ShaderParams,
+        vs: &str,
+        fs: &str,
+    ) -> Result<()> {
+        let prev = self
             .shaders
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        // Removes deprecated `VertexArrayObject`s.
-        {
-            let ctx = &self.ctx;
-            self.state.vaos.retain(|&(h, _), vao| {
-                if h == shader.handle {
-                    ctx.delete_vertex_array(Some(&vao));
-                    false
-                } else {
-                    true
-                }
-            });
+        if let Err(err) = self.create_shader(handle, params, vs, fs) {
+            self.shaders.create(handle, prev);
+            return Err(err);
         }
 
-        if self.state.binded_shader == Some(handle) {
-            self.state.binded_shader = None;
-        }
+        self.free_shader(prev)
+    }
 
-        self.ctx.delete_program(Some(&shader.id));
-        check(&self.ctx)
+    unsafe fn delete_shader(&mut self, handle: ShaderHandle) -> Result<()> {
+        let shader = self
+            .shaders
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        self.free_shader(shader)
     }
 
     unsafe fn create_texture(
@@ -386,9 +462,10 @@ impl Visitor for WebGLVisitor {
         }
 
         let id = self.ctx.create_texture().unwrap();
+        let target: u32 = params.dimension.into();
         let mut allocated = false;
 
-        if let Some(mut data) = data {
+        if let Some(data) = data {
             let len = data.bytes.len();
             if len > 0 {
                 Self::bind_texture(
@@ -396,49 +473,30 @@ impl Visitor for WebGLVisitor {
                     &mut self.state,
                     Some(Sampler::Texture(handle)),
                     0,
+                    target,
                     Some(&id),
                 )?;
 
-                Self::bind_texture_params(&self.ctx, params.wrap, params.filter, len as u32)?;
-
-                let (internal_format, format, pixel_type) = params.format.into();
-                let mut dims = (params.dimensions.x as i32, params.dimensions.y as i32);
+                let levels = if params.generate_mipmaps {
+                    params.mipmap_levels()
+                } else {
+                    len as u32
+                };
 
-                if params.format.compressed() {
-                    for (i, v) in data.bytes.drain(..).enumerate() {
-                        let mv = ::std::slice::from_raw_parts_mut(v.as_ptr() as *mut u8, v.len());
-                        self.ctx.compressed_tex_image_2d_with_u8_array(
-                            WebGL::TEXTURE_2D,
-                            i as i32,
-                            internal_format,
-                            dims.0,
-                            dims.1,
-                            0,
-                            mv,
-                        );
+                Self::bind_texture_params(
+                    &self.ctx,
+                    &self.capabilities,
+                    target,
+                    &params.sampler,
+                    levels,
+                )?;
 
-                        dims.0 = (dims.0 / 2).max(1);
-                        dims.1 = (dims.1 / 2).max(1);
-                    }
-                } else {
-                    for (i, v) in data.bytes.drain(..).enumerate() {
-                        let mv = ::std::slice::from_raw_parts_mut(v.as_ptr() as *mut u8, v.len());
-                        self.ctx
-                            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-                                WebGL::TEXTURE_2D,
-                                i as i32,
-                                internal_format as i32,
-                                dims.0,
-                                dims.1,
-                                0,
-                                format,
-                                pixel_type,
-                                Some(mv),
-                            ).unwrap();
+                for (i, v) in data.bytes.iter().enumerate() {
+                    Self::tex_image(&self.ctx, &params, i as u32, Some(v))?;
+                }
 
-                        dims.0 = (dims.0 / 2).max(1);
-                        dims.1 = (dims.1 / 2).max(1);
-                    }
+                if params.generate_mipmaps {
+                    self.ctx.generate_mipmap(target);
                 }
 
                 allocated = true;
@@ -463,6 +521,7 @@ impl Visitor for WebGLVisitor {
     unsafe fn update_texture(
         &mut self,
         handle: TextureHandle,
+        layer: u32,
         area: Aabb2<u32>,
         data: &[u8],
     ) -> Result<()> {
@@ -482,70 +541,115 @@ impl Visitor for WebGLVisitor {
         if data.len() > area.volume() as usize
             || area.min.x >= texture.params.dimensions.x
             || area.min.y >= texture.params.dimensions.y
+            || layer >= texture.params.images(0)
         {
             bail!("Trying to update texture data out of bounds.");
         }
 
-        let (internal_format, format, pixel_type) = texture.params.format.into();
+        let (_, format, pixel_type) = texture.params.format.into();
+        let target: u32 = texture.params.dimension.into();
 
         Self::bind_texture(
             &self.ctx,
             &mut self.state,
             Some(Sampler::Texture(handle)),
             0,
+            target,
             Some(&texture.id),
         )?;
 
         if !*texture.allocated.borrow() {
-            Self::bind_texture_params(&self.ctx, texture.params.wrap, texture.params.filter, 1)?;
+            let levels = if texture.params.generate_mipmaps {
+                texture.params.mipmap_levels()
+            } else {
+                1
+            };
 
-            self.ctx
-                .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-                    WebGL::TEXTURE_2D,
-                    0,
-                    internal_format as i32,
-                    texture.params.dimensions.x as i32,
-                    texture.params.dimensions.y as i32,
-                    0,
-                    format,
-                    pixel_type,
-                    None,
-                ).unwrap();
+            Self::bind_texture_params(
+                &self.ctx,
+                &self.capabilities,
+                target,
+                &texture.params.sampler,
+                levels,
+            )?;
 
+            Self::tex_image(&self.ctx, &texture.params, 0, None)?;
             *texture.allocated.borrow_mut() = true;
         }
 
         let mv = ::std::slice::from_raw_parts_mut(data.as_ptr() as *mut u8, data.len());
-        self.ctx
-            .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
-                WebGL::TEXTURE_2D,
-                0,
-                area.min.x as i32,
-                area.min.y as i32,
-                area.dim().x as i32,
-                area.dim().y as i32,
-                format,
-                pixel_type,
-                Some(mv),
-            ).unwrap();
+        match texture.params.dimension {
+            TextureDimension::D2 | TextureDimension::Cube => {
+                let target = if texture.params.dimension == TextureDimension::Cube {
+                    WebGL::TEXTURE_CUBE_MAP_POSITIVE_X + layer
+                } else {
+                    WebGL::TEXTURE_2D
+                };
+
+                self.ctx
+                    .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                        target,
+                        0,
+                        area.min.x as i32,
+                        area.min.y as i32,
+                        area.dim().x as i32,
+                        area.dim().y as i32,
+                        format,
+                        pixel_type,
+                        Some(mv),
+                    ).unwrap();
+            }
+            TextureDimension::D2Array | TextureDimension::D3 => {
+                self.ctx
+                    .tex_sub_image_3d_with_opt_u8_array(
+                        target,
+                        0,
+                        area.min.x as i32,
+                        area.min.y as i32,
+                        layer as i32,
+                        area.dim().x as i32,
+                        area.dim().y as i32,
+                        1,
+                        format,
+                        pixel_type,
+                        Some(mv),
+                    ).unwrap();
+            }
+        }
+
+        if texture.params.generate_mipmaps {
+            self.ctx.generate_mipmap(target);
+        }
 
         check(&self.ctx)
     }
 
-    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
-        let texture = self
+    unsafe fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        params: TextureParams,
+        data: Option<TextureData>,
+    ) -> Result<()> {
+        let prev = self
             .textures
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        for v in self.state.binded_textures.iter_mut() {
-            if *v == Some(Sampler::Texture(handle)) {
-                *v = None;
-            }
+        if let Err(err) = self.create_texture(handle, params, data) {
+            self.textures.create(handle, prev);
+            return Err(err);
         }
 
-        self.ctx.delete_texture(Some(&texture.id));
-        check(&self.ctx)
+        self.free_texture(prev)
+    }
+
+    unsafe fn delete_texture(&mut self, handle: TextureHandle) -> Result<()> {
+        let texture = self
+            .textures
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        self.free_texture(texture)
     }
 
     unsafe fn create_render_texture(
@@ -553,7 +657,13 @@ impl Visitor for WebGLVisitor {
         handle: RenderTextureHandle,
         params: RenderTextureParams,
     ) -> Result<()> {
-        let id = if params.sampler {
+        if params.format == RenderTextureFormat::RGBA16F
+            && !self.capabilities.extensions.ext_color_buffer_float
+        {
+            bail!("Floating point render texture is not supported without EXT_color_buffer_float.");
+        }
+
+        let id = if let Some(sampler) = params.sampler {
             let id = self.ctx.create_texture().unwrap();
 
             Self::bind_texture(
@@ -561,9 +671,16 @@ impl Visitor for WebGLVisitor {
                 &mut self.state,
                 Some(Sampler::RenderTexture(handle)),
                 0,
+                WebGL::TEXTURE_2D,
                 Some(&id),
             )?;
-            Self::bind_texture_params(&self.ctx, params.wrap, params.filter, 1)?;
+            Self::bind_texture_params(
+                &self.ctx,
+                &self.capabilities,
+                WebGL::TEXTURE_2D,
+                &sampler,
+                1,
+            )?;
 
             let (internal_format, format, pixel_type) = params.format.into();
             self.ctx
@@ -710,27 +827,80 @@ impl Visitor for WebGLVisitor {
         )
     }
 
+    unsafe fn reload_mesh(
+        &mut self,
+        handle: MeshHandle,
+        params: MeshParams,
+        data: Option<MeshData>,
+    ) -> Result<()> {
+        let prev = self
+            .meshes
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if let Err(err) = self.create_mesh(handle, params, data) {
+            self.meshes.create(handle, prev);
+            return Err(err);
+        }
+
+        self.free_mesh(prev)
+    }
+
     unsafe fn delete_mesh(&mut self, handle: MeshHandle) -> Result<()> {
         let mesh = self
             .meshes
             .free(handle)
             .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
 
-        // Removes deprecated `VertexArrayObject`s.
-        {
-            let ctx = &self.ctx;
-            self.state.vaos.retain(|&(_, h), vao| {
-                if h == mesh.handle {
-                    ctx.delete_vertex_array(Some(&vao));
-                    false
-                } else {
-                    true
-                }
-            });
-        }
+        self.free_mesh(mesh)
+    }
 
-        self.ctx.delete_buffer(Some(&mesh.vbo));
-        self.ctx.delete_buffer(Some(&mesh.ibo));
+    unsafe fn create_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        params: UniformBlockParams,
+    ) -> Result<()> {
+        let bytes = vec![0; params.layout.size()];
+        let id = Self::create_buffer(
+            &self.ctx,
+            WebGL::UNIFORM_BUFFER,
+            MeshHint::Dynamic,
+            bytes.len(),
+            None,
+        )?;
+
+        let block = GLUniformBlockData {
+            handle,
+            id,
+            params,
+            bytes,
+        };
+
+        self.uniform_blocks.create(handle, block);
+        Ok(())
+    }
+
+    unsafe fn update_uniform_block(
+        &mut self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> Result<()> {
+        let block = self
+            .uniform_blocks
+            .get_mut(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        block.params.layout.encode(&mut block.bytes, variables)?;
+        Self::update_buffer(&self.ctx, WebGL::UNIFORM_BUFFER, &block.id, 0, &block.bytes)
+    }
+
+    unsafe fn delete_uniform_block(&mut self, handle: UniformBlockHandle) -> Result<()> {
+        let block = self
+            .uniform_blocks
+            .free(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        self.ctx.delete_buffer(Some(&block.id));
         check(&self.ctx)
     }
 
@@ -761,10 +931,14 @@ impl Visitor for WebGLVisitor {
         if !self.state.cleared_surfaces.contains(&handle) {
             // Sets depth write enable to make sure that we can clear depth buffer properly.
             if surface.params.clear_depth.is_some() {
-                self.state.binded_shader = None;
                 Self::set_depth_test(&self.ctx, &mut self.state, true, Comparison::Always)?;
             }
 
+            // Resets the stencil write mask to make sure that we can clear stencil buffer.
+            if surface.params.clear_stencil.is_some() {
+                Self::set_stencil(&self.ctx, &mut self.state, None)?;
+            }
+
             // Clears frame buffer.
             Self::clear(
                 &self.ctx,
@@ -783,8 +957,10 @@ impl Visitor for WebGLVisitor {
     unsafe fn draw(
         &mut self,
         shader: ShaderHandle,
+        state: Option<RenderState>,
         mesh: MeshHandle,
         mesh_index: MeshIndex,
+        instances: Option<MeshInstances>,
         uniforms: &[UniformVar],
     ) -> Result<u32> {
         // Bind program and associated uniforms and textures.
@@ -793,21 +969,51 @@ impl Visitor for WebGLVisitor {
             .get(shader)
             .ok_or_else(|| format_err!("{:?} is invalid.", shader))?;
 
+        let rs = state.unwrap_or(shader.params.state);
         Self::bind_shader(&self.ctx, &mut self.state, &shader)?;
+        Self::set_render_state(&self.ctx, &mut self.state, rs)?;
 
-        let mut index = 0usize;
+        let (mut index, mut binding) = (0usize, 0);
         for &(field, variable) in uniforms {
             if let Some(tp) = shader.params.uniforms.variable_type(field) {
-                if tp != variable.variable_type() {
+                // The sampler type of texture depends on its dimension, and textures those
+                // are not ready yet are treated as compatible.
+                let vtp = match variable {
+                    UniformVariable::Texture(handle)
+                        if tp != UniformVariableType::RenderTexture =>
+                    {
+                        self.textures
+                            .get(handle)
+                            .map(|v| v.params.dimension.into())
+                            .unwrap_or(tp)
+                    }
+                    _ => variable.variable_type(),
+                };
+
+                if tp != vtp {
                     let name = shader.params.uniforms.variable_name(field).unwrap();
                     bail!(
                         "The uniform {} needs a {:?} instead of {:?}.",
                         name,
                         tp,
-                        variable.variable_type(),
+                        vtp,
                     );
                 }
 
+                if let UniformVariable::UniformBlock(handle) = variable {
+                    let location = shader.hash_uniform_block_index(field).unwrap();
+                    let id = self.uniform_blocks.get(handle).map(|v| &v.id);
+
+                    self.ctx
+                        .uniform_block_binding(&shader.id, location, binding);
+                    self.ctx
+                        .bind_buffer_base(WebGL::UNIFORM_BUFFER, binding, id);
+                    check(&self.ctx)?;
+
+                    binding += 1;
+                    continue;
+                }
+
                 let location = shader.hash_uniform_location(field).unwrap();
                 match variable {
                     UniformVariable::Texture(handle) => {
@@ -820,10 +1026,18 @@ impl Visitor for WebGLVisitor {
                                 &mut self.state,
                                 Some(Sampler::Texture(handle)),
                                 index,
+                                texture.params.dimension.into(),
                                 Some(&texture.id),
                             )?;
                         } else {
-                            Self::bind_texture(&self.ctx, &mut self.state, None, index, None)?;
+                            Self::bind_texture(
+                                &self.ctx,
+                                &mut self.state,
+                                None,
+                                index,
+                                WebGL::TEXTURE_2D,
+                                None,
+                            )?;
                         }
 
                         index += 1;
@@ -840,6 +1054,7 @@ impl Visitor for WebGLVisitor {
                                         &mut self.state,
                                         Some(Sampler::RenderTexture(handle)),
                                         index,
+                                        WebGL::TEXTURE_2D,
                                         Some(w),
                                     )?;
                                 }
@@ -848,7 +1063,14 @@ impl Visitor for WebGLVisitor {
                                 }
                             }
                         } else {
-                            Self::bind_texture(&self.ctx, &mut self.state, None, index, None)?;
+                            Self::bind_texture(
+                                &self.ctx,
+                                &mut self.state,
+                                None,
+                                index,
+                                WebGL::TEXTURE_2D,
+                                None,
+                            )?;
                         }
 
                         index += 1;
@@ -862,9 +1084,24 @@ impl Visitor for WebGLVisitor {
             }
         }
 
+        let instance_mesh = match instances {
+            Some(MeshInstances {
+                mesh: Some(handle),
+                divisor,
+                ..
+            }) => {
+                let mesh = self
+                    .meshes
+                    .get(handle)
+                    .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+                Some((mesh, divisor.max(1)))
+            }
+            _ => None,
+        };
+
         if let Some(mesh) = self.meshes.get(mesh) {
             // Bind vertex buffer and vertex array object.
-            Self::bind_mesh(&self.ctx, &mut self.state, &shader, &mesh)?;
+            Self::bind_mesh(&self.ctx, &mut self.state, &shader, &mesh, instance_mesh)?;
 
             let (from, len) = match mesh_index {
                 MeshIndex::Ptr(from, len) => {
@@ -893,15 +1130,28 @@ impl Visitor for WebGLVisitor {
                 MeshIndex::All => (0, mesh.params.num_idxes),
             };
 
-            self.ctx.draw_elements_with_i32(
-                mesh.params.primitive.into(),
-                len as i32,
-                mesh.params.index_format.into(),
-                from as i32,
-            );
+            if let Some(instances) = instances {
+                self.ctx.draw_elements_instanced_with_i32(
+                    mesh.params.primitive.into(),
+                    len as i32,
+                    mesh.params.index_format.into(),
+                    from as i32,
+                    instances.count as i32,
+                );
 
-            check(&self.ctx)?;
-            Ok(mesh.params.primitive.assemble(len as u32))
+                check(&self.ctx)?;
+                Ok(mesh.params.primitive.assemble(len as u32) * instances.count)
+            } else {
+                self.ctx.draw_elements_with_i32(
+                    mesh.params.primitive.into(),
+                    len as i32,
+                    mesh.params.index_format.into(),
+                    from as i32,
+                );
+
+                check(&self.ctx)?;
+                Ok(mesh.params.primitive.assemble(len as u32))
+            }
         } else {
             Ok(0)
         }
@@ -915,6 +1165,77 @@ impl Visitor for WebGLVisitor {
         Self::set_viewport(&self.ctx, &mut self.state, vp)
     }
 
+    unsafe fn read_surface(
+        &mut self,
+        handle: SurfaceHandle,
+        dimensions: Vector2<u32>,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let surface = self
+            .surfaces
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if surface.id.is_some() && surface.params.colors[0].is_none() {
+            bail!("Trying to read pixels from surface without color attachment.");
+        }
+
+        let dimensions = surface.dims.unwrap_or(dimensions);
+        if area.max.x > dimensions.x || area.max.y > dimensions.y {
+            bail!("Trying to read pixels out of bounds.");
+        }
+
+        self.ctx.bind_framebuffer(WebGL::FRAMEBUFFER, surface.id.as_ref());
+        let result = Self::read_pixels(&self.ctx, area);
+        self.restore_framebuffer();
+        result
+    }
+
+    unsafe fn read_framebuffer(
+        &mut self,
+        dimensions: Vector2<u32>,
+    ) -> Result<(Vector2<u32>, Box<[u8]>)> {
+        let area = Aabb2::new(
+            Point2::new(0, 0),
+            Point2::new(dimensions.x, dimensions.y),
+        );
+
+        self.ctx.bind_framebuffer(WebGL::FRAMEBUFFER, None);
+        let result = Self::read_pixels(&self.ctx, area);
+        self.restore_framebuffer();
+        Ok((dimensions, result?))
+    }
+
+    unsafe fn read_render_texture(
+        &mut self,
+        handle: RenderTextureHandle,
+        area: Aabb2<u32>,
+    ) -> Result<Box<[u8]>> {
+        let rt = self
+            .render_textures
+            .get(handle)
+            .ok_or_else(|| format_err!("{:?} is invalid.", handle))?;
+
+        if !rt.params.format.is_color() {
+            bail!("Trying to read pixels from depth render texture.");
+        }
+
+        if area.max.x > rt.params.dimensions.x || area.max.y > rt.params.dimensions.y {
+            bail!("Trying to read pixels out of bounds.");
+        }
+
+        // Attaches the render texture to a temporary framebuffer.
+        let id = self.ctx.create_framebuffer().unwrap();
+        self.ctx.bind_framebuffer(WebGL::FRAMEBUFFER, Some(&id));
+
+        let result = Self::bind_surface_render_texture(&self.ctx, rt, 0)
+            .and_then(|_| Self::read_pixels(&self.ctx, area));
+
+        self.ctx.delete_framebuffer(Some(&id));
+        self.restore_framebuffer();
+        result
+    }
+
     unsafe fn flush(&mut self) -> Result<()> {
         self.ctx.finish();
         Ok(())
@@ -922,15 +1243,45 @@ impl Visitor for WebGLVisitor {
 }
 
 impl WebGLVisitor {
+    unsafe fn read_pixels(ctx: &WebGL, area: Aabb2<u32>) -> Result<Box<[u8]>> {
+        let mut bytes = vec![0u8; area.volume() as usize * 4];
+
+        ctx.pixel_storei(WebGL::PACK_ALIGNMENT, 1);
+        ctx.read_pixels_with_opt_u8_array(
+            area.min.x as i32,
+            area.min.y as i32,
+            area.dim().x as i32,
+            area.dim().y as i32,
+            WebGL::RGBA,
+            WebGL::UNSIGNED_BYTE,
+            Some(&mut bytes),
+        ).unwrap();
+
+        check(ctx)?;
+        Ok(bytes.into_boxed_slice())
+    }
+
+    /// Rebinds the framebuffer of binded surface, after reading from another one.
+    unsafe fn restore_framebuffer(&self) {
+        let id = self
+            .state
+            .binded_surface
+            .and_then(|v| self.surfaces.get(v))
+            .and_then(|v| v.id.as_ref());
+
+        self.ctx.bind_framebuffer(WebGL::FRAMEBUFFER, id);
+    }
+
     unsafe fn bind_surface_render_texture(
         ctx: &WebGL,
         rt: &GLRenderTextureData,
         index: usize,
     ) -> Result<()> {
         let location = match rt.params.format {
-            RenderTextureFormat::RGB8 | RenderTextureFormat::RGBA4 | RenderTextureFormat::RGBA8 => {
-                WebGL::COLOR_ATTACHMENT0 + index as u32
-            }
+            RenderTextureFormat::RGB8
+            | RenderTextureFormat::RGBA4
+            | RenderTextureFormat::RGBA8
+            | RenderTextureFormat::RGBA16F => WebGL::COLOR_ATTACHMENT0 + index as u32,
             RenderTextureFormat::Depth16
             | RenderTextureFormat::Depth24
             | RenderTextureFormat::Depth32 => WebGL::DEPTH_ATTACHMENT,
@@ -958,6 +1309,58 @@ impl WebGLVisitor {
 }
 
 impl WebGLVisitor {
+    unsafe fn free_shader(&mut self, shader: GLShaderData) -> Result<()> {
+        // Removes deprecated `VertexArrayObject`s.
+        {
+            let ctx = &self.ctx;
+            self.state.vaos.retain(|&(h, _, _), vao| {
+                if h == shader.handle {
+                    ctx.delete_vertex_array(Some(&vao));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        if self.state.binded_shader == Some(shader.handle) {
+            self.state.binded_shader = None;
+        }
+
+        self.ctx.delete_program(Some(&shader.id));
+        check(&self.ctx)
+    }
+
+    unsafe fn free_texture(&mut self, texture: GLTextureData) -> Result<()> {
+        for v in self.state.binded_textures.iter_mut() {
+            if *v == Some(Sampler::Texture(texture.handle)) {
+                *v = None;
+            }
+        }
+
+        self.ctx.delete_texture(Some(&texture.id));
+        check(&self.ctx)
+    }
+
+    unsafe fn free_mesh(&mut self, mesh: GLMeshData) -> Result<()> {
+        // Removes deprecated `VertexArrayObject`s.
+        {
+            let ctx = &self.ctx;
+            self.state.vaos.retain(|&(_, h, instance), vao| {
+                if h == mesh.handle || instance.map(|v| v.0) == Some(mesh.handle) {
+                    ctx.delete_vertex_array(Some(&vao));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        self.ctx.delete_buffer(Some(&mesh.vbo));
+        self.ctx.delete_buffer(Some(&mesh.ibo));
+        check(&self.ctx)
+    }
+
     unsafe fn compile(ctx: &WebGL, tp: u32, src: &str) -> Result<WebGlShader> {
         let shader = ctx
             .create_shader(tp)
@@ -1023,16 +1426,20 @@ impl WebGLVisitor {
         ctx.use_program(Some(&shader.id));
         check(ctx)?;
 
-        let rs = &shader.params.state;
+        state.binded_shader = Some(shader.handle);
+        Ok(())
+    }
+
+    /// Applies the render state of draw call, only the changed parts are submitted since
+    /// all the setters are backed by the state cache.
+    unsafe fn set_render_state(ctx: &WebGL, state: &mut WebGLState, rs: RenderState) -> Result<()> {
         Self::set_cull_face(ctx, state, rs.cull_face)?;
         Self::set_front_face_order(ctx, state, rs.front_face_order)?;
         Self::set_depth_test(ctx, state, rs.depth_write, rs.depth_test)?;
         Self::set_depth_write_offset(ctx, state, rs.depth_write_offset)?;
         Self::set_color_blend(ctx, state, rs.color_blend)?;
         Self::set_color_write(ctx, state, rs.color_write)?;
-
-        state.binded_shader = Some(shader.handle);
-        Ok(())
+        Self::set_stencil(ctx, state, rs.stencil)
     }
 
     unsafe fn bind_mesh(
@@ -1040,10 +1447,16 @@ impl WebGLVisitor {
         state: &mut WebGLState,
         shader: &GLShaderData,
         mesh: &GLMeshData,
+        instance_mesh: Option<(&GLMeshData, u32)>,
     ) -> Result<()> {
         assert!(state.binded_shader == Some(shader.handle));
 
-        let k = (shader.handle, mesh.handle);
+        let k = (
+            shader.handle,
+            mesh.handle,
+            instance_mesh.map(|(v, divisor)| (v.handle, divisor)),
+        );
+
         if state.binded_vao != Some(k) {
             if let Some(vao) = state.vaos.get(&k).cloned() {
                 ctx.bind_vertex_array(Some(&vao));
@@ -1051,10 +1464,19 @@ impl WebGLVisitor {
             } else {
                 let vao = ctx.create_vertex_array().unwrap();
                 ctx.bind_vertex_array(Some(&vao));
-                ctx.bind_buffer(WebGL::ARRAY_BUFFER, Some(&mesh.vbo));
 
                 for (name, size, required) in shader.params.attributes.iter() {
-                    if let Some(element) = mesh.params.layout.element(name) {
+                    // Per-instance attributes take precedence over per-vertex ones.
+                    let stream = match instance_mesh {
+                        Some((v, divisor)) if v.params.layout.element(name).is_some() => {
+                            Some((v, divisor))
+                        }
+                        _ if mesh.params.layout.element(name).is_some() => Some((mesh, 0)),
+                        _ => None,
+                    };
+
+                    if let Some((mesh, divisor)) = stream {
+                        let element = mesh.params.layout.element(name).unwrap();
                         if element.size < size {
                             bail!(
                                 "Vertex buffer has incompatible attribute `{:?}` [{:?} - {:?}].",
@@ -1068,15 +1490,32 @@ impl WebGLVisitor {
                         let stride = mesh.params.layout.stride();
 
                         let location = shader.attribute_location(ctx, name.into())?;
+                        ctx.bind_buffer(WebGL::ARRAY_BUFFER, Some(&mesh.vbo));
                         ctx.enable_vertex_attrib_array(location as u32);
-                        ctx.vertex_attrib_pointer_with_i32(
-                            location as u32,
-                            element.size as i32,
-                            element.format.into(),
-                            element.normalized,
-                            stride as i32,
-                            offset as i32,
-                        );
+
+                        // Integer attributes are passed to shaders without conversion.
+                        if element.format.integer() {
+                            ctx.vertex_attrib_i_pointer_with_i32(
+                                location as u32,
+                                element.size as i32,
+                                element.format.into(),
+                                stride as i32,
+                                offset as i32,
+                            );
+                        } else {
+                            ctx.vertex_attrib_pointer_with_i32(
+                                location as u32,
+                                element.size as i32,
+                                element.format.into(),
+                                element.normalized,
+                                stride as i32,
+                                offset as i32,
+                            );
+                        }
+
+                        if divisor > 0 {
+                            ctx.vertex_attrib_divisor(location as u32, divisor);
+                        }
                     } else {
                         if required {
                             bail!(
@@ -1106,6 +1545,7 @@ impl WebGLVisitor {
         match *variable {
             UniformVariable::Texture(_) => unreachable!(),
             UniformVariable::RenderTexture(_) => unreachable!(),
+            UniformVariable::UniformBlock(_) => unreachable!(),
             UniformVariable::I32(v) => ctx.uniform1i(Some(&location), v),
             UniformVariable::F32(v) => ctx.uniform1f(Some(&location), v),
             UniformVariable::Vector2f(v) => ctx.uniform2f(Some(&location), v[0], v[1]),
@@ -1151,6 +1591,10 @@ impl WebGLVisitor {
         ctx.color_mask(true, true, true, true);
         rs.color_write = (true, true, true, true);
 
+        ctx.disable(WebGL::STENCIL_TEST);
+        ctx.stencil_mask(0xFF);
+        rs.stencil = None;
+
         ctx.disable(WebGL::SCISSOR_TEST);
         state.scissor = SurfaceScissor::Disable;
 
@@ -1309,6 +1753,45 @@ impl WebGLVisitor {
         Ok(())
     }
 
+    /// Enable or disable stencil test, and specify the test and operations of front- and
+    /// back-facing polygons.
+    unsafe fn set_stencil(
+        ctx: &WebGL,
+        state: &mut WebGLState,
+        stencil: Option<StencilState>,
+    ) -> Result<()> {
+        let state = &mut state.render_state;
+
+        if state.stencil != stencil {
+            if let Some(v) = stencil {
+                if state.stencil == None {
+                    ctx.enable(WebGL::STENCIL_TEST);
+                }
+
+                for &(face, s) in &[(WebGL::FRONT, v.front), (WebGL::BACK, v.back)] {
+                    let (reference, mask) = (i32::from(s.reference), u32::from(s.read_mask));
+                    ctx.stencil_func_separate(face, s.comparison.into(), reference, mask);
+                    ctx.stencil_op_separate(
+                        face,
+                        s.fail.into(),
+                        s.depth_fail.into(),
+                        s.pass.into(),
+                    );
+                    ctx.stencil_mask_separate(face, u32::from(s.write_mask));
+                }
+            } else {
+                ctx.disable(WebGL::STENCIL_TEST);
+                // The write mask also affects `clear`.
+                ctx.stencil_mask(0xFF);
+            }
+
+            state.stencil = stencil;
+            check(&ctx)?;
+        }
+
+        Ok(())
+    }
+
     /// Set the scissor box relative to the top-lef corner of th window, in pixels.
     unsafe fn set_scissor(
         ctx: &WebGL,
@@ -1395,6 +1878,7 @@ impl WebGLVisitor {
         state: &mut WebGLState,
         sampler: Option<Sampler>,
         index: usize,
+        target: u32,
         id: Option<&WebGlTexture>,
     ) -> Result<()> {
         if state.binded_texture_index != index {
@@ -1408,7 +1892,7 @@ impl WebGLVisitor {
 
         if state.binded_textures[index] != sampler {
             state.binded_textures[index] = sampler;
-            ctx.bind_texture(WebGL::TEXTURE_2D, id);
+            ctx.bind_texture(target, id);
         }
 
         check(ctx)
@@ -1416,59 +1900,148 @@ impl WebGLVisitor {
 
     unsafe fn bind_texture_params(
         ctx: &WebGL,
-        wrap: TextureWrap,
-        filter: TextureFilter,
+        caps: &Capabilities,
+        target: u32,
+        sampler: &SamplerParams,
         levels: u32,
     ) -> Result<()> {
-        let wrap: u32 = wrap.into();
+        let wrap: u32 = sampler.wrap.into();
         let wrap = wrap as i32;
 
-        ctx.tex_parameteri(WebGL::TEXTURE_2D, WebGL::TEXTURE_WRAP_S, wrap);
-        ctx.tex_parameteri(WebGL::TEXTURE_2D, WebGL::TEXTURE_WRAP_T, wrap);
+        ctx.tex_parameteri(target, WebGL::TEXTURE_WRAP_S, wrap);
+        ctx.tex_parameteri(target, WebGL::TEXTURE_WRAP_T, wrap);
 
-        match filter {
-            TextureFilter::Nearest => {
-                let min_filter = if levels > 1 {
-                    WebGL::NEAREST_MIPMAP_NEAREST
-                } else {
-                    WebGL::NEAREST
-                } as i32;
+        if target == WebGL::TEXTURE_CUBE_MAP || target == WebGL::TEXTURE_3D {
+            ctx.tex_parameteri(target, WebGL::TEXTURE_WRAP_R, wrap);
+        }
 
-                ctx.tex_parameteri(WebGL::TEXTURE_2D, WebGL::TEXTURE_MIN_FILTER, min_filter);
+        let mip_filter = if levels > 1 { sampler.mip_filter } else { None };
+        let min_filter = types::texture_min_filter(sampler.min_filter, mip_filter);
+        let mag_filter: u32 = sampler.mag_filter.into();
+        ctx.tex_parameteri(target, WebGL::TEXTURE_MIN_FILTER, min_filter as i32);
+        ctx.tex_parameteri(target, WebGL::TEXTURE_MAG_FILTER, mag_filter as i32);
 
-                ctx.tex_parameteri(
-                    WebGL::TEXTURE_2D,
-                    WebGL::TEXTURE_MAG_FILTER,
-                    WebGL::NEAREST as i32,
-                );
-            }
-            TextureFilter::Linear => {
-                let min_filter = if levels > 1 {
-                    WebGL::LINEAR_MIPMAP_LINEAR
-                } else {
-                    WebGL::LINEAR
-                } as i32;
+        if levels > 1 {
+            ctx.tex_parameteri(target, WebGL::TEXTURE_BASE_LEVEL, 0);
+            ctx.tex_parameteri(target, WebGL::TEXTURE_MAX_LEVEL, (levels - 1) as i32);
+        }
 
-                ctx.tex_parameteri(WebGL::TEXTURE_2D, WebGL::TEXTURE_MIN_FILTER, min_filter);
-                ctx.tex_parameteri(
-                    WebGL::TEXTURE_2D,
-                    WebGL::TEXTURE_MAG_FILTER,
-                    WebGL::LINEAR as i32,
-                );
-            }
+        // WebGL does NOT support LOD bias and border color of texture object.
+        if let Some(cmp) = sampler.compare {
+            let func: u32 = cmp.into();
+            let mode = WebGL::COMPARE_REF_TO_TEXTURE as i32;
+            ctx.tex_parameteri(target, WebGL::TEXTURE_COMPARE_MODE, mode);
+            ctx.tex_parameteri(target, WebGL::TEXTURE_COMPARE_FUNC, func as i32);
         }
 
-        if levels > 1 {
-            ctx.tex_parameteri(WebGL::TEXTURE_2D, WebGL::TEXTURE_BASE_LEVEL, 0);
-            ctx.tex_parameteri(
-                WebGL::TEXTURE_2D,
-                WebGL::TEXTURE_MAX_LEVEL,
-                (levels - 1) as i32,
-            );
+        if sampler.anisotropy > 1 && caps.max_anisotropy > 1.0 {
+            let anisotropy = f32::from(sampler.anisotropy).min(caps.max_anisotropy);
+            ctx.tex_parameterf(target, TEXTURE_MAX_ANISOTROPY, anisotropy);
         }
 
         check(&ctx)
     }
+
+    /// Specifies all the images of mipmap `level`, or allocates them if `data` is none.
+    /// The images of cube, array and 3D textures are stored continuously in `data`.
+    unsafe fn tex_image(
+        ctx: &WebGL,
+        params: &TextureParams,
+        level: u32,
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        let (internal_format, format, pixel_type) = params.format.into();
+        let dims = Vector2::new(
+            (params.dimensions.x >> level).max(1),
+            (params.dimensions.y >> level).max(1),
+        );
+
+        let images = params.images(level);
+        let compressed = params.format.compressed();
+
+        match params.dimension {
+            TextureDimension::D2 | TextureDimension::Cube => {
+                let size = match (params.dimension, data) {
+                    (TextureDimension::D2, Some(v)) => v.len(),
+                    _ => params.format.size(dims) as usize,
+                };
+
+                for i in 0..images {
+                    let target = if params.dimension == TextureDimension::Cube {
+                        WebGL::TEXTURE_CUBE_MAP_POSITIVE_X + i
+                    } else {
+                        WebGL::TEXTURE_2D
+                    };
+
+                    let mv = data.map(|v| {
+                        let v = &v[size * i as usize..size * (i as usize + 1)];
+                        ::std::slice::from_raw_parts_mut(v.as_ptr() as *mut u8, v.len())
+                    });
+
+                    if compressed {
+                        if let Some(mv) = mv {
+                            ctx.compressed_tex_image_2d_with_u8_array(
+                                target,
+                                level as i32,
+                                internal_format,
+                                dims.x as i32,
+                                dims.y as i32,
+                                0,
+                                mv,
+                            );
+                        }
+                    } else {
+                        ctx.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                            target,
+                            level as i32,
+                            internal_format as i32,
+                            dims.x as i32,
+                            dims.y as i32,
+                            0,
+                            format,
+                            pixel_type,
+                            mv,
+                        ).unwrap();
+                    }
+                }
+            }
+            TextureDimension::D2Array | TextureDimension::D3 => {
+                let target: u32 = params.dimension.into();
+                let mv =
+                    data.map(|v| ::std::slice::from_raw_parts_mut(v.as_ptr() as *mut u8, v.len()));
+
+                if compressed {
+                    if let Some(mv) = mv {
+                        ctx.compressed_tex_image_3d_with_u8_array(
+                            target,
+                            level as i32,
+                            internal_format,
+                            dims.x as i32,
+                            dims.y as i32,
+                            images as i32,
+                            0,
+                            mv,
+                        );
+                    }
+                } else {
+                    ctx.tex_image_3d_with_opt_u8_array(
+                        target,
+                        level as i32,
+                        internal_format as i32,
+                        dims.x as i32,
+                        dims.y as i32,
+                        images as i32,
+                        0,
+                        format,
+                        pixel_type,
+                        mv,
+                    ).unwrap();
+                }
+            }
+        }
+
+        check(ctx)
+    }
 }
 
 impl WebGLVisitor {
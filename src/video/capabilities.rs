@@ -0,0 +1,162 @@
+//! The features and limits of the video backend.
+
+use super::assets::texture::{RenderTextureFormat, TextureFormat};
+
+const TEXTURE_FORMATS: [TextureFormat; 24] = [
+    TextureFormat::R8,
+    TextureFormat::RG8,
+    TextureFormat::RGB8,
+    TextureFormat::RGBA8,
+    TextureFormat::RGB565,
+    TextureFormat::RGBA4,
+    TextureFormat::RGBA5551,
+    TextureFormat::RGBA1010102,
+    TextureFormat::R16F,
+    TextureFormat::RG16F,
+    TextureFormat::RGB16F,
+    TextureFormat::RGBA16F,
+    TextureFormat::R32F,
+    TextureFormat::RG32F,
+    TextureFormat::RGB32F,
+    TextureFormat::RGBA32F,
+    TextureFormat::PvrtcRGB4BPP,
+    TextureFormat::PvrtcRGB2BPP,
+    TextureFormat::PvrtcRGBA4BPP,
+    TextureFormat::PvrtcRGBA2BPP,
+    TextureFormat::Etc2RGB4BPP,
+    TextureFormat::Etc2RGBA8BPP,
+    TextureFormat::S3tcDxt1RGB4BPP,
+    TextureFormat::S3tcDxt5RGBA8BPP,
+];
+
+const RENDER_TEXTURE_FORMATS: [RenderTextureFormat; 8] = [
+    RenderTextureFormat::RGB8,
+    RenderTextureFormat::RGBA4,
+    RenderTextureFormat::RGBA8,
+    RenderTextureFormat::Depth16,
+    RenderTextureFormat::Depth24,
+    RenderTextureFormat::Depth32,
+    RenderTextureFormat::Depth24Stencil8,
+    RenderTextureFormat::RGBA16F,
+];
+
+/// Describes the features and limits of the video backend, which never change once the
+/// video system is setup.
+///
+/// The headless backend reports `VideoParams::capabilities`, which makes it possible to
+/// simulate low-end devices in tests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// The maximum width and height of textures.
+    pub max_texture_size: u32,
+    /// The maximum number of vertex attributes of shader.
+    pub max_vertex_attributes: u32,
+    /// The maximum number of textures that can be bound to a shader.
+    pub max_texture_units: u32,
+    /// The maximum number of color attachments of surface.
+    pub max_color_attachments: u32,
+    /// The maximum degree of anisotropic filtering, `1.0` if not supported.
+    pub max_anisotropy: f32,
+    /// The formats that textures could be created with.
+    pub texture_formats: Vec<TextureFormat>,
+    /// The formats that render textures could be created with.
+    pub render_texture_formats: Vec<RenderTextureFormat>,
+    /// Whether `MeshInstances` could be drawn.
+    pub instancing: bool,
+    /// Whether uniform blocks could be created.
+    pub uniform_blocks: bool,
+    /// Whether floating point textures could be sampled.
+    pub float_textures: bool,
+    /// Whether vertex attributes could be half floats, packed `2_10_10_10` or integers.
+    pub extended_vertex_formats: bool,
+    /// Whether textures could be wrapped with `TextureWrap::Border`, which falls back to
+    /// `TextureWrap::Clamp` otherwise.
+    pub texture_border_clamp: bool,
+}
+
+impl Default for Capabilities {
+    /// Everything is supported, with the limits of a typical desktop GPU.
+    fn default() -> Self {
+        Capabilities {
+            max_texture_size: 16384,
+            max_vertex_attributes: 16,
+            max_texture_units: 32,
+            max_color_attachments: 8,
+            max_anisotropy: 16.0,
+            texture_formats: TEXTURE_FORMATS.to_vec(),
+            render_texture_formats: RENDER_TEXTURE_FORMATS.to_vec(),
+            instancing: true,
+            uniform_blocks: true,
+            float_textures: true,
+            extended_vertex_formats: true,
+            texture_border_clamp: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// The capabilities of a low-end device, which only guarantees the features of
+    /// OpenGL ES 2.0.
+    pub fn minimal() -> Self {
+        Capabilities {
+            max_texture_size: 2048,
+            max_vertex_attributes: 8,
+            max_texture_units: 8,
+            max_color_attachments: 1,
+            max_anisotropy: 1.0,
+            texture_formats: vec![
+                TextureFormat::R8,
+                TextureFormat::RGB8,
+                TextureFormat::RGBA8,
+                TextureFormat::RGB565,
+                TextureFormat::RGBA4,
+                TextureFormat::RGBA5551,
+            ],
+            render_texture_formats: vec![
+                RenderTextureFormat::RGB8,
+                RenderTextureFormat::RGBA4,
+                RenderTextureFormat::RGBA8,
+                RenderTextureFormat::Depth16,
+            ],
+            instancing: false,
+            uniform_blocks: false,
+            float_textures: false,
+            extended_vertex_formats: false,
+            texture_border_clamp: false,
+        }
+    }
+
+    /// Collects the texture formats which pass `filter`.
+    pub(crate) fn texture_formats<F>(filter: F) -> Vec<TextureFormat>
+    where
+        F: Fn(TextureFormat) -> bool,
+    {
+        TEXTURE_FORMATS
+            .iter()
+            .cloned()
+            .filter(|&v| filter(v))
+            .collect()
+    }
+
+    /// Collects the render texture formats which pass `filter`.
+    pub(crate) fn render_texture_formats<F>(filter: F) -> Vec<RenderTextureFormat>
+    where
+        F: Fn(RenderTextureFormat) -> bool,
+    {
+        RENDER_TEXTURE_FORMATS
+            .iter()
+            .cloned()
+            .filter(|&v| filter(v))
+            .collect()
+    }
+
+    #[inline]
+    pub fn support_texture_format(&self, format: TextureFormat) -> bool {
+        self.texture_formats.contains(&format)
+    }
+
+    #[inline]
+    pub fn support_render_texture_format(&self, format: RenderTextureFormat) -> bool {
+        self.render_texture_formats.contains(&format)
+    }
+}
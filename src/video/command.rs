@@ -1,8 +1,9 @@
 use crate::math::prelude::Aabb2;
-use crate::utils::prelude::{DataBuffer, HashValue};
+use crate::utils::prelude::{DataBuffer, DataBufferPtr, HashValue};
 
 use super::assets::prelude::*;
 use super::backends::frame::Command;
+use super::backends::UniformVar;
 use super::errors::*;
 use super::MAX_UNIFORM_VARIABLES;
 
@@ -11,6 +12,7 @@ use super::MAX_UNIFORM_VARIABLES;
 pub struct CommandBuffer {
     cmds: Vec<Command>,
     bufs: DataBuffer,
+    transients: Vec<(DataBufferPtr<[u32]>, u32)>,
 }
 
 impl CommandBuffer {
@@ -20,15 +22,58 @@ impl CommandBuffer {
         CommandBuffer {
             cmds: Vec::with_capacity(32),
             bufs: DataBuffer::with_capacity(512),
+            transients: Vec::new(),
         }
     }
 
+    /// Allocates `verts` vertices and `idxes` indices of transient geometry, which is
+    /// rebuilt every frame, e.g. UI, debug lines or particles.
+    ///
+    /// The geometry is stored in a ring buffer shared by all the allocations with the same
+    /// `layout` and `primitive`, so it should be submitted in the current frame. The
+    /// indices are relative to the allocated vertices.
+    pub fn alloc_transient(
+        &mut self,
+        layout: VertexLayout,
+        primitive: MeshPrimitive,
+        verts: usize,
+        idxes: usize,
+    ) -> Result<TransientMesh<'_>> {
+        let (mesh, vertex, index) = super::alloc_transient(layout, primitive, verts, idxes)?;
+        let stride = layout.stride() as usize;
+
+        let vptr = self.bufs.alloc_slice::<u8>(verts * stride);
+        let cmd = Command::UpdateVertexBuffer(mesh, vertex * stride, vptr);
+        self.cmds.push(cmd);
+
+        let iptr = self.bufs.alloc_slice::<u32>(idxes);
+        let cmd =
+            Command::UpdateIndexBuffer(mesh, index * IndexFormat::U32.stride(), iptr.into_bytes());
+        self.cmds.push(cmd);
+        self.transients.push((iptr, vertex as u32));
+
+        Ok(TransientMesh {
+            mesh,
+            mesh_index: MeshIndex::Ptr(index, idxes),
+            vptr,
+            iptr,
+            bufs: &mut self.bufs,
+        })
+    }
+
     /// Draws ur mesh.
     #[inline]
     pub fn draw(&mut self, dc: Draw) {
         let len = dc.uniforms_len;
         let ptr = self.bufs.extend_from_slice(&dc.uniforms[0..len]);
-        let cmd = Command::Draw(dc.shader, dc.mesh, dc.mesh_index, ptr);
+        let cmd = Command::Draw(
+            dc.shader,
+            dc.state.map(Box::new),
+            dc.mesh,
+            dc.mesh_index,
+            dc.instances,
+            ptr,
+        );
         self.cmds.push(cmd);
     }
 
@@ -50,9 +95,22 @@ impl CommandBuffer {
     /// Update a contiguous subregion of an existing two-dimensional texture object.
     #[inline]
     pub fn update_texture(&mut self, id: TextureHandle, area: Aabb2<u32>, bytes: &[u8]) {
+        self.update_texture_layer(id, 0, area, bytes);
+    }
+
+    /// Update a contiguous subregion of the specified face of cube texture, layer of
+    /// array texture or depth slice of 3D texture.
+    #[inline]
+    pub fn update_texture_layer(
+        &mut self,
+        id: TextureHandle,
+        layer: u32,
+        area: Aabb2<u32>,
+        bytes: &[u8],
+    ) {
         let bufs = &mut self.bufs;
         let ptr = bufs.extend_from_slice(bytes);
-        self.cmds.push(Command::UpdateTexture(id, area, ptr));
+        self.cmds.push(Command::UpdateTexture(id, layer, area, ptr));
     }
 
     /// Update a subset of dynamic vertex buffer. Use `offset` specifies the offset
@@ -75,27 +133,43 @@ impl CommandBuffer {
         self.cmds.push(Command::UpdateIndexBuffer(id, offset, ptr));
     }
 
+    /// Updates the variables of uniform block, the others keep unchanged. All the draw calls
+    /// that bind this uniform block after this command will see the new values.
+    #[inline]
+    pub fn update_uniform_block(&mut self, id: UniformBlockHandle, variables: &[UniformVar]) {
+        let bufs = &mut self.bufs;
+        let ptr = bufs.extend_from_slice(variables);
+        self.cmds.push(Command::UpdateUniformBlock(id, ptr));
+    }
+
     /// Clears the batch, and submits all the commands into video device. Its guaranteed that
     /// all the commands in this batch will be executed one by one in order.
     ///
     /// Notes that this method has no effect on the allocated capacity of the underlying storage.
     pub fn submit(&mut self, surface: SurfaceHandle) -> Result<()> {
+        // Rebases the indices of transient geometry to the vertices in ring buffer.
+        for (ptr, vertex) in self.transients.drain(..) {
+            for v in self.bufs.as_mut_slice(ptr) {
+                *v += vertex;
+            }
+        }
+
         let doubele_frame = unsafe { super::frames() };
         let mut frame = doubele_frame.write();
         frame.cmds.push(Command::Bind(surface));
 
         for v in self.cmds.drain(..) {
             match v {
-                Command::Draw(shader, mesh, mesh_index, ptr) => {
+                Command::Draw(shader, state, mesh, mesh_index, instances, ptr) => {
                     let vars = self.bufs.as_slice(ptr);
                     let ptr = frame.bufs.extend_from_slice(vars);
-                    let cmd = Command::Draw(shader, mesh, mesh_index, ptr);
+                    let cmd = Command::Draw(shader, state, mesh, mesh_index, instances, ptr);
                     frame.cmds.push(cmd);
                 }
 
-                Command::UpdateTexture(id, area, ptr) => {
+                Command::UpdateTexture(id, layer, area, ptr) => {
                     let ptr = frame.bufs.extend_from_slice(self.bufs.as_slice(ptr));
-                    frame.cmds.push(Command::UpdateTexture(id, area, ptr));
+                    frame.cmds.push(Command::UpdateTexture(id, layer, area, ptr));
                 }
 
                 Command::UpdateVertexBuffer(id, offset, ptr) => {
@@ -109,6 +183,11 @@ impl CommandBuffer {
                     frame.cmds.push(Command::UpdateIndexBuffer(id, offset, ptr));
                 }
 
+                Command::UpdateUniformBlock(id, ptr) => {
+                    let ptr = frame.bufs.extend_from_slice(self.bufs.as_slice(ptr));
+                    frame.cmds.push(Command::UpdateUniformBlock(id, ptr));
+                }
+
                 other => frame.cmds.push(other),
             }
         }
@@ -118,6 +197,38 @@ impl CommandBuffer {
     }
 }
 
+/// The transient geometry allocated by `CommandBuffer::alloc_transient`.
+pub struct TransientMesh<'a> {
+    /// The mesh which should be drawn with `mesh_index`.
+    pub mesh: MeshHandle,
+    pub mesh_index: MeshIndex,
+    vptr: DataBufferPtr<[u8]>,
+    iptr: DataBufferPtr<[u32]>,
+    bufs: &'a mut DataBuffer,
+}
+
+impl<'a> TransientMesh<'a> {
+    /// Gets the bytes of vertices, which are laid out as the `VertexLayout`.
+    #[inline]
+    pub fn vertices(&mut self) -> &mut [u8] {
+        self.bufs.as_mut_slice(self.vptr)
+    }
+
+    /// Gets the indices, which are relative to the first allocated vertex.
+    #[inline]
+    pub fn indices(&mut self) -> &mut [u32] {
+        self.bufs.as_mut_slice(self.iptr)
+    }
+
+    /// Creates a draw call of this geometry with `shader`.
+    #[inline]
+    pub fn draw(&self, shader: ShaderHandle) -> Draw {
+        let mut dc = Draw::new(shader, self.mesh);
+        dc.mesh_index = self.mesh_index;
+        dc
+    }
+}
+
 /// The draw call buffer of video system, which provides simple sort functionality for convenience.
 pub struct DrawCommandBuffer<T: Ord + Copy> {
     cmds: Vec<(T, Command)>,
@@ -144,7 +255,14 @@ impl<T: Ord + Copy> DrawCommandBuffer<T> {
     pub fn draw(&mut self, order: T, dc: Draw) {
         let len = dc.uniforms_len;
         let ptr = self.bufs.extend_from_slice(&dc.uniforms[0..len]);
-        let cmd = Command::Draw(dc.shader, dc.mesh, dc.mesh_index, ptr);
+        let cmd = Command::Draw(
+            dc.shader,
+            dc.state.map(Box::new),
+            dc.mesh,
+            dc.mesh_index,
+            dc.instances,
+            ptr,
+        );
         self.cmds.push((order, cmd));
     }
 
@@ -159,10 +277,10 @@ impl<T: Ord + Copy> DrawCommandBuffer<T> {
 
         self.cmds.as_mut_slice().sort_by_key(|v| v.0);
         for v in self.cmds.drain(..) {
-            if let (_, Command::Draw(shader, mesh, mesh_index, ptr)) = v {
+            if let (_, Command::Draw(shader, state, mesh, mesh_index, instances, ptr)) = v {
                 let vars = self.bufs.as_slice(ptr);
                 let ptr = frame.bufs.extend_from_slice(vars);
-                let cmd = Command::Draw(shader, mesh, mesh_index, ptr);
+                let cmd = Command::Draw(shader, state, mesh, mesh_index, instances, ptr);
                 frame.cmds.push(cmd);
             }
         }
@@ -181,6 +299,9 @@ pub struct Draw {
     pub shader: ShaderHandle,
     pub mesh: MeshHandle,
     pub mesh_index: MeshIndex,
+    pub instances: Option<MeshInstances>,
+    /// Replaces the `ShaderParams::state` of shader in this draw call if specified.
+    pub state: Option<RenderState>,
 }
 
 impl Draw {
@@ -193,9 +314,28 @@ impl Draw {
             uniforms: [nil; MAX_UNIFORM_VARIABLES],
             uniforms_len: 0,
             mesh_index: MeshIndex::All,
+            instances: None,
+            state: None,
         }
     }
 
+    /// Overrides the render state of shader, e.g. blending or depth write, which makes it
+    /// possible to draw the same material opaque and transparent with one shader object.
+    ///
+    /// The state is applied through the state cache of backend, so only the changed parts
+    /// are submitted to the device.
+    #[inline]
+    pub fn set_render_state(&mut self, state: RenderState) {
+        self.state = Some(state);
+    }
+
+    /// Draws `count` instances of mesh in one go, the optional per-instance attributes are
+    /// fetched from the vertices of `instances.mesh`.
+    #[inline]
+    pub fn set_instances(&mut self, instances: MeshInstances) {
+        self.instances = Some(instances);
+    }
+
     /// Binds the named field with `UniformVariable`.
     pub fn set_uniform_variable<F, V>(&mut self, field: F, variable: V)
     where
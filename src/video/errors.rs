@@ -12,6 +12,14 @@ pub enum Error {
     SampleRenderBuffer,
     #[fail(display = "Failed to create surface, errors:\n{}\n", _0)]
     SurfaceInvalid(String),
+    #[fail(display = "Failed to create texture, errors:\n{}\n", _0)]
+    TextureInvalid(String),
+    #[fail(display = "Invalid mesh, errors:\n{}\n", _0)]
+    MeshInvalid(String),
+    #[fail(display = "Failed to create uniform block, errors:\n{}\n", _0)]
+    UniformBlockInvalid(String),
+    #[fail(display = "Failed to compile render graph, errors:\n{}\n", _0)]
+    RenderGraphInvalid(String),
     #[fail(display = "Attribute({}) is undefined.", _0)]
     AttributeUndefined(String),
 }
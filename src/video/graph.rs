@@ -0,0 +1,641 @@
+//! A frame graph built on top of surfaces and render textures.
+//!
+//! Passes are declared with the named targets they read and write, instead of binding
+//! surfaces manually in submission order. When the graph is executed, it will:
+//!
+//! 1. Order the passes topologically, a pass always runs after the writers of targets
+//!    it reads. Writers of the same target keep their declaration order.
+//! 2. Cull the passes which contribute nothing to the backbuffer, external surfaces or
+//!    imported targets.
+//! 3. Allocate the transient targets from `RenderGraphCache`, targets with the same
+//!    `RenderTextureParams` and disjoint lifetimes share the same render texture.
+//!
+//! The graph itself should be re-declared every frame, while the `RenderGraphCache` is
+//! kept around to reuse the render textures and surfaces between frames.
+
+use crate::errors::Result;
+use crate::math::prelude::Color;
+use crate::utils::prelude::FastHashMap;
+
+use super::assets::prelude::*;
+use super::command::CommandBuffer;
+use super::errors::Error;
+
+type PassFn<'a> = FnMut(&RenderPassContext, &mut CommandBuffer) -> Result<()> + 'a;
+
+enum TargetKind {
+    /// The render texture is allocated and recycled by the graph.
+    Transient(RenderTextureParams),
+    /// The render texture is owned by user, which outlives the graph.
+    Imported(RenderTextureHandle),
+}
+
+struct Target {
+    name: String,
+    kind: TargetKind,
+}
+
+struct Pass<'a> {
+    name: String,
+    reads: Vec<String>,
+    colors: Vec<String>,
+    depth_stencil: Option<String>,
+    backbuffer: bool,
+    surface: Option<SurfaceHandle>,
+    clear_color: Option<Color<f32>>,
+    clear_depth: Option<f32>,
+    clear_stencil: Option<i32>,
+    execute: Option<Box<PassFn<'a>>>,
+}
+
+/// The passes and targets of a frame.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    targets: Vec<Target>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        RenderGraph {
+            targets: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    /// Declares a transient target, which is only valid during the execution of graph.
+    pub fn create_target<T: Into<String>>(&mut self, name: T, params: RenderTextureParams) {
+        self.targets.push(Target {
+            name: name.into(),
+            kind: TargetKind::Transient(params),
+        });
+    }
+
+    /// Imports a render texture as target, e.g. the shadow map or the history buffer
+    /// which must survive between frames. The passes writing imported targets are never
+    /// culled.
+    pub fn import_target<T: Into<String>>(&mut self, name: T, handle: RenderTextureHandle) {
+        self.targets.push(Target {
+            name: name.into(),
+            kind: TargetKind::Imported(handle),
+        });
+    }
+
+    /// Declares a pass, the pass is added into graph once `RenderPassBuilder::execute`
+    /// is called.
+    pub fn add_pass<T: Into<String>>(&mut self, name: T) -> RenderPassBuilder<'_, 'a> {
+        RenderPassBuilder {
+            graph: self,
+            pass: Pass {
+                name: name.into(),
+                reads: Vec::new(),
+                colors: Vec::new(),
+                depth_stencil: None,
+                backbuffer: false,
+                surface: None,
+                clear_color: None,
+                clear_depth: None,
+                clear_stencil: None,
+                execute: None,
+            },
+        }
+    }
+
+    /// Compiles and executes the passes. The commands recorded by passes are submitted
+    /// into video system in order.
+    pub fn execute(mut self, cache: &mut RenderGraphCache) -> Result<()> {
+        let schedule = self.compile()?;
+        let result = self.dispatch(&schedule, cache);
+        cache.collect();
+        result
+    }
+
+    fn dispatch(&mut self, schedule: &Schedule, cache: &mut RenderGraphCache) -> Result<()> {
+        let mut names = FastHashMap::default();
+        let mut handles = Vec::with_capacity(self.targets.len());
+        for (i, v) in self.targets.iter().enumerate() {
+            let handle = match v.kind {
+                TargetKind::Imported(handle) => Some(handle),
+                TargetKind::Transient(params) => match schedule.slots[i] {
+                    Some(slot) => Some(cache.render_texture(slot, params)?),
+                    None => None,
+                },
+            };
+
+            names.insert(v.name.as_str(), i);
+            handles.push(handle);
+        }
+
+        // Targets written by alive passes are always allocated.
+        let handle = |name: &String| handles[names[name.as_str()]].unwrap();
+
+        for &i in &schedule.order {
+            let pass = &mut self.passes[i];
+
+            let surface = match pass.surface {
+                Some(surface) => surface,
+                None => {
+                    let mut params = SurfaceParams::default();
+                    if !pass.backbuffer {
+                        let colors: Vec<_> = pass.colors.iter().map(&handle).collect();
+                        let depth_stencil = pass.depth_stencil.as_ref().map(&handle);
+                        params.set_attachments(&colors, depth_stencil)?;
+                    }
+
+                    params.set_clear(pass.clear_color, pass.clear_depth, pass.clear_stencil);
+                    cache.surface(params)?
+                }
+            };
+
+            let ctx = RenderPassContext {
+                surface,
+                names: &names,
+                handles: &handles,
+            };
+
+            if let Some(ref mut execute) = pass.execute {
+                execute(&ctx, &mut cache.cmds)?;
+            }
+
+            cache.cmds.submit(surface)?;
+        }
+
+        Ok(())
+    }
+
+    fn compile(&self) -> Result<Schedule> {
+        let mut names = FastHashMap::default();
+        for (i, v) in self.targets.iter().enumerate() {
+            if names.insert(v.name.as_str(), i).is_some() {
+                let err = format!("Target {} is declared more than once.", v.name);
+                return Err(Error::RenderGraphInvalid(err).into());
+            }
+        }
+
+        let target = |pass: &Pass, name: &String| match names.get(name.as_str()) {
+            Some(&v) => Ok(v),
+            None => {
+                let err = format!("Pass {} uses undeclared target {}.", pass.name, name);
+                Err(Error::RenderGraphInvalid(err))
+            }
+        };
+
+        // Resolves the targets of passes, and collects the writers of targets.
+        let mut reads = Vec::with_capacity(self.passes.len());
+        let mut writes = Vec::with_capacity(self.passes.len());
+        let mut writers = vec![Vec::new(); self.targets.len()];
+        for (i, pass) in self.passes.iter().enumerate() {
+            let r = pass
+                .reads
+                .iter()
+                .map(|v| target(pass, v))
+                .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+            let w = pass
+                .colors
+                .iter()
+                .chain(pass.depth_stencil.as_ref())
+                .map(|v| target(pass, v))
+                .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+            for &v in &w {
+                if r.contains(&v) {
+                    let err = format!(
+                        "Pass {} reads and writes {} at the same time.",
+                        pass.name, self.targets[v].name
+                    );
+                    return Err(Error::RenderGraphInvalid(err).into());
+                }
+
+                writers[v].push(i);
+            }
+
+            reads.push(r);
+            writes.push(w);
+        }
+
+        // Culls the passes which have no contribution to the backbuffer, external surfaces
+        // or imported targets.
+        let mut alive = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = (0..self.passes.len())
+            .filter(|&i| {
+                self.passes[i].backbuffer
+                    || self.passes[i].surface.is_some()
+                    || writes[i].iter().any(|&v| match self.targets[v].kind {
+                        TargetKind::Imported(_) => true,
+                        TargetKind::Transient(_) => false,
+                    })
+            })
+            .collect();
+
+        while let Some(i) = stack.pop() {
+            if alive[i] {
+                continue;
+            }
+
+            alive[i] = true;
+            for &v in &reads[i] {
+                if writers[v].is_empty() {
+                    if let TargetKind::Transient(_) = self.targets[v].kind {
+                        let err = format!(
+                            "Pass {} reads {}, which is never written.",
+                            self.passes[i].name, self.targets[v].name
+                        );
+                        return Err(Error::RenderGraphInvalid(err).into());
+                    }
+                }
+
+                stack.extend(writers[v].iter().filter(|&&w| w != i));
+            }
+        }
+
+        // Builds the dependencies between alive passes.
+        let mut deps: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        for i in (0..self.passes.len()).filter(|&i| alive[i]) {
+            for &v in &reads[i] {
+                deps[i].extend(writers[v].iter().filter(|&&w| alive[w]));
+            }
+
+            for &v in &writes[i] {
+                deps[i].extend(writers[v].iter().filter(|&&w| alive[w] && w < i));
+            }
+        }
+
+        // Sorts passes topologically, the declaration order is preserved if possible.
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut scheduled = vec![false; self.passes.len()];
+        loop {
+            let next = (0..self.passes.len())
+                .find(|&i| alive[i] && !scheduled[i] && deps[i].iter().all(|&v| scheduled[v]));
+
+            match next {
+                Some(i) => {
+                    scheduled[i] = true;
+                    order.push(i);
+                }
+                None => break,
+            }
+        }
+
+        if order.len() != alive.iter().filter(|&&v| v).count() {
+            let names: Vec<_> = (0..self.passes.len())
+                .filter(|&i| alive[i] && !scheduled[i])
+                .map(|i| self.passes[i].name.as_str())
+                .collect();
+
+            let err = format!("Passes {:?} have cyclic dependencies.", names);
+            return Err(Error::RenderGraphInvalid(err).into());
+        }
+
+        // Finds the lifetimes of transient targets, in the scheduled order.
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.targets.len()];
+        for (step, &i) in order.iter().enumerate() {
+            for &v in reads[i].iter().chain(&writes[i]) {
+                let lifetime = lifetimes[v].get_or_insert((step, step));
+                lifetime.1 = step;
+            }
+        }
+
+        // Aliases the transient targets whose lifetimes are disjoint.
+        let mut physicals: Vec<(RenderTextureParams, usize)> = Vec::new();
+        let mut slots = vec![None; self.targets.len()];
+        for step in 0..order.len() {
+            for (i, v) in self.targets.iter().enumerate() {
+                if let (TargetKind::Transient(params), Some((first, last))) =
+                    (&v.kind, lifetimes[i])
+                {
+                    if first != step {
+                        continue;
+                    }
+
+                    let slot = match physicals.iter().position(|v| v.1 < step && v.0 == *params) {
+                        Some(slot) => {
+                            physicals[slot].1 = last;
+                            slot
+                        }
+                        None => {
+                            physicals.push((*params, last));
+                            physicals.len() - 1
+                        }
+                    };
+
+                    slots[i] = Some(slot);
+                }
+            }
+        }
+
+        Ok(Schedule { order, slots })
+    }
+}
+
+/// The compiled graph.
+struct Schedule {
+    /// The alive passes in execution order.
+    order: Vec<usize>,
+    /// The index of physical render texture of each target, imported or unused targets
+    /// are `None`.
+    slots: Vec<Option<usize>>,
+}
+
+/// The builder of pass.
+pub struct RenderPassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: Pass<'a>,
+}
+
+impl<'g, 'a> RenderPassBuilder<'g, 'a> {
+    /// Declares that the pass samples `target`.
+    #[inline]
+    pub fn read<T: Into<String>>(mut self, target: T) -> Self {
+        self.pass.reads.push(target.into());
+        self
+    }
+
+    /// Appends `target` to the color attachments of pass.
+    #[inline]
+    pub fn write<T: Into<String>>(mut self, target: T) -> Self {
+        self.pass.colors.push(target.into());
+        self
+    }
+
+    /// Sets the depth/stencil attachment of pass.
+    #[inline]
+    pub fn write_depth_stencil<T: Into<String>>(mut self, target: T) -> Self {
+        self.pass.depth_stencil = Some(target.into());
+        self
+    }
+
+    /// Renders into the default framebuffer, the pass would never be culled.
+    #[inline]
+    pub fn write_backbuffer(mut self) -> Self {
+        self.pass.backbuffer = true;
+        self
+    }
+
+    /// Renders into an existing surface, e.g. the surface of camera. The pass would never
+    /// be culled, and its clear flags are ignored since the surface has its own.
+    #[inline]
+    pub fn write_surface(mut self, surface: SurfaceHandle) -> Self {
+        self.pass.surface = Some(surface);
+        self
+    }
+
+    /// Sets the clear flags of pass. Notes that nothing is cleared by default, unlike
+    /// `SurfaceParams`.
+    pub fn with_clear<C, D, S>(mut self, color: C, depth: D, stencil: S) -> Self
+    where
+        C: Into<Option<Color<f32>>>,
+        D: Into<Option<f32>>,
+        S: Into<Option<i32>>,
+    {
+        self.pass.clear_color = color.into();
+        self.pass.clear_depth = depth.into();
+        self.pass.clear_stencil = stencil.into();
+        self
+    }
+
+    /// Adds the pass into graph, the `func` records the draw calls of pass when the graph
+    /// is executed.
+    pub fn execute<F>(mut self, func: F)
+    where
+        F: FnMut(&RenderPassContext, &mut CommandBuffer) -> Result<()> + 'a,
+    {
+        self.pass.execute = Some(Box::new(func));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+/// The resources available to pass during execution.
+pub struct RenderPassContext<'c> {
+    surface: SurfaceHandle,
+    names: &'c FastHashMap<&'c str, usize>,
+    handles: &'c [Option<RenderTextureHandle>],
+}
+
+impl<'c> RenderPassContext<'c> {
+    /// Gets the surface this pass renders into.
+    #[inline]
+    pub fn surface(&self) -> SurfaceHandle {
+        self.surface
+    }
+
+    /// Gets the render texture of target, it returns `None` if the target is not used by
+    /// any alive pass.
+    pub fn render_texture(&self, target: &str) -> Option<RenderTextureHandle> {
+        self.names.get(target).and_then(|&v| self.handles[v])
+    }
+}
+
+/// The render textures and surfaces allocated by `RenderGraph`. Resources which are not
+/// used by the latest execution are deleted.
+#[derive(Default)]
+pub struct RenderGraphCache {
+    render_textures: Vec<(RenderTextureParams, RenderTextureHandle, Option<usize>)>,
+    surfaces: Vec<(SurfaceParams, SurfaceHandle, bool)>,
+    cmds: CommandBuffer,
+}
+
+impl Drop for RenderGraphCache {
+    fn drop(&mut self) {
+        for v in self.surfaces.drain(..) {
+            crate::video::delete_surface(v.1);
+        }
+
+        for v in self.render_textures.drain(..) {
+            crate::video::delete_render_texture(v.1);
+        }
+    }
+}
+
+impl RenderGraphCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of render textures allocated.
+    #[inline]
+    pub fn render_textures_len(&self) -> usize {
+        self.render_textures.len()
+    }
+
+    fn render_texture(
+        &mut self,
+        slot: usize,
+        params: RenderTextureParams,
+    ) -> Result<RenderTextureHandle> {
+        if let Some(v) = self.render_textures.iter().find(|v| v.2 == Some(slot)) {
+            return Ok(v.1);
+        }
+
+        if let Some(v) = self
+            .render_textures
+            .iter_mut()
+            .find(|v| v.2.is_none() && v.0 == params)
+        {
+            v.2 = Some(slot);
+            return Ok(v.1);
+        }
+
+        let handle = crate::video::create_render_texture(params)?;
+        self.render_textures.push((params, handle, Some(slot)));
+        Ok(handle)
+    }
+
+    fn surface(&mut self, params: SurfaceParams) -> Result<SurfaceHandle> {
+        // Every pass has its own surface, since surfaces are only cleared once per frame.
+        if let Some(v) = self.surfaces.iter_mut().find(|v| !v.2 && v.0 == params) {
+            v.2 = true;
+            return Ok(v.1);
+        }
+
+        let handle = crate::video::create_surface(params)?;
+        self.surfaces.push((params, handle, true));
+        Ok(handle)
+    }
+
+    fn collect(&mut self) {
+        self.surfaces.retain(|v| {
+            if !v.2 {
+                crate::video::delete_surface(v.1);
+            }
+
+            v.2
+        });
+
+        self.render_textures.retain(|v| {
+            if v.2.is_none() {
+                crate::video::delete_render_texture(v.1);
+            }
+
+            v.2.is_some()
+        });
+
+        for v in &mut self.surfaces {
+            v.2 = false;
+        }
+
+        for v in &mut self.render_textures {
+            v.2 = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+
+    fn params(size: u32) -> RenderTextureParams {
+        let mut params = RenderTextureParams::default();
+        params.dimensions = (size, size).into();
+        params
+    }
+
+    fn pass<'a>(graph: &mut RenderGraph<'a>, name: &str, reads: &[&str], writes: &[&str]) {
+        let mut builder = graph.add_pass(name);
+        for &v in reads {
+            builder = builder.read(v);
+        }
+
+        for &v in writes {
+            builder = builder.write(v);
+        }
+
+        builder.execute(|_, _| Ok(()));
+    }
+
+    #[test]
+    fn schedule() {
+        let mut graph = RenderGraph::new();
+        graph.create_target("a", params(4));
+        graph.create_target("b", params(4));
+        graph.create_target("c", params(4));
+        graph.create_target("unused", params(8));
+
+        // Declared out of order.
+        graph
+            .add_pass("final")
+            .read("c")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+        pass(&mut graph, "blur", &["b"], &["c"]);
+        pass(&mut graph, "scene", &[], &["a"]);
+        pass(&mut graph, "bright", &["a"], &["b"]);
+        pass(&mut graph, "culled", &["a"], &["unused"]);
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.order, [2, 3, 1, 0]);
+
+        // The `a` is dead once `b` is written, so `c` takes its render texture.
+        assert_eq!(schedule.slots, [Some(0), Some(1), Some(0), None]);
+    }
+
+    #[test]
+    fn writers() {
+        let mut graph = RenderGraph::new();
+        graph.import_target("shadow", RenderTextureHandle::new(1, 1));
+        graph.create_target("a", params(4));
+        graph.create_target("b", params(2));
+
+        pass(&mut graph, "accumulate", &["a"], &["b"]);
+        pass(&mut graph, "shadow", &[], &["shadow"]);
+        pass(&mut graph, "first", &[], &["a"]);
+        pass(&mut graph, "second", &["shadow"], &["a"]);
+        graph
+            .add_pass("final")
+            .read("b")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.order, [1, 2, 3, 0, 4]);
+        assert_eq!(schedule.slots, [None, Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut graph = RenderGraph::new();
+        graph.create_target("a", params(4));
+        graph.create_target("b", params(4));
+        pass(&mut graph, "x", &["a"], &["b"]);
+        pass(&mut graph, "y", &["b"], &["a"]);
+        graph
+            .add_pass("final")
+            .read("b")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+        assert!(graph.compile().is_err());
+
+        let mut graph = RenderGraph::new();
+        graph.create_target("a", params(4));
+        graph
+            .add_pass("final")
+            .read("a")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+        assert!(graph.compile().is_err());
+
+        let mut graph = RenderGraph::new();
+        graph
+            .add_pass("final")
+            .read("a")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+        assert!(graph.compile().is_err());
+
+        let mut graph = RenderGraph::new();
+        graph.create_target("a", params(4));
+        graph.create_target("a", params(4));
+        assert!(graph.compile().is_err());
+
+        let mut graph = RenderGraph::new();
+        graph.create_target("a", params(4));
+        graph
+            .add_pass("final")
+            .read("a")
+            .write("a")
+            .write_backbuffer()
+            .execute(|_, _| Ok(()));
+        assert!(graph.compile().is_err());
+    }
+}
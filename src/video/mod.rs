@@ -61,7 +61,9 @@
 //! few, precompiled resource objects in video module.
 //!
 //! All resources types can be created instantly from data in memory, and meshes, textures
-//! can also be loaded asynchronously from the filesystem.
+//! and shaders can also be loaded asynchronously from the filesystem. With a watching `Dir`
+//! (see `crate::res`), they are reloaded behind the same handles once their files changed
+//! on disk. A shader whose new sources fail to compile keeps running the old program.
 //!
 //! And the actual resource objects are usually private and opaque, you will get a `Handle`
 //! immediately for every resource objects you created instead of some kind of reference.
@@ -142,6 +144,38 @@
 //! video::delete_shader(shader);
 //! ```
 //!
+//! Shaders could also be loaded from the `VSHD` files, which bundle the `ShaderParams`
+//! with the vertex and fragment sources, with `video::create_shader_from`.
+//!
+//! The render state of shader could be overridden per draw call with
+//! `Draw::set_render_state`, so the same shader object serves e.g. both the opaque and
+//! transparent passes.
+//!
+//! #### Shader Variants
+//!
+//! The `ShaderVariants` compiles the permutations of the same sources on demand. The
+//! `#include` directives are expanded, and the `#version`/precision header of backend and
+//! the defines of `ShaderVariantKey` are inserted before compiling. The header is GLSL 3.30
+//! on desktop and GLSL ES 3.00 on WebGL, and sources in GLSL ES 1.00 are still accepted.
+//! The default precision of floats is `highp` on desktop and `mediump` on WebGL, which
+//! could be overridden with `ShaderPreprocessor::set_precision`.
+//!
+//! ```rust
+//! use crayon::prelude::*;
+//! application::oneshot().unwrap();
+//!
+//! let mut variants = ShaderVariants::new(ShaderParams::default(), "..", "..");
+//! variants
+//!     .preprocessor_mut()
+//!     .add_include("lighting.glsl", "vec3 lambert(vec3 n, vec3 l) { .. }");
+//!
+//! let key = ShaderVariantKey::new().define_value("MAX_LITS", 4);
+//! let shader = variants.variant(&key).unwrap();
+//!
+//! // The variant is cached.
+//! assert_eq!(variants.variant(&key).unwrap(), shader);
+//! ```
+//!
 //! ### Texture Object
 //!
 //! A texture object is a container of one or more images. It can be the source of a texture
@@ -162,8 +196,84 @@
 //!
 //! #### Compressed Texture Format
 //!
-//! _TODO_: Cube texture.
-//! _TODO_: 3D texture.
+//! Textures could be compressed with PVRTC, ETC2 or S3TC, and loaded from the KTX 1,
+//! KTX 2 and DDS containers exported by most texture tools. Check
+//! `Capabilities::texture_formats` for the formats that the device samples natively.
+//!
+//! The compressed textures whose formats are not supported are decoded into `RGBA8` on
+//! CPU when they are loaded by `create_texture_from`, except the 2 bits PVRTC formats.
+//! It costs more memory and loading time, but the same assets work everywhere.
+//!
+//! #### Image Files
+//!
+//! Besides the files of offline tools and the KTX and DDS containers, `create_texture_from`
+//! also accepts PNG, JPEG, TGA and BMP images, which are decoded into `RGBA8` on CPU. The
+//! bytes of image could be decoded immediately with `create_texture_from_image_bytes`,
+//! which optionally flips rows and premultiplies alpha with `ImageOptions`.
+//!
+//! #### Cube, Array and 3D Texture
+//!
+//! The `dimension` of `TextureParams` decides how the images of texture are organized.
+//! The images of every mipmap level are stored continuously, e.g. the six faces of cube
+//! texture in order of `+X`, `-X`, `+Y`, `-Y`, `+Z` and `-Z`. They are sampled with the
+//! `TextureCube`, `Texture2DArray` and `Texture3D` uniform types respectively.
+//!
+//! ```rust
+//! use crayon::prelude::*;
+//! application::oneshot().unwrap();
+//!
+//! let mut params = TextureParams::default();
+//! params.hint = TextureHint::Dynamic;
+//! params.dimension = TextureDimension::Cube;
+//! params.dimensions = (1, 1).into();
+//!
+//! let data = TextureData {
+//!     bytes: vec![vec![255; 4 * 6].into_boxed_slice()],
+//! };
+//!
+//! let texture = video::create_texture(params, data).unwrap();
+//!
+//! // Updates the `-Y` face of cube.
+//! let area = Aabb2::new([0, 0].into(), [1, 1].into());
+//! video::update_texture_layer(texture, 3, area, &[0, 0, 0, 255]).unwrap();
+//!
+//! video::delete_texture(texture);
+//! ```
+//!
+//! #### Sampler State
+//!
+//! The `sampler` of `TextureParams` specifies the filters, anisotropy, LOD bias, border
+//! color and the depth comparison of texture fetches. With `generate_mipmaps`, the whole
+//! mipmap chain is generated from the base level when the texture is created or updated.
+//!
+//! ```rust
+//! use crayon::prelude::*;
+//! application::oneshot().unwrap();
+//!
+//! let mut params = TextureParams::default();
+//! params.dimensions = (4, 4).into();
+//! params.generate_mipmaps = true;
+//! params.sampler.mip_filter = Some(TextureFilter::Linear);
+//! params.sampler.anisotropy = 4;
+//!
+//! let data = TextureData {
+//!     bytes: vec![vec![255; 4 * 4 * 4].into_boxed_slice()],
+//! };
+//!
+//! let texture = video::create_texture(params, data).unwrap();
+//! video::delete_texture(texture);
+//!
+//! // Render textures with `compare` could be sampled as shadow maps.
+//! let mut params = RenderTextureParams::default();
+//! params.format = RenderTextureFormat::Depth24;
+//! params.sampler = Some(SamplerParams {
+//!     compare: Some(Comparison::LessOrEqual),
+//!     ..SamplerParams::default()
+//! });
+//!
+//! let rt = video::create_render_texture(params).unwrap();
+//! video::delete_render_texture(rt);
+//! ```
 //!
 //! ### Mesh Object
 //!
@@ -180,10 +290,124 @@
 //! video::delete_mesh(mesh);
 //! ```
 //!
+//! Besides the files of offline tools, `create_mesh_from` also accepts Wavefront OBJ and
+//! PLY files. The groups of OBJ are mapped to sub-meshes, which could be drawn separately
+//! with `MeshIndex::SubMesh`.
+//!
+//! The bounding box of `MeshParams` is computed from the positions of vertices if it's
+//! left empty. `MeshGeometry` provides a typed view of vertices, which could be used to
+//! compute normals and tangents, weld vertices or optimize the order of triangles before
+//! creating meshes.
+//!
+//! The levels of detail could be generated with `MeshGeometry::generate_lods`, whose
+//! simplified indices are appended as extra sub-meshes. Draws with `MeshIndex::All` would
+//! draw every level, so pick one with `video::mesh_lod_index` instead, which avoids
+//! cloning the whole `MeshParams` every frame.
+//!
+//! Vertices could be compressed with `VertexFormat::HalfFloat` and the packed
+//! `Int2_10_10_10Rev` formats, the `pack` function generated by `impl_vertex!` converts
+//! floats into them. The integer formats like `UInt8` are passed to shaders without
+//! conversion, e.g. the indices of bones. These formats require OpenGL 3.3, OpenGL ES 3.0
+//! or WebGL2, check `Capabilities::extended_vertex_formats` before using them.
+//!
+//! ### Uniform Block Object
+//!
+//! Uniform block object holds a set of uniform variables in video memory, which could be
+//! shared across draw calls and shaders. Its useful for per-camera data like view and
+//! projection matrices, which would be uploaded only once per surface.
+//!
+//! ```rust
+//! use crayon::prelude::*;
+//! application::oneshot().unwrap();
+//!
+//! // Declares the variables in block, they are packed with the `std140` layout rules.
+//! let mut params = UniformBlockParams::default();
+//! params.layout = UniformBlockLayout::build()
+//!     .with("u_ViewMatrix", UniformVariableType::Matrix4f)
+//!     .with("u_ProjectionMatrix", UniformVariableType::Matrix4f)
+//!     .finish();
+//!
+//! // Creates an uniform block object, and binds it with the name of block in shader by
+//! // declaring the name as `UniformVariableType::UniformBlock` in `ShaderParams`.
+//! let block = video::create_uniform_block(params).unwrap();
+//!
+//! // Updates the variables of block.
+//! let m = math::prelude::Matrix4::from_scale(1.0);
+//! video::update_uniform_block(block, &[("u_ViewMatrix".into(), m.into())]).unwrap();
+//!
+//! // Deletes the uniform block object.
+//! video::delete_uniform_block(block);
+//! ```
+//!
+//! # Render Graph
+//!
+//! Instead of creating surfaces and render textures by hand, passes could be declared in a
+//! `RenderGraph` with the named targets they read and write. The graph orders the passes,
+//! culls the ones whose outputs are never used, and aliases the transient render textures
+//! between passes.
+//!
+//! ```rust
+//! use crayon::prelude::*;
+//! application::oneshot().unwrap();
+//!
+//! // The cache keeps render textures and surfaces between frames.
+//! let mut cache = RenderGraphCache::new();
+//!
+//! let mut params = RenderTextureParams::default();
+//! params.format = RenderTextureFormat::RGBA8;
+//! params.dimensions = (568, 320).into();
+//!
+//! let mut graph = RenderGraph::new();
+//! graph.create_target("scene", params);
+//!
+//! graph
+//!     .add_pass("scene")
+//!     .write("scene")
+//!     .with_clear(Color::gray(), None, None)
+//!     .execute(|_, cmds| {
+//!         // Records the draw calls into `cmds`.
+//!         Ok(())
+//!     });
+//!
+//! graph
+//!     .add_pass("post_effect")
+//!     .read("scene")
+//!     .write_backbuffer()
+//!     .execute(|ctx, cmds| {
+//!         let texture = ctx.render_texture("scene").unwrap();
+//!         Ok(())
+//!     });
+//!
+//! graph.execute(&mut cache).unwrap();
+//! ```
+//!
+//! # Validation
+//!
+//! Misuses like drawing a mesh whose `VertexLayout` does not match the `AttributeLayout` of
+//! shader, or using handles after deletion, might surface as opaque GL errors or nothing at
+//! all. Setting `VideoParams::validation` wraps the backend with a `ValidationVisitor`, which
+//! tracks every resource object and reports these with descriptive errors.
+//!
+//! # Capabilities
+//!
+//! The features and limits of backend, e.g. the supported texture formats, are queried with
+//! `video::capabilities()`. The headless backend reports `VideoParams::capabilities` instead,
+//! and rejects resources beyond it, so the fallbacks for low-end devices could be tested with
+//! `Capabilities::minimal()`.
+//!
+//! The compressed textures which are not supported by backend are decoded into `RGBA8` on CPU
+//! when they are loaded from files, except the 2BPP variants of PVRTC.
+//!
 //! # Commands
 //!
 //! _TODO_: CommandBuffer
 //! _TODO_: DrawCommandBuffer
+//!
+//! Dynamic geometry that is rebuilt every frame, e.g. UI, debug lines or particles, could
+//! be allocated with `CommandBuffer::alloc_transient` instead of managing streaming meshes
+//! by hand. The vertices and indices are filled through the returned `TransientMesh`, and
+//! drawn with its `mesh` and `mesh_index`. They are stored in the ring buffers of video
+//! system, which are only valid in the current frame.
 
 /// Maximum number of attributes in vertex layout.
 pub const MAX_VERTEX_ATTRIBUTES: usize = 12;
@@ -193,49 +417,83 @@ pub const MAX_FRAMEBUFFER_ATTACHMENTS: usize = 8;
 pub const MAX_UNIFORM_VARIABLES: usize = 32;
 /// Maximum number of textures in shader.
 pub const MAX_UNIFORM_TEXTURE_SLOTS: usize = 8;
+/// Maximum number of vertices in the transient mesh of each vertex layout.
+pub const MAX_TRANSIENT_VERTICES: usize = 64 * 1024;
+/// Maximum number of indices in the transient mesh of each vertex layout.
+pub const MAX_TRANSIENT_INDICES: usize = 192 * 1024;
 
 #[macro_use]
 pub mod assets;
+pub mod capabilities;
 pub mod command;
 pub mod errors;
+pub mod graph;
+pub mod screenshot;
 
 mod system;
+mod transient;
 
 mod backends;
 
+pub use self::backends::{software, trace, validation};
+pub use self::backends::{UniformVar, Visitor};
+
 pub mod prelude {
     pub use super::assets::prelude::*;
-    pub use super::command::{CommandBuffer, Draw, DrawCommandBuffer};
+    pub use super::capabilities::Capabilities;
+    pub use super::command::{CommandBuffer, Draw, DrawCommandBuffer, TransientMesh};
+    pub use super::graph::{RenderGraph, RenderGraphCache, RenderPassBuilder, RenderPassContext};
+    pub use super::screenshot::RecordParams;
+    pub use super::VideoParams;
 }
 
 use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::math::prelude::Aabb2;
+use crate::math::prelude::{Aabb2, Aabb3};
 use crate::prelude::CrResult;
+use crate::res::request::Request;
 use crate::res::utils::prelude::ResourceState;
 use crate::utils::double_buf::DoubleBuf;
 
 use self::assets::prelude::*;
 use self::backends::frame::Frame;
+use self::capabilities::Capabilities;
 use self::errors::*;
 use self::ins::{ctx, CTX};
+use self::software::SoftwareParams;
 use self::system::VideoSystem;
 
+/// The setup parameters of video system.
+#[derive(Debug, Clone, Default)]
+pub struct VideoParams {
+    /// Rasterizes draw calls on CPU when running in headless mode. Otherwise all
+    /// the commands will be discarded silently.
+    pub software: Option<SoftwareParams>,
+    /// Wraps the backend with `validation::ValidationVisitor`, which checks every command
+    /// and reports misuses with descriptive errors. It's useful when debugging, but slows
+    /// down the dispatching of frames.
+    pub validation: bool,
+    /// The capabilities reported by the headless backend, which makes it possible to
+    /// simulate low-end devices. Everything is supported by default. It's ignored by the
+    /// software rasterizer and the GPU backends.
+    pub capabilities: Option<Capabilities>,
+}
+
 /// Setup the video system.
-pub(crate) unsafe fn setup() -> CrResult<()> {
+pub(crate) unsafe fn setup(params: VideoParams) -> CrResult<()> {
     debug_assert!(CTX.is_null(), "duplicated setup of video system.");
 
-    let ctx = VideoSystem::new()?;
+    let ctx = VideoSystem::new(params)?;
     CTX = Box::into_raw(Box::new(ctx));
     Ok(())
 }
 
 /// Setup the video system.
-pub(crate) unsafe fn headless() {
+pub(crate) unsafe fn headless(params: VideoParams) {
     debug_assert!(CTX.is_null(), "duplicated setup of video system.");
 
-    let ctx = VideoSystem::headless();
+    let ctx = VideoSystem::headless(params);
     CTX = Box::into_raw(Box::new(ctx));
 }
 
@@ -253,6 +511,58 @@ pub(crate) unsafe fn frames() -> Arc<DoubleBuf<Frame>> {
     ctx().frames()
 }
 
+pub(crate) fn alloc_transient(
+    layout: VertexLayout,
+    primitive: MeshPrimitive,
+    verts: usize,
+    idxes: usize,
+) -> Result<(MeshHandle, usize, usize)> {
+    ctx().alloc_transient(layout, primitive, verts, idxes)
+}
+
+/// Gets the features and limits of the video backend, e.g. the supported texture formats.
+/// It's useful to pick fallbacks on low-end devices.
+#[inline]
+pub fn capabilities() -> Capabilities {
+    ctx().capabilities()
+}
+
+/// Starts capturing the dispatched frames into `writer`. The trace could be replayed
+/// into any `Visitor` with `trace::TraceReader` later.
+#[inline]
+pub fn start_capture<T>(writer: T) -> CrResult<()>
+where
+    T: std::io::Write + Send + 'static,
+{
+    ctx().start_capture(writer)
+}
+
+/// Stops capturing frames and flushes the trace. Returns the number of captured frames.
+#[inline]
+pub fn stop_capture() -> CrResult<usize> {
+    ctx().stop_capture()
+}
+
+/// Saves the default framebuffer into PNG file at `path`, once current frame is
+/// dispatched. The encoding and writing are performed in background.
+#[inline]
+pub fn capture_screenshot<T: Into<std::path::PathBuf>>(path: T) {
+    ctx().capture_screenshot(path)
+}
+
+/// Starts recording every `params.interval`th frame as numbered PNG files. The timestep
+/// of frames is fixed during recording, and restored once recording is stopped.
+#[inline]
+pub fn start_recording(params: screenshot::RecordParams) {
+    ctx().start_recording(params)
+}
+
+/// Stops recording frames.
+#[inline]
+pub fn stop_recording() {
+    ctx().stop_recording()
+}
+
 /// Creates an surface with `SurfaceParams`.
 #[inline]
 pub fn create_surface(params: SurfaceParams) -> Result<SurfaceHandle> {
@@ -277,6 +587,14 @@ pub fn delete_surface(handle: SurfaceHandle) {
     ctx().delete_surface(handle)
 }
 
+/// Reads a block of pixels from the surface asynchronously. The request will be resolved
+/// with tightly packed RGBA8 bytes, whose rows are ordered from bottom to top, once
+/// current frame is dispatched.
+#[inline]
+pub fn read_surface(handle: SurfaceHandle, area: Aabb2<u32>) -> Request {
+    ctx().read_surface(handle, area)
+}
+
 /// Create a shader with initial shaders and render state. It encapusulates all the
 /// informations we need to configurate graphics pipeline before real drawing.
 #[inline]
@@ -284,6 +602,18 @@ pub fn create_shader(params: ShaderParams, vs: String, fs: String) -> Result<Sha
     ctx().create_shader(params, vs, fs)
 }
 
+/// Creates a shader object from file asynchronously.
+#[inline]
+pub fn create_shader_from<T: AsRef<str>>(url: T) -> CrResult<ShaderHandle> {
+    ctx().create_shader_from(url)
+}
+
+/// Creates a shader object from file asynchronously.
+#[inline]
+pub fn create_shader_from_uuid(uuid: Uuid) -> CrResult<ShaderHandle> {
+    ctx().create_shader_from_uuid(uuid)
+}
+
 /// Gets the `ShaderParams` if available.
 #[inline]
 pub fn shader(handle: ShaderHandle) -> Option<ShaderParams> {
@@ -329,6 +659,16 @@ pub fn mesh(handle: MeshHandle) -> Option<MeshParams> {
     ctx().mesh(handle)
 }
 
+/// Selects the level of detail of mesh, `screen_size` maps the bounding box of mesh to
+/// its size on screen. Returns `MeshIndex::All` if the mesh has only one level.
+#[inline]
+pub fn mesh_lod_index<F>(handle: MeshHandle, screen_size: F) -> MeshIndex
+where
+    F: FnOnce(&Aabb3<f32>) -> f32,
+{
+    ctx().mesh_lod_index(handle, screen_size)
+}
+
 /// Get the resource state of specified mesh.
 #[inline]
 pub fn mesh_state(handle: MeshHandle) -> ResourceState {
@@ -367,6 +707,18 @@ where
     ctx().create_texture(params, data)
 }
 
+/// Creates a texture object from the bytes of PNG, JPEG, TGA or BMP image, which is
+/// decoded into `RGBA8` immediately. The format and dimensions of `params` are
+/// overwritten by the image.
+#[inline]
+pub fn create_texture_from_image_bytes(
+    params: TextureParams,
+    bytes: &[u8],
+    options: ImageOptions,
+) -> CrResult<TextureHandle> {
+    ctx().create_texture_from_image_bytes(params, bytes, options)
+}
+
 /// Creates a texture object from file asynchronously.
 #[inline]
 pub fn create_texture_from<T: AsRef<str>>(url: T) -> CrResult<TextureHandle> {
@@ -391,6 +743,18 @@ pub fn update_texture(handle: TextureHandle, area: Aabb2<u32>, data: &[u8]) -> C
     ctx().update_texture(handle, area, data)
 }
 
+/// Update a contiguous subregion of the specified face of cube texture, layer of array
+/// texture or depth slice of 3D texture.
+#[inline]
+pub fn update_texture_layer(
+    handle: TextureHandle,
+    layer: u32,
+    area: Aabb2<u32>,
+    data: &[u8],
+) -> CrResult<()> {
+    ctx().update_texture_layer(handle, layer, area, data)
+}
+
 /// Delete the texture object.
 #[inline]
 pub fn delete_texture(handle: TextureHandle) {
@@ -415,12 +779,51 @@ pub fn render_texture_state(handle: RenderTextureHandle) -> ResourceState {
     ctx().render_texture_state(handle)
 }
 
+/// Reads a block of pixels from the render texture asynchronously. The request will be
+/// resolved with tightly packed RGBA8 bytes, whose rows are ordered from bottom to top,
+/// once current frame is dispatched.
+#[inline]
+pub fn read_render_texture(handle: RenderTextureHandle, area: Aabb2<u32>) -> Request {
+    ctx().read_render_texture(handle, area)
+}
+
 /// Delete the render texture object.
 #[inline]
 pub fn delete_render_texture(handle: RenderTextureHandle) {
     ctx().delete_render_texture(handle)
 }
 
+/// Creates an uniform block object, which shares its variables with all the draw calls
+/// that bind it. The variables are initialized with zeros.
+#[inline]
+pub fn create_uniform_block(params: UniformBlockParams) -> Result<UniformBlockHandle> {
+    ctx().create_uniform_block(params)
+}
+
+/// Gets the `UniformBlockParams` if available.
+#[inline]
+pub fn uniform_block(handle: UniformBlockHandle) -> Option<UniformBlockParams> {
+    ctx().uniform_block(handle)
+}
+
+/// Get the resource state of specified uniform block.
+#[inline]
+pub fn uniform_block_state(handle: UniformBlockHandle) -> ResourceState {
+    ctx().uniform_block_state(handle)
+}
+
+/// Updates the variables of uniform block, the others keep unchanged.
+#[inline]
+pub fn update_uniform_block(handle: UniformBlockHandle, variables: &[UniformVar]) -> CrResult<()> {
+    ctx().update_uniform_block(handle, variables)
+}
+
+/// Delete the uniform block object.
+#[inline]
+pub fn delete_uniform_block(handle: UniformBlockHandle) {
+    ctx().delete_uniform_block(handle)
+}
+
 mod ins {
     use super::system::VideoSystem;
 
@@ -28,7 +28,7 @@ impl Inspectable for VideoSystemShared {
 
         self.textures.iter(|_, value, rc, uuid| {
             fields.textures.push(VideoInspectResourceField {
-                size: value.format.size(value.dimensions),
+                size: value.format.size(value.dimensions) * value.images(0),
                 rc: rc,
                 uuid: uuid,
             });
@@ -0,0 +1,168 @@
+//! Screenshots and frame-sequence recording into PNG files.
+//!
+//! The default framebuffer is read back after all the commands of frame are dispatched,
+//! and the PNG encoding and file writing happen in the workers of `sched`, so the main
+//! thread is only blocked by the readback itself.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+
+use crate::errors::*;
+use crate::math::prelude::Vector2;
+
+/// The setup parameters of frame-sequence recording.
+#[derive(Debug, Clone)]
+pub struct RecordParams {
+    /// The directory which numbered PNGs are written into.
+    pub directory: PathBuf,
+    /// Captures every `interval`th frame.
+    pub interval: u32,
+    /// The frames per second of the sequence. The timestep of frames is fixed to
+    /// `1 / fps` during recording, so the sequence plays at the right speed regardless
+    /// of how long the encoding takes. Zero keeps the current timestep.
+    pub fps: u32,
+}
+
+impl Default for RecordParams {
+    fn default() -> Self {
+        RecordParams {
+            directory: PathBuf::from("screenshots"),
+            interval: 1,
+            fps: 30,
+        }
+    }
+}
+
+impl RecordParams {
+    /// Gets the virtual timestep of frames, which is `1 / fps`. Returns `None` if `fps`
+    /// is zero.
+    pub fn timestep(&self) -> Option<Duration> {
+        if self.fps > 0 {
+            Some(Duration::from_nanos(1_000_000_000 / u64::from(self.fps)))
+        } else {
+            None
+        }
+    }
+}
+
+pub(crate) struct Recorder {
+    params: RecordParams,
+    frames: u64,
+    index: u64,
+}
+
+impl Recorder {
+    pub fn new(params: RecordParams) -> Self {
+        Recorder {
+            params,
+            frames: 0,
+            index: 0,
+        }
+    }
+
+    /// Advances one frame. Returns the file path if this frame should be captured.
+    pub fn advance(&mut self) -> Option<PathBuf> {
+        let interval = u64::from(self.params.interval.max(1));
+        let captured = self.frames % interval == 0;
+        self.frames += 1;
+
+        if captured {
+            let name = format!("{:06}.png", self.index);
+            self.index += 1;
+            Some(self.params.directory.join(name))
+        } else {
+            None
+        }
+    }
+}
+
+/// Encodes the tightly packed RGBA8 pixels into PNG, the rows of `bytes` are ordered
+/// from bottom to top just like the readbacks.
+pub fn encode_png(dimensions: Vector2<u32>, bytes: &[u8]) -> Result<Vec<u8>> {
+    if dimensions.x == 0 || dimensions.y == 0 {
+        bail!("Trying to encode empty image.");
+    }
+
+    let stride = dimensions.x as usize * 4;
+    if bytes.len() != stride * dimensions.y as usize {
+        bail!("Trying to encode image with mismatched pixels.");
+    }
+
+    // PNG stores the rows from top to bottom.
+    let mut pixels = Vec::with_capacity(bytes.len());
+    for row in bytes.chunks(stride).rev() {
+        pixels.extend_from_slice(row);
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(&pixels, dimensions.x, dimensions.y, ColorType::Rgba8)?;
+    Ok(png)
+}
+
+/// Encodes and writes the PNG file in background.
+pub(crate) fn save(path: PathBuf, dimensions: Vector2<u32>, bytes: Arc<[u8]>) {
+    crate::sched::spawn(move || {
+        if let Err(err) = write(&path, dimensions, &bytes) {
+            warn!("Failed to save screenshot {:?}. {}", path, err);
+        }
+    });
+}
+
+fn write(path: &Path, dimensions: Vector2<u32>, bytes: &[u8]) -> Result<()> {
+    let png = encode_png(dimensions, bytes)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, png)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn png() {
+        // A 1x2 image, with red pixel at bottom and green pixel at top.
+        let bytes = [255, 0, 0, 255, 0, 255, 0, 255];
+        let png = encode_png(Vector2::new(1, 2), &bytes).unwrap();
+
+        assert_eq!(
+            &png[0..8],
+            &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+        );
+
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.dimensions(), (1, 2));
+        assert_eq!(image.into_raw(), vec![0, 255, 0, 255, 255, 0, 0, 255]);
+
+        assert!(encode_png(Vector2::new(0, 2), &[]).is_err());
+        assert!(encode_png(Vector2::new(2, 2), &bytes).is_err());
+    }
+
+    #[test]
+    fn recorder() {
+        let mut recorder = Recorder::new(RecordParams {
+            directory: PathBuf::from("frames"),
+            interval: 2,
+            fps: 60,
+        });
+
+        assert_eq!(recorder.advance(), Some(PathBuf::from("frames/000000.png")));
+        assert_eq!(recorder.advance(), None);
+        assert_eq!(recorder.advance(), Some(PathBuf::from("frames/000001.png")));
+        assert_eq!(
+            recorder.params.timestep(),
+            Some(Duration::from_nanos(16_666_666))
+        );
+    }
+}
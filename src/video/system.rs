@@ -1,36 +1,55 @@
-use std::sync::{Arc, RwLock};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::application::prelude::{LifecycleListener, LifecycleListenerHandle};
-use crate::math::prelude::{Aabb2, Vector2};
+use crate::math::prelude::{Aabb2, Aabb3, Vector2};
 use crate::prelude::CrResult;
+use crate::res::request::Request;
 use crate::res::utils::prelude::{ResourcePool, ResourceState};
 use crate::utils::prelude::{DoubleBuf, ObjectPool};
 
 use super::assets::mesh_loader::MeshLoader;
 use super::assets::prelude::*;
+use super::assets::shader_loader::ShaderLoader;
+use super::assets::texture_image;
 use super::assets::texture_loader::TextureLoader;
 use super::backends::frame::*;
-use super::backends::{self, Visitor};
+use super::backends::trace::TraceWriter;
+use super::backends::{self, UniformVar, Visitor};
+use super::capabilities::Capabilities;
 use super::errors::*;
+use super::screenshot::{self, RecordParams, Recorder};
+use super::transient::TransientBuffers;
+use super::VideoParams;
 
 /// The centralized management of video sub-system.
 pub struct VideoSystem {
     lis: LifecycleListenerHandle,
     state: Arc<VideoState>,
+    capabilities: Capabilities,
 }
 
 struct VideoState {
     frames: Arc<DoubleBuf<Frame>>,
     surfaces: RwLock<ObjectPool<SurfaceHandle, SurfaceParams>>,
-    shaders: RwLock<ObjectPool<ShaderHandle, ShaderParams>>,
+    shaders: RwLock<ResourcePool<ShaderHandle, ShaderLoader>>,
     meshes: RwLock<ResourcePool<MeshHandle, MeshLoader>>,
     textures: RwLock<ResourcePool<TextureHandle, TextureLoader>>,
     render_textures: RwLock<ObjectPool<RenderTextureHandle, RenderTextureParams>>,
+    uniform_blocks: RwLock<ObjectPool<UniformBlockHandle, UniformBlockParams>>,
+    transients: Mutex<TransientBuffers>,
+    capture: Mutex<Option<TraceWriter<Box<dyn Write + Send>>>>,
+    screenshots: Mutex<Vec<PathBuf>>,
+    recorder: Mutex<Option<Recorder>>,
+    /// The fixed timestep before recording, which is restored once recording is stopped.
+    timestep: Mutex<Option<Duration>>,
 }
 
 impl VideoState {
-    fn new() -> Self {
+    fn new(capabilities: Capabilities) -> Self {
         let frames = Arc::new(DoubleBuf::new(
             Frame::with_capacity(64 * 1024),
             Frame::with_capacity(64 * 1024),
@@ -38,10 +57,19 @@ impl VideoState {
 
         VideoState {
             surfaces: RwLock::new(ObjectPool::new()),
-            shaders: RwLock::new(ObjectPool::new()),
+            shaders: RwLock::new(ResourcePool::new(ShaderLoader::new(frames.clone()))),
             meshes: RwLock::new(ResourcePool::new(MeshLoader::new(frames.clone()))),
-            textures: RwLock::new(ResourcePool::new(TextureLoader::new(frames.clone()))),
+            textures: RwLock::new(ResourcePool::new(TextureLoader::new(
+                frames.clone(),
+                capabilities,
+            ))),
             render_textures: RwLock::new(ObjectPool::new()),
+            uniform_blocks: RwLock::new(ObjectPool::new()),
+            transients: Mutex::new(TransientBuffers::new()),
+            capture: Mutex::new(None),
+            screenshots: Mutex::new(Vec::new()),
+            recorder: Mutex::new(None),
+            timestep: Mutex::new(None),
             frames,
         }
     }
@@ -58,6 +86,8 @@ impl LifecycleListener for Lifecycle {
         // Swap internal commands frame.
         self.state.frames.swap();
         self.state.frames.write().clear();
+        self.state.transients.lock().unwrap().advance();
+        self.state.shaders.write().unwrap().advance()?;
         self.state.meshes.write().unwrap().advance()?;
         self.state.textures.write().unwrap().advance()?;
         Ok(())
@@ -72,10 +102,39 @@ impl LifecycleListener for Lifecycle {
             crate::window::resize(dimensions);
         }
 
-        self.state
-            .frames
-            .write_back_buf()
-            .dispatch(self.visitor.as_mut(), self.last_dimensions)?;
+        let mut frame = self.state.frames.write_back_buf();
+
+        // Records the frame before its commands are drained by dispatching. The capture
+        // is stopped once it fails, while the frame should be dispatched anyway.
+        {
+            let mut capture = self.state.capture.lock().unwrap();
+            if let Some(ref mut trace) = *capture {
+                if let Err(err) = trace.write(&frame, self.last_dimensions) {
+                    warn!("Failed to capture frame, the capture is stopped. {}", err);
+                    *capture = None;
+                }
+            }
+        }
+
+        frame.dispatch(self.visitor.as_mut(), self.last_dimensions)?;
+
+        // Reads the default framebuffer back for screenshots and recording.
+        let mut paths: Vec<_> = self.state.screenshots.lock().unwrap().drain(..).collect();
+        if let Some(ref mut recorder) = *self.state.recorder.lock().unwrap() {
+            paths.extend(recorder.advance());
+        }
+
+        if !paths.is_empty() {
+            match unsafe { self.visitor.read_framebuffer(self.last_dimensions) } {
+                Ok((dimensions, bytes)) => {
+                    let bytes: Arc<[u8]> = bytes.into();
+                    for path in paths {
+                        screenshot::save(path, dimensions, bytes.clone());
+                    }
+                }
+                Err(err) => warn!("Failed to read framebuffer back. {}", err),
+            }
+        }
 
         Ok(())
     }
@@ -89,12 +148,14 @@ impl Drop for VideoSystem {
 
 impl VideoSystem {
     /// Create a new `VideoSystem`.
-    pub fn new() -> CrResult<Self> {
-        let state = Arc::new(VideoState::new());
-        let visitor = backends::new()?;
+    pub fn new(params: VideoParams) -> CrResult<Self> {
+        let visitor = backends::new(params)?;
+        let capabilities = visitor.capabilities();
+        let state = Arc::new(VideoState::new(capabilities.clone()));
 
         Ok(VideoSystem {
             state: state.clone(),
+            capabilities,
             lis: crate::application::attach(Lifecycle {
                 state,
                 visitor,
@@ -104,12 +165,14 @@ impl VideoSystem {
     }
 
     /// Create a headless `VideoSystem`.
-    pub fn headless() -> Self {
-        let state = Arc::new(VideoState::new());
-        let visitor = backends::new_headless();
+    pub fn headless(params: VideoParams) -> Self {
+        let visitor = backends::new_headless(params);
+        let capabilities = visitor.capabilities();
+        let state = Arc::new(VideoState::new(capabilities.clone()));
 
         VideoSystem {
             state: state.clone(),
+            capabilities,
             lis: crate::application::attach(Lifecycle {
                 state,
                 visitor,
@@ -121,6 +184,79 @@ impl VideoSystem {
     pub(crate) fn frames(&self) -> Arc<DoubleBuf<Frame>> {
         self.state.frames.clone()
     }
+
+    /// Allocates `verts` vertices and `idxes` indices from the transient mesh of `layout`
+    /// and `primitive`. Returns the mesh, and the first vertex and index of allocation.
+    pub(crate) fn alloc_transient(
+        &self,
+        layout: VertexLayout,
+        primitive: MeshPrimitive,
+        verts: usize,
+        idxes: usize,
+    ) -> Result<(MeshHandle, usize, usize)> {
+        let mut transients = self.state.transients.lock().unwrap();
+        transients.alloc(layout, primitive, verts, idxes, |params| {
+            self.create_mesh(params, None)
+                .map_err(|err| Error::MeshInvalid(err.to_string()))
+        })
+    }
+
+    #[inline]
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    /// Starts capturing the dispatched frames into `writer` as a trace.
+    pub fn start_capture<T>(&self, writer: T) -> CrResult<()>
+    where
+        T: Write + Send + 'static,
+    {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        *self.state.capture.lock().unwrap() = Some(TraceWriter::new(writer)?);
+        Ok(())
+    }
+
+    /// Stops capturing and flushes the trace. Returns the number of captured frames.
+    pub fn stop_capture(&self) -> CrResult<usize> {
+        match self.state.capture.lock().unwrap().take() {
+            Some(trace) => {
+                let frames = trace.frames();
+                trace.into_inner()?;
+                Ok(frames)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Saves the default framebuffer into PNG file once current frame is dispatched.
+    pub fn capture_screenshot<T: Into<PathBuf>>(&self, path: T) {
+        self.state.screenshots.lock().unwrap().push(path.into());
+    }
+
+    /// Starts recording the dispatched frames into numbered PNG files, and fixes the
+    /// timestep of frames to `params.timestep()` if it's not `None`.
+    pub fn start_recording(&self, params: RecordParams) {
+        let mut recorder = self.state.recorder.lock().unwrap();
+
+        // Restarting keeps the timestep before the first recording.
+        if recorder.is_none() {
+            *self.state.timestep.lock().unwrap() = crate::application::fixed_timestep();
+        }
+
+        if let Some(timestep) = params.timestep() {
+            crate::application::set_fixed_timestep(Some(timestep));
+        }
+
+        *recorder = Some(Recorder::new(params));
+    }
+
+    /// Stops recording, and restores the timestep of frames before recording.
+    pub fn stop_recording(&self) {
+        if self.state.recorder.lock().unwrap().take().is_some() {
+            let timestep = self.state.timestep.lock().unwrap().take();
+            crate::application::set_fixed_timestep(timestep);
+        }
+    }
 }
 
 impl VideoSystem {
@@ -158,6 +294,23 @@ impl VideoSystem {
             self.state.frames.write().cmds.push(cmd);
         }
     }
+
+    /// Reads a block of pixels from the surface asynchronously. The request will be
+    /// resolved with tightly packed RGBA8 bytes once current frame is dispatched.
+    pub fn read_surface(&self, handle: SurfaceHandle, area: Aabb2<u32>) -> Request {
+        if !self.state.surfaces.read().unwrap().contains(handle) {
+            return Request::err(format_err!("{:?} is invalid.", handle));
+        }
+
+        let latch = Request::latch();
+
+        {
+            let cmd = Command::ReadSurface(Box::new((handle, area, latch.clone())));
+            self.state.frames.write().cmds.push(cmd);
+        }
+
+        Request::new(latch)
+    }
 }
 
 impl VideoSystem {
@@ -171,39 +324,42 @@ impl VideoSystem {
     ) -> Result<ShaderHandle> {
         params.validate(&vs, &fs)?;
 
-        let handle = self.state.shaders.write().unwrap().create(params.clone());
-
-        {
-            let cmd = Command::CreateShader(Box::new((handle, params, vs, fs)));
-            self.state.frames.write().cmds.push(cmd);
-        }
+        let mut shaders = self.state.shaders.write().unwrap();
+        let handle = shaders
+            .create((params, vs, fs))
+            .map_err(|err| Error::ShaderInvalid(err.to_string()))?;
 
         Ok(handle)
     }
 
+    /// Creates a shader object from file asynchronously.
+    pub fn create_shader_from<T: AsRef<str>>(&self, url: T) -> CrResult<ShaderHandle> {
+        let mut shaders = self.state.shaders.write().unwrap();
+        shaders.create_from(url)
+    }
+
+    /// Creates a shader object from file asynchronously.
+    pub fn create_shader_from_uuid(&self, uuid: Uuid) -> CrResult<ShaderHandle> {
+        let mut shaders = self.state.shaders.write().unwrap();
+        shaders.create_from_uuid(uuid)
+    }
+
     /// Gets the `ShaderParams` if available.
     #[inline]
     pub fn shader(&self, handle: ShaderHandle) -> Option<ShaderParams> {
-        self.state.shaders.read().unwrap().get(handle).cloned()
+        self.state.shaders.read().unwrap().resource(handle).cloned()
     }
 
     /// Get the resource state of specified shader.
     #[inline]
     pub fn shader_state(&self, handle: ShaderHandle) -> ResourceState {
-        if self.state.shaders.read().unwrap().contains(handle) {
-            ResourceState::Ok
-        } else {
-            ResourceState::NotReady
-        }
+        self.state.shaders.read().unwrap().state(handle)
     }
 
     /// Delete shader state object.
     #[inline]
     pub fn delete_shader(&self, handle: ShaderHandle) {
-        if self.state.shaders.write().unwrap().free(handle).is_some() {
-            let cmd = Command::DeleteShader(handle);
-            self.state.frames.write().cmds.push(cmd);
-        }
+        self.state.shaders.write().unwrap().delete(handle);
     }
 }
 
@@ -238,6 +394,21 @@ impl VideoSystem {
         self.state.meshes.read().unwrap().resource(handle).cloned()
     }
 
+    /// Selects the level of detail of mesh without cloning its `MeshParams`. The
+    /// `screen_size` maps the bounding box of mesh to its size on screen, it's only called
+    /// if the mesh is ready and has more than one level.
+    pub fn mesh_lod_index<F>(&self, handle: MeshHandle, screen_size: F) -> MeshIndex
+    where
+        F: FnOnce(&Aabb3<f32>) -> f32,
+    {
+        match self.state.meshes.read().unwrap().resource(handle) {
+            Some(params) if !params.lods.is_empty() => {
+                params.lod_index(params.lod(screen_size(&params.aabb)))
+            }
+            _ => MeshIndex::All,
+        }
+    }
+
     /// Get the resource state of specified mesh.
     #[inline]
     pub fn mesh_state(&self, handle: MeshHandle) -> ResourceState {
@@ -304,6 +475,25 @@ impl VideoSystem {
         textures.create((params, data.into()))
     }
 
+    /// Creates a texture object from the bytes of PNG, JPEG, TGA or BMP image, which is
+    /// decoded into `RGBA8` immediately. The format and dimensions of `params` are
+    /// overwritten by the image.
+    pub fn create_texture_from_image_bytes(
+        &self,
+        mut params: TextureParams,
+        bytes: &[u8],
+        options: ImageOptions,
+    ) -> CrResult<TextureHandle> {
+        let format = ImageFormat::detect(bytes)
+            .ok_or_else(|| Error::TextureInvalid("The format of image is unknown.".into()))?;
+        let (dimensions, data) = texture_image::decode(format, bytes, options)?;
+
+        params.format = TextureFormat::RGBA8;
+        params.dimensions = dimensions;
+        params.dimension = TextureDimension::D2;
+        self.create_texture(params, data)
+    }
+
     /// Creates a texture object from file asynchronously.
     pub fn create_texture_from<T: AsRef<str>>(&self, url: T) -> CrResult<TextureHandle> {
         let mut textures = self.state.textures.write().unwrap();
@@ -323,17 +513,30 @@ impl VideoSystem {
     }
 
     /// Update a contiguous subregion of an existing two-dimensional texture object.
+    #[inline]
     pub fn update_texture(
         &self,
         handle: TextureHandle,
         area: Aabb2<u32>,
         data: &[u8],
+    ) -> CrResult<()> {
+        self.update_texture_layer(handle, 0, area, data)
+    }
+
+    /// Update a contiguous subregion of the specified face of cube texture, layer of
+    /// array texture or depth slice of 3D texture.
+    pub fn update_texture_layer(
+        &self,
+        handle: TextureHandle,
+        layer: u32,
+        area: Aabb2<u32>,
+        data: &[u8],
     ) -> CrResult<()> {
         let textures = self.state.textures.read().unwrap();
         if textures.contains(handle) {
             let mut frame = self.state.frames.write();
             let ptr = frame.bufs.extend_from_slice(data);
-            let cmd = Command::UpdateTexture(handle, area, ptr);
+            let cmd = Command::UpdateTexture(handle, layer, area, ptr);
             frame.cmds.push(cmd);
             Ok(())
         } else {
@@ -383,6 +586,23 @@ impl VideoSystem {
         }
     }
 
+    /// Reads a block of pixels from the render texture asynchronously. The request will
+    /// be resolved with tightly packed RGBA8 bytes once current frame is dispatched.
+    pub fn read_render_texture(&self, handle: RenderTextureHandle, area: Aabb2<u32>) -> Request {
+        if !self.state.render_textures.read().unwrap().contains(handle) {
+            return Request::err(format_err!("{:?} is invalid.", handle));
+        }
+
+        let latch = Request::latch();
+
+        {
+            let cmd = Command::ReadRenderTexture(Box::new((handle, area, latch.clone())));
+            self.state.frames.write().cmds.push(cmd);
+        }
+
+        Request::new(latch)
+    }
+
     /// Delete the render texture object.
     pub fn delete_render_texture(&self, handle: RenderTextureHandle) {
         if self
@@ -399,6 +619,81 @@ impl VideoSystem {
     }
 }
 
+impl VideoSystem {
+    /// Creates an uniform block object, which shares its variables with all the draw calls
+    /// that bind it. The variables are initialized with zeros.
+    pub fn create_uniform_block(&self, params: UniformBlockParams) -> Result<UniformBlockHandle> {
+        params.validate()?;
+
+        let handle = self
+            .state
+            .uniform_blocks
+            .write()
+            .unwrap()
+            .create(params.clone());
+
+        {
+            let cmd = Command::CreateUniformBlock(Box::new((handle, params)));
+            self.state.frames.write().cmds.push(cmd);
+        }
+
+        Ok(handle)
+    }
+
+    /// Gets the `UniformBlockParams` if available.
+    pub fn uniform_block(&self, handle: UniformBlockHandle) -> Option<UniformBlockParams> {
+        self.state
+            .uniform_blocks
+            .read()
+            .unwrap()
+            .get(handle)
+            .cloned()
+    }
+
+    /// Get the resource state of specified uniform block.
+    #[inline]
+    pub fn uniform_block_state(&self, handle: UniformBlockHandle) -> ResourceState {
+        if self.state.uniform_blocks.read().unwrap().contains(handle) {
+            ResourceState::Ok
+        } else {
+            ResourceState::NotReady
+        }
+    }
+
+    /// Updates the variables of uniform block, the others keep unchanged.
+    pub fn update_uniform_block(
+        &self,
+        handle: UniformBlockHandle,
+        variables: &[UniformVar],
+    ) -> CrResult<()> {
+        let uniform_blocks = self.state.uniform_blocks.read().unwrap();
+        if uniform_blocks.contains(handle) {
+            let mut frame = self.state.frames.write();
+            let ptr = frame.bufs.extend_from_slice(variables);
+            let cmd = Command::UpdateUniformBlock(handle, ptr);
+            frame.cmds.push(cmd);
+            Ok(())
+        } else {
+            bail!("{:?} is invalid.", handle);
+        }
+    }
+
+    /// Delete the uniform block object.
+    pub fn delete_uniform_block(&self, handle: UniformBlockHandle) {
+        if self
+            .state
+            .uniform_blocks
+            .write()
+            .unwrap()
+            .free(handle)
+            .is_some()
+        {
+            let cmd = Command::DeleteUniformBlock(handle);
+            self.state.frames.write().cmds.push(cmd);
+        }
+    }
+}
+
 fn dimensions_pixels() -> Vector2<u32> {
     let dimensions = crate::window::dimensions();
     let dpr = crate::window::device_pixel_ratio();
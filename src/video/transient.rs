@@ -0,0 +1,216 @@
+//! The persistent ring buffers of transient geometry, e.g. UI, debug lines or particles,
+//! which are rebuilt every frame.
+//!
+//! Every combination of `VertexLayout` and `MeshPrimitive` owns a streaming mesh, whose
+//! vertices and indices are allocated in a ring. The allocations of the frame that is
+//! waiting to be dispatched are kept until the next frame begins, so they would never be
+//! overwritten before drawing.
+
+use super::assets::prelude::*;
+use super::errors::*;
+use super::{MAX_TRANSIENT_INDICES, MAX_TRANSIENT_VERTICES};
+
+/// A ring of elements, which tracks the allocations of two frames.
+#[derive(Debug, Clone, Copy)]
+struct Ring {
+    capacity: usize,
+    head: usize,
+    tail: usize,
+    frame: usize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Ring {
+            capacity,
+            head: 0,
+            tail: 0,
+            frame: 0,
+        }
+    }
+
+    /// Finds `len` contiguous elements, returns the first element and the new head of
+    /// ring. The head never catches the tail up, so they are equal only if the ring is
+    /// empty.
+    fn reserve(&self, len: usize) -> Option<(usize, usize)> {
+        if self.head >= self.tail {
+            if self.head + len <= self.capacity {
+                Some((self.head, self.head + len))
+            } else if len < self.tail {
+                // Wraps around, and the rest of ring is skipped.
+                Some((0, len))
+            } else {
+                None
+            }
+        } else if self.head + len < self.tail {
+            Some((self.head, self.head + len))
+        } else {
+            None
+        }
+    }
+
+    /// Begins a new frame, the allocations before the previous frame are released.
+    fn advance(&mut self) {
+        self.tail = self.frame;
+        self.frame = self.head;
+    }
+}
+
+struct TransientRing {
+    layout: VertexLayout,
+    primitive: MeshPrimitive,
+    mesh: MeshHandle,
+    vertices: Ring,
+    indices: Ring,
+}
+
+/// The transient meshes of all the vertex layouts.
+#[derive(Default)]
+pub(crate) struct TransientBuffers {
+    rings: Vec<TransientRing>,
+}
+
+impl TransientBuffers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Allocates `verts` vertices and `idxes` indices from the mesh of `layout` and
+    /// `primitive`, which is created with `create` if not exists. Returns the mesh, and
+    /// the first vertex and index of allocation.
+    pub fn alloc<F>(
+        &mut self,
+        layout: VertexLayout,
+        primitive: MeshPrimitive,
+        verts: usize,
+        idxes: usize,
+        create: F,
+    ) -> Result<(MeshHandle, usize, usize)>
+    where
+        F: FnOnce(MeshParams) -> Result<MeshHandle>,
+    {
+        if verts > MAX_TRANSIENT_VERTICES || idxes > MAX_TRANSIENT_INDICES {
+            return Err(Error::OutOfBounds);
+        }
+
+        let index = match self
+            .rings
+            .iter()
+            .position(|v| v.layout == layout && v.primitive == primitive)
+        {
+            Some(index) => index,
+            None => {
+                let mut params = MeshParams::default();
+                params.hint = MeshHint::Stream;
+                params.layout = layout;
+                params.index_format = IndexFormat::U32;
+                params.primitive = primitive;
+                params.num_verts = MAX_TRANSIENT_VERTICES;
+                params.num_idxes = MAX_TRANSIENT_INDICES;
+
+                self.rings.push(TransientRing {
+                    layout,
+                    primitive,
+                    mesh: create(params)?,
+                    vertices: Ring::new(MAX_TRANSIENT_VERTICES),
+                    indices: Ring::new(MAX_TRANSIENT_INDICES),
+                });
+
+                self.rings.len() - 1
+            }
+        };
+
+        let ring = &mut self.rings[index];
+        match (ring.vertices.reserve(verts), ring.indices.reserve(idxes)) {
+            (Some((vertex, vhead)), Some((index, ihead))) => {
+                ring.vertices.head = vhead;
+                ring.indices.head = ihead;
+                Ok((ring.mesh, vertex, index))
+            }
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+
+    /// Begins a new frame.
+    pub fn advance(&mut self) {
+        for v in &mut self.rings {
+            v.vertices.advance();
+            v.indices.advance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::handle::HandleLike;
+
+    #[test]
+    fn ring() {
+        let mut ring = Ring::new(8);
+        assert_eq!(ring.reserve(9), None);
+        assert_eq!(ring.reserve(8), Some((0, 8)));
+
+        ring.head = 5;
+        ring.advance();
+        assert_eq!(ring.reserve(3), Some((5, 8)));
+
+        // The first frame is waiting to be dispatched.
+        ring.head = 7;
+        ring.advance();
+        assert_eq!(ring.reserve(1), Some((7, 8)));
+        assert_eq!(ring.reserve(2), Some((0, 2)));
+        assert_eq!(ring.reserve(5), None);
+
+        ring.head = 8;
+        ring.advance();
+        assert_eq!(ring.reserve(5), Some((0, 5)));
+        assert_eq!(ring.reserve(7), None);
+
+        ring.head = 5;
+        assert_eq!(ring.reserve(1), Some((5, 6)));
+        assert_eq!(ring.reserve(2), None);
+    }
+
+    #[test]
+    fn alloc() {
+        let layout = VertexLayout::build()
+            .with(Attribute::Position, VertexFormat::Float, 2, false)
+            .finish();
+
+        let mut created = 0;
+        let mut buffers = TransientBuffers::new();
+        let mut create = |params: MeshParams| -> Result<MeshHandle> {
+            assert_eq!(params.hint, MeshHint::Stream);
+            created += 1;
+            Ok(MeshHandle::new(created, 1))
+        };
+
+        let triangles = MeshPrimitive::Triangles;
+        let (mesh, vertex, index) = buffers.alloc(layout, triangles, 4, 6, &mut create).unwrap();
+        assert_eq!((vertex, index), (0, 0));
+
+        let v = buffers.alloc(layout, triangles, 3, 3, &mut create).unwrap();
+        assert_eq!(v, (mesh, 4, 6));
+
+        let v = buffers
+            .alloc(layout, MeshPrimitive::Lines, 2, 2, &mut create)
+            .unwrap();
+        assert_ne!(v.0, mesh);
+
+        let v = buffers.alloc(layout, triangles, MAX_TRANSIENT_VERTICES, 0, &mut create);
+        assert!(v.is_err());
+
+        // The allocations of the previous frame are released after two frames.
+        buffers.advance();
+        buffers.advance();
+        let v = buffers.alloc(
+            layout,
+            triangles,
+            MAX_TRANSIENT_VERTICES - 7,
+            0,
+            &mut create,
+        );
+        assert_eq!(v.unwrap(), (mesh, 7, 9));
+    }
+}